@@ -0,0 +1,107 @@
+//! Compares reporting delivery results from a thread standing in for the producer poll thread,
+//! with the bounded channel and `blocking_send` the delivery callback first used, and the
+//! unbounded channel it used next, against the shared per-request state of `kafka::deliveries`.
+//! Each result is boxed with a cloned sender, like the delivery opaque of a message.
+//!
+//! Run with `cargo bench --bench delivery`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use tokio::sync::mpsc;
+
+use ingest::kafka::{Delivery, Records, deliveries};
+
+// the capacity of the channel of a JSON lines request before
+const BOUNDED_CAPACITY: usize = 512;
+
+// roughly the size of a delivery report
+type Report = (u64, i32, i64);
+
+async fn bounded(messages: u64) -> u64 {
+    let (tx, mut rx) = mpsc::channel::<Report>(BOUNDED_CAPACITY);
+    let opaques: Vec<_> = (0..messages).map(|i| Box::new((tx.clone(), i))).collect();
+    drop(tx);
+    let poll_thread = std::thread::spawn(move || {
+        for opaque in opaques {
+            let (tx, i) = *opaque;
+            tx.blocking_send((i, 0, i as i64)).unwrap();
+        }
+    });
+    let mut count = 0;
+    while rx.recv().await.is_some() {
+        count += 1;
+    }
+    poll_thread.join().unwrap();
+    count
+}
+
+async fn unbounded(messages: u64) -> u64 {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Report>();
+    let opaques: Vec<_> = (0..messages).map(|i| Box::new((tx.clone(), i))).collect();
+    drop(tx);
+    let poll_thread = std::thread::spawn(move || {
+        for opaque in opaques {
+            let (tx, i) = *opaque;
+            // the result of a cancelled request is dropped
+            let _ = tx.send((i, 0, i as i64));
+        }
+    });
+    let mut count = 0;
+    while rx.recv().await.is_some() {
+        count += 1;
+    }
+    poll_thread.join().unwrap();
+    count
+}
+
+async fn shared(messages: u64) -> u64 {
+    let (tx, mut rx) = deliveries();
+    let opaques: Vec<_> = (0..messages).map(|i| Box::new((tx.clone(), i))).collect();
+    drop(tx);
+    let poll_thread = std::thread::spawn(move || {
+        for opaque in opaques {
+            let (tx, i) = *opaque;
+            tx.send(Ok(Delivery {
+                records: Records::single(1),
+                topic: String::new(),
+                partition: 0,
+                offset: i as i64,
+                timestamp: None,
+            }));
+        }
+    });
+    let mut count = 0;
+    while rx.recv().await.is_some() {
+        count += 1;
+    }
+    poll_thread.join().unwrap();
+    count
+}
+
+fn bench_deliveries(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("deliveries");
+    for messages in [1, 512, 64 * 1024] {
+        group.throughput(Throughput::Elements(messages));
+        group.bench_with_input(
+            BenchmarkId::new("bounded_blocking_send", messages),
+            &messages,
+            |b, &messages| b.to_async(&runtime).iter(|| bounded(messages)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("unbounded", messages),
+            &messages,
+            |b, &messages| b.to_async(&runtime).iter(|| unbounded(messages)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("shared", messages),
+            &messages,
+            |b, &messages| b.to_async(&runtime).iter(|| shared(messages)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_deliveries);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+//! Compares splitting JSON lines with `framing::frames` against the previous
+//! `LinesCodec` based splitting, which allocated a `String` per line and copied it again
+//! after trimming.
+//!
+//! Run with `cargo bench --bench framing`.
+
+use bytes::Bytes;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use futures::stream::{self, StreamExt};
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tokio_util::io::StreamReader;
+
+use ingest::config::Framing;
+use ingest::framing::{frames, trim};
+
+const MAX_LENGTH: usize = 1024 * 1024;
+// roughly the size of the chunks actix web hands to the request handler
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn body_chunks(line: &str, total_size: usize) -> Vec<Bytes> {
+    let line = format!("{}\n", line);
+    let body = Bytes::from(line.repeat(total_size / line.len()));
+    (0..body.len())
+        .step_by(CHUNK_SIZE)
+        .map(|start| body.slice(start..(start + CHUNK_SIZE).min(body.len())))
+        .collect()
+}
+
+async fn split_lines_codec(chunks: Vec<Bytes>) -> usize {
+    let reader = StreamReader::new(stream::iter(
+        chunks.into_iter().map(Ok::<Bytes, std::io::Error>),
+    ));
+    FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LENGTH))
+        .map(|line| Bytes::from(line.unwrap().trim().as_bytes().to_vec()))
+        .fold(0, |count, line| async move { count + line.len() })
+        .await
+}
+
+async fn split_frames(chunks: Vec<Bytes>) -> usize {
+    let body_stream = stream::iter(chunks.into_iter().map(Ok::<Bytes, std::io::Error>));
+    frames(body_stream, &Framing::Newline, MAX_LENGTH)
+        .map(|line| trim(line.unwrap()))
+        .fold(0, |count, line| async move { count + line.len() })
+        .await
+}
+
+fn bench_json_lines(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("json_lines");
+    for (name, line) in [
+        ("small", r#"{"a":1}"#.to_owned()),
+        (
+            "medium",
+            r#"{"some":{"deeper":{"nested":"data"}},"list":[1,2,3]}"#.repeat(4),
+        ),
+        (
+            "large",
+            format!(r#"{{"data":"{}"}}"#, "x".repeat(16 * 1024)),
+        ),
+    ] {
+        let chunks = body_chunks(&line, 16 * 1024 * 1024);
+        let size: usize = chunks.iter().map(|c| c.len()).sum();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("lines_codec", name),
+            &chunks,
+            |b, chunks| {
+                b.to_async(&runtime)
+                    .iter(|| split_lines_codec(chunks.clone()))
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("frames", name), &chunks, |b, chunks| {
+            b.to_async(&runtime).iter(|| split_frames(chunks.clone()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_json_lines);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = tonic_prost_build::Config::new();
+    // use the bundled protoc unless one is provided
+    if std::env::var_os("PROTOC").is_none() {
+        config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    }
+    tonic_prost_build::configure().compile_with_config(
+        config,
+        &["proto/ingest.proto"],
+        &["proto"],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Admission control of the ingest requests by the memory their bodies can take, so that a burst
+//! of large uploads can't exhaust the memory.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::UpDownCounter;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::MemoryBudgetConfig;
+use crate::metrics::{Metrics, SCHEMA_ID_ATTRIBUTE};
+
+// the budgets are semaphores with a permit per KiB, since permits are acquired as u32
+const PERMIT_BYTES: u64 = 1024;
+
+// the metrics attribute of the requests admitted by endpoint
+const ENDPOINT_ATTRIBUTE: &str = "ingest.endpoint";
+
+/// Bytes of requests in flight, up to a limit.
+pub struct Budget {
+    semaphore: Arc<Semaphore>,
+    permits: u32,
+}
+
+impl Budget {
+    pub fn new(max_in_flight_bytes: u64) -> Budget {
+        let permits = max_in_flight_bytes
+            .div_ceil(PERMIT_BYTES)
+            .clamp(1, u32::MAX as u64) as u32;
+        Budget {
+            semaphore: Arc::new(Semaphore::new(permits as usize)),
+            permits,
+        }
+    }
+
+    /// Requests larger than the budget take all of it.
+    async fn acquire(&self, bytes: u64) -> OwnedSemaphorePermit {
+        let permits = bytes.div_ceil(PERMIT_BYTES).clamp(1, self.permits as u64) as u32;
+        self.semaphore
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .expect("Budget semaphores are never closed")
+    }
+}
+
+pub struct Admission {
+    global: Option<Budget>,
+    default_schema: Option<Budget>,
+    /// Budgets of the schemas with their own configuration
+    schemas: HashMap<String, Budget>,
+    queue_timeout: Duration,
+    pub retry_after_seconds: u64,
+    metrics: Metrics,
+}
+
+/// Holds the budget of an admitted request until dropped.
+pub struct Admitted {
+    _permits: Vec<OwnedSemaphorePermit>,
+    bytes: i64,
+    in_flight_bytes: UpDownCounter<i64>,
+    attributes: [KeyValue; 1],
+}
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        self.in_flight_bytes.add(-self.bytes, &self.attributes);
+    }
+}
+
+impl Admission {
+    pub fn new(
+        config: &MemoryBudgetConfig,
+        default_schema: Option<Budget>,
+        schemas: HashMap<String, Budget>,
+        metrics: Metrics,
+    ) -> Admission {
+        Admission {
+            global: config.max_in_flight_bytes.map(Budget::new),
+            default_schema,
+            schemas,
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+            retry_after_seconds: config.retry_after_seconds,
+            metrics,
+        }
+    }
+
+    /// Waits up to the queue timeout for the global and the schema budgets to have room for
+    /// `bytes`. None when the request is rejected.
+    pub async fn admit(&self, schema_id: &str, bytes: u64) -> Option<Admitted> {
+        let schema_budget = match self.schemas.get(schema_id) {
+            Some(budget) => Some(budget),
+            None => self.default_schema.as_ref(),
+        };
+        let attribute = KeyValue::new(SCHEMA_ID_ATTRIBUTE, schema_id.to_owned());
+        self.acquire(self.global.iter().chain(schema_budget), bytes, attribute)
+            .await
+    }
+
+    /// Like `admit`, for the requests of an endpoint whose schemas are only known once their
+    /// body is read, which only take from the global budget.
+    pub async fn admit_to_endpoint(&self, endpoint: &str, bytes: u64) -> Option<Admitted> {
+        let attribute = KeyValue::new(ENDPOINT_ATTRIBUTE, endpoint.to_owned());
+        self.acquire(self.global.iter(), bytes, attribute).await
+    }
+
+    async fn acquire(
+        &self,
+        budgets: impl Iterator<Item = &Budget>,
+        bytes: u64,
+        attribute: KeyValue,
+    ) -> Option<Admitted> {
+        // always acquired in the same order, so that requests can't wait for each other
+        let acquire = async {
+            let mut permits = Vec::with_capacity(2);
+            for budget in budgets {
+                permits.push(budget.acquire(bytes).await);
+            }
+            permits
+        };
+        let attributes = [attribute];
+        match tokio::time::timeout(self.queue_timeout, acquire).await {
+            Ok(permits) => {
+                let bytes = bytes as i64;
+                self.metrics.in_flight_bytes.add(bytes, &attributes);
+                Some(Admitted {
+                    _permits: permits,
+                    bytes,
+                    in_flight_bytes: self.metrics.in_flight_bytes.clone(),
+                    attributes,
+                })
+            }
+            Err(_) => {
+                self.metrics.rejected_requests.add(1, &attributes);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use crate::config::MemoryBudgetConfig;
+use crate::metrics::Metrics;
+
+use super::{Admission, Budget};
+
+fn admission(max_in_flight_bytes: Option<u64>, schemas: HashMap<String, Budget>) -> Admission {
+    Admission::new(
+        &MemoryBudgetConfig {
+            max_in_flight_bytes,
+            queue_timeout_ms: 10,
+            retry_after_seconds: 1,
+        },
+        None,
+        schemas,
+        Metrics::new(),
+    )
+}
+
+#[tokio::test]
+async fn test_global_budget() {
+    let admission = admission(Some(4096), HashMap::new());
+    let first = admission.admit("1", 3000).await.unwrap();
+    assert!(admission.admit("2", 3000).await.is_none());
+    // a small request still fits
+    let second = admission.admit("2", 1000).await.unwrap();
+    drop(first);
+    drop(second);
+    assert!(admission.admit("2", 3000).await.is_some());
+}
+
+#[tokio::test]
+async fn test_request_larger_than_budget_takes_all_of_it() {
+    let admission = admission(Some(4096), HashMap::new());
+    let admitted = admission.admit("1", 1_000_000).await.unwrap();
+    assert!(admission.admit("1", 1).await.is_none());
+    drop(admitted);
+    assert!(admission.admit("1", 1).await.is_some());
+}
+
+#[tokio::test]
+async fn test_schema_budget() {
+    let admission = admission(None, HashMap::from([("1".to_owned(), Budget::new(2048))]));
+    let _admitted = admission.admit("1", 2048).await.unwrap();
+    assert!(admission.admit("1", 1).await.is_none());
+    // other schemas have no budget
+    assert!(admission.admit("2", 1_000_000).await.is_some());
+}
+
+#[tokio::test]
+async fn test_endpoint_global_budget_only() {
+    let admission = admission(
+        Some(4096),
+        HashMap::from([("1".to_owned(), Budget::new(1024))]),
+    );
+    let admitted = admission
+        .admit_to_endpoint("elasticsearch", 3072)
+        .await
+        .unwrap();
+    // the schema budgets are left to the requests of the schemas
+    let _schema = admission.admit("1", 1024).await.unwrap();
+    assert!(admission.admit_to_endpoint("splunk_hec", 1).await.is_none());
+    drop(admitted);
+    assert!(admission.admit_to_endpoint("splunk_hec", 1).await.is_some());
+}
+
+#[tokio::test]
+async fn test_queued_until_budget_released() {
+    let admission = Admission {
+        queue_timeout: std::time::Duration::from_secs(5),
+        ..admission(Some(1024), HashMap::new())
+    };
+    let admitted = admission.admit("1", 1024).await.unwrap();
+    let (queued, ()) = tokio::join!(admission.admit("1", 1024), async move {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        drop(admitted);
+    });
+    assert!(queued.is_some());
+}
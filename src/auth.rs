@@ -0,0 +1,201 @@
+//! API key authentication of ingest requests, with the keys and the SHA-256 of their secrets
+//! loaded from a JSON file or a directory of JSON files.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use actix_web::http::header::{AUTHORIZATION, HeaderMap};
+use actix_web::web::Query;
+use common::config::ConfigError;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::config::ApiKeysConfig;
+use crate::error::{Error, Result};
+use crate::forward::wildcard_matches;
+
+/// A key as listed in a keys file.
+#[derive(Deserialize)]
+struct ApiKeyEntry {
+    id: String,
+    /// Hex SHA-256 of the secret.
+    sha256: String,
+    schemas: Vec<String>,
+    #[serde(default)]
+    methods: Vec<String>,
+}
+
+pub struct ApiKey {
+    pub id: String,
+    schemas: Vec<String>,
+    methods: Vec<String>,
+}
+
+impl ApiKey {
+    /// Whether the key may send requests with `method` to `schema_id`. Schema id patterns can have
+    /// `*` wildcards, and keys without methods may use all of them.
+    pub fn allows(&self, schema_id: &str, method: &str) -> bool {
+        self.schemas
+            .iter()
+            .any(|pattern| wildcard_matches(pattern, schema_id))
+            && (self.methods.is_empty() || self.methods.iter().any(|m| m == method))
+    }
+}
+
+/// The keys by the SHA-256 of their secrets.
+pub struct ApiKeys(HashMap<[u8; 32], ApiKey>);
+
+impl ApiKeys {
+    /// Loads a JSON file, or all the `.json` files of a directory, each a list of keys.
+    pub fn load(path: &Path) -> Result<ApiKeys> {
+        let mut keys = HashMap::new();
+        if path.is_dir() {
+            let mut paths = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<PathBuf>>>()?;
+            paths.sort();
+            for path in paths
+                .iter()
+                .filter(|path| path.is_file() && path.extension() == Some(OsStr::new("json")))
+            {
+                load_file(path, &mut keys)?;
+            }
+        } else {
+            load_file(path, &mut keys)?;
+        }
+        Ok(ApiKeys(keys))
+    }
+
+    pub fn authenticate(&self, secret: &str) -> Option<&ApiKey> {
+        self.0
+            .get(&<[u8; 32]>::from(Sha256::digest(secret.as_bytes())))
+    }
+}
+
+fn load_file(path: &Path, keys: &mut HashMap<[u8; 32], ApiKey>) -> Result<()> {
+    let invalid = |e: &dyn fmt::Display| {
+        Error::from(ConfigError::Invalid(format!(
+            "Could not load API keys '{}': {}",
+            path.display(),
+            e
+        )))
+    };
+    let entries: Vec<ApiKeyEntry> =
+        serde_json::from_slice(&fs::read(path)?).map_err(|e| invalid(&e))?;
+    for entry in entries {
+        let hash = hex::decode(&entry.sha256)
+            .ok()
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .ok_or_else(|| invalid(&format!("key '{}' has an invalid sha256", entry.id)))?;
+        let key = ApiKey {
+            id: entry.id,
+            schemas: entry.schemas,
+            methods: entry
+                .methods
+                .iter()
+                .map(|method| method.to_ascii_uppercase())
+                .collect(),
+        };
+        if let Some(other) = keys.get(&hash) {
+            return Err(invalid(&format!(
+                "keys '{}' and '{}' have the same secret",
+                other.id, key.id
+            )));
+        }
+        keys.insert(hash, key);
+    }
+    Ok(())
+}
+
+/// The id of the key a request is authenticated with, in the request extensions.
+pub struct ApiKeyId(pub String);
+
+/// The errors of API key authentication, responded to with their status and JSON.
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    MissingKey,
+    InvalidAuthorization,
+    InvalidKey,
+    /// The id of a key that does not allow the schema or the method.
+    Forbidden(String),
+}
+
+impl AuthError {
+    pub fn status(&self) -> u16 {
+        match self {
+            AuthError::MissingKey | AuthError::InvalidAuthorization | AuthError::InvalidKey => 401,
+            AuthError::Forbidden(_) => 403,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingKey => "missing_api_key",
+            AuthError::InvalidAuthorization => "invalid_authorization",
+            AuthError::InvalidKey => "invalid_api_key",
+            AuthError::Forbidden(_) => "forbidden",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"error": self.code(), "message": self.to_string()})
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingKey => f.write_str("API key is required"),
+            AuthError::InvalidAuthorization => f.write_str("Invalid authorization"),
+            AuthError::InvalidKey => f.write_str("Invalid API key"),
+            AuthError::Forbidden(id) => write!(f, "API key '{}' is not allowed", id),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// The key of a request, from the configured header, the bearer token or the query parameter.
+pub fn request_key(
+    config: &ApiKeysConfig,
+    headers: &HeaderMap,
+    query: &str,
+) -> std::result::Result<String, AuthError> {
+    if let Some(key) = headers.get(config.header.as_str()) {
+        return key
+            .to_str()
+            .map(str::to_owned)
+            .map_err(|_| AuthError::InvalidKey);
+    }
+    if config.bearer
+        && let Some(authorization) = headers.get(AUTHORIZATION)
+    {
+        return authorization
+            .to_str()
+            .ok()
+            .and_then(|authorization| authorization.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim().to_owned())
+            .ok_or(AuthError::InvalidAuthorization);
+    }
+    if let Some(name) = &config.query_parameter
+        && let Ok(mut parameters) = Query::<HashMap<String, String>>::from_query(query)
+        && let Some(key) = parameters.0.remove(name)
+    {
+        return Ok(key);
+    }
+    Err(AuthError::MissingKey)
+}
+
+/// Whether a request header carries the key, so that it is not forwarded.
+pub fn is_key_header(config: &ApiKeysConfig, name: &str) -> bool {
+    name.eq_ignore_ascii_case(&config.header)
+        || (config.bearer && name.eq_ignore_ascii_case(AUTHORIZATION.as_str()))
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::PathBuf;
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::config::ApiKeysConfig;
+
+use super::{ApiKeys, AuthError, is_key_header, request_key};
+
+// echo -n secret | sha256sum
+const SECRET_SHA256: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+// echo -n other | sha256sum
+const OTHER_SHA256: &str = "d9298a10d1b0735837dc4bd85dac641b0f3cef27a47e5d53a54f2f3f5b2fcffa";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("ingest-auth-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(query_parameter: Option<&str>) -> ApiKeysConfig {
+    ApiKeysConfig {
+        path: String::new(),
+        header: "x-api-key".to_owned(),
+        bearer: true,
+        query_parameter: query_parameter.map(str::to_owned),
+    }
+}
+
+fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for &(name, value) in headers {
+        map.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        );
+    }
+    map
+}
+
+#[test]
+fn test_load_directory() {
+    let dir = temp_dir("directory");
+    fs::write(
+        dir.join("a.json"),
+        format!(
+            r#"[{{"id": "a", "sha256": "{}", "schemas": ["logs-*"], "methods": ["post"]}}]"#,
+            SECRET_SHA256
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("b.json"),
+        format!(
+            r#"[{{"id": "b", "sha256": "{}", "schemas": ["1"]}}]"#,
+            OTHER_SHA256
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("README"), "not keys").unwrap();
+    let keys = ApiKeys::load(&dir).unwrap();
+
+    let a = keys.authenticate("secret").unwrap();
+    assert_eq!(a.id, "a");
+    assert!(a.allows("logs-app", "POST"));
+    assert!(!a.allows("logs-app", "PUT"));
+    assert!(!a.allows("1", "POST"));
+    let b = keys.authenticate("other").unwrap();
+    assert_eq!(b.id, "b");
+    assert!(b.allows("1", "PUT"));
+    assert!(keys.authenticate("unknown").is_none());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_load_invalid() {
+    let dir = temp_dir("invalid");
+    let path = dir.join("keys.json");
+    fs::write(&path, r#"[{"id": "a", "sha256": "abc", "schemas": ["1"]}]"#).unwrap();
+    assert!(ApiKeys::load(&path).is_err());
+    fs::write(
+        &path,
+        format!(
+            r#"[{{"id": "a", "sha256": "{0}", "schemas": ["1"]}}, {{"id": "b", "sha256": "{0}", "schemas": ["2"]}}]"#,
+            SECRET_SHA256
+        ),
+    )
+    .unwrap();
+    assert!(ApiKeys::load(&path).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_request_key() {
+    let config = config(Some("api_key"));
+    assert_eq!(
+        request_key(&config, &headers(&[("x-api-key", "secret")]), ""),
+        Ok("secret".to_owned())
+    );
+    assert_eq!(
+        request_key(&config, &headers(&[("authorization", "Bearer secret")]), ""),
+        Ok("secret".to_owned())
+    );
+    assert_eq!(
+        request_key(
+            &config,
+            &headers(&[("authorization", "Basic c2VjcmV0")]),
+            ""
+        ),
+        Err(AuthError::InvalidAuthorization)
+    );
+    assert_eq!(
+        request_key(&config, &headers(&[]), "a=1&api_key=secret"),
+        Ok("secret".to_owned())
+    );
+    assert_eq!(
+        request_key(&config, &headers(&[]), "a=1"),
+        Err(AuthError::MissingKey)
+    );
+    // query parameters are only accepted when configured
+    assert_eq!(
+        request_key(&self::config(None), &headers(&[]), "api_key=secret"),
+        Err(AuthError::MissingKey)
+    );
+}
+
+#[test]
+fn test_is_key_header() {
+    let config = config(None);
+    assert!(is_key_header(&config, "X-Api-Key"));
+    assert!(is_key_header(&config, "authorization"));
+    assert!(!is_key_header(&config, "user-agent"));
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(AuthError::MissingKey.status(), 401);
+    assert_eq!(
+        AuthError::Forbidden("a".to_owned()).to_json(),
+        serde_json::json!({"error": "forbidden", "message": "API key 'a' is not allowed"})
+    );
+}
@@ -0,0 +1,110 @@
+//! Packing of consecutive records into a single Kafka message.
+
+use std::borrow::Cow;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::config::{BatchFormat, BatchingConfig};
+use crate::kafka::Records;
+
+pub struct Batch {
+    format: BatchFormat,
+    max_bytes: usize,
+    max_count: u64,
+    max_event_size_bytes: usize,
+    buf: BytesMut,
+    records: Records,
+}
+
+impl Batch {
+    pub fn new(config: &BatchingConfig, max_event_size_bytes: usize) -> Batch {
+        Batch {
+            format: config.format.clone(),
+            max_bytes: config
+                .max_bytes
+                .map(|b| b as usize)
+                .unwrap_or(max_event_size_bytes)
+                .min(max_event_size_bytes),
+            max_count: config.max_count.unwrap_or(u64::MAX).max(1),
+            max_event_size_bytes,
+            buf: BytesMut::new(),
+            records: Records { count: 0, bytes: 0 },
+        }
+    }
+
+    /// The max length of a record that fits in a batch on its own without exceeding the max
+    /// event size.
+    pub fn max_record_length(&self) -> usize {
+        match self.format {
+            BatchFormat::Newline => self.max_event_size_bytes,
+            // opening and closing brackets
+            BatchFormat::JsonArray => self.max_event_size_bytes.saturating_sub(2),
+        }
+    }
+
+    /// Adds a JSON record to the batch. If the record does not fit, the current batch is returned
+    /// to be sent and the record starts a new batch.
+    pub fn push(&mut self, record: &[u8]) -> Option<(Bytes, Records)> {
+        // newlines are only whitespace between the tokens of JSON, they are escaped in strings,
+        // so a pretty-printed record is put on a single line by dropping them
+        let record: Cow<'_, [u8]> = match self.format {
+            BatchFormat::Newline if record.contains(&b'\n') => Cow::Owned(
+                record
+                    .iter()
+                    .copied()
+                    .filter(|b| *b != b'\n' && *b != b'\r')
+                    .collect(),
+            ),
+            _ => Cow::Borrowed(record),
+        };
+        let full = if self.records.count > 0
+            && (self.records.count >= self.max_count
+                || self.len_with(record.len()) > self.max_bytes)
+        {
+            self.take()
+        } else {
+            None
+        };
+
+        if self.records.count == 0 {
+            if let BatchFormat::JsonArray = self.format {
+                self.buf.extend_from_slice(b"[");
+            }
+        } else {
+            match self.format {
+                BatchFormat::Newline => self.buf.extend_from_slice(b"\n"),
+                BatchFormat::JsonArray => self.buf.extend_from_slice(b","),
+            }
+        }
+        self.buf.extend_from_slice(&record);
+        self.records.count += 1;
+        self.records.bytes += record.len();
+
+        full
+    }
+
+    /// Returns the batch to be sent, if it has any records, and starts a new one.
+    pub fn take(&mut self) -> Option<(Bytes, Records)> {
+        if self.records.count == 0 {
+            return None;
+        }
+        if let BatchFormat::JsonArray = self.format {
+            self.buf.extend_from_slice(b"]");
+        }
+        let records = self.records;
+        self.records = Records { count: 0, bytes: 0 };
+        Some((self.buf.split().freeze(), records))
+    }
+
+    // the length of the finished batch if the record was added to it
+    fn len_with(&self, record_len: usize) -> usize {
+        let len = self.buf.len() + 1 + record_len;
+        match self.format {
+            BatchFormat::Newline => len,
+            BatchFormat::JsonArray => len + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
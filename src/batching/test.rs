@@ -0,0 +1,73 @@
+use crate::config::{BatchFormat, BatchingConfig};
+
+use super::Batch;
+
+fn batch(format: BatchFormat, max_bytes: Option<u64>, max_count: Option<u64>) -> Batch {
+    Batch::new(
+        &BatchingConfig {
+            format,
+            max_bytes,
+            max_count,
+        },
+        20,
+    )
+}
+
+#[test]
+fn test_newline_max_count() {
+    let mut b = batch(BatchFormat::Newline, None, Some(2));
+    assert!(b.push(b"{}").is_none());
+    assert!(b.push(b"[1]").is_none());
+    let (payload, records) = b.push(b"2").unwrap();
+    assert_eq!(payload, "{}\n[1]");
+    assert_eq!((records.count, records.bytes), (2, 5));
+
+    let (payload, records) = b.take().unwrap();
+    assert_eq!(payload, "2");
+    assert_eq!((records.count, records.bytes), (1, 1));
+    assert!(b.take().is_none());
+}
+
+#[test]
+fn test_json_array_max_bytes() {
+    let mut b = batch(BatchFormat::JsonArray, Some(10), None);
+    assert!(b.push(b"123").is_none());
+    assert!(b.push(b"45").is_none());
+    // [123,45,6] would be 10 bytes, [123,45,67] is 11
+    let (payload, records) = b.push(b"67").unwrap();
+    assert_eq!(payload, "[123,45]");
+    assert_eq!((records.count, records.bytes), (2, 5));
+    let (payload, _) = b.take().unwrap();
+    assert_eq!(payload, "[67]");
+}
+
+#[test]
+fn test_max_bytes_capped_at_max_event_size() {
+    let mut b = batch(BatchFormat::JsonArray, Some(100), None);
+    assert_eq!(b.max_record_length(), 18);
+    assert!(b.push(b"123456789").is_none());
+    let (payload, _) = b.push(b"123456789").unwrap();
+    assert_eq!(payload, "[123456789]");
+}
+
+#[test]
+fn test_record_larger_than_max_bytes_is_sent_alone() {
+    let mut b = batch(BatchFormat::Newline, Some(4), None);
+    assert!(b.push(b"123456").is_none());
+    let (payload, _) = b.push(b"1").unwrap();
+    assert_eq!(payload, "123456");
+    assert!(b.push(b"2").is_none());
+    let (payload, records) = b.take().unwrap();
+    assert_eq!(payload, "1\n2");
+    assert_eq!(records.count, 2);
+}
+
+#[test]
+fn test_newline_compacts_multi_line_records() {
+    let mut b = batch(BatchFormat::Newline, None, None);
+    assert!(b.push(b"{\r\n  \"a\": 1\n}").is_none());
+    assert!(b.push(b"[\n2]").is_none());
+    let (payload, records) = b.take().unwrap();
+    assert_eq!(payload, "{  \"a\": 1}\n[2]");
+    assert_eq!((records.count, records.bytes), (2, 13));
+}
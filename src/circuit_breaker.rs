@@ -0,0 +1,98 @@
+//! Circuit breaker of a Kafka producer, so that sends fail fast while its cluster is unhealthy
+//! instead of waiting for `message.timeout.ms`.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::CircuitBreakerConfig;
+
+/// Opens after consecutive delivery errors, or when all the brokers are down. Once open for
+/// `open_seconds` it is half open and lets a single trial message through: its delivery closes
+/// it, and its delivery error opens it again.
+///
+/// Updated from the producer poll thread, so it is lock free.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    consecutive_failures: AtomicU32,
+    // milliseconds since `created` when the circuit opened, plus one, 0 while closed
+    opened_at: AtomicU64,
+    // whether the trial message of the half open circuit was let through
+    trial_sent: AtomicBool,
+    created: Instant,
+    clock: Box<dyn Fn() -> Instant + Send + Sync>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> CircuitBreaker {
+        CircuitBreaker::with_clock(config, Instant::now)
+    }
+
+    fn with_clock(
+        config: &CircuitBreakerConfig,
+        clock: impl Fn() -> Instant + Send + Sync + 'static,
+    ) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold: config.failure_threshold.max(1),
+            open_duration: Duration::from_secs(config.open_seconds),
+            consecutive_failures: AtomicU32::new(0),
+            opened_at: AtomicU64::new(0),
+            trial_sent: AtomicBool::new(false),
+            created: clock(),
+            clock: Box::new(clock),
+        }
+    }
+
+    /// Whether a message can be sent. When half open, only the first caller is let through with
+    /// the trial message, and sends fail fast until its delivery result.
+    pub fn allow(&self) -> bool {
+        match self.opened_at.load(Ordering::Acquire) {
+            0 => true,
+            opened_at => {
+                let open_for = self.now_millis().saturating_sub(opened_at);
+                open_for >= self.open_duration.as_millis() as u64
+                    && self
+                        .trial_sent
+                        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+            }
+        }
+    }
+
+    /// Lets another trial message through, when the one let through by `allow` could not be sent
+    /// at all and so will never have a delivery result. Does nothing while closed.
+    pub fn release_trial(&self) {
+        if self.opened_at.load(Ordering::Acquire) != 0 {
+            self.trial_sent.store(false, Ordering::Release);
+        }
+    }
+
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Release);
+        self.opened_at.store(0, Ordering::Release);
+        self.trial_sent.store(false, Ordering::Release);
+    }
+
+    pub fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+        if failures >= self.failure_threshold || self.trial_sent.load(Ordering::Acquire) {
+            self.open();
+        }
+    }
+
+    /// Opens the circuit, or keeps it open for another `open_seconds`.
+    pub fn open(&self) {
+        self.opened_at.store(self.now_millis(), Ordering::Release);
+        self.trial_sent.store(false, Ordering::Release);
+    }
+
+    fn now_millis(&self) -> u64 {
+        (self.clock)()
+            .saturating_duration_since(self.created)
+            .as_millis() as u64
+            + 1
+    }
+}
+
+#[cfg(test)]
+mod test;
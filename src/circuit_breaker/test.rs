@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::CircuitBreakerConfig;
+
+use super::CircuitBreaker;
+
+fn circuit_breaker(open_seconds: u64) -> CircuitBreaker {
+    CircuitBreaker::new(&CircuitBreakerConfig {
+        failure_threshold: 3,
+        open_seconds,
+    })
+}
+
+#[test]
+fn test_opens_after_consecutive_failures() {
+    let circuit_breaker = circuit_breaker(30);
+    circuit_breaker.record_failure();
+    circuit_breaker.record_failure();
+    assert!(circuit_breaker.allow());
+    // a delivery resets the count
+    circuit_breaker.record_success();
+    circuit_breaker.record_failure();
+    circuit_breaker.record_failure();
+    assert!(circuit_breaker.allow());
+    circuit_breaker.record_failure();
+    assert!(!circuit_breaker.allow());
+    circuit_breaker.record_success();
+    assert!(circuit_breaker.allow());
+}
+
+#[test]
+fn test_opened_when_brokers_down() {
+    let circuit_breaker = circuit_breaker(30);
+    circuit_breaker.open();
+    assert!(!circuit_breaker.allow());
+}
+
+// a circuit breaker with a clock the test moves forward
+fn circuit_breaker_with_clock(open_seconds: u64) -> (CircuitBreaker, impl Fn(u64)) {
+    let now = Arc::new(Mutex::new(Instant::now()));
+    let circuit_breaker = CircuitBreaker::with_clock(
+        &CircuitBreakerConfig {
+            failure_threshold: 3,
+            open_seconds,
+        },
+        {
+            let now = now.clone();
+            move || *now.lock().unwrap()
+        },
+    );
+    let advance = move |seconds| *now.lock().unwrap() += Duration::from_secs(seconds);
+    (circuit_breaker, advance)
+}
+
+#[test]
+fn test_half_open_after_open_seconds() {
+    let (circuit_breaker, advance) = circuit_breaker_with_clock(30);
+    circuit_breaker.open();
+    advance(29);
+    assert!(!circuit_breaker.allow());
+
+    // a single trial message is let through
+    advance(1);
+    assert!(circuit_breaker.allow());
+    assert!(!circuit_breaker.allow());
+    advance(1);
+    assert!(!circuit_breaker.allow());
+
+    // its delivery closes the circuit
+    circuit_breaker.record_success();
+    assert!(circuit_breaker.allow());
+    assert!(circuit_breaker.allow());
+}
+
+#[test]
+fn test_half_open_trial_failure() {
+    let (circuit_breaker, advance) = circuit_breaker_with_clock(30);
+    circuit_breaker.open();
+    advance(30);
+    assert!(circuit_breaker.allow());
+
+    // a single delivery error of the trial opens it again for open_seconds
+    circuit_breaker.record_failure();
+    assert!(!circuit_breaker.allow());
+    advance(29);
+    assert!(!circuit_breaker.allow());
+    advance(1);
+    assert!(circuit_breaker.allow());
+    assert!(!circuit_breaker.allow());
+}
+
+#[test]
+fn test_half_open_trial_not_sent() {
+    let (circuit_breaker, advance) = circuit_breaker_with_clock(30);
+    circuit_breaker.open();
+    advance(30);
+    assert!(circuit_breaker.allow());
+    assert!(!circuit_breaker.allow());
+
+    // a trial refused by the producer queue lets the next message through instead
+    circuit_breaker.release_trial();
+    assert!(circuit_breaker.allow());
+    assert!(!circuit_breaker.allow());
+    circuit_breaker.record_success();
+
+    // and does nothing while closed
+    circuit_breaker.release_trial();
+    assert!(circuit_breaker.allow());
+}
@@ -0,0 +1,211 @@
+//! CloudEvents received in the HTTP binary and structured modes, mapped to Kafka messages as in
+//! the Kafka protocol binding: the attributes are `ce_` prefixed headers, `datacontenttype` is the
+//! `content-type` header and the data is the message value.
+
+use std::fmt;
+
+use actix_web::http::header::{CONTENT_TYPE, HeaderMap};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+pub const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+pub const BATCH_CONTENT_TYPE: &str = "application/cloudevents-batch+json";
+const SPEC_VERSION: &str = "1.0";
+const REQUIRED_ATTRIBUTES: [&str; 4] = ["id", "source", "specversion", "type"];
+// of the attribute headers of HTTP requests and of Kafka messages
+const HTTP_PREFIX: &str = "ce-";
+const KAFKA_PREFIX: &str = "ce_";
+const KAFKA_CONTENT_TYPE: &str = "content-type";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The attributes are headers and the body is the data.
+    Binary,
+    /// The body is an event.
+    Structured,
+    /// The body is an array of events.
+    Batch,
+}
+
+impl Mode {
+    pub fn from_content_type(content_type: Option<&str>) -> Mode {
+        match content_type
+            .and_then(|c| c.split(';').next())
+            .map(str::trim)
+        {
+            Some(STRUCTURED_CONTENT_TYPE) => Mode::Structured,
+            Some(BATCH_CONTENT_TYPE) => Mode::Batch,
+            _ => Mode::Binary,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CloudEventsError {
+    MissingAttribute(&'static str),
+    UnsupportedSpecVersion(String),
+    InvalidEvent(String),
+}
+
+impl fmt::Display for CloudEventsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloudEventsError::MissingAttribute(attribute) => {
+                write!(f, "Missing required CloudEvents attribute '{}'", attribute)
+            }
+            CloudEventsError::UnsupportedSpecVersion(version) => {
+                write!(f, "Unsupported CloudEvents specversion '{}'", version)
+            }
+            CloudEventsError::InvalidEvent(e) => write!(f, "Invalid CloudEvent: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CloudEventsError {}
+
+/// An event of a structured mode request.
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    /// The attributes, as Kafka headers
+    pub headers: Vec<(String, Bytes)>,
+    pub data: Bytes,
+    /// Whether the data is JSON, as it is when `datacontenttype` is not set
+    pub is_json: bool,
+}
+
+/// The Kafka headers of the attribute headers of a binary mode request, and of its content type.
+pub fn binary_headers(
+    request_headers: &HeaderMap,
+) -> Result<Vec<(String, Bytes)>, CloudEventsError> {
+    let mut headers: Vec<(String, Bytes)> = request_headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let attribute = name.as_str().strip_prefix(HTTP_PREFIX)?;
+            Some((
+                format!("{}{}", KAFKA_PREFIX, attribute),
+                Bytes::from(percent_decode(value.as_bytes())),
+            ))
+        })
+        .collect();
+    if let Some(content_type) = request_headers.get(CONTENT_TYPE) {
+        headers.push((
+            KAFKA_CONTENT_TYPE.to_owned(),
+            Bytes::copy_from_slice(content_type.as_bytes()),
+        ));
+    }
+    validate(&headers)?;
+    Ok(headers)
+}
+
+/// The events of a structured or batch mode request body.
+pub fn structured(body: &[u8], mode: Mode) -> Result<Vec<Event>, CloudEventsError> {
+    let invalid = |e: serde_json::Error| CloudEventsError::InvalidEvent(e.to_string());
+    match mode {
+        Mode::Batch => {
+            let events: Vec<Map<String, Value>> = serde_json::from_slice(body).map_err(invalid)?;
+            events.into_iter().map(event).collect()
+        }
+        _ => Ok(vec![event(serde_json::from_slice(body).map_err(invalid)?)?]),
+    }
+}
+
+fn event(mut envelope: Map<String, Value>) -> Result<Event, CloudEventsError> {
+    let invalid = |e: &str| Err(CloudEventsError::InvalidEvent(e.to_owned()));
+    let content_type = match envelope.remove("datacontenttype") {
+        Some(Value::String(content_type)) => Some(content_type),
+        None | Some(Value::Null) => None,
+        Some(_) => return invalid("datacontenttype must be a string"),
+    };
+    let is_json = content_type.as_deref().is_none_or(is_json_content_type);
+    let (data, is_json) = match (envelope.remove("data"), envelope.remove("data_base64")) {
+        (Some(_), Some(_)) => return invalid("data and data_base64 are mutually exclusive"),
+        // the text of non JSON data, like XML
+        (Some(Value::String(data)), None) if !is_json => (Bytes::from(data), false),
+        (Some(data), None) => (
+            Bytes::from(serde_json::to_vec(&data).expect("JSON values serialize")),
+            is_json,
+        ),
+        (None, Some(Value::String(data))) => match STANDARD.decode(data) {
+            Ok(data) => (Bytes::from(data), false),
+            Err(_) => return invalid("data_base64 is not valid base64"),
+        },
+        (None, Some(_)) => return invalid("data_base64 must be a string"),
+        (None, None) => (Bytes::new(), is_json),
+    };
+
+    let mut headers: Vec<(String, Bytes)> = envelope
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => Bytes::from(value),
+                value => Bytes::from(value.to_string()),
+            };
+            (format!("{}{}", KAFKA_PREFIX, name), value)
+        })
+        .collect();
+    if let Some(content_type) = content_type {
+        headers.push((KAFKA_CONTENT_TYPE.to_owned(), Bytes::from(content_type)));
+    }
+    validate(&headers)?;
+    Ok(Event {
+        headers,
+        data,
+        is_json,
+    })
+}
+
+fn validate(headers: &[(String, Bytes)]) -> Result<(), CloudEventsError> {
+    for attribute in REQUIRED_ATTRIBUTES {
+        let value = headers
+            .iter()
+            .find(|(name, _)| name.strip_prefix(KAFKA_PREFIX) == Some(attribute))
+            .map(|(_, value)| value)
+            .filter(|value| !value.is_empty())
+            .ok_or(CloudEventsError::MissingAttribute(attribute))?;
+        if attribute == "specversion" && value != SPEC_VERSION {
+            return Err(CloudEventsError::UnsupportedSpecVersion(
+                String::from_utf8_lossy(value).into_owned(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    media_type == "application/json" || media_type == "text/json" || media_type.ends_with("+json")
+}
+
+/// The values of attribute headers are percent encoded.
+fn percent_decode(value: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        let escaped = value
+            .get(i + 1..i + 3)
+            .filter(|hex| value[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(value[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod test;
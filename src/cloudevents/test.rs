@@ -0,0 +1,160 @@
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use bytes::Bytes;
+
+use super::{CloudEventsError, Event, Mode, binary_headers, structured};
+
+fn header(name: &str, value: &str) -> (String, Bytes) {
+    (name.to_owned(), Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn request_headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut header_map = HeaderMap::new();
+    for &(name, value) in headers {
+        header_map.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        );
+    }
+    header_map
+}
+
+#[test]
+fn test_mode() {
+    assert_eq!(
+        Mode::from_content_type(Some("application/cloudevents+json; charset=UTF-8")),
+        Mode::Structured
+    );
+    assert_eq!(
+        Mode::from_content_type(Some("application/cloudevents-batch+json")),
+        Mode::Batch
+    );
+    assert_eq!(
+        Mode::from_content_type(Some("application/json")),
+        Mode::Binary
+    );
+    assert_eq!(Mode::from_content_type(None), Mode::Binary);
+}
+
+#[test]
+fn test_binary_headers() {
+    let mut headers = binary_headers(&request_headers(&[
+        ("ce-specversion", "1.0"),
+        ("ce-id", "1"),
+        ("ce-source", "/orders"),
+        ("ce-type", "order.created"),
+        ("ce-subject", "caf%C3%A9%2"),
+        ("content-type", "application/json"),
+        ("x-other", "a"),
+    ]))
+    .unwrap();
+    headers.sort();
+    assert_eq!(
+        headers,
+        vec![
+            header("ce_id", "1"),
+            header("ce_source", "/orders"),
+            header("ce_specversion", "1.0"),
+            header("ce_subject", "café%2"),
+            header("ce_type", "order.created"),
+            header("content-type", "application/json"),
+        ]
+    );
+}
+
+#[test]
+fn test_binary_headers_invalid() {
+    assert_eq!(
+        binary_headers(&request_headers(&[
+            ("ce-specversion", "1.0"),
+            ("ce-id", "1"),
+            ("ce-type", "order.created"),
+        ])),
+        Err(CloudEventsError::MissingAttribute("source"))
+    );
+    assert_eq!(
+        binary_headers(&request_headers(&[
+            ("ce-specversion", "0.3"),
+            ("ce-id", "1"),
+            ("ce-source", "/orders"),
+            ("ce-type", "order.created"),
+        ])),
+        Err(CloudEventsError::UnsupportedSpecVersion("0.3".to_owned()))
+    );
+}
+
+#[test]
+fn test_structured() {
+    let events = structured(
+        br#"{"specversion": "1.0", "id": "1", "source": "/orders", "type": "order.created",
+            "priority": 2, "data": {"order": 3}}"#,
+        Mode::Structured,
+    )
+    .unwrap();
+    assert_eq!(
+        events,
+        vec![Event {
+            headers: vec![
+                header("ce_specversion", "1.0"),
+                header("ce_id", "1"),
+                header("ce_source", "/orders"),
+                header("ce_type", "order.created"),
+                header("ce_priority", "2"),
+            ],
+            data: Bytes::from_static(br#"{"order":3}"#),
+            is_json: true,
+        }]
+    );
+}
+
+#[test]
+fn test_structured_batch() {
+    let events = structured(
+        br#"[
+            {"specversion": "1.0", "id": "1", "source": "/s", "type": "t",
+             "datacontenttype": "application/xml", "data": "<a/>"},
+            {"specversion": "1.0", "id": "2", "source": "/s", "type": "t", "data_base64": "AAE="},
+            {"specversion": "1.0", "id": "3", "source": "/s", "type": "t"}
+        ]"#,
+        Mode::Batch,
+    )
+    .unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].data, Bytes::from_static(b"<a/>"));
+    assert!(!events[0].is_json);
+    assert_eq!(
+        events[0].headers.last(),
+        Some(&header("content-type", "application/xml"))
+    );
+    assert_eq!(events[1].data, Bytes::from_static(&[0, 1]));
+    assert!(!events[1].is_json);
+    assert_eq!(events[2].data, Bytes::new());
+}
+
+#[test]
+fn test_structured_invalid() {
+    assert_eq!(
+        structured(
+            br#"{"specversion": "1.0", "id": "1", "type": "t"}"#,
+            Mode::Structured
+        ),
+        Err(CloudEventsError::MissingAttribute("source"))
+    );
+    assert_eq!(
+        structured(
+            br#"[{"specversion": "1.0", "id": "", "source": "/s", "type": "t"}]"#,
+            Mode::Batch
+        ),
+        Err(CloudEventsError::MissingAttribute("id"))
+    );
+    assert!(matches!(
+        structured(
+            br#"{"specversion": "1.0", "id": "1", "source": "/s", "type": "t", "data": 1, "data_base64": "AA=="}"#,
+            Mode::Structured
+        ),
+        Err(CloudEventsError::InvalidEvent(_))
+    ));
+    assert!(matches!(
+        structured(br#"{"specversion": "1.0"}"#, Mode::Batch),
+        Err(CloudEventsError::InvalidEvent(_))
+    ));
+}
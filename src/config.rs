@@ -16,6 +16,26 @@ pub struct HeaderNames {
     pub http_method: String,
     pub http_header_prefix: String,
     pub ingest_version: String,
+    pub record_count: String,
+    pub geo_country: String,
+    pub geo_city: String,
+    pub geo_latitude: String,
+    pub geo_longitude: String,
+    pub user_agent_browser: String,
+    pub user_agent_browser_version: String,
+    pub user_agent_os: String,
+    pub user_agent_device: String,
+    pub syslog_facility: String,
+    pub syslog_severity: String,
+    pub syslog_timestamp: String,
+    pub syslog_hostname: String,
+    pub syslog_app_name: String,
+    pub syslog_proc_id: String,
+    pub syslog_msg_id: String,
+    pub fluent_tag: String,
+    pub failover: String,
+    pub api_key_id: String,
+    pub jwt_claim_prefix: String,
 }
 
 impl Default for HeaderNames {
@@ -27,30 +47,128 @@ impl Default for HeaderNames {
             http_method: "ncube-ingest-http-method".to_owned(),
             http_header_prefix: "ncube-ingest-http-header-".to_owned(),
             ingest_version: "ncube-ingest-version".to_owned(),
+            record_count: "ncube-ingest-record-count".to_owned(),
+            geo_country: "ncube-ingest-geo-country".to_owned(),
+            geo_city: "ncube-ingest-geo-city".to_owned(),
+            geo_latitude: "ncube-ingest-geo-latitude".to_owned(),
+            geo_longitude: "ncube-ingest-geo-longitude".to_owned(),
+            user_agent_browser: "ncube-ingest-ua-browser".to_owned(),
+            user_agent_browser_version: "ncube-ingest-ua-browser-version".to_owned(),
+            user_agent_os: "ncube-ingest-ua-os".to_owned(),
+            user_agent_device: "ncube-ingest-ua-device".to_owned(),
+            syslog_facility: "ncube-ingest-syslog-facility".to_owned(),
+            syslog_severity: "ncube-ingest-syslog-severity".to_owned(),
+            syslog_timestamp: "ncube-ingest-syslog-timestamp".to_owned(),
+            syslog_hostname: "ncube-ingest-syslog-hostname".to_owned(),
+            syslog_app_name: "ncube-ingest-syslog-app-name".to_owned(),
+            syslog_proc_id: "ncube-ingest-syslog-proc-id".to_owned(),
+            syslog_msg_id: "ncube-ingest-syslog-msg-id".to_owned(),
+            fluent_tag: "ncube-ingest-fluent-tag".to_owned(),
+            failover: "ncube-ingest-failover".to_owned(),
+            api_key_id: "ncube-ingest-api-key-id".to_owned(),
+            jwt_claim_prefix: "ncube-ingest-jwt-claim-".to_owned(),
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl HeaderNames {
+    /// Whether a header name is one of the headers the service adds, which clients cannot set.
+    pub fn is_reserved(&self, name: &str) -> bool {
+        let names = [
+            &self.schema_id,
+            &self.ip,
+            &self.http_url,
+            &self.http_method,
+            &self.ingest_version,
+            &self.record_count,
+            &self.geo_country,
+            &self.geo_city,
+            &self.geo_latitude,
+            &self.geo_longitude,
+            &self.user_agent_browser,
+            &self.user_agent_browser_version,
+            &self.user_agent_os,
+            &self.user_agent_device,
+            &self.syslog_facility,
+            &self.syslog_severity,
+            &self.syslog_timestamp,
+            &self.syslog_hostname,
+            &self.syslog_app_name,
+            &self.syslog_proc_id,
+            &self.syslog_msg_id,
+            &self.fluent_tag,
+            &self.failover,
+            &self.api_key_id,
+        ];
+        let prefixes = [&self.http_header_prefix, &self.jwt_claim_prefix];
+        let name = name.to_ascii_lowercase();
+        names
+            .iter()
+            .any(|reserved| name == reserved.to_ascii_lowercase())
+            || prefixes
+                .iter()
+                .any(|prefix| name.starts_with(&prefix.to_ascii_lowercase()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ContentType {
     #[serde(rename = "application/json")]
     Json,
     #[serde(rename = "application/jsonlines")]
     Jsonlines,
+    #[serde(rename = "application/json-seq")]
+    JsonSeq,
     #[serde(rename = "application/octet-stream")]
     Binary,
 }
 
+impl ContentType {
+    /// Whether messages of this content type are JSON text, and so should be whitespace trimmed.
+    pub fn is_json(&self) -> bool {
+        match self {
+            ContentType::Json | ContentType::Jsonlines | ContentType::JsonSeq => true,
+            ContentType::Binary => false,
+        }
+    }
+
+    /// The framing used when none is configured for this content type.
+    pub fn default_framing(&self) -> Framing {
+        match self {
+            ContentType::Json | ContentType::Binary => Framing::None,
+            ContentType::Jsonlines => Framing::Newline,
+            ContentType::JsonSeq => Framing::JsonSeq,
+        }
+    }
+}
+
 impl fmt::Display for ContentType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ContentType::Json => write!(f, "application/json"),
             ContentType::Jsonlines => write!(f, "application/jsonlines"),
+            ContentType::JsonSeq => write!(f, "application/json-seq"),
             ContentType::Binary => write!(f, "application/octet-stream"),
         }
     }
 }
 
+/// How a request body is split into individual Kafka messages.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Framing {
+    /// The whole body is a single message.
+    None,
+    /// Messages are separated by `\n`.
+    Newline,
+    /// RFC 7464 JSON text sequences, messages are preceded by the `0x1E` record separator.
+    JsonSeq,
+    /// Messages are separated by an arbitrary byte, e.g. `0` for NUL-delimited records.
+    Delimiter { delimiter: u8 },
+    /// Messages are preceded by their length as a 4 byte big-endian unsigned integer.
+    LengthPrefixed,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct SchemaConfig {
     #[serde(default = "default_content_type_from_header")]
@@ -74,6 +192,275 @@ pub struct SchemaConfig {
     pub python_request_processor: Vec<PythonProcessorConfig>,
     #[serde(default = "default_librdkafka_config_name")]
     pub librdkafka_config: String,
+    #[serde(default)]
+    pub framing: HashMap<ContentType, Framing>,
+    #[serde(default = "default_validate_utf8")]
+    pub validate_utf8: bool,
+    #[serde(default)]
+    pub batching: Option<BatchingConfig>,
+    #[serde(default)]
+    pub enrich: Option<EnrichConfig>,
+    #[serde(default)]
+    pub geoip: bool,
+    #[serde(default)]
+    pub user_agent: bool,
+    #[serde(default)]
+    pub redact: Option<RedactConfig>,
+    #[serde(default)]
+    pub filter: Vec<FilterRule>,
+    /// Whether to accept WebSocket connections at `/ingest/{schema_id}/ws`.
+    #[serde(default)]
+    pub websocket: bool,
+    /// Whether requests are CloudEvents, in the binary or structured mode.
+    #[serde(default)]
+    pub cloudevents: bool,
+    /// Whether responses to requests of a single message report where it was delivered.
+    #[serde(default)]
+    pub response_delivery: bool,
+    /// Whether responses to requests of several messages report the offsets of the messages.
+    #[serde(default)]
+    pub response_partition_offsets: bool,
+    /// Whether requests are responded to once their messages are delivered or enqueued.
+    #[serde(default)]
+    pub ack_mode: AckMode,
+    /// Bytes of request bodies of the schema in flight, on top of the `memory_budget`.
+    #[serde(default)]
+    pub max_in_flight_bytes: Option<u64>,
+    /// Whether requests need an API key of the service `api_keys` that allows the schema.
+    #[serde(default)]
+    pub api_key_auth: bool,
+    /// Verification of the HMAC signature of the requests.
+    #[serde(default)]
+    pub signature: Option<SignatureConfig>,
+    /// Validation of the JWT bearer tokens of the requests.
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+}
+
+impl SchemaConfig {
+    /// The framing configured for the content type, or the content type's default framing.
+    pub fn framing(&self, content_type: &ContentType) -> Framing {
+        self.framing
+            .get(content_type)
+            .cloned()
+            .unwrap_or_else(|| content_type.default_framing())
+    }
+}
+
+/// Packing of consecutive framed JSON messages into a single Kafka message.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchingConfig {
+    #[serde(default)]
+    pub format: BatchFormat,
+    /// Max size of a batch, capped at `max_event_size_bytes`.
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// Max number of records in a batch.
+    #[serde(default)]
+    pub max_count: Option<u64>,
+}
+
+/// When HTTP requests are responded to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AckMode {
+    /// Once the messages are delivered.
+    #[default]
+    Sync,
+    /// With 202 once the messages are enqueued in the producer.
+    Accepted,
+    /// With 202 and a receipt id once the messages are enqueued in the producer. The delivery
+    /// status is then available at `/ingest/receipts/{receipt_id}`.
+    Receipt,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchFormat {
+    /// Records joined with `\n`.
+    #[default]
+    Newline,
+    /// Records as the elements of a JSON array.
+    JsonArray,
+}
+
+/// Injection of the metadata forwarded as Kafka headers into JSON object messages.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnrichConfig {
+    /// Header names of the forwarded metadata to add, like `ncube-ingest-ip`.
+    pub fields: Vec1<String>,
+    /// Put the metadata fields in an object under this key instead of the top level.
+    #[serde(default)]
+    pub envelope_key: Option<String>,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+}
+
+/// What to do when a metadata field already exists in the message.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    Overwrite,
+    Keep,
+    /// Reject the message with a 400 response.
+    Reject,
+}
+
+/// Validation of JWT bearer tokens with the keys of a JWKS file or of a PEM public key.
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtConfig {
+    /// The accepted algorithms, like `RS256`, `ES256`, `EdDSA` or `HS256`.
+    pub algorithms: Vec1<jsonwebtoken::Algorithm>,
+    /// JWKS file of the keys, reloaded when it changes.
+    #[serde(default)]
+    pub jwks_file: Option<String>,
+    /// PEM public key of the RS, PS, ES or EdDSA algorithms, instead of a JWKS file.
+    #[serde(default)]
+    pub pem: Option<String>,
+    #[serde(default)]
+    pub issuer: Option<String>,
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Claims tokens need to have, besides `exp`.
+    #[serde(default)]
+    pub required_claims: Vec<String>,
+    /// How far off `exp` and `nbf` can be, for clock skew.
+    #[serde(default = "default_leeway_seconds")]
+    pub leeway_seconds: u64,
+    /// Claims forwarded as Kafka headers.
+    #[serde(default)]
+    pub forward_claims: Vec<String>,
+    /// Claim with the schema id patterns tokens may send to, as an array or space separated.
+    #[serde(default)]
+    pub schemas_claim: Option<String>,
+}
+
+/// Verification of the HMAC signature of webhook requests, by the preset of a provider or
+/// configured. The options override the ones of the preset.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SignatureConfig {
+    pub provider: SignatureProvider,
+    /// File with the secret the signatures are computed with.
+    pub secret_file: String,
+    /// Header with the signature.
+    #[serde(default)]
+    pub header: Option<String>,
+    #[serde(default)]
+    pub algorithm: Option<HmacAlgorithm>,
+    #[serde(default)]
+    pub encoding: Option<SignatureEncoding>,
+    /// Prefix of the signature in its header, like `sha256=`.
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// What is signed, where `{timestamp}` is the signed timestamp and `{body}` the request body.
+    #[serde(default)]
+    pub signed_content: Option<String>,
+    /// Header with the signed timestamp, in seconds since the epoch.
+    #[serde(default)]
+    pub timestamp_header: Option<String>,
+    /// How far from now signed timestamps can be, so that requests can't be replayed later.
+    #[serde(default = "default_timestamp_tolerance_seconds")]
+    pub timestamp_tolerance_seconds: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureProvider {
+    /// `X-Hub-Signature-256`
+    Github,
+    /// `Stripe-Signature`, with the signed timestamp in the header
+    Stripe,
+    /// `X-Slack-Signature` v0, with the signed timestamp in `X-Slack-Request-Timestamp`
+    Slack,
+    /// `X-Shopify-Hmac-Sha256`
+    Shopify,
+    /// No preset, `header` is required.
+    Hmac,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HmacAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+/// Redaction of fields of JSON messages before they are forwarded.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RedactConfig {
+    pub rules: Vec<RedactRule>,
+    /// File with the key used by the `hash` action.
+    #[serde(default)]
+    pub hmac_key_file: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RedactRule {
+    /// A JSON pointer like `/user/email`, or a dot separated path where `*` matches any key and
+    /// `**` any number of nested keys, like `**.email`.
+    pub path: String,
+    pub action: RedactAction,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactAction {
+    Drop,
+    Mask,
+    /// Replace with the hex encoded HMAC-SHA256 of the value.
+    Hash,
+}
+
+/// A rule deciding whether a message is dropped. A message matches a rule when it matches all of
+/// its conditions, and the first rule that matches applies.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FilterRule {
+    #[serde(default)]
+    pub header: Option<HeaderCondition>,
+    #[serde(default)]
+    pub field: Option<FieldCondition>,
+    #[serde(default)]
+    pub min_size_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_size_bytes: Option<usize>,
+    pub action: FilterAction,
+}
+
+/// Matches when the request header is present, with one of `values` when set.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HeaderCondition {
+    pub name: String,
+    #[serde(default)]
+    pub values: Option<Vec<String>>,
+}
+
+/// Matches when the field of a JSON message at the JSON pointer `path` is present, with one of
+/// `values` when set.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FieldCondition {
+    pub path: String,
+    #[serde(default)]
+    pub values: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterAction {
+    Drop,
+    /// Keeps the fraction `rate` of the messages, chosen by hashing the field at the JSON pointer
+    /// `key_field` so that all messages with the same key are either kept or dropped.
+    Sample {
+        rate: f64,
+        key_field: String,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -103,6 +490,23 @@ pub struct PartialSchemaConfig {
     pub destination_topic: Option<String>,
     pub python_request_processor: Vec<PythonProcessorConfig>,
     pub librdkafka_config: Option<String>,
+    pub framing: HashMap<ContentType, Framing>,
+    pub validate_utf8: Option<bool>,
+    pub batching: Option<BatchingConfig>,
+    pub enrich: Option<EnrichConfig>,
+    pub geoip: Option<bool>,
+    pub user_agent: Option<bool>,
+    pub redact: Option<RedactConfig>,
+    pub filter: Option<Vec<FilterRule>>,
+    pub websocket: Option<bool>,
+    pub cloudevents: Option<bool>,
+    pub response_delivery: Option<bool>,
+    pub response_partition_offsets: Option<bool>,
+    pub ack_mode: Option<AckMode>,
+    pub max_in_flight_bytes: Option<u64>,
+    pub api_key_auth: Option<bool>,
+    pub signature: Option<SignatureConfig>,
+    pub jwt: Option<JwtConfig>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -115,8 +519,13 @@ pub struct PartialSchemaConfigWithSchemaId {
 #[derive(Clone, Debug, Deserialize)]
 pub struct ServiceConfig {
     pub address: SocketAddr,
+    /// Address of the gRPC server, which is only started when set.
+    #[serde(default)]
+    pub grpc_address: Option<SocketAddr>,
     #[serde(default = "default_keepalive_seconds")]
     pub keepalive_seconds: u64,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
     #[serde(default = "default_max_event_size_bytes")]
     pub max_event_size_bytes: u64,
     #[serde(default = "default_num_workers")]
@@ -126,6 +535,306 @@ pub struct ServiceConfig {
     pub default_schema_config: SchemaConfig,
     #[serde(default)]
     pub schema_config: Vec<PartialSchemaConfigWithSchemaId>,
+    /// MaxMind DB format file used by schemas with `geoip` enabled.
+    #[serde(default)]
+    pub geoip_database: Option<String>,
+    /// ua-parser regexes file used by schemas with `user_agent` enabled.
+    #[serde(default)]
+    pub user_agent_database: Option<String>,
+    #[serde(default = "default_database_reload_check_seconds")]
+    pub database_reload_check_seconds: u64,
+    /// Receipts of the `receipt` ack mode kept in memory, the oldest are dropped beyond it.
+    #[serde(default = "default_max_receipts")]
+    pub max_receipts: usize,
+    #[serde(default)]
+    pub memory_budget: MemoryBudgetConfig,
+    #[serde(default)]
+    pub tcp_listener: Vec<TcpListenerConfig>,
+    #[serde(default)]
+    pub udp_listener: Vec<UdpListenerConfig>,
+    #[serde(default)]
+    pub syslog_listener: Vec<SyslogListenerConfig>,
+    #[serde(default)]
+    pub forward_listener: Vec<ForwardListenerConfig>,
+    /// The OTLP/HTTP receiver, served by the HTTP server.
+    #[serde(default)]
+    pub otlp: Option<OtlpConfig>,
+    /// The Elasticsearch bulk API, served by the HTTP server.
+    #[serde(default)]
+    pub elasticsearch: Option<ElasticsearchConfig>,
+    /// The Splunk HTTP Event Collector API, served by the HTTP server.
+    #[serde(default)]
+    pub splunk_hec: Option<SplunkHecConfig>,
+    /// The Kafka REST Proxy produce API, served by the HTTP server.
+    #[serde(default)]
+    pub rest_proxy: Option<RestProxyConfig>,
+    /// The API keys of the schemas with `api_key_auth`.
+    #[serde(default)]
+    pub api_keys: Option<ApiKeysConfig>,
+}
+
+/// Where API keys are loaded from, and where requests can have them, checked in this order.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiKeysConfig {
+    /// JSON file, or directory of JSON files, of the keys with hashed secrets. Reloaded when it
+    /// changes.
+    pub path: String,
+    /// Header with the key.
+    #[serde(default = "default_api_key_header")]
+    pub header: String,
+    /// Whether the key can be a bearer token of the `Authorization` header.
+    #[serde(default = "default_api_key_bearer")]
+    pub bearer: bool,
+    /// Query parameter with the key, not accepted when unset.
+    #[serde(default)]
+    pub query_parameter: Option<String>,
+}
+
+/// A TCP listener for newline delimited records, all sent to the same schema.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TcpListenerConfig {
+    pub address: SocketAddr,
+    pub schema_id: String,
+    /// Connections without data for this long are closed.
+    #[serde(default = "default_idle_timeout_seconds")]
+    pub idle_timeout_seconds: u64,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// A UDP listener for datagrams of one or more newline delimited records, all sent to the same
+/// schema.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UdpListenerConfig {
+    pub address: SocketAddr,
+    pub schema_id: String,
+}
+
+/// A syslog listener, sending each message to the schema of the first route it matches.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SyslogListenerConfig {
+    pub address: SocketAddr,
+    pub protocol: SyslogProtocol,
+    /// The schema of the messages that match no route.
+    pub schema_id: String,
+    #[serde(default)]
+    pub format: SyslogFormat,
+    #[serde(default)]
+    pub route: Vec<SyslogRoute>,
+    /// TCP connections without data for this long are closed.
+    #[serde(default = "default_idle_timeout_seconds")]
+    pub idle_timeout_seconds: u64,
+    /// Only for TCP listeners.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogProtocol {
+    Tcp,
+    Udp,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFormat {
+    /// The parsed message as a JSON object.
+    #[default]
+    Json,
+    /// The message as received, with the parsed header fields as Kafka headers.
+    Raw,
+}
+
+/// Messages match a route when their facility and app name are among the given ones, each
+/// condition is ignored when not set.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SyslogRoute {
+    #[serde(default)]
+    pub facility: Option<Vec<String>>,
+    #[serde(default)]
+    pub app_name: Option<Vec<String>>,
+    pub schema_id: String,
+}
+
+/// A Fluent Forward listener, sending records to the schema of the first route matching their tag.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ForwardListenerConfig {
+    pub address: SocketAddr,
+    /// The schema of the records whose tag matches no route. The tag is the schema id when not set.
+    #[serde(default)]
+    pub schema_id: Option<String>,
+    #[serde(default)]
+    pub route: Vec<ForwardRoute>,
+    /// The record field to set to the event time, unless the record has it.
+    #[serde(default)]
+    pub time_key: Option<String>,
+    /// The maximum size of a forward message, and of its decompressed entries.
+    #[serde(default = "default_max_chunk_size_bytes")]
+    pub max_chunk_size_bytes: u64,
+    /// Connections without data for this long are closed.
+    #[serde(default = "default_idle_timeout_seconds")]
+    pub idle_timeout_seconds: u64,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// Routes the records with a tag matching the fluentd style pattern `tag` to `schema_id`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ForwardRoute {
+    pub tag: String,
+    pub schema_id: String,
+}
+
+/// Each signal is received at its `/v1/{signal}` endpoint when configured.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OtlpConfig {
+    #[serde(default)]
+    pub logs: Option<OtlpSignalConfig>,
+    #[serde(default)]
+    pub traces: Option<OtlpSignalConfig>,
+    #[serde(default)]
+    pub metrics: Option<OtlpSignalConfig>,
+    /// The maximum size of an export request, after decompression.
+    #[serde(default = "default_max_request_size_bytes")]
+    pub max_request_size_bytes: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OtlpSignalConfig {
+    /// The schema whose config the messages are sent with.
+    pub schema_id: String,
+    #[serde(default)]
+    pub format: OtlpFormat,
+}
+
+/// The format of the Kafka messages of a signal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpFormat {
+    /// An export request in protobuf per resource.
+    #[default]
+    Protobuf,
+    /// A JSON object per log record, span or metric, with its resource and scope.
+    Json,
+}
+
+/// Sends each document of bulk requests to the schema of the first route matching its index.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ElasticsearchConfig {
+    /// The schema of the documents whose index matches no route. Those documents fail when not
+    /// set.
+    #[serde(default)]
+    pub schema_id: Option<String>,
+    #[serde(default)]
+    pub route: Vec<IndexRoute>,
+    /// The Elasticsearch version reported to clients.
+    #[serde(default = "default_elasticsearch_version")]
+    pub version: String,
+    #[serde(default = "default_max_request_size_bytes")]
+    pub max_request_size_bytes: u64,
+}
+
+/// Routes the documents of the indices matching `index`, where `*` matches any characters, to
+/// `schema_id`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct IndexRoute {
+    pub index: String,
+    pub schema_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SplunkHecConfig {
+    pub token: Vec<HecToken>,
+    #[serde(default = "default_max_request_size_bytes")]
+    pub max_request_size_bytes: u64,
+}
+
+/// The events sent with `token` are sent to `schema_id`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HecToken {
+    pub token: String,
+    pub schema_id: String,
+}
+
+/// Produces the records of clients to the allowed topics as is, without any schema config.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RestProxyConfig {
+    /// The id of the cluster in v3 API paths.
+    #[serde(default = "default_rest_proxy_cluster_id")]
+    pub cluster_id: String,
+    pub topic: Vec<RestProxyTopic>,
+    #[serde(default = "default_max_request_size_bytes")]
+    pub max_request_size_bytes: u64,
+}
+
+/// A topic records can be produced to, with the producer of the named librdkafka config.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RestProxyTopic {
+    pub name: String,
+    #[serde(default = "default_librdkafka_config_name")]
+    pub librdkafka_config: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain.
+    pub certificate_file: String,
+    /// PEM file with the private key.
+    pub private_key_file: String,
+    /// Connections that have not completed the TLS handshake after this long are closed.
+    #[serde(default = "default_handshake_timeout_seconds")]
+    pub handshake_timeout_seconds: u64,
+}
+
+/// The phases of a graceful shutdown.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShutdownConfig {
+    /// How long the readiness check fails before new requests are rejected, for load balancers
+    /// to stop sending requests.
+    #[serde(default)]
+    pub drain_delay_seconds: u64,
+    /// How long the requests and connections in flight have to finish once new requests are
+    /// rejected.
+    #[serde(default = "default_grace_period_seconds")]
+    pub grace_period_seconds: u64,
+    /// How long the producers have to deliver the messages in flight.
+    #[serde(default = "default_flush_timeout_seconds")]
+    pub flush_timeout_seconds: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> ShutdownConfig {
+        ShutdownConfig {
+            drain_delay_seconds: 0,
+            grace_period_seconds: default_grace_period_seconds(),
+            flush_timeout_seconds: default_flush_timeout_seconds(),
+        }
+    }
+}
+
+/// Admission of the HTTP ingest requests by the size of their bodies.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MemoryBudgetConfig {
+    /// Bytes of request bodies of all the schemas in flight, unlimited when unset.
+    #[serde(default)]
+    pub max_in_flight_bytes: Option<u64>,
+    /// How long requests wait for room in the budgets before they are rejected.
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    /// `Retry-After` of the rejected requests.
+    #[serde(default = "default_retry_after_seconds")]
+    pub retry_after_seconds: u64,
+}
+
+impl Default for MemoryBudgetConfig {
+    fn default() -> MemoryBudgetConfig {
+        MemoryBudgetConfig {
+            max_in_flight_bytes: None,
+            queue_timeout_ms: default_queue_timeout_ms(),
+            retry_after_seconds: default_retry_after_seconds(),
+        }
+    }
 }
 
 #[derive(Clone, Default, Debug, Deserialize)]
@@ -137,6 +846,22 @@ pub struct LibrdkafkaConfig {
     pub config: HashMap<String, String>,
     #[serde(default)]
     pub config_from_file: HashMap<String, String>,
+    /// Fails sends fast while the cluster is unhealthy.
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Name of the librdkafka config to produce with while the circuit of this one is open.
+    #[serde(default)]
+    pub failover: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive delivery errors that open the circuit.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a trial message is let through.
+    #[serde(default = "default_open_seconds")]
+    pub open_seconds: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -161,6 +886,30 @@ impl CommonConfig for Config {
     const DEFAULT_CONFIG_PATH: &'static str = "/etc/ncube-ingest/ingest.toml";
 }
 
+const fn default_idle_timeout_seconds() -> u64 {
+    300
+}
+
+const fn default_handshake_timeout_seconds() -> u64 {
+    10
+}
+
+const fn default_max_chunk_size_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+const fn default_max_request_size_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_elasticsearch_version() -> String {
+    "8.11.0".to_owned()
+}
+
+fn default_rest_proxy_cluster_id() -> String {
+    crate::PKG_NAME.to_owned()
+}
+
 const fn default_keepalive_seconds() -> u64 {
     300
 }
@@ -169,6 +918,31 @@ const fn default_keepalive_seconds() -> u64 {
 const fn default_max_event_size_bytes() -> u64 {
     1 * 1024 * 1024 // 1Mb, kafka default and events hubs limit
 }
+const fn default_database_reload_check_seconds() -> u64 {
+    60
+}
+
+const fn default_max_receipts() -> usize {
+    100_000
+}
+const fn default_leeway_seconds() -> u64 {
+    60
+}
+const fn default_timestamp_tolerance_seconds() -> u64 {
+    300
+}
+fn default_api_key_header() -> String {
+    "x-api-key".to_owned()
+}
+const fn default_api_key_bearer() -> bool {
+    true
+}
+const fn default_queue_timeout_ms() -> u64 {
+    100
+}
+const fn default_retry_after_seconds() -> u64 {
+    1
+}
 fn default_num_workers() -> usize {
     num_cpus::get_physical()
 }
@@ -196,9 +970,28 @@ const fn default_response_status() -> u16 {
 const fn default_forward_ingest_version() -> bool {
     true
 }
+const fn default_validate_utf8() -> bool {
+    true
+}
 fn default_allowed_methods() -> Vec1<String> {
     vec1!["POST".to_owned()]
 }
+const fn default_grace_period_seconds() -> u64 {
+    30
+}
+
+const fn default_flush_timeout_seconds() -> u64 {
+    30
+}
+
+const fn default_failure_threshold() -> u32 {
+    5
+}
+
+const fn default_open_seconds() -> u64 {
+    30
+}
+
 fn default_librdkafka_config_name() -> String {
     "main".to_owned()
 }
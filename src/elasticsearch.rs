@@ -0,0 +1,212 @@
+//! Parsing of Elasticsearch bulk API requests, and the responses expected by their clients.
+
+use std::fmt;
+
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Index,
+    Create,
+    Update,
+    Delete,
+}
+
+impl Action {
+    fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "index" => Some(Action::Index),
+            "create" => Some(Action::Create),
+            "update" => Some(Action::Update),
+            "delete" => Some(Action::Delete),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Index => "index",
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BulkItem {
+    pub action: Action,
+    pub index: String,
+    pub id: Option<String>,
+    /// The line following the action, for all actions but delete.
+    pub source: Option<Bytes>,
+}
+
+/// Errors failing the whole request, with the number of the line they are found at.
+#[derive(Debug, PartialEq)]
+pub enum BulkError {
+    MalformedAction(usize),
+    UnknownAction(usize, String),
+    MissingIndex(usize),
+    MissingSource(usize),
+}
+
+impl fmt::Display for BulkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BulkError::*;
+
+        match self {
+            MalformedAction(line) => write!(
+                f,
+                "Malformed action/metadata line [{}], expected a JSON object with a single action",
+                line
+            ),
+            UnknownAction(line, name) => write!(
+                f,
+                "Malformed action/metadata line [{}], expected field [create], [delete], [index] \
+                or [update] but found [{}]",
+                line, name
+            ),
+            MissingIndex(line) => {
+                write!(f, "Validation Failed: index is missing at line [{}]", line)
+            }
+            MissingSource(line) => {
+                write!(f, "The source of the action at line [{}] is missing", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BulkError {}
+
+/// Parses the newline delimited action and source lines of a bulk request, with the index of the
+/// request path, if any, as the default index of the actions.
+pub fn parse(body: &Bytes, default_index: Option<&str>) -> Result<Vec<BulkItem>, BulkError> {
+    let mut lines = lines(body);
+    let mut items = Vec::new();
+    while let Some((number, line)) = lines.next() {
+        let action_line: Map<String, Value> =
+            serde_json::from_slice(&line).map_err(|_| BulkError::MalformedAction(number))?;
+        if action_line.len() != 1 {
+            return Err(BulkError::MalformedAction(number));
+        }
+        let (name, metadata) = action_line
+            .into_iter()
+            .next()
+            .expect("The line has one field");
+        let Some(action) = Action::from_name(&name) else {
+            return Err(BulkError::UnknownAction(number, name));
+        };
+        let Value::Object(metadata) = metadata else {
+            return Err(BulkError::MalformedAction(number));
+        };
+        let field = |name: &str| metadata.get(name).and_then(Value::as_str);
+        let index = field("_index")
+            .or(default_index)
+            .ok_or(BulkError::MissingIndex(number))?
+            .to_owned();
+        let id = field("_id").map(str::to_owned);
+        let source = match action {
+            Action::Delete => None,
+            _ => match lines.next() {
+                Some((_, source)) => Some(source),
+                None => return Err(BulkError::MissingSource(number)),
+            },
+        };
+        items.push(BulkItem {
+            action,
+            index,
+            id,
+            source,
+        });
+    }
+    Ok(items)
+}
+
+/// The non-blank lines of a body, with their line number.
+fn lines(body: &Bytes) -> impl Iterator<Item = (usize, Bytes)> {
+    let mut start = 0;
+    let mut number = 0;
+    std::iter::from_fn(move || {
+        while start < body.len() {
+            let end = body[start..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map_or(body.len(), |position| start + position);
+            let line = body.slice(start..end);
+            start = end + 1;
+            number += 1;
+            if !line.trim_ascii().is_empty() {
+                return Some((number, line));
+            }
+        }
+        None
+    })
+}
+
+/// Why an item failed, in the format of Elasticsearch errors.
+#[derive(Debug, PartialEq)]
+pub struct ItemError {
+    pub status: u16,
+    pub error_type: &'static str,
+    pub reason: String,
+}
+
+/// The result of an item in a bulk response.
+pub fn item_response(item: &BulkItem, result: Result<(), ItemError>) -> Value {
+    let mut response = Map::new();
+    response.insert("_index".to_owned(), json!(item.index));
+    if let Some(id) = &item.id {
+        response.insert("_id".to_owned(), json!(id));
+    }
+    match result {
+        Ok(()) => {
+            response.insert("_version".to_owned(), json!(1));
+            response.insert("result".to_owned(), json!("created"));
+            response.insert(
+                "_shards".to_owned(),
+                json!({"total": 1, "successful": 1, "failed": 0}),
+            );
+            response.insert("_seq_no".to_owned(), json!(0));
+            response.insert("_primary_term".to_owned(), json!(1));
+            response.insert("status".to_owned(), json!(201));
+        }
+        Err(e) => {
+            response.insert("status".to_owned(), json!(e.status));
+            response.insert(
+                "error".to_owned(),
+                json!({"type": e.error_type, "reason": e.reason}),
+            );
+        }
+    }
+    json!({ item.action.name(): response })
+}
+
+/// The body of a failed request.
+pub fn error_response(status: u16, error_type: &str, reason: &str) -> Value {
+    json!({
+        "error": {
+            "root_cause": [{"type": error_type, "reason": reason}],
+            "type": error_type,
+            "reason": reason,
+        },
+        "status": status,
+    })
+}
+
+/// The cluster information that clients check the version of.
+pub fn info(version: &str) -> Value {
+    json!({
+        "name": crate::PKG_NAME,
+        "cluster_name": crate::PKG_NAME,
+        "version": {
+            "number": version,
+            "build_flavor": "default",
+        },
+        "tagline": "You Know, for Search",
+    })
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,99 @@
+use bytes::Bytes;
+use serde_json::json;
+
+use super::{Action, BulkError, BulkItem, ItemError, item_response, parse};
+
+#[test]
+fn test_parse() {
+    let body = Bytes::from_static(
+        b"{\"index\":{\"_index\":\"logs\",\"_id\":\"1\"}}\n{\"a\":1}\n\
+        \n\
+        {\"create\":{}}\r\n{\"b\":2}\r\n\
+        {\"delete\":{\"_index\":\"logs\",\"_id\":\"2\"}}\n\
+        {\"update\":{\"_id\":\"3\"}}\n{\"doc\":{\"c\":3}}",
+    );
+    let items = parse(&body, Some("default")).unwrap();
+    assert_eq!(
+        items,
+        vec![
+            BulkItem {
+                action: Action::Index,
+                index: "logs".to_owned(),
+                id: Some("1".to_owned()),
+                source: Some(Bytes::from_static(b"{\"a\":1}")),
+            },
+            BulkItem {
+                action: Action::Create,
+                index: "default".to_owned(),
+                id: None,
+                source: Some(Bytes::from_static(b"{\"b\":2}\r")),
+            },
+            BulkItem {
+                action: Action::Delete,
+                index: "logs".to_owned(),
+                id: Some("2".to_owned()),
+                source: None,
+            },
+            BulkItem {
+                action: Action::Update,
+                index: "default".to_owned(),
+                id: Some("3".to_owned()),
+                source: Some(Bytes::from_static(b"{\"doc\":{\"c\":3}}")),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_invalid() {
+    let parse = |body: &'static [u8]| parse(&Bytes::from_static(body), None);
+    assert_eq!(
+        parse(b"{\"a\":1}\n{\"index\":{}}\n"),
+        Err(BulkError::UnknownAction(1, "a".to_owned()))
+    );
+    assert_eq!(
+        parse(b"{\"index\":{},\"create\":{}}\n"),
+        Err(BulkError::MalformedAction(1))
+    );
+    assert_eq!(
+        parse(b"{\"index\":\"logs\"}\n"),
+        Err(BulkError::MalformedAction(1))
+    );
+    assert_eq!(
+        parse(b"\n{\"index\":{}}\n{}\n"),
+        Err(BulkError::MissingIndex(2))
+    );
+    assert_eq!(
+        parse(b"{\"delete\":{\"_index\":\"a\"}}\n{\"index\":{\"_index\":\"a\"}}\n"),
+        Err(BulkError::MissingSource(2))
+    );
+}
+
+#[test]
+fn test_item_response() {
+    let item = BulkItem {
+        action: Action::Create,
+        index: "logs".to_owned(),
+        id: Some("1".to_owned()),
+        source: None,
+    };
+    assert_eq!(item_response(&item, Ok(()))["create"]["status"], 201);
+    assert_eq!(
+        item_response(
+            &item,
+            Err(ItemError {
+                status: 400,
+                error_type: "illegal_argument_exception",
+                reason: "invalid".to_owned(),
+            })
+        ),
+        json!({
+            "create": {
+                "_index": "logs",
+                "_id": "1",
+                "status": 400,
+                "error": {"type": "illegal_argument_exception", "reason": "invalid"}
+            }
+        })
+    );
+}
@@ -0,0 +1,117 @@
+//! Injection of request metadata into JSON messages.
+
+use std::fmt;
+
+use bytes::Bytes;
+use serde_json::{Map, Value};
+
+use crate::config::{ConflictPolicy, EnrichConfig};
+
+pub mod geoip;
+pub mod user_agent;
+
+#[derive(Debug)]
+pub enum EnrichError {
+    /// The message is not a JSON object, so there is nowhere to put the metadata.
+    NotAnObject,
+    /// A metadata field already exists in the message and the conflict policy is `reject`.
+    Conflict(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for EnrichError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use EnrichError::*;
+
+        match self {
+            NotAnObject => write!(f, "Message is not a JSON object"),
+            Conflict(field) => write!(f, "Message already contains field '{}'", field),
+            Json(e) => write!(f, "Invalid JSON message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EnrichError {}
+
+impl From<serde_json::Error> for EnrichError {
+    fn from(e: serde_json::Error) -> EnrichError {
+        EnrichError::Json(e)
+    }
+}
+
+/// Adds the configured fields of the metadata to the JSON object message as string fields, at the
+/// top level or under the configured envelope key.
+pub fn enrich(
+    message: &[u8],
+    metadata: &[(String, Bytes)],
+    config: &EnrichConfig,
+) -> Result<Bytes, EnrichError> {
+    let mut object = match serde_json::from_slice(message)? {
+        Value::Object(object) => object,
+        _ => return Err(EnrichError::NotAnObject),
+    };
+
+    let fields = metadata
+        .iter()
+        .filter(|(name, _)| config.fields.contains(name))
+        .map(|(name, value)| {
+            (
+                name.clone(),
+                Value::String(String::from_utf8_lossy(value).into_owned()),
+            )
+        });
+
+    if let Some(envelope_key) = &config.envelope_key {
+        match object.get_mut(envelope_key) {
+            Some(Value::Object(envelope)) => insert_fields(envelope, fields, &config.on_conflict)?,
+            Some(_) => {
+                if let Some(envelope) = resolve_conflict(envelope_key, &config.on_conflict)? {
+                    let mut envelope_object = Map::new();
+                    insert_fields(&mut envelope_object, fields, &config.on_conflict)?;
+                    object.insert(envelope, Value::Object(envelope_object));
+                }
+            }
+            None => {
+                let mut envelope_object = Map::new();
+                insert_fields(&mut envelope_object, fields, &config.on_conflict)?;
+                object.insert(envelope_key.clone(), Value::Object(envelope_object));
+            }
+        }
+    } else {
+        insert_fields(&mut object, fields, &config.on_conflict)?;
+    }
+
+    Ok(Bytes::from(serde_json::to_vec(&Value::Object(object))?))
+}
+
+fn insert_fields(
+    object: &mut Map<String, Value>,
+    fields: impl Iterator<Item = (String, Value)>,
+    on_conflict: &ConflictPolicy,
+) -> Result<(), EnrichError> {
+    for (name, value) in fields {
+        if object.contains_key(&name) {
+            if let Some(name) = resolve_conflict(&name, on_conflict)? {
+                object.insert(name, value);
+            }
+        } else {
+            object.insert(name, value);
+        }
+    }
+    Ok(())
+}
+
+// returns the field name if it should be overwritten
+fn resolve_conflict(
+    name: &str,
+    on_conflict: &ConflictPolicy,
+) -> Result<Option<String>, EnrichError> {
+    match on_conflict {
+        ConflictPolicy::Overwrite => Ok(Some(name.to_owned())),
+        ConflictPolicy::Keep => Ok(None),
+        ConflictPolicy::Reject => Err(EnrichError::Conflict(name.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,70 @@
+//! Geolocation of client IPs from a MaxMind DB format file.
+
+use std::net::IpAddr;
+use std::path::Path;
+
+use bytes::Bytes;
+use common::config::ConfigError;
+use maxminddb::{Reader, geoip2};
+use tracing::debug;
+
+use crate::config::HeaderNames;
+use crate::error::{Error, Result};
+
+pub struct GeoIp(Reader<Vec<u8>>);
+
+impl GeoIp {
+    pub fn open(path: &Path) -> Result<GeoIp> {
+        Reader::open_readfile(path).map(GeoIp).map_err(|e| {
+            Error::from(ConfigError::Invalid(format!(
+                "Could not open GeoIP database '{}': {}",
+                path.display(),
+                e
+            )))
+        })
+    }
+
+    /// Kafka headers with the country ISO code, English city name and coordinates of the IP,
+    /// for the values that are known.
+    pub fn headers(&self, ip: &str, header_names: &HeaderNames) -> Vec<(String, Bytes)> {
+        let mut headers = Vec::new();
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            return headers;
+        };
+        let city: geoip2::City = match self.0.lookup(ip) {
+            Ok(Some(city)) => city,
+            Ok(None) => return headers,
+            Err(e) => {
+                debug!(%ip, "GeoIP lookup failed: {}", e);
+                return headers;
+            }
+        };
+
+        if let Some(iso_code) = city.country.and_then(|c| c.iso_code) {
+            headers.push((
+                header_names.geo_country.clone(),
+                Bytes::from(iso_code.to_owned()),
+            ));
+        }
+        if let Some(name) = city
+            .city
+            .and_then(|c| c.names)
+            .and_then(|names| names.get("en").copied())
+        {
+            headers.push((header_names.geo_city.clone(), Bytes::from(name.to_owned())));
+        }
+        if let Some(location) = city.location
+            && let (Some(latitude), Some(longitude)) = (location.latitude, location.longitude)
+        {
+            headers.push((
+                header_names.geo_latitude.clone(),
+                Bytes::from(latitude.to_string()),
+            ));
+            headers.push((
+                header_names.geo_longitude.clone(),
+                Bytes::from(longitude.to_string()),
+            ));
+        }
+        headers
+    }
+}
@@ -0,0 +1,114 @@
+use bytes::Bytes;
+use vec1::vec1;
+
+use crate::config::{ConflictPolicy, EnrichConfig};
+
+use super::{EnrichError, enrich};
+
+fn metadata() -> Vec<(String, Bytes)> {
+    vec![
+        ("schema".to_owned(), Bytes::from_static(b"1")),
+        ("ip".to_owned(), Bytes::from_static(b"127.0.0.1")),
+    ]
+}
+
+fn config(envelope_key: Option<&str>, on_conflict: ConflictPolicy) -> EnrichConfig {
+    EnrichConfig {
+        fields: vec1!["schema".to_owned(), "ip".to_owned()],
+        envelope_key: envelope_key.map(|k| k.to_owned()),
+        on_conflict,
+    }
+}
+
+#[test]
+fn test_enrich_top_level() {
+    let enriched = enrich(
+        br#"{"a":1}"#,
+        &metadata(),
+        &config(None, ConflictPolicy::Overwrite),
+    )
+    .unwrap();
+    assert_eq!(enriched, r#"{"a":1,"schema":"1","ip":"127.0.0.1"}"#);
+}
+
+#[test]
+fn test_enrich_configured_fields() {
+    let mut metadata = metadata();
+    metadata.push((
+        "ncube-ingest-http-header-authorization".to_owned(),
+        Bytes::from_static(b"secret"),
+    ));
+    let enriched = enrich(
+        br#"{"a":1}"#,
+        &metadata,
+        &EnrichConfig {
+            fields: vec1!["ip".to_owned()],
+            ..config(None, ConflictPolicy::Overwrite)
+        },
+    )
+    .unwrap();
+    assert_eq!(enriched, r#"{"a":1,"ip":"127.0.0.1"}"#);
+}
+
+#[test]
+fn test_enrich_envelope() {
+    let enriched = enrich(
+        br#"{"a":1}"#,
+        &metadata(),
+        &config(Some("_meta"), ConflictPolicy::Overwrite),
+    )
+    .unwrap();
+    assert_eq!(
+        enriched,
+        r#"{"a":1,"_meta":{"schema":"1","ip":"127.0.0.1"}}"#
+    );
+
+    let enriched = enrich(
+        br#"{"_meta":{"ip":"1.1.1.1","x":true}}"#,
+        &metadata(),
+        &config(Some("_meta"), ConflictPolicy::Keep),
+    )
+    .unwrap();
+    assert_eq!(
+        enriched,
+        r#"{"_meta":{"ip":"1.1.1.1","x":true,"schema":"1"}}"#
+    );
+}
+
+#[test]
+fn test_enrich_conflicts() {
+    let message = br#"{"ip":"1.1.1.1"}"#;
+    let enriched = enrich(
+        message,
+        &metadata(),
+        &config(None, ConflictPolicy::Overwrite),
+    )
+    .unwrap();
+    assert_eq!(enriched, r#"{"ip":"127.0.0.1","schema":"1"}"#);
+
+    let enriched = enrich(message, &metadata(), &config(None, ConflictPolicy::Keep)).unwrap();
+    assert_eq!(enriched, r#"{"ip":"1.1.1.1","schema":"1"}"#);
+
+    let r = enrich(message, &metadata(), &config(None, ConflictPolicy::Reject));
+    assert!(matches!(r, Err(EnrichError::Conflict(field)) if field == "ip"));
+
+    let r = enrich(
+        br#"{"_meta":1}"#,
+        &metadata(),
+        &config(Some("_meta"), ConflictPolicy::Reject),
+    );
+    assert!(matches!(r, Err(EnrichError::Conflict(field)) if field == "_meta"));
+}
+
+#[test]
+fn test_enrich_not_an_object() {
+    let r = enrich(
+        b"[1,2]",
+        &metadata(),
+        &config(None, ConflictPolicy::Overwrite),
+    );
+    assert!(matches!(r, Err(EnrichError::NotAnObject)));
+
+    let r = enrich(b"{", &metadata(), &config(None, ConflictPolicy::Overwrite));
+    assert!(matches!(r, Err(EnrichError::Json(_))));
+}
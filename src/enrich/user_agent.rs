@@ -0,0 +1,71 @@
+//! User-Agent parsing with a ua-parser regexes file.
+
+use std::path::Path;
+
+use bytes::Bytes;
+use common::config::ConfigError;
+use uaparser::{Parser, UserAgentParser};
+
+use crate::config::HeaderNames;
+use crate::error::{Error, Result};
+
+// ua-parser's family for anything it does not recognize
+const UNKNOWN: &str = "Other";
+
+pub struct UserAgent(UserAgentParser);
+
+impl UserAgent {
+    pub fn open(path: &Path) -> Result<UserAgent> {
+        UserAgentParser::from_yaml(&path.to_string_lossy())
+            .map(UserAgent)
+            .map_err(|e| {
+                Error::from(ConfigError::Invalid(format!(
+                    "Could not load User-Agent regexes '{}': {:?}",
+                    path.display(),
+                    e
+                )))
+            })
+    }
+
+    /// Kafka headers with the browser, browser version, OS and device families of the
+    /// User-Agent, for the values that are recognized.
+    pub fn headers(&self, user_agent: &str, header_names: &HeaderNames) -> Vec<(String, Bytes)> {
+        let client = self.0.parse(user_agent);
+        let mut headers = Vec::new();
+
+        if client.user_agent.family != UNKNOWN {
+            headers.push((
+                header_names.user_agent_browser.clone(),
+                Bytes::from(client.user_agent.family.into_owned()),
+            ));
+            let version = [
+                client.user_agent.major,
+                client.user_agent.minor,
+                client.user_agent.patch,
+            ]
+            .into_iter()
+            .map_while(|part| part)
+            .collect::<Vec<_>>()
+            .join(".");
+            if !version.is_empty() {
+                headers.push((
+                    header_names.user_agent_browser_version.clone(),
+                    Bytes::from(version),
+                ));
+            }
+        }
+        if client.os.family != UNKNOWN {
+            headers.push((
+                header_names.user_agent_os.clone(),
+                Bytes::from(client.os.family.into_owned()),
+            ));
+        }
+        if client.device.family != UNKNOWN {
+            headers.push((
+                header_names.user_agent_device.clone(),
+                Bytes::from(client.device.family.into_owned()),
+            ));
+        }
+        headers
+    }
+}
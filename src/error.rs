@@ -5,11 +5,12 @@ use common::config::ConfigError;
 use common::logging::LoggingError;
 use pyo3::PyErr;
 use rdkafka::error::KafkaError;
-use tracing::error;
+use tracing::{debug, error};
 
+use crate::kafka;
 use crate::python::pyerror_with_traceback_string;
 
-// use crate::server::WSError;
+use crate::server::WSError;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -21,9 +22,9 @@ pub enum Error {
     Config(ConfigError),
     ActixWeb(actix_web::Error),
     Python(PyErr),
-    // /// Used when server is shutting down and no more websocket connections
-    // /// are accepted.
-    // WSNotAccepted,
+    /// Used when server is shutting down and no more websocket connections
+    /// are accepted.
+    WSNotAccepted,
 }
 
 impl fmt::Display for Error {
@@ -37,10 +38,10 @@ impl fmt::Display for Error {
             Config(e) => write!(f, "Configuration error: {}", e),
             Python(e) => write!(f, "Python error:\n{}", pyerror_with_traceback_string(e)),
             ActixWeb(e) => write!(f, "Actix-web error:\n{}", e),
-            // WSNotAccepted => write!(
-            //     f,
-            //     "Server shutting down. No more WebSocket connections accepted"
-            // ),
+            WSNotAccepted => write!(
+                f,
+                "Server shutting down. No more WebSocket connections accepted"
+            ),
         }
     }
 }
@@ -56,7 +57,7 @@ impl StdError for Error {
             Config(e) => Some(e),
             Python(e) => Some(e),
             ActixWeb(e) => Some(e),
-            // WSNotAccepted => None,
+            WSNotAccepted => None,
         }
     }
 }
@@ -66,11 +67,13 @@ impl ResponseError for Error {
         use Error::*;
 
         match self {
+            // fails fast while the circuit of the producer is open
+            Kafka(e) if kafka::is_unavailable(e) => StatusCode::SERVICE_UNAVAILABLE,
             Kafka(_) | IO(_) | Logging(_) | Config(_) | Python(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
             ActixWeb(e) => e.as_response_error().status_code(),
-            // WSNotAccepted => StatusCode::CONFLICT,
+            WSNotAccepted => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 
@@ -78,17 +81,17 @@ impl ResponseError for Error {
         use Error::*;
 
         match self {
-            // WSNotAccepted => {
-            //     debug!(
-            //         "Sending {} response to client; Client error: {}",
-            //         status_code, self
-            //     );
-            //
-            //     res.json(JSONError::from(self))
-            // }
+            WSNotAccepted => {
+                let status_code = self.status_code();
+                debug!(
+                    "Sending {} response to client; Client error: {}",
+                    status_code, self
+                );
+                HttpResponse::build(status_code).finish()
+            }
             ActixWeb(e) => e.error_response(),
             Kafka(_) | IO(_) | Logging(_) | Config(_) | Python(_) => {
-                let status_code = StatusCode::INTERNAL_SERVER_ERROR;
+                let status_code = self.status_code();
                 let mut res = HttpResponse::build(status_code);
                 error!(
                     "Sending {} response to client; Internal error: {}",
@@ -100,22 +103,24 @@ impl ResponseError for Error {
     }
 }
 
-// impl WSError for Error {
-//     fn message(&self) -> String {
-//         use Error::*;
-//
-//         match self {
-//             Kafka(_) | IO(_) | Logging(_) | Config(_) | WSNotAccepted => {
-//                 error!(
-//                     "Sending unsuccessful response to client; Internal error: {}",
-//                     self
-//                 );
-//
-//                 "Internal server error".to_string()
-//             }
-//         }
-//     }
-// }
+impl WSError for Error {
+    fn message(&self) -> String {
+        use Error::*;
+
+        match self {
+            ActixWeb(e) => e.to_string(),
+            WSNotAccepted => self.to_string(),
+            Kafka(_) | IO(_) | Logging(_) | Config(_) | Python(_) => {
+                error!(
+                    "Sending unsuccessful response to client; Internal error: {}",
+                    self
+                );
+
+                "Internal server error".to_string()
+            }
+        }
+    }
+}
 
 impl From<KafkaError> for Error {
     fn from(e: KafkaError) -> Error {
@@ -0,0 +1,136 @@
+//! Dropping and sampling of messages by declarative rules.
+
+use std::cell::OnceCell;
+
+use actix_web::http::header::{HeaderMap, HeaderName};
+use common::config::ConfigError;
+use serde_json::Value;
+
+use crate::config::{FilterAction, FilterRule, HeaderCondition};
+use crate::error::{Error, Result};
+
+// the sample rate resolution
+const SAMPLE_BUCKETS: u64 = 1_000_000;
+
+pub struct Filter {
+    rules: Vec<FilterRule>,
+}
+
+impl Filter {
+    pub fn new(rules: &[FilterRule]) -> Result<Filter> {
+        for rule in rules {
+            if let Some(header) = &rule.header {
+                HeaderName::from_bytes(header.name.as_bytes()).map_err(|_| {
+                    ConfigError::Invalid(format!("Invalid filter header name '{}'", header.name))
+                })?;
+            }
+            if let Some(field) = &rule.field {
+                check_pointer(&field.path)?;
+            }
+            if let FilterAction::Sample { rate, key_field } = &rule.action {
+                if !(0.0..=1.0).contains(rate) {
+                    return Err(Error::from(ConfigError::Invalid(format!(
+                        "Invalid sample rate {}, expected a value between 0 and 1",
+                        rate
+                    ))));
+                }
+                check_pointer(key_field)?;
+            }
+        }
+        Ok(Filter {
+            rules: rules.to_vec(),
+        })
+    }
+
+    /// Whether to keep the message. The first rule the message matches decides, messages
+    /// matching no rule are kept. Fields are only looked up in messages of JSON content types,
+    /// so field conditions never match other messages, and sampled messages without the key
+    /// field are kept.
+    pub fn keep(&self, message: &[u8], is_json: bool, request_headers: &HeaderMap) -> bool {
+        // only parsed when a rule needs a field
+        let json = OnceCell::new();
+        let field = |pointer: &str| {
+            json.get_or_init(|| {
+                if is_json {
+                    serde_json::from_slice::<Value>(message).ok()
+                } else {
+                    None
+                }
+            })
+            .as_ref()
+            .and_then(|value| value.pointer(pointer))
+        };
+
+        for rule in &self.rules {
+            if rule.min_size_bytes.is_some_and(|min| message.len() < min)
+                || rule.max_size_bytes.is_some_and(|max| message.len() > max)
+            {
+                continue;
+            }
+            if let Some(header) = &rule.header
+                && !header_matches(header, request_headers)
+            {
+                continue;
+            }
+            if let Some(condition) = &rule.field {
+                match field(&condition.path) {
+                    None => continue,
+                    Some(value) => {
+                        if let Some(values) = &condition.values
+                            && !values.contains(value)
+                        {
+                            continue;
+                        }
+                    }
+                }
+            }
+            return match &rule.action {
+                FilterAction::Drop => false,
+                FilterAction::Sample { rate, key_field } => match field(key_field) {
+                    None => true,
+                    Some(key) => sampled(key, *rate),
+                },
+            };
+        }
+        true
+    }
+}
+
+fn check_pointer(path: &str) -> Result<()> {
+    if path.is_empty() || path.starts_with('/') {
+        Ok(())
+    } else {
+        Err(Error::from(ConfigError::Invalid(format!(
+            "Invalid filter field '{}', expected a JSON pointer",
+            path
+        ))))
+    }
+}
+
+fn header_matches(condition: &HeaderCondition, headers: &HeaderMap) -> bool {
+    match &condition.values {
+        None => headers.contains_key(condition.name.as_str()),
+        Some(values) => headers.get_all(condition.name.as_str()).any(|v| {
+            v.to_str()
+                .is_ok_and(|v| values.iter().any(|value| value == v))
+        }),
+    }
+}
+
+fn sampled(key: &Value, rate: f64) -> bool {
+    let hash = match key {
+        Value::String(s) => fnv1a(s.as_bytes()),
+        other => fnv1a(other.to_string().as_bytes()),
+    };
+    hash % SAMPLE_BUCKETS < (rate * SAMPLE_BUCKETS as f64).round() as u64
+}
+
+/// 64 bit FNV-1a, which unlike the std hasher is stable across releases and instances.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,116 @@
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::config::FilterRule;
+
+use super::Filter;
+
+fn filter(rules: serde_json::Value) -> Filter {
+    let rules: Vec<FilterRule> = serde_json::from_value(rules).unwrap();
+    Filter::new(&rules).unwrap()
+}
+
+fn keep(filter: &Filter, message: &str) -> bool {
+    filter.keep(message.as_bytes(), true, &HeaderMap::new())
+}
+
+#[test]
+fn test_drop_by_field_value() {
+    let filter = filter(serde_json::json!([
+        {"field": {"path": "/level", "values": ["debug", "trace"]}, "action": {"type": "drop"}}
+    ]));
+    assert!(!keep(&filter, r#"{"level":"debug"}"#));
+    assert!(!keep(&filter, r#"{"level":"trace"}"#));
+    assert!(keep(&filter, r#"{"level":"info"}"#));
+    assert!(keep(&filter, r#"{"other":"debug"}"#));
+    assert!(keep(&filter, "not json"));
+    assert!(!filter.keep(br#"{"level":"debug"}"#, true, &HeaderMap::new()));
+    assert!(filter.keep(br#"{"level":"debug"}"#, false, &HeaderMap::new()));
+}
+
+#[test]
+fn test_drop_by_field_presence_and_size() {
+    let filter = filter(serde_json::json!([
+        {"field": {"path": "/debug"}, "max_size_bytes": 20, "action": {"type": "drop"}},
+        {"min_size_bytes": 30, "action": {"type": "drop"}}
+    ]));
+    assert!(!keep(&filter, r#"{"debug":true}"#));
+    assert!(keep(&filter, r#"{"debug":true,"a":"123456"}"#));
+    assert!(!keep(&filter, r#"{"a":"12345678901234567890123"}"#));
+}
+
+#[test]
+fn test_drop_by_header() {
+    let filter = filter(serde_json::json!([
+        {"header": {"name": "X-Debug", "values": ["1"]}, "action": {"type": "drop"}}
+    ]));
+    let mut headers = HeaderMap::new();
+    assert!(filter.keep(b"{}", true, &headers));
+    headers.insert(
+        HeaderName::from_static("x-debug"),
+        HeaderValue::from_static("0"),
+    );
+    assert!(filter.keep(b"{}", true, &headers));
+    headers.insert(
+        HeaderName::from_static("x-debug"),
+        HeaderValue::from_static("1"),
+    );
+    assert!(!filter.keep(b"{}", true, &headers));
+}
+
+#[test]
+fn test_first_matching_rule_applies() {
+    let filter = filter(serde_json::json!([
+        {"field": {"path": "/user", "values": ["admin"]}, "action": {"type": "sample", "rate": 1.0, "key_field": "/user"}},
+        {"action": {"type": "drop"}}
+    ]));
+    assert!(keep(&filter, r#"{"user":"admin"}"#));
+    assert!(!keep(&filter, r#"{"user":"other"}"#));
+}
+
+#[test]
+fn test_sample_is_deterministic() {
+    let filter = filter(serde_json::json!([
+        {"action": {"type": "sample", "rate": 0.1, "key_field": "/session/id"}}
+    ]));
+    let mut kept = 0;
+    for i in 0..10000 {
+        let message = format!(r#"{{"session":{{"id":"session-{}"}}}}"#, i);
+        let first = keep(&filter, &message);
+        assert_eq!(keep(&filter, &message), first);
+        if first {
+            kept += 1;
+        }
+    }
+    assert!((800..1200).contains(&kept), "kept {}", kept);
+
+    // messages without the key are kept
+    assert!(keep(&filter, r#"{"session":{}}"#));
+}
+
+#[test]
+fn test_sample_rate_bounds() {
+    let none = filter(serde_json::json!([
+        {"action": {"type": "sample", "rate": 0.0, "key_field": "/id"}}
+    ]));
+    let all = filter(serde_json::json!([
+        {"action": {"type": "sample", "rate": 1.0, "key_field": "/id"}}
+    ]));
+    for i in 0..100 {
+        let message = format!(r#"{{"id":{}}}"#, i);
+        assert!(!keep(&none, &message));
+        assert!(keep(&all, &message));
+    }
+}
+
+#[test]
+fn test_invalid_rules() {
+    for rules in [
+        serde_json::json!([{"action": {"type": "sample", "rate": 1.5, "key_field": "/id"}}]),
+        serde_json::json!([{"action": {"type": "sample", "rate": 0.5, "key_field": "id"}}]),
+        serde_json::json!([{"field": {"path": "level"}, "action": {"type": "drop"}}]),
+        serde_json::json!([{"header": {"name": "a b"}, "action": {"type": "drop"}}]),
+    ] {
+        let rules: Vec<FilterRule> = serde_json::from_value(rules).unwrap();
+        assert!(Filter::new(&rules).is_err());
+    }
+}
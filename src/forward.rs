@@ -0,0 +1,455 @@
+//! Decoding of the Fluent Forward protocol v1 messages sent by fluentd and fluent-bit, in the
+//! Message, Forward and (compressed) PackedForward modes.
+
+use std::fmt;
+use std::io::{self, Read};
+
+use bytes::{Bytes, BytesMut};
+use flate2::read::MultiGzDecoder;
+use rmpv::Value;
+use serde_json::Number;
+use tokio_util::codec::Decoder;
+
+use crate::framing::FrameError;
+
+const EVENT_TIME_EXT_TYPE: i8 = 0;
+
+#[derive(Debug)]
+pub enum ForwardError {
+    Invalid(&'static str),
+    Decode(rmpv::decode::Error),
+    Decompress(io::Error),
+    /// The decompressed entries are larger than the maximum size.
+    TooLarge,
+}
+
+impl fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ForwardError::*;
+
+        match self {
+            Invalid(reason) => write!(f, "Invalid forward message: {}", reason),
+            Decode(e) => write!(f, "Invalid forward message: {}", e),
+            Decompress(e) => write!(f, "Invalid compressed forward entries: {}", e),
+            TooLarge => write!(f, "Forward entries exceed the maximum allowed size"),
+        }
+    }
+}
+
+impl std::error::Error for ForwardError {}
+
+#[derive(Debug, PartialEq)]
+pub enum EventTime {
+    Seconds(u64),
+    /// The EventTime extension type.
+    Precise {
+        seconds: u32,
+        nanoseconds: u32,
+    },
+    Float(f64),
+}
+
+impl EventTime {
+    /// Seconds since the epoch, with a fractional part for precise times.
+    pub fn to_json(&self) -> serde_json::Value {
+        match *self {
+            EventTime::Seconds(seconds) => seconds.into(),
+            EventTime::Precise {
+                seconds,
+                nanoseconds,
+            } => float(seconds as f64 + nanoseconds as f64 / 1e9),
+            EventTime::Float(seconds) => float(seconds),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub time: EventTime,
+    pub record: Value,
+}
+
+impl Entry {
+    /// The record as JSON, with the time set to the `time_key` field of map records that don't
+    /// have it. Binary strings are converted to UTF-8 strings, and map keys to strings.
+    pub fn into_json(self, time_key: Option<&str>) -> serde_json::Value {
+        let mut record = to_json(self.record);
+        if let (Some(time_key), serde_json::Value::Object(object)) = (time_key, &mut record)
+            && !object.contains_key(time_key)
+        {
+            object.insert(time_key.to_owned(), self.time.to_json());
+        }
+        record
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ForwardMessage {
+    pub tag: String,
+    pub entries: Vec<Entry>,
+    /// The id to acknowledge once the entries are stored, when the client requires acks.
+    pub chunk: Option<String>,
+}
+
+/// Decodes a message in any of the modes. `max_size` limits the size of decompressed entries.
+pub fn decode(message: &[u8], max_size: usize) -> Result<ForwardMessage, ForwardError> {
+    let Value::Array(mut fields) =
+        rmpv::decode::read_value(&mut &message[..]).map_err(ForwardError::Decode)?
+    else {
+        return Err(ForwardError::Invalid("message is not an array"));
+    };
+
+    // [tag, time, record, options?] in Message mode, [tag, entries, options?] otherwise
+    let is_message_mode = matches!(fields.get(1), Some(Value::Integer(_) | Value::Ext(..)));
+    let options_index = if is_message_mode { 3 } else { 2 };
+    if fields.len() < options_index || fields.len() > options_index + 1 {
+        return Err(ForwardError::Invalid("unexpected number of fields"));
+    }
+    let options = if fields.len() > options_index {
+        Options::new(fields.pop().unwrap_or(Value::Nil))?
+    } else {
+        Options::default()
+    };
+
+    let mut fields = fields.into_iter();
+    let tag = match fields.next() {
+        Some(Value::String(tag)) => tag
+            .into_str()
+            .ok_or(ForwardError::Invalid("tag is not valid UTF-8"))?,
+        _ => return Err(ForwardError::Invalid("tag is not a string")),
+    };
+    let entries = match (fields.next(), fields.next()) {
+        (Some(time), Some(record)) if is_message_mode => vec![Entry {
+            time: event_time(&time)?,
+            record,
+        }],
+        (Some(Value::Array(entries)), None) => {
+            entries.into_iter().map(entry).collect::<Result<_, _>>()?
+        }
+        (Some(Value::Binary(packed)), None) => packed_entries(packed, &options, max_size)?,
+        (Some(Value::String(packed)), None) => {
+            packed_entries(packed.into_bytes(), &options, max_size)?
+        }
+        _ => return Err(ForwardError::Invalid("entries are not an array or binary")),
+    };
+
+    Ok(ForwardMessage {
+        tag,
+        entries,
+        chunk: options.chunk,
+    })
+}
+
+#[derive(Default)]
+struct Options {
+    chunk: Option<String>,
+    gzip: bool,
+}
+
+impl Options {
+    fn new(options: Value) -> Result<Options, ForwardError> {
+        let options = match options {
+            Value::Map(options) => options,
+            Value::Nil => return Ok(Options::default()),
+            _ => return Err(ForwardError::Invalid("options are not a map")),
+        };
+        let mut parsed = Options::default();
+        for (key, value) in options {
+            match key.as_str() {
+                Some("chunk") => {
+                    parsed.chunk = Some(
+                        value
+                            .as_str()
+                            .ok_or(ForwardError::Invalid("chunk is not a string"))?
+                            .to_owned(),
+                    )
+                }
+                Some("compressed") => {
+                    parsed.gzip = match value.as_str() {
+                        Some("gzip") => true,
+                        Some("text") => false,
+                        _ => return Err(ForwardError::Invalid("unsupported compression")),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// `[time, record]`, where the time can also be `[time, metadata]` like fluent-bit sends it.
+fn entry(value: Value) -> Result<Entry, ForwardError> {
+    let Value::Array(fields) = value else {
+        return Err(ForwardError::Invalid("entry is not an array"));
+    };
+    let [time, record]: [Value; 2] = fields
+        .try_into()
+        .map_err(|_| ForwardError::Invalid("entry is not a time and a record"))?;
+    let time = match time {
+        Value::Array(mut time) if !time.is_empty() => event_time(&time.swap_remove(0))?,
+        time => event_time(&time)?,
+    };
+    Ok(Entry { time, record })
+}
+
+/// Decodes concatenated entries, gzip compressed when set in the options.
+fn packed_entries(
+    packed: Vec<u8>,
+    options: &Options,
+    max_size: usize,
+) -> Result<Vec<Entry>, ForwardError> {
+    let packed = if options.gzip {
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(&packed[..])
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(ForwardError::Decompress)?;
+        if decompressed.len() > max_size {
+            return Err(ForwardError::TooLarge);
+        }
+        decompressed
+    } else {
+        packed
+    };
+
+    let mut entries = Vec::new();
+    let mut rest = &packed[..];
+    while !rest.is_empty() {
+        let value = rmpv::decode::read_value(&mut rest).map_err(ForwardError::Decode)?;
+        entries.push(entry(value)?);
+    }
+    Ok(entries)
+}
+
+fn event_time(time: &Value) -> Result<EventTime, ForwardError> {
+    match time {
+        Value::Integer(seconds) => seconds
+            .as_u64()
+            .map(EventTime::Seconds)
+            .ok_or(ForwardError::Invalid("time is negative")),
+        Value::F32(seconds) => Ok(EventTime::Float(*seconds as f64)),
+        Value::F64(seconds) => Ok(EventTime::Float(*seconds)),
+        Value::Ext(EVENT_TIME_EXT_TYPE, data) if data.len() == 8 => Ok(EventTime::Precise {
+            seconds: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            nanoseconds: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+        }),
+        _ => Err(ForwardError::Invalid("time is not an integer or EventTime")),
+    }
+}
+
+fn to_json(value: Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+
+    match value {
+        Value::Nil => Json::Null,
+        Value::Boolean(b) => Json::Bool(b),
+        Value::Integer(i) => match (i.as_u64(), i.as_i64()) {
+            (Some(u), _) => u.into(),
+            (_, Some(i)) => i.into(),
+            _ => Json::Null,
+        },
+        Value::F32(f) => float(f as f64),
+        Value::F64(f) => float(f),
+        Value::String(s) => Json::String(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        Value::Binary(b) => Json::String(String::from_utf8_lossy(&b).into_owned()),
+        Value::Array(values) => Json::Array(values.into_iter().map(to_json).collect()),
+        Value::Map(entries) => Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match to_json(key) {
+                        Json::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+        ref ext @ Value::Ext(..) => event_time(ext).map_or(Json::Null, |time| time.to_json()),
+    }
+}
+
+fn float(f: f64) -> serde_json::Value {
+    Number::from_f64(f).map_or(serde_json::Value::Null, serde_json::Value::Number)
+}
+
+/// The response acknowledging a chunk.
+pub fn ack(chunk: &str) -> Vec<u8> {
+    let mut response = Vec::new();
+    rmpv::encode::write_value(
+        &mut response,
+        &Value::Map(vec![(Value::from("ack"), Value::from(chunk))]),
+    )
+    .expect("Writing to a Vec does not fail");
+    response
+}
+
+/// A fluentd style tag pattern, where `*` matches any characters within a tag part, and a `**`
+/// part matches zero or more tag parts.
+pub struct TagPattern(Vec<String>);
+
+impl TagPattern {
+    pub fn new(pattern: &str) -> TagPattern {
+        TagPattern(pattern.split('.').map(str::to_owned).collect())
+    }
+
+    pub fn matches(&self, tag: &str) -> bool {
+        let tag: Vec<&str> = tag.split('.').collect();
+        parts_match(&self.0, &tag)
+    }
+}
+
+fn parts_match(pattern: &[String], tag: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => tag.is_empty(),
+        Some((part, rest)) if part == "**" => {
+            (0..=tag.len()).any(|skipped| parts_match(rest, &tag[skipped..]))
+        }
+        Some((part, rest)) => tag.split_first().is_some_and(|(tag_part, tag_rest)| {
+            wildcard_matches(part, tag_part) && parts_match(rest, tag_rest)
+        }),
+    }
+}
+
+/// Whether a pattern where `*` matches any characters matches the whole value.
+pub fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == value,
+        Some((prefix, rest)) => value.strip_prefix(prefix).is_some_and(|value| {
+            (0..=value.len())
+                .filter(|i| value.is_char_boundary(*i))
+                .any(|i| wildcard_matches(rest, &value[i..]))
+        }),
+    }
+}
+
+/// Decoder for a stream of MessagePack values, each a forward message.
+pub struct ForwardCodec {
+    max_length: usize,
+    // of the value at the start of the buffer, so that it is not rescanned on each read
+    scan: Scan,
+}
+
+impl ForwardCodec {
+    pub fn new(max_length: usize) -> ForwardCodec {
+        ForwardCodec {
+            max_length,
+            scan: Scan::default(),
+        }
+    }
+}
+
+impl Decoder for ForwardCodec {
+    type Item = Bytes;
+    type Error = FrameError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, FrameError> {
+        match self.scan.resume(buf) {
+            Some(length) if length > self.max_length => Err(FrameError::MaxLengthExceeded),
+            Some(length) => {
+                self.scan = Scan::default();
+                Ok(Some(buf.split_to(length).freeze()))
+            }
+            // or whose declared lengths already exceed it
+            None if buf.len().max(self.scan.position) > self.max_length => {
+                Err(FrameError::MaxLengthExceeded)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, FrameError> {
+        match self.decode(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => {
+                buf.clear();
+                self.scan = Scan::default();
+                Err(FrameError::Truncated)
+            }
+        }
+    }
+}
+
+/// The progress of the scan of the MessagePack value at the start of a buffer. Only the markers
+/// and lengths are read, the value is validated when decoded.
+struct Scan {
+    // of the next marker to read
+    position: usize,
+    // values left to scan, including the nested ones
+    remaining_values: usize,
+}
+
+impl Default for Scan {
+    fn default() -> Scan {
+        Scan {
+            position: 0,
+            remaining_values: 1,
+        }
+    }
+}
+
+impl Scan {
+    /// Scans the bytes of `buf` not scanned yet. Returns the length of the value, or None when
+    /// `buf` only has part of it.
+    fn resume(&mut self, buf: &[u8]) -> Option<usize> {
+        while self.remaining_values > 0 {
+            let (length, values) = marker_length(buf, self.position)?;
+            self.position += length;
+            self.remaining_values = self.remaining_values - 1 + values;
+        }
+        (self.position <= buf.len()).then_some(self.position)
+    }
+}
+
+/// The length of the marker at `position` with the bytes following it, and its number of nested
+/// values, or None when `buf` ends before its length.
+fn marker_length(buf: &[u8], position: usize) -> Option<(usize, usize)> {
+    let marker = *buf.get(position)?;
+    let position = position + 1;
+    // the bytes following the marker, and the number of nested values
+    let (skipped, values) = match marker {
+        0x00..=0x7f | 0xc0..=0xc3 | 0xe0..=0xff => (0, 0),
+        0x80..=0x8f => (0, 2 * (marker & 0x0f) as usize),
+        0x90..=0x9f => (0, (marker & 0x0f) as usize),
+        0xa0..=0xbf => ((marker & 0x1f) as usize, 0),
+        // bin and str
+        0xc4 | 0xd9 => (1 + read_length(buf, position, 1)?, 0),
+        0xc5 | 0xda => (2 + read_length(buf, position, 2)?, 0),
+        0xc6 | 0xdb => (4 + read_length(buf, position, 4)?, 0),
+        // ext, with its type after the length
+        0xc7 => (2 + read_length(buf, position, 1)?, 0),
+        0xc8 => (3 + read_length(buf, position, 2)?, 0),
+        0xc9 => (5 + read_length(buf, position, 4)?, 0),
+        // numbers
+        0xcc | 0xd0 => (1, 0),
+        0xcd | 0xd1 => (2, 0),
+        0xca | 0xce | 0xd2 => (4, 0),
+        0xcb | 0xcf | 0xd3 => (8, 0),
+        // fixext, with its type
+        0xd4 => (2, 0),
+        0xd5 => (3, 0),
+        0xd6 => (5, 0),
+        0xd7 => (9, 0),
+        0xd8 => (17, 0),
+        // arrays and maps
+        0xdc => (2, read_length(buf, position, 2)?),
+        0xdd => (4, read_length(buf, position, 4)?),
+        0xde => (2, 2 * read_length(buf, position, 2)?),
+        0xdf => (4, 2 * read_length(buf, position, 4)?),
+    };
+    Some((1 + skipped, values))
+}
+
+fn read_length(buf: &[u8], position: usize, size: usize) -> Option<usize> {
+    let bytes = buf.get(position..position + size)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0, |length, byte| (length << 8) | *byte as usize),
+    )
+}
+
+#[cfg(test)]
+mod test;
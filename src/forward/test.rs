@@ -0,0 +1,295 @@
+use std::io::Write;
+
+use bytes::BytesMut;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rmpv::Value;
+use serde_json::json;
+use tokio_util::codec::Decoder;
+
+use crate::framing::FrameError;
+
+use super::{
+    Entry, EventTime, ForwardCodec, ForwardError, ForwardMessage, TagPattern, ack, decode,
+};
+
+fn encode(value: &Value) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    rmpv::encode::write_value(&mut encoded, value).unwrap();
+    encoded
+}
+
+fn record(message: &str) -> Value {
+    Value::Map(vec![(Value::from("log"), Value::from(message))])
+}
+
+fn event_time(seconds: u32, nanoseconds: u32) -> Value {
+    let mut data = seconds.to_be_bytes().to_vec();
+    data.extend_from_slice(&nanoseconds.to_be_bytes());
+    Value::Ext(0, data)
+}
+
+fn options(options: &[(&str, &str)]) -> Value {
+    Value::Map(
+        options
+            .iter()
+            .map(|(key, value)| (Value::from(*key), Value::from(*value)))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_decode_message_mode() {
+    let message = encode(&Value::Array(vec![
+        Value::from("app.web"),
+        Value::from(1700000000),
+        record("a"),
+    ]));
+    assert_eq!(
+        decode(&message, 1024).unwrap(),
+        ForwardMessage {
+            tag: "app.web".to_owned(),
+            entries: vec![Entry {
+                time: EventTime::Seconds(1700000000),
+                record: record("a"),
+            }],
+            chunk: None,
+        }
+    );
+
+    let message = encode(&Value::Array(vec![
+        Value::from("app.web"),
+        event_time(1700000000, 500_000_000),
+        record("a"),
+        options(&[("chunk", "c1")]),
+    ]));
+    let message = decode(&message, 1024).unwrap();
+    assert_eq!(message.chunk.as_deref(), Some("c1"));
+    assert_eq!(
+        message.entries[0].time,
+        EventTime::Precise {
+            seconds: 1700000000,
+            nanoseconds: 500_000_000
+        }
+    );
+}
+
+#[test]
+fn test_decode_forward_mode() {
+    let message = encode(&Value::Array(vec![
+        Value::from("app"),
+        Value::Array(vec![
+            Value::Array(vec![Value::from(1), record("a")]),
+            // fluent-bit metadata
+            Value::Array(vec![
+                Value::Array(vec![event_time(2, 0), Value::Map(vec![])]),
+                record("b"),
+            ]),
+        ]),
+        options(&[("chunk", "c2"), ("size", "2")]),
+    ]));
+    let message = decode(&message, 1024).unwrap();
+    assert_eq!(message.chunk.as_deref(), Some("c2"));
+    assert_eq!(
+        message.entries,
+        vec![
+            Entry {
+                time: EventTime::Seconds(1),
+                record: record("a"),
+            },
+            Entry {
+                time: EventTime::Precise {
+                    seconds: 2,
+                    nanoseconds: 0
+                },
+                record: record("b"),
+            },
+        ]
+    );
+}
+
+fn packed_entries() -> Vec<u8> {
+    let mut packed = encode(&Value::Array(vec![Value::from(1), record("a")]));
+    packed.extend(encode(&Value::Array(vec![event_time(2, 0), record("b")])));
+    packed
+}
+
+#[test]
+fn test_decode_packed_forward_mode() {
+    let message = encode(&Value::Array(vec![
+        Value::from("app"),
+        Value::Binary(packed_entries()),
+    ]));
+    let message = decode(&message, 1024).unwrap();
+    assert_eq!(message.entries.len(), 2);
+    assert_eq!(message.entries[1].record, record("b"));
+}
+
+#[test]
+fn test_decode_compressed_packed_forward_mode() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&packed_entries()).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let message = encode(&Value::Array(vec![
+        Value::from("app"),
+        Value::Binary(compressed),
+        options(&[("compressed", "gzip")]),
+    ]));
+
+    let decoded = decode(&message, 1024).unwrap();
+    assert_eq!(decoded.entries.len(), 2);
+    assert_eq!(decoded.entries[0].record, record("a"));
+
+    assert!(matches!(decode(&message, 10), Err(ForwardError::TooLarge)));
+}
+
+#[test]
+fn test_decode_invalid() {
+    let invalid = [
+        Value::from("app"),
+        Value::Array(vec![Value::from("app")]),
+        Value::Array(vec![Value::from(1), Value::Array(vec![])]),
+        Value::Array(vec![Value::from("app"), Value::from(1)]),
+        Value::Array(vec![
+            Value::from("app"),
+            Value::Array(vec![Value::Array(vec![Value::from(1)])]),
+        ]),
+        Value::Array(vec![
+            Value::from("app"),
+            Value::Array(vec![]),
+            options(&[("compressed", "zstd")]),
+        ]),
+    ];
+    for message in invalid {
+        assert!(matches!(
+            decode(&encode(&message), 1024),
+            Err(ForwardError::Invalid(_))
+        ));
+    }
+}
+
+#[test]
+fn test_entry_into_json() {
+    let entry = Entry {
+        time: EventTime::Precise {
+            seconds: 1,
+            nanoseconds: 500_000_000,
+        },
+        record: Value::Map(vec![
+            (Value::from("log"), Value::Binary(b"bytes".to_vec())),
+            (
+                Value::from(1),
+                Value::Array(vec![Value::Nil, Value::from(-1)]),
+            ),
+        ]),
+    };
+    assert_eq!(
+        entry.into_json(Some("time")),
+        json!({"log": "bytes", "1": [null, -1], "time": 1.5})
+    );
+
+    let entry = Entry {
+        time: EventTime::Seconds(1),
+        record: Value::Map(vec![(Value::from("time"), Value::from("own"))]),
+    };
+    assert_eq!(entry.into_json(Some("time")), json!({"time": "own"}));
+}
+
+#[test]
+fn test_ack() {
+    assert_eq!(
+        rmpv::decode::read_value(&mut &ack("c1")[..]).unwrap(),
+        Value::Map(vec![(Value::from("ack"), Value::from("c1"))])
+    );
+}
+
+#[test]
+fn test_tag_pattern() {
+    let pattern = TagPattern::new("kube.*.web");
+    assert!(pattern.matches("kube.prod.web"));
+    assert!(!pattern.matches("kube.prod.api"));
+    assert!(!pattern.matches("kube.web"));
+
+    let pattern = TagPattern::new("kube.**");
+    assert!(pattern.matches("kube"));
+    assert!(pattern.matches("kube.prod.web"));
+    assert!(!pattern.matches("kubernetes"));
+
+    let pattern = TagPattern::new("app-*.logs");
+    assert!(pattern.matches("app-a.logs"));
+    assert!(pattern.matches("app-.logs"));
+    assert!(!pattern.matches("api.logs"));
+}
+
+#[test]
+fn test_codec() {
+    let first = encode(&Value::Array(vec![
+        Value::from("app"),
+        Value::Array(vec![Value::Array(vec![Value::from(1), record("a")])]),
+        options(&[("chunk", "c1")]),
+    ]));
+    let second = encode(&Value::Array(vec![
+        Value::from("app"),
+        Value::Binary(vec![0; 300]),
+    ]));
+
+    let mut codec = ForwardCodec::new(1024);
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(&first[..first.len() - 1]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(&first[first.len() - 1..]);
+    buf.extend_from_slice(&second[..10]);
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), first);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(&second[10..]);
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), second);
+    assert!(buf.is_empty());
+
+    buf.extend_from_slice(&second[..10]);
+    assert!(matches!(
+        codec.decode_eof(&mut buf),
+        Err(FrameError::Truncated)
+    ));
+
+    let mut codec = ForwardCodec::new(100);
+    buf.extend_from_slice(&second);
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(FrameError::MaxLengthExceeded)
+    ));
+}
+
+#[test]
+fn test_codec_resumes_scan() {
+    let entries = (0..100)
+        .map(|i| Value::Array(vec![Value::from(i), record("a")]))
+        .collect();
+    let message = encode(&Value::Array(vec![
+        Value::from("app"),
+        Value::Array(entries),
+    ]));
+
+    // only the last incomplete value, at most 4 bytes here, is scanned again as more are read
+    let mut codec = ForwardCodec::new(4096);
+    let mut buf = BytesMut::new();
+    for (i, byte) in message.iter().enumerate() {
+        buf.extend_from_slice(&[*byte]);
+        match codec.decode(&mut buf).unwrap() {
+            Some(frame) => {
+                assert_eq!(i, message.len() - 1);
+                assert_eq!(frame, message);
+            }
+            None => assert!(buf.len() < codec.scan.position + 4),
+        }
+    }
+    assert!(buf.is_empty());
+    assert_eq!(codec.scan.position, 0);
+
+    // a declared length over the max fails before the bytes are read
+    let mut codec = ForwardCodec::new(100);
+    buf.extend_from_slice(&encode(&Value::Binary(vec![0; 300]))[..10]);
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(FrameError::MaxLengthExceeded)
+    ));
+}
@@ -0,0 +1,239 @@
+//! Splitting of request bodies into individual messages.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::{fmt, io};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use futures::{Stream, future};
+use tokio_util::codec::{Decoder, FramedRead};
+use tokio_util::io::StreamReader;
+
+use crate::config::Framing;
+
+const NEWLINE: u8 = b'\n';
+const JSON_SEQ_RECORD_SEPARATOR: u8 = 0x1E;
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// A frame is larger than the configured maximum length.
+    MaxLengthExceeded,
+    /// The body ended in the middle of a frame.
+    Truncated,
+    Io(io::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FrameError::*;
+
+        match self {
+            MaxLengthExceeded => write!(f, "Frame exceeds the maximum allowed length"),
+            Truncated => write!(f, "Body ended in the middle of a frame"),
+            Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> FrameError {
+        FrameError::Io(e)
+    }
+}
+
+/// Splits a body stream into frames according to `framing`. Frames longer than `max_length`
+/// result in `FrameError::MaxLengthExceeded`, after which the stream ends. With `Framing::None`
+/// the whole body is a single frame.
+pub fn frames<'a, S>(
+    body_stream: S,
+    framing: &Framing,
+    max_length: usize,
+) -> LocalBoxStream<'a, Result<Bytes, FrameError>>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin + 'a,
+{
+    match framing {
+        Framing::None => stream::once(whole_body(body_stream, max_length))
+            .filter(|frame| future::ready(!matches!(frame, Ok(frame) if frame.is_empty())))
+            .boxed_local(),
+        Framing::Newline => DelimitedFrames::new(body_stream, NEWLINE, max_length).boxed_local(),
+        Framing::JsonSeq => {
+            DelimitedFrames::new(body_stream, JSON_SEQ_RECORD_SEPARATOR, max_length).boxed_local()
+        }
+        Framing::Delimiter { delimiter } => {
+            DelimitedFrames::new(body_stream, *delimiter, max_length).boxed_local()
+        }
+        Framing::LengthPrefixed => FramedRead::new(
+            StreamReader::new(body_stream),
+            LengthPrefixedCodec { max_length },
+        )
+        .boxed_local(),
+    }
+}
+
+/// Reads a whole body as a single frame. A body of a single chunk is not copied.
+async fn whole_body<S>(mut body_stream: S, max_length: usize) -> Result<Bytes, FrameError>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    let mut first = Bytes::new();
+    let mut rest = BytesMut::new();
+    while let Some(chunk) = body_stream.next().await {
+        let chunk = chunk?;
+        if first.len() + rest.len() + chunk.len() > max_length {
+            return Err(FrameError::MaxLengthExceeded);
+        }
+        if first.is_empty() {
+            first = chunk;
+        } else {
+            rest.extend_from_slice(&chunk);
+        }
+    }
+    if rest.is_empty() {
+        Ok(first)
+    } else {
+        let mut body = BytesMut::with_capacity(first.len() + rest.len());
+        body.extend_from_slice(&first);
+        body.extend_from_slice(&rest);
+        Ok(body.freeze())
+    }
+}
+
+/// Splits a stream of chunks on a delimiter byte. Frames that are contained in a single chunk
+/// are sliced out of it without copying, only frames spanning chunks are copied into a buffer.
+/// The delimiter is not part of the returned frames, and the last frame does not need to be
+/// followed by a delimiter.
+pub struct DelimitedFrames<S> {
+    body_stream: S,
+    delimiter: u8,
+    max_length: usize,
+    // the unsplit remainder of the last chunk read
+    chunk: Bytes,
+    // the start of a frame that spans chunks
+    partial: BytesMut,
+    done: bool,
+}
+
+impl<S> DelimitedFrames<S>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    pub fn new(body_stream: S, delimiter: u8, max_length: usize) -> Self {
+        Self {
+            body_stream,
+            delimiter,
+            max_length,
+            chunk: Bytes::new(),
+            partial: BytesMut::new(),
+            done: false,
+        }
+    }
+
+    fn fail(&mut self, e: FrameError) -> Poll<Option<Result<Bytes, FrameError>>> {
+        self.done = true;
+        self.chunk.clear();
+        self.partial.clear();
+        Poll::Ready(Some(Err(e)))
+    }
+}
+
+impl<S> Stream for DelimitedFrames<S>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    type Item = Result<Bytes, FrameError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(index) = this.chunk.iter().position(|b| *b == this.delimiter) {
+                if this.partial.len() + index > this.max_length {
+                    return this.fail(FrameError::MaxLengthExceeded);
+                }
+                let frame = if this.partial.is_empty() {
+                    this.chunk.slice(..index)
+                } else {
+                    this.partial.extend_from_slice(&this.chunk[..index]);
+                    this.partial.split().freeze()
+                };
+                this.chunk.advance(index + 1);
+                return Poll::Ready(Some(Ok(frame)));
+            }
+
+            if !this.chunk.is_empty() {
+                if this.partial.len() + this.chunk.len() > this.max_length {
+                    return this.fail(FrameError::MaxLengthExceeded);
+                }
+                this.partial.extend_from_slice(&this.chunk);
+                this.chunk.clear();
+            }
+
+            if this.done {
+                return if this.partial.is_empty() {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Ready(Some(Ok(this.partial.split().freeze())))
+                };
+            }
+
+            match ready!(this.body_stream.poll_next_unpin(cx)) {
+                Some(Ok(chunk)) => this.chunk = chunk,
+                Some(Err(e)) => return this.fail(FrameError::from(e)),
+                None => this.done = true,
+            }
+        }
+    }
+}
+
+/// Decoder for frames preceded by their length as a 4 byte big-endian unsigned integer.
+pub struct LengthPrefixedCodec {
+    max_length: usize,
+}
+
+impl Decoder for LengthPrefixedCodec {
+    type Item = Bytes;
+    type Error = FrameError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, FrameError> {
+        if buf.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+        let mut length_bytes = [0u8; LENGTH_PREFIX_SIZE];
+        length_bytes.copy_from_slice(&buf[..LENGTH_PREFIX_SIZE]);
+        let length = u32::from_be_bytes(length_bytes) as usize;
+        if length > self.max_length {
+            return Err(FrameError::MaxLengthExceeded);
+        }
+        if buf.len() < LENGTH_PREFIX_SIZE + length {
+            buf.reserve(LENGTH_PREFIX_SIZE + length - buf.len());
+            return Ok(None);
+        }
+        buf.advance(LENGTH_PREFIX_SIZE);
+        Ok(Some(buf.split_to(length).freeze()))
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, FrameError> {
+        match self.decode(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(FrameError::Truncated),
+        }
+    }
+}
+
+/// Strips leading and trailing ASCII whitespace without copying.
+pub fn trim(frame: Bytes) -> Bytes {
+    let trimmed = frame.trim_ascii();
+    if trimmed.len() == frame.len() {
+        frame
+    } else {
+        frame.slice_ref(trimmed)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,134 @@
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+
+use crate::config::Framing;
+
+use super::{FrameError, frames, trim};
+
+async fn split(
+    chunks: &[&'static [u8]],
+    framing: Framing,
+    max_length: usize,
+) -> Vec<Result<Bytes, FrameError>> {
+    let body_stream = stream::iter(chunks.iter().map(|c| Ok(Bytes::from_static(c))));
+    frames(body_stream, &framing, max_length).collect().await
+}
+
+fn unwrap_all(frames: Vec<Result<Bytes, FrameError>>) -> Vec<Bytes> {
+    frames.into_iter().map(|f| f.unwrap()).collect()
+}
+
+#[tokio::test]
+async fn test_newline_single_chunk() {
+    let frames = unwrap_all(split(&[b"a\nbb\n\nccc"], Framing::Newline, 10).await);
+    assert_eq!(frames, vec!["a", "bb", "", "ccc"]);
+}
+
+#[tokio::test]
+async fn test_newline_spanning_chunks() {
+    let frames = unwrap_all(
+        split(
+            &[b"a", b"b\nc", b"", b"d", b"e\n", b"\n"],
+            Framing::Newline,
+            10,
+        )
+        .await,
+    );
+    assert_eq!(frames, vec!["ab", "cde", ""]);
+}
+
+#[tokio::test]
+async fn test_newline_slices_single_chunk_frames() {
+    let chunk = Bytes::from_static(b"first\nsecond\n");
+    let chunk_range = chunk.as_ptr_range();
+    let body_stream = stream::iter(vec![Ok(chunk.clone())]);
+    let frames: Vec<Bytes> = frames(body_stream, &Framing::Newline, 10)
+        .map(|f| f.unwrap())
+        .collect()
+        .await;
+    assert_eq!(frames, vec!["first", "second"]);
+    for frame in frames {
+        assert!(chunk_range.contains(&frame.as_ptr()));
+    }
+}
+
+#[tokio::test]
+async fn test_newline_max_length() {
+    let frames = split(&[b"12\n3", b"4\n563\n23"], Framing::Newline, 2).await;
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].as_ref().unwrap(), "12");
+    assert_eq!(frames[1].as_ref().unwrap(), "34");
+    assert!(matches!(frames[2], Err(FrameError::MaxLengthExceeded)));
+}
+
+#[tokio::test]
+async fn test_newline_max_length_without_delimiter() {
+    let frames = split(&[b"12", b"345"], Framing::Newline, 4).await;
+    assert_eq!(frames.len(), 1);
+    assert!(matches!(frames[0], Err(FrameError::MaxLengthExceeded)));
+}
+
+#[tokio::test]
+async fn test_none() {
+    let frames = unwrap_all(split(&[b"a\nb", b"", b"c\n"], Framing::None, 10).await);
+    assert_eq!(frames, vec!["a\nbc\n"]);
+    assert!(split(&[b"", b""], Framing::None, 10).await.is_empty());
+    let frames = split(&[b"12345", b"67890", b"1"], Framing::None, 10).await;
+    assert!(matches!(frames[..], [Err(FrameError::MaxLengthExceeded)]));
+}
+
+#[tokio::test]
+async fn test_json_seq() {
+    let frames = unwrap_all(
+        split(
+            &[b"\x1e{\"a\":1}\n\x1e", b"{\"b\":2}\n"],
+            Framing::JsonSeq,
+            10,
+        )
+        .await,
+    );
+    assert_eq!(frames, vec!["", "{\"a\":1}\n", "{\"b\":2}\n"]);
+}
+
+#[tokio::test]
+async fn test_custom_delimiter() {
+    let frames = unwrap_all(
+        split(
+            &[b"a\0b\nc\0", b"d"],
+            Framing::Delimiter { delimiter: 0 },
+            10,
+        )
+        .await,
+    );
+    assert_eq!(frames, vec!["a", "b\nc", "d"]);
+}
+
+#[tokio::test]
+async fn test_length_prefixed() {
+    let frames = unwrap_all(
+        split(
+            &[b"\0\0\0\x02ab\0\0", b"\0\x03\xff\n", b"\0"],
+            Framing::LengthPrefixed,
+            10,
+        )
+        .await,
+    );
+    assert_eq!(frames, vec![&b"ab"[..], &b"\xff\n\0"[..]]);
+}
+
+#[tokio::test]
+async fn test_length_prefixed_errors() {
+    let frames = split(&[b"\0\0\0\x0babc"], Framing::LengthPrefixed, 10).await;
+    assert!(matches!(frames[0], Err(FrameError::MaxLengthExceeded)));
+
+    let frames = split(&[b"\0\0\0\x02ab\0\0\0\x03a"], Framing::LengthPrefixed, 10).await;
+    assert_eq!(frames[0].as_ref().unwrap(), "ab");
+    assert!(matches!(frames[1], Err(FrameError::Truncated)));
+}
+
+#[test]
+fn test_trim() {
+    assert_eq!(trim(Bytes::from_static(b" \r\n\t{}\r\n")), "{}");
+    assert_eq!(trim(Bytes::from_static(b"{}")), "{}");
+    assert_eq!(trim(Bytes::from_static(b" \t\r\n")), "");
+}
@@ -0,0 +1,178 @@
+//! Parsing of Splunk HTTP Event Collector requests, and the responses expected by their clients.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::config::HecToken;
+
+/// The schemas of the tokens by the SHA-256 of the tokens, which are not compared in variable
+/// time.
+pub struct HecTokens(HashMap<[u8; 32], String>);
+
+impl HecTokens {
+    pub fn new(tokens: &[HecToken]) -> HecTokens {
+        HecTokens(
+            tokens
+                .iter()
+                .map(|token| (digest(&token.token), token.schema_id.clone()))
+                .collect(),
+        )
+    }
+
+    pub fn schema_id(&self, token: &str) -> Option<&str> {
+        self.0.get(&digest(token)).map(String::as_str)
+    }
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// The errors of HEC, with their status and code.
+#[derive(Debug, PartialEq)]
+pub enum HecError {
+    TokenRequired,
+    InvalidAuthorization,
+    InvalidToken,
+    NoData,
+    /// The number of the first invalid event.
+    InvalidDataFormat(usize),
+    ServerBusy,
+    EventRequired(usize),
+    EventBlank(usize),
+}
+
+impl HecError {
+    pub fn status(&self) -> u16 {
+        use HecError::*;
+
+        match self {
+            TokenRequired | InvalidAuthorization => 401,
+            InvalidToken => 403,
+            NoData | InvalidDataFormat(_) | EventRequired(_) | EventBlank(_) => 400,
+            ServerBusy => 503,
+        }
+    }
+
+    fn code(&self) -> u8 {
+        use HecError::*;
+
+        match self {
+            TokenRequired => 2,
+            InvalidAuthorization => 3,
+            InvalidToken => 4,
+            NoData => 5,
+            InvalidDataFormat(_) => 6,
+            ServerBusy => 9,
+            EventRequired(_) => 12,
+            EventBlank(_) => 13,
+        }
+    }
+
+    fn invalid_event_number(&self) -> Option<usize> {
+        match self {
+            HecError::InvalidDataFormat(number)
+            | HecError::EventRequired(number)
+            | HecError::EventBlank(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut response = json!({"text": self.to_string(), "code": self.code()});
+        if let Some(number) = self.invalid_event_number() {
+            response["invalid-event-number"] = json!(number);
+        }
+        response
+    }
+}
+
+impl fmt::Display for HecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use HecError::*;
+
+        let text = match self {
+            TokenRequired => "Token is required",
+            InvalidAuthorization => "Invalid authorization",
+            InvalidToken => "Invalid token",
+            NoData => "No data",
+            InvalidDataFormat(_) => "Invalid data format",
+            ServerBusy => "Server is busy",
+            EventRequired(_) => "Event field is required",
+            EventBlank(_) => "Event field cannot be blank",
+        };
+        f.write_str(text)
+    }
+}
+
+impl std::error::Error for HecError {}
+
+pub fn success() -> Value {
+    json!({"text": "Success", "code": 0})
+}
+
+pub fn healthy() -> Value {
+    json!({"text": "HEC is healthy", "code": 17})
+}
+
+/// The token of an `Authorization: Splunk <token>` header.
+pub fn token(authorization: Option<&[u8]>) -> Result<&str, HecError> {
+    let authorization = authorization.ok_or(HecError::TokenRequired)?;
+    std::str::from_utf8(authorization)
+        .ok()
+        .and_then(|authorization| authorization.strip_prefix("Splunk "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or(HecError::InvalidAuthorization)
+}
+
+/// Parses the concatenated event objects of the event endpoint. Each event needs a non-blank
+/// `event` field, and keeps its metadata fields.
+pub fn events(body: &[u8]) -> Result<Vec<Map<String, Value>>, HecError> {
+    let mut events = Vec::new();
+    for (number, event) in serde_json::Deserializer::from_slice(body)
+        .into_iter::<Value>()
+        .enumerate()
+    {
+        let Ok(Value::Object(event)) = event else {
+            return Err(HecError::InvalidDataFormat(number));
+        };
+        match event.get("event") {
+            None | Some(Value::Null) => return Err(HecError::EventRequired(number)),
+            Some(Value::String(s)) if s.is_empty() => return Err(HecError::EventBlank(number)),
+            Some(_) => events.push(event),
+        }
+    }
+    if events.is_empty() {
+        return Err(HecError::NoData);
+    }
+    Ok(events)
+}
+
+/// Splits the body of the raw endpoint into an event per line, with the metadata fields of the
+/// query string.
+pub fn raw_events(
+    body: &[u8],
+    metadata: &Map<String, Value>,
+) -> Result<Vec<Map<String, Value>>, HecError> {
+    let body = std::str::from_utf8(body).map_err(|_| HecError::InvalidDataFormat(0))?;
+    let events: Vec<_> = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut event = metadata.clone();
+            event.insert("event".to_owned(), json!(line));
+            event
+        })
+        .collect();
+    if events.is_empty() {
+        return Err(HecError::NoData);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test;
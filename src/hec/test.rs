@@ -0,0 +1,95 @@
+use serde_json::{Map, Value, json};
+
+use super::{HecError, HecTokens, events, raw_events, token};
+use crate::config::HecToken;
+
+#[test]
+fn test_events() {
+    let events = events(
+        br#"{"event": "a", "host": "web-1"}
+        {"event": {"b": 1}, "sourcetype": "json", "fields": {"env": "prod"}}{"time": 1.5, "event": 2}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        events.into_iter().map(Value::Object).collect::<Vec<_>>(),
+        vec![
+            json!({"event": "a", "host": "web-1"}),
+            json!({"event": {"b": 1}, "sourcetype": "json", "fields": {"env": "prod"}}),
+            json!({"time": 1.5, "event": 2}),
+        ]
+    );
+}
+
+#[test]
+fn test_events_invalid() {
+    assert_eq!(events(b""), Err(HecError::NoData));
+    assert_eq!(events(b" \n"), Err(HecError::NoData));
+    assert_eq!(
+        events(br#"{"event": "a"} {"event": "#),
+        Err(HecError::InvalidDataFormat(1))
+    );
+    assert_eq!(
+        events(br#"{"event": "a"} ["b"]"#),
+        Err(HecError::InvalidDataFormat(1))
+    );
+    assert_eq!(
+        events(br#"{"event": "a"} {"host": "b"}"#),
+        Err(HecError::EventRequired(1))
+    );
+    assert_eq!(events(br#"{"event": ""}"#), Err(HecError::EventBlank(0)));
+}
+
+#[test]
+fn test_raw_events() {
+    let mut metadata = Map::new();
+    metadata.insert("sourcetype".to_owned(), json!("access_combined"));
+    let events = raw_events(b"line 1\r\n\nline 2", &metadata).unwrap();
+    assert_eq!(
+        events.into_iter().map(Value::Object).collect::<Vec<_>>(),
+        vec![
+            json!({"sourcetype": "access_combined", "event": "line 1"}),
+            json!({"sourcetype": "access_combined", "event": "line 2"}),
+        ]
+    );
+    assert_eq!(raw_events(b"\n", &metadata), Err(HecError::NoData));
+    assert_eq!(
+        raw_events(b"\xff", &metadata),
+        Err(HecError::InvalidDataFormat(0))
+    );
+}
+
+#[test]
+fn test_token() {
+    assert_eq!(token(Some(b"Splunk abc")), Ok("abc"));
+    assert_eq!(token(None), Err(HecError::TokenRequired));
+    assert_eq!(
+        token(Some(b"Bearer abc")),
+        Err(HecError::InvalidAuthorization)
+    );
+    assert_eq!(token(Some(b"Splunk ")), Err(HecError::InvalidAuthorization));
+}
+
+#[test]
+fn test_error_json() {
+    assert_eq!(
+        HecError::EventBlank(3).to_json(),
+        json!({"text": "Event field cannot be blank", "code": 13, "invalid-event-number": 3})
+    );
+    assert_eq!(HecError::InvalidToken.status(), 403);
+}
+
+#[test]
+fn test_hec_tokens() {
+    let tokens = HecTokens::new(&[
+        HecToken {
+            token: "token-a".to_owned(),
+            schema_id: "a".to_owned(),
+        },
+        HecToken {
+            token: "token-b".to_owned(),
+            schema_id: "b".to_owned(),
+        },
+    ]);
+    assert_eq!(tokens.schema_id("token-b"), Some("b"));
+    assert_eq!(tokens.schema_id("token-"), None);
+}
@@ -0,0 +1,276 @@
+//! Validation of JWT bearer tokens, like OIDC access tokens, with the keys of a JWKS file or of a
+//! PEM public key.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_web::http::header::{AUTHORIZATION, HeaderMap};
+use bytes::Bytes;
+use common::config::ConfigError;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use serde_json::{Map, Value, json};
+
+use crate::config::{HeaderNames, JwtConfig};
+use crate::error::{Error, Result};
+use crate::forward::wildcard_matches;
+use crate::reload::Reloadable;
+
+type Claims = Map<String, Value>;
+
+/// The keys of a JWKS file, with their key id.
+pub struct Jwks(Vec<(Option<String>, DecodingKey)>);
+
+impl Jwks {
+    pub fn load(path: &Path) -> Result<Jwks> {
+        let invalid = |e: &dyn fmt::Display| {
+            Error::from(ConfigError::Invalid(format!(
+                "Could not load JWKS '{}': {}",
+                path.display(),
+                e
+            )))
+        };
+        let jwk_set: JwkSet = serde_json::from_slice(&fs::read(path)?).map_err(|e| invalid(&e))?;
+        let mut keys = Vec::with_capacity(jwk_set.keys.len());
+        for jwk in &jwk_set.keys {
+            let key = DecodingKey::from_jwk(jwk).map_err(|e| invalid(&e))?;
+            keys.push((jwk.common.key_id.clone(), key));
+        }
+        Ok(Jwks(keys))
+    }
+
+    /// The keys with the key id of a token, or all of them for tokens without one.
+    fn candidates<'a>(&'a self, kid: Option<&'a str>) -> impl Iterator<Item = &'a DecodingKey> {
+        self.0
+            .iter()
+            .filter(move |(key_id, _)| kid.is_none() || key_id.as_deref() == kid)
+            .map(|(_, key)| key)
+    }
+}
+
+enum Keys {
+    Jwks(Arc<Reloadable<Jwks>>),
+    Pem(DecodingKey),
+}
+
+pub struct JwtValidator {
+    keys: Keys,
+    validation: Validation,
+    required_claims: Vec<String>,
+    forward_claims: Vec<String>,
+    schemas_claim: Option<String>,
+}
+
+impl JwtValidator {
+    pub fn new(config: &JwtConfig) -> Result<JwtValidator> {
+        let invalid = |s: &str| Error::from(ConfigError::Invalid(format!("'jwt' {}", s)));
+        let keys = match (&config.jwks_file, &config.pem) {
+            (Some(jwks_file), None) => Keys::Jwks(Reloadable::load(jwks_file, Jwks::load)?),
+            (None, Some(pem)) => Keys::Pem(pem_key(pem, &config.algorithms).map_err(invalid)?),
+            _ => return Err(invalid("requires either 'jwks_file' or 'pem'")),
+        };
+
+        let mut validation = Validation::new(*config.algorithms.first());
+        validation.algorithms = config.algorithms.to_vec();
+        validation.leeway = config.leeway_seconds;
+        validation.set_required_spec_claims(&["exp"]);
+        if let Some(issuer) = &config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        Ok(JwtValidator {
+            keys,
+            validation,
+            required_claims: config.required_claims.clone(),
+            forward_claims: config.forward_claims.clone(),
+            schemas_claim: config.schemas_claim.clone(),
+        })
+    }
+
+    /// The JWKS to watch for changes, if the keys are from a file.
+    pub fn jwks(&self) -> Option<&Arc<Reloadable<Jwks>>> {
+        match &self.keys {
+            Keys::Jwks(jwks) => Some(jwks),
+            Keys::Pem(_) => None,
+        }
+    }
+
+    /// Validates a token for a request to `schema_id`, and returns the claims to forward as Kafka
+    /// headers.
+    pub fn validate(
+        &self,
+        token: &str,
+        schema_id: &str,
+        header_names: &HeaderNames,
+    ) -> std::result::Result<Vec<(String, Bytes)>, JwtError> {
+        let claims = self.claims(token)?;
+        if let Some(claim) = self
+            .required_claims
+            .iter()
+            .find(|c| !claims.contains_key(*c))
+        {
+            return Err(JwtError::MissingClaim(claim.clone()));
+        }
+        if let Some(schemas_claim) = &self.schemas_claim {
+            let allowed = match claims.get(schemas_claim) {
+                Some(Value::String(patterns)) => patterns
+                    .split_whitespace()
+                    .any(|pattern| wildcard_matches(pattern, schema_id)),
+                Some(Value::Array(patterns)) => patterns
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .any(|pattern| wildcard_matches(pattern, schema_id)),
+                _ => false,
+            };
+            if !allowed {
+                return Err(JwtError::Forbidden);
+            }
+        }
+        Ok(self
+            .forward_claims
+            .iter()
+            .filter_map(|name| {
+                let value = match claims.get(name)? {
+                    Value::String(s) => Bytes::from(s.clone()),
+                    value => Bytes::from(value.to_string()),
+                };
+                Some((header_names.jwt_claim_prefix.clone() + name, value))
+            })
+            .collect())
+    }
+
+    fn claims(&self, token: &str) -> std::result::Result<Claims, JwtError> {
+        let header = decode_header(token).map_err(|_| JwtError::InvalidToken)?;
+        let jwks;
+        let candidates: Vec<&DecodingKey> = match &self.keys {
+            Keys::Jwks(reloadable) => {
+                jwks = reloadable.get();
+                jwks.candidates(header.kid.as_deref()).collect()
+            }
+            Keys::Pem(key) => vec![key],
+        };
+        if candidates.is_empty() {
+            return Err(JwtError::UnknownKey);
+        }
+        for key in candidates {
+            match decode::<Claims>(token, key, &self.validation) {
+                Ok(token_data) => return Ok(token_data.claims),
+                // the claims are only validated once the signature is
+                Err(e) => match e.kind() {
+                    ErrorKind::ExpiredSignature => return Err(JwtError::Expired),
+                    ErrorKind::ImmatureSignature
+                    | ErrorKind::InvalidIssuer
+                    | ErrorKind::InvalidAudience
+                    | ErrorKind::InvalidSubject => return Err(JwtError::InvalidClaims),
+                    ErrorKind::MissingRequiredClaim(claim) => {
+                        return Err(JwtError::MissingClaim(claim.clone()));
+                    }
+                    _ => continue,
+                },
+            }
+        }
+        Err(JwtError::InvalidSignature)
+    }
+}
+
+/// The key of a PEM, for the family of the configured algorithms.
+fn pem_key(pem: &str, algorithms: &[Algorithm]) -> std::result::Result<DecodingKey, &'static str> {
+    use Algorithm::*;
+
+    let pem = pem.as_bytes();
+    let key = if algorithms
+        .iter()
+        .all(|a| matches!(a, RS256 | RS384 | RS512 | PS256 | PS384 | PS512))
+    {
+        DecodingKey::from_rsa_pem(pem)
+    } else if algorithms.iter().all(|a| matches!(a, ES256 | ES384)) {
+        DecodingKey::from_ec_pem(pem)
+    } else if algorithms.iter().all(|a| *a == EdDSA) {
+        DecodingKey::from_ed_pem(pem)
+    } else {
+        return Err("with a 'pem' requires algorithms of the same RS and PS, ES or EdDSA family");
+    };
+    key.map_err(|_| "has an invalid 'pem' for its algorithms")
+}
+
+/// The token of an `Authorization: Bearer` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .split_once(' ')
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+}
+
+/// The claims forwarded as Kafka headers, in the request extensions.
+pub struct ForwardedClaims(pub Vec<(String, Bytes)>);
+
+/// The reasons tokens are rejected, responded to with their status and JSON.
+#[derive(Debug, PartialEq)]
+pub enum JwtError {
+    MissingToken,
+    InvalidToken,
+    UnknownKey,
+    InvalidSignature,
+    Expired,
+    /// The issuer, audience or not before time are not valid.
+    InvalidClaims,
+    MissingClaim(String),
+    /// The schemas claim does not allow the schema.
+    Forbidden,
+}
+
+impl JwtError {
+    pub fn status(&self) -> u16 {
+        match self {
+            JwtError::Forbidden => 403,
+            _ => 401,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            JwtError::MissingToken => "missing_token",
+            JwtError::InvalidToken => "invalid_token",
+            JwtError::UnknownKey => "unknown_key",
+            JwtError::InvalidSignature => "invalid_signature",
+            JwtError::Expired => "expired_token",
+            JwtError::InvalidClaims => "invalid_claims",
+            JwtError::MissingClaim(_) => "missing_claim",
+            JwtError::Forbidden => "forbidden",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"error": self.code(), "message": self.to_string()})
+    }
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtError::MissingToken => f.write_str("Bearer token is required"),
+            JwtError::InvalidToken => f.write_str("Invalid token"),
+            JwtError::UnknownKey => f.write_str("Token signed with an unknown key"),
+            JwtError::InvalidSignature => f.write_str("Invalid token signature"),
+            JwtError::Expired => f.write_str("Token is expired"),
+            JwtError::InvalidClaims => f.write_str("Invalid token issuer, audience or not before"),
+            JwtError::MissingClaim(claim) => write!(f, "Token is missing the '{}' claim", claim),
+            JwtError::Forbidden => f.write_str("Token is not allowed for the schema"),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+#[cfg(test)]
+mod test;
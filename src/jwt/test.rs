@@ -0,0 +1,152 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use serde_json::{Value, json};
+use vec1::vec1;
+
+use crate::config::{HeaderNames, JwtConfig};
+
+use super::{JwtError, JwtValidator};
+
+const SECRET: &[u8] = b"a shared secret of thirty six bytes.";
+
+fn jwks_file(name: &str) -> String {
+    let path =
+        std::env::temp_dir().join(format!("ingest-jwks-test-{}-{}", name, std::process::id()));
+    let jwks = json!({"keys": [{
+        "kty": "oct",
+        "kid": "k1",
+        "alg": "HS256",
+        // base64url of SECRET
+        "k": "YSBzaGFyZWQgc2VjcmV0IG9mIHRoaXJ0eSBzaXggYnl0ZXMu"
+    }]});
+    fs::write(&path, jwks.to_string()).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+fn config(jwks_file: String) -> JwtConfig {
+    JwtConfig {
+        algorithms: vec1![Algorithm::HS256],
+        jwks_file: Some(jwks_file),
+        pem: None,
+        issuer: Some("https://issuer".to_owned()),
+        audience: Some("ingest".to_owned()),
+        required_claims: vec!["sub".to_owned()],
+        leeway_seconds: 0,
+        forward_claims: vec!["sub".to_owned(), "tenant".to_owned()],
+        schemas_claim: Some("schemas".to_owned()),
+    }
+}
+
+fn token(kid: Option<&str>, claims: Value) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut all_claims = json!({
+        "iss": "https://issuer",
+        "aud": "ingest",
+        "exp": now + 60,
+        "sub": "app-1",
+        "tenant": 7,
+        "schemas": "logs-* 1"
+    });
+    // null removes a claim
+    for (name, value) in claims.as_object().unwrap() {
+        if value.is_null() {
+            all_claims.as_object_mut().unwrap().remove(name);
+        } else {
+            all_claims[name] = value.clone();
+        }
+    }
+    let header = Header {
+        kid: kid.map(str::to_owned),
+        ..Header::new(Algorithm::HS256)
+    };
+    encode(&header, &all_claims, &EncodingKey::from_secret(SECRET)).unwrap()
+}
+
+#[test]
+fn test_validate() {
+    let validator = JwtValidator::new(&config(jwks_file("validate"))).unwrap();
+    let header_names = HeaderNames::default();
+    assert_eq!(
+        validator.validate(&token(Some("k1"), json!({})), "logs-app", &header_names),
+        Ok(vec![
+            (
+                "ncube-ingest-jwt-claim-sub".to_owned(),
+                Bytes::from("app-1")
+            ),
+            ("ncube-ingest-jwt-claim-tenant".to_owned(), Bytes::from("7")),
+        ])
+    );
+    // tokens without a key id are tried with all the keys
+    assert!(
+        validator
+            .validate(&token(None, json!({})), "1", &header_names)
+            .is_ok()
+    );
+    assert_eq!(
+        validator.validate(&token(Some("k2"), json!({})), "1", &header_names),
+        Err(JwtError::UnknownKey)
+    );
+}
+
+#[test]
+fn test_validate_rejected() {
+    let validator = JwtValidator::new(&config(jwks_file("rejected"))).unwrap();
+    let header_names = HeaderNames::default();
+    let validate = |claims: Value, schema_id: &str| {
+        validator.validate(&token(Some("k1"), claims), schema_id, &header_names)
+    };
+    assert_eq!(validate(json!({"exp": 1}), "1"), Err(JwtError::Expired));
+    assert_eq!(
+        validate(json!({"iss": "https://other"}), "1"),
+        Err(JwtError::InvalidClaims)
+    );
+    assert_eq!(
+        validate(json!({"aud": "other"}), "1"),
+        Err(JwtError::InvalidClaims)
+    );
+    assert_eq!(
+        validate(json!({"sub": null}), "1"),
+        Err(JwtError::MissingClaim("sub".to_owned()))
+    );
+    assert_eq!(
+        validate(json!({"exp": null}), "1"),
+        Err(JwtError::MissingClaim("exp".to_owned()))
+    );
+    assert_eq!(validate(json!({}), "2"), Err(JwtError::Forbidden));
+    assert_eq!(validate(json!({"schemas": ["2"]}), "2").map(|_| ()), Ok(()));
+    assert_eq!(
+        validator.validate("not a token", "1", &header_names),
+        Err(JwtError::InvalidToken)
+    );
+    let tampered = token(Some("k1"), json!({})) + "x";
+    assert_eq!(
+        validator.validate(&tampered, "1", &header_names),
+        Err(JwtError::InvalidSignature)
+    );
+}
+
+#[test]
+fn test_config() {
+    let jwks_file = jwks_file("config");
+    assert!(
+        JwtValidator::new(&JwtConfig {
+            pem: Some("-----BEGIN PUBLIC KEY-----".to_owned()),
+            ..config(jwks_file.clone())
+        })
+        .is_err()
+    );
+    assert!(
+        JwtValidator::new(&JwtConfig {
+            jwks_file: None,
+            pem: Some("-----BEGIN PUBLIC KEY-----".to_owned()),
+            ..config(jwks_file)
+        })
+        .is_err()
+    );
+}
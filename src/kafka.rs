@@ -2,39 +2,210 @@
 
 use bytes::Bytes;
 use common::config::ConfigError;
-use rdkafka::error::KafkaError;
-use rdkafka::message::{DeliveryResult, Header, OwnedHeaders};
+use futures::future;
+use futures::task::AtomicWaker;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{BaseRecord, ProducerContext, ThreadedProducer};
-use rdkafka::{ClientConfig, ClientContext, producer::Producer, util::Timeout};
-use std::collections::HashMap;
+use rdkafka::{ClientConfig, ClientContext, Message, producer::Producer as _, util::Timeout};
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tracing::{error, instrument, trace};
 
+use crate::circuit_breaker::CircuitBreaker;
 use crate::config::Config;
 use crate::error::{Error, Result};
 
-struct ProducerCtx;
+/// The records packed in a Kafka message.
+#[derive(Clone, Copy, Debug)]
+pub struct Records {
+    pub count: u64,
+    pub bytes: usize,
+}
+
+impl Records {
+    pub fn single(bytes: usize) -> Records {
+        Records { count: 1, bytes }
+    }
+}
+
+/// Reported back on the delivery channel once a message is delivered.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub records: Records,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since the epoch, when the message has a timestamp
+    pub timestamp: Option<i64>,
+}
+
+pub type DeliveryResult = std::result::Result<Delivery, KafkaError>;
+
+/// The error of the messages not sent because the circuit of their producer is open.
+pub const CIRCUIT_OPEN: KafkaError =
+    KafkaError::MessageProduction(RDKafkaErrorCode::AllBrokersDown);
+
+/// Whether the cluster of a producer is unavailable, which clients can retry later.
+pub fn is_unavailable(error: &KafkaError) -> bool {
+    error.rdkafka_error_code() == Some(RDKafkaErrorCode::AllBrokersDown)
+}
+
+/// Creates the delivery state of a request, which its messages report their delivery result to.
+///
+/// Reporting a result never blocks the producer poll thread, which serves all requests: it is
+/// queued and the receiver woken. The queue holds at most the results of the messages a request
+/// has in flight, which librdkafka bounds with `queue.buffering.max.messages`.
+pub fn deliveries() -> (DeliverySender, DeliveryReceiver) {
+    let shared = Arc::new(Deliveries {
+        senders: AtomicUsize::new(1),
+        results: Mutex::new(VecDeque::new()),
+        waker: AtomicWaker::new(),
+        closed: AtomicBool::new(false),
+    });
+    (DeliverySender(shared.clone()), DeliveryReceiver(shared))
+}
+
+/// The delivery state of a request, shared by its sender handles, the messages it has in flight
+/// and its receiver.
+struct Deliveries {
+    // sender handles, including those of the messages in flight
+    senders: AtomicUsize,
+    results: Mutex<VecDeque<DeliveryResult>>,
+    waker: AtomicWaker,
+    // set once the receiver is dropped, after which results are dropped
+    closed: AtomicBool,
+}
+
+impl Deliveries {
+    fn results(&self) -> MutexGuard<'_, VecDeque<DeliveryResult>> {
+        // the lock is only held to push or pop a result, so it is not poisoned
+        self.results.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub struct DeliverySender(Arc<Deliveries>);
+
+impl DeliverySender {
+    /// Reports a delivery result without blocking. The result is dropped when the request is
+    /// gone, like when its client disconnected.
+    pub fn send(&self, result: DeliveryResult) {
+        if self.0.closed.load(Ordering::Acquire) {
+            trace!("Delivery result dropped, the request is gone");
+            return;
+        }
+        self.0.results().push_back(result);
+        self.0.waker.wake();
+    }
+}
+
+impl Clone for DeliverySender {
+    fn clone(&self) -> DeliverySender {
+        self.0.senders.fetch_add(1, Ordering::Relaxed);
+        DeliverySender(self.0.clone())
+    }
+}
+
+impl Drop for DeliverySender {
+    fn drop(&mut self) {
+        if self.0.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.waker.wake();
+        }
+    }
+}
+
+pub struct DeliveryReceiver(Arc<Deliveries>);
+
+impl DeliveryReceiver {
+    /// The next delivery result, or None once all the senders are dropped and the results
+    /// received.
+    pub async fn recv(&mut self) -> Option<DeliveryResult> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<DeliveryResult>> {
+        if let Some(result) = self.try_recv() {
+            return Poll::Ready(Some(result));
+        }
+        self.0.waker.register(cx.waker());
+        // a sender reports its results before it is dropped, so once none are left the results
+        // are all queued
+        let done = self.0.senders.load(Ordering::Acquire) == 0;
+        match self.try_recv() {
+            Some(result) => Poll::Ready(Some(result)),
+            None if done => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+
+    /// The next delivery result if one is ready.
+    pub fn try_recv(&mut self) -> Option<DeliveryResult> {
+        self.0.results().pop_front()
+    }
+}
+
+impl Drop for DeliveryReceiver {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+        self.0.results().clear();
+    }
+}
+
+/// The delivery opaque of a message: the delivery state of its request, and the records it
+/// packs.
+struct InFlight {
+    delivery_tx: DeliverySender,
+    records: Records,
+}
 
-impl ClientContext for ProducerCtx {}
+struct ProducerCtx {
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl ClientContext for ProducerCtx {
+    fn error(&self, error: KafkaError, reason: &str) {
+        if let Some(circuit_breaker) = &self.circuit_breaker
+            && is_unavailable(&error)
+        {
+            circuit_breaker.open();
+        }
+        error!(target: "librdkafka", "librdkafka: {}: {}", error, reason);
+    }
+}
 
 impl ProducerContext for ProducerCtx {
-    type DeliveryOpaque = Box<mpsc::Sender<std::result::Result<usize, KafkaError>>>;
+    type DeliveryOpaque = Box<InFlight>;
 
     fn delivery(
         &self,
-        delivery_result: &DeliveryResult<'_>,
+        delivery_result: &rdkafka::message::DeliveryResult<'_>,
         delivery_opaque: Self::DeliveryOpaque,
     ) {
+        let InFlight {
+            delivery_tx,
+            records,
+        } = *delivery_opaque;
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            match delivery_result {
+                Ok(_) => circuit_breaker.record_success(),
+                Err(_) => circuit_breaker.record_failure(),
+            }
+        }
         let delivery_message = match delivery_result {
-            Ok(msg) => Ok(msg.payload_len()),
+            Ok(message) => Ok(Delivery {
+                records,
+                topic: message.topic().to_owned(),
+                partition: message.partition(),
+                offset: message.offset(),
+                timestamp: message.timestamp().to_millis(),
+            }),
             Err(e) => Err(e.0.clone()),
         };
-        if delivery_opaque.blocking_send(delivery_message).is_err() {
-            panic!("Could not send delivery result, the delivery result channel has been closed")
-        }
+        delivery_tx.send(delivery_message);
     }
 }
 
@@ -46,12 +217,36 @@ type KafkaProducer = ThreadedProducer<ProducerCtx>;
 pub struct Kafka(Arc<KafkaInner>);
 
 pub struct KafkaInner {
-    producers: HashMap<String, KafkaProducer>,
+    producers: HashMap<String, Producer>,
+    failover_header: String,
+}
+
+struct Producer {
+    producer: KafkaProducer,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Name of the producer to send with while the circuit is open
+    failover: Option<String>,
+}
+
+impl Producer {
+    /// Whether messages can be sent, which takes the trial of a half open circuit.
+    fn allow(&self) -> bool {
+        self.circuit_breaker
+            .as_ref()
+            .is_none_or(|circuit_breaker| circuit_breaker.allow())
+    }
+
+    /// Releases the trial taken by `allow`, when the message was refused before being queued.
+    fn release_trial(&self) {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.release_trial();
+        }
+    }
 }
 
 impl Kafka {
     pub fn start(config: &Config) -> Result<Kafka> {
-        let mut producers: HashMap<String, KafkaProducer> = HashMap::new();
+        let mut producers: HashMap<String, Producer> = HashMap::new();
         for librdkafka_config in &config.librdkafka {
             let mut producer_config = ClientConfig::new();
 
@@ -64,7 +259,17 @@ impl Kafka {
                 producer_config.set(key, value);
             }
 
-            let producer = producer_config.create_with_context(ProducerCtx {})?;
+            let circuit_breaker = librdkafka_config
+                .circuit_breaker
+                .as_ref()
+                .map(|config| Arc::new(CircuitBreaker::new(config)));
+            let producer = Producer {
+                producer: producer_config.create_with_context(ProducerCtx {
+                    circuit_breaker: circuit_breaker.clone(),
+                })?,
+                circuit_breaker,
+                failover: librdkafka_config.failover.clone(),
+            };
             if producers
                 .insert(librdkafka_config.name.to_owned(), producer)
                 .is_some()
@@ -76,10 +281,31 @@ impl Kafka {
             }
         }
 
-        Ok(Self(Arc::new(KafkaInner { producers })))
+        for (name, producer) in &producers {
+            let Some(failover) = &producer.failover else {
+                continue;
+            };
+            if producer.circuit_breaker.is_none() {
+                return Err(Error::from(ConfigError::Invalid(format!(
+                    "Librdkafka configuration '{}' has a failover but no circuit_breaker",
+                    name
+                ))));
+            }
+            if failover == name || !producers.contains_key(failover) {
+                return Err(Error::from(ConfigError::Invalid(format!(
+                    "Failover '{}' of librdkafka configuration '{}' is not another librdkafka configuration",
+                    failover, name
+                ))));
+            }
+        }
+
+        Ok(Self(Arc::new(KafkaInner {
+            producers,
+            failover_header: config.headers.failover.clone(),
+        })))
     }
 
-    fn get_producer(&self, name: &str) -> &KafkaProducer {
+    fn get_producer(&self, name: &str) -> &Producer {
         &self.0.producers[name]
     }
 
@@ -91,60 +317,126 @@ impl Kafka {
             .collect::<Vec<&str>>()
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn send(
+        &self,
+        data: &[u8],
+        key: Option<&[u8]>,
+        headers: &[(String, Bytes)],
+        topic: &str,
+        producer_name: &str,
+        records: Records,
+        delivery_tx: DeliverySender,
+    ) -> std::result::Result<(), KafkaError> {
+        // an empty key with partitioner:consistent_random will randomly distribute across
+        // the partitions. only the gRPC endpoint lets clients set the key
+        // XXX: figure out how to specify key over HTTP
+        // options:
+        // 1. support passing the partition key as a header in the POST request
+        // 2. specify it as a json attribute. would need to parse json
+        // 3. how to specify it in newline-delimited json?
+        // 4. support adding it in python request processor?
+        // 5. should not employ partitioning in actix web workers. (cannot. they are given some
+        // kind of connection object before the data is even read). but in general should not
+        // if a client is spamming requests for the same partition, client should switch to streaming
+        self.send_to_partition(
+            Some(data),
+            Some(key.unwrap_or_default()),
+            headers,
+            topic,
+            None,
+            producer_name,
+            records,
+            delivery_tx,
+        )
+    }
+
+    /// Sends a message to a partition, or to the one chosen by the partitioner when None. A
+    /// message without payload is a tombstone.
     #[instrument(
         level = "trace",
         name = "send_kafka_message",
-        skip(self, data, headers)
+        skip(self, data, key, headers, delivery_tx)
     )]
-    pub fn send(
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_to_partition(
         &self,
-        data: &[u8],
+        data: Option<&[u8]>,
+        key: Option<&[u8]>,
         headers: &[(String, Bytes)],
         topic: &str,
+        partition: Option<i32>,
         producer_name: &str,
-        delivery_tx: mpsc::Sender<std::result::Result<usize, KafkaError>>,
+        records: Records,
+        delivery_tx: DeliverySender,
     ) -> std::result::Result<(), KafkaError> {
-        let mut kafka_headers = OwnedHeaders::new_with_capacity(headers.len());
+        // the failover is only used while its own circuit is closed
+        let mut producer = self.get_producer(producer_name);
+        let mut failover_from = None;
+        if !producer.allow() {
+            match producer.failover.as_deref() {
+                Some(failover) if self.get_producer(failover).allow() => {
+                    producer = self.get_producer(failover);
+                    failover_from = Some(producer_name);
+                }
+                _ => return Err(CIRCUIT_OPEN),
+            }
+        }
+
+        let mut kafka_headers = OwnedHeaders::new_with_capacity(headers.len() + 1);
         for (key, val) in headers {
             kafka_headers = kafka_headers.insert(Header {
                 key,
                 value: Some(val.as_ref()),
             });
         }
+        if let Some(failover_from) = failover_from {
+            kafka_headers = kafka_headers.insert(Header {
+                key: &self.0.failover_header,
+                value: Some(failover_from),
+            });
+        }
+
+        let mut record: BaseRecord<'_, [u8], [u8], _> = BaseRecord::with_opaque_to(
+            topic,
+            Box::new(InFlight {
+                delivery_tx,
+                records,
+            }),
+        )
+        .headers(kafka_headers);
+        record.payload = data;
+        record.key = key;
+        record.partition = partition;
 
-        let record = BaseRecord::with_opaque_to(topic, Box::new(delivery_tx))
-            .payload(data)
-            .headers(kafka_headers)
-            .key("")
-            // an empty key with partitioner:consistent_random will randomly distribute across
-            // the partitions
-            // XXX: figure out how to specify key
-            // options:
-            // 1. support passing the partition key as a header in the POST request
-            // 2. specify it as a json attribute. would need to parse json
-            // 3. how to specify it in newline-delimited json?
-            // 4. support adding it in python request processor?
-            // 5. should not employ partitioning in actix web workers. (cannot. they are given some
-            // kind of connection object before the data is even read). but in general should not
-            // if a client is spamming requests for the same partition, client should switch to streaming
-            ;
-
-        self.get_producer(producer_name)
-            .send(record)
-            .map_err(|(error, _)| error)?;
+        if let Err((error, _)) = producer.producer.send(record) {
+            producer.release_trial();
+            return Err(error);
+        }
         trace!(topic, "Message successfully sent to kafka broker");
         Ok(())
     }
 
-    pub fn stop(self) {
+    /// Flushes the producers within `timeout`, and returns the count of messages left unflushed
+    /// by the producers that could not flush them all.
+    pub fn stop(self, timeout: Duration) -> HashMap<String, i32> {
         trace!("Flushing kafka producers");
 
+        // the producers share the timeout
+        let deadline = Instant::now() + timeout;
+        let mut unflushed = HashMap::new();
         for (name, producer) in self.0.producers.iter() {
-            if let Err(e) = producer.flush(Timeout::After(Duration::from_secs(30))) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Err(e) = producer.producer.flush(Timeout::After(remaining)) {
                 error!("Flushing kafka producer '{}' failed with error {}", name, e);
+                unflushed.insert(name.clone(), producer.producer.in_flight_count());
             }
         }
 
         trace!("Done flushing kafka producers");
+        unflushed
     }
 }
+
+#[cfg(test)]
+mod test;
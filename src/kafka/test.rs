@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use rdkafka::error::KafkaError;
+use rdkafka::types::RDKafkaErrorCode;
+
+use super::{Delivery, Records, deliveries};
+
+fn delivery(offset: i64) -> Delivery {
+    Delivery {
+        records: Records::single(3),
+        topic: "test".to_owned(),
+        partition: 0,
+        offset,
+        timestamp: None,
+    }
+}
+
+#[tokio::test]
+async fn test_deliveries_received_until_senders_dropped() {
+    let (delivery_tx, mut delivery_rx) = deliveries();
+    let other_tx = delivery_tx.clone();
+    delivery_tx.send(Ok(delivery(1)));
+    drop(delivery_tx);
+    // reported from a thread, like the producer poll thread
+    std::thread::spawn(move || {
+        other_tx.send(Err(KafkaError::MessageProduction(
+            RDKafkaErrorCode::MessageTimedOut,
+        )))
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(delivery_rx.recv().await.unwrap().unwrap().offset, 1);
+    assert!(delivery_rx.recv().await.unwrap().is_err());
+    assert!(delivery_rx.recv().await.is_none());
+}
+
+#[test]
+fn test_deliveries_try_recv() {
+    let (delivery_tx, mut delivery_rx) = deliveries();
+    assert!(delivery_rx.try_recv().is_none());
+    delivery_tx.send(Ok(delivery(2)));
+    assert_eq!(delivery_rx.try_recv().unwrap().unwrap().offset, 2);
+    assert!(delivery_rx.try_recv().is_none());
+}
+
+#[test]
+fn test_deliveries_dropped_once_receiver_gone() {
+    let (delivery_tx, delivery_rx) = deliveries();
+    drop(delivery_rx);
+    // a request cancelled before its messages are delivered
+    delivery_tx.send(Ok(delivery(3)));
+}
+
+#[tokio::test]
+async fn test_deliveries_wake_receiver() {
+    let (delivery_tx, mut delivery_rx) = deliveries();
+    // the receiver waits for the result before it is reported
+    let poll_thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        delivery_tx.send(Ok(delivery(4)));
+    });
+    assert_eq!(delivery_rx.recv().await.unwrap().unwrap().offset, 4);
+    assert!(delivery_rx.recv().await.is_none());
+    poll_thread.join().unwrap();
+}
@@ -1,10 +1,30 @@
 pub use config::Config;
 pub use server::Server;
 
-mod kafka;
+mod admission;
+mod auth;
+mod batching;
+mod circuit_breaker;
+mod cloudevents;
+mod elasticsearch;
+mod enrich;
+mod filter;
+mod forward;
+mod hec;
+mod jwt;
+mod metrics;
+mod receipts;
+mod redact;
+mod reload;
+mod rest_proxy;
+mod signature;
+mod syslog;
 
 pub mod config;
 pub mod error;
+pub mod framing;
+pub mod kafka;
+pub mod proto;
 pub mod python;
 pub mod server;
 
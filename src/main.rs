@@ -1,10 +1,15 @@
+use std::process::ExitCode;
+
 use common::config::CommonConfig;
 use tokio::signal;
 use tracing::{debug, info};
 
 use ingest::{Config, Server, error::Result};
 
-fn main() -> Result<()> {
+/// The exit code when messages are left unflushed on shutdown.
+const UNFLUSHED_EXIT_CODE: u8 = 2;
+
+fn main() -> Result<ExitCode> {
     let config = Config::load()?;
     debug!("Config: {:?}", config);
 
@@ -20,7 +25,7 @@ fn main() -> Result<()> {
         .block_on(run(config))
 }
 
-async fn run(config: Config) -> Result<()> {
+async fn run(config: Config) -> Result<ExitCode> {
     let _guard = common::logging::init(
         config.logging.clone(),
         ingest::PKG_NAME,
@@ -33,9 +38,13 @@ async fn run(config: Config) -> Result<()> {
 
     close_signal().await?;
     info!("Shutting down...");
-    server.stop().await;
+    let unflushed = server.stop().await;
 
-    Ok(())
+    if unflushed.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(UNFLUSHED_EXIT_CODE))
+    }
 }
 
 #[cfg(windows)]
@@ -0,0 +1,66 @@
+//! Application metrics, exported through the global OpenTelemetry meter provider.
+
+use opentelemetry::metrics::{Counter, UpDownCounter};
+use opentelemetry::{KeyValue, global};
+
+/// Attribute with the schema id, the same one the HTTP metrics use.
+pub const SCHEMA_ID_ATTRIBUTE: &str = "ingest.schema.id";
+
+/// Needs to be created after the meter provider is set up.
+#[derive(Clone)]
+pub struct Metrics {
+    pub redacted_fields: Counter<u64>,
+    pub dropped_messages: Counter<u64>,
+    pub socket_dropped_records: Counter<u64>,
+    pub in_flight_bytes: UpDownCounter<i64>,
+    pub rejected_requests: Counter<u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let meter = global::meter(crate::PKG_NAME);
+        Metrics {
+            redacted_fields: meter
+                .u64_counter("ingest.redacted_fields")
+                .with_description("Number of JSON fields dropped, masked or hashed by redaction")
+                .build(),
+            dropped_messages: meter
+                .u64_counter("ingest.dropped_messages")
+                .with_description("Number of messages dropped by filter rules")
+                .build(),
+            socket_dropped_records: meter
+                .u64_counter("ingest.socket.dropped_records")
+                .with_description(
+                    "Number of records received by TCP or UDP listeners that were not sent to Kafka",
+                )
+                .build(),
+            in_flight_bytes: meter
+                .i64_up_down_counter("ingest.memory_budget.in_flight_bytes")
+                .with_description("Bytes of request bodies admitted by the memory budget")
+                .with_unit("By")
+                .build(),
+            rejected_requests: meter
+                .u64_counter("ingest.memory_budget.rejected_requests")
+                .with_description("Number of requests rejected because the memory budget was exhausted")
+                .build(),
+        }
+    }
+
+    /// `reason` is one of `invalid`, `too_large` or `kafka`.
+    pub fn record_socket_dropped(&self, schema_id: &str, reason: &'static str) {
+        self.socket_dropped_records.add(
+            1,
+            &[
+                KeyValue::new(SCHEMA_ID_ATTRIBUTE, schema_id.to_owned()),
+                KeyValue::new("ingest.drop.reason", reason),
+            ],
+        );
+    }
+
+    pub fn record_dropped(&self, count: u64, schema_id: &str) {
+        self.dropped_messages.add(
+            count,
+            &[KeyValue::new(SCHEMA_ID_ATTRIBUTE, schema_id.to_owned())],
+        );
+    }
+}
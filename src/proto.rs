@@ -0,0 +1,3 @@
+//! The gRPC ingest service and messages generated from `proto/ingest.proto`, including a client.
+
+tonic::include_proto!("ingest.v1");
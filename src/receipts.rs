@@ -0,0 +1,96 @@
+//! Delivery receipts of the requests acknowledged before their messages are delivered.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptStatus {
+    Pending,
+    Delivered,
+    /// At least one message failed to be delivered
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Receipt {
+    pub receipt_id: String,
+    pub status: ReceiptStatus,
+    pub schema_id: String,
+    /// Messages accepted with the request
+    pub message_count: u64,
+    pub delivered_count: u64,
+    /// The first delivery error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A bounded store of receipts, which drops the oldest receipts once full.
+pub struct Receipts {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    receipts: HashMap<String, Receipt>,
+    // the receipt ids, oldest first
+    order: VecDeque<String>,
+}
+
+impl Receipts {
+    pub fn new(capacity: usize) -> Receipts {
+        Receipts {
+            capacity,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Creates a pending receipt and returns its id.
+    pub fn create(&self, schema_id: &str, message_count: u64, delivered_count: u64) -> String {
+        let receipt_id = Uuid::new_v4().to_string();
+        let receipt = Receipt {
+            receipt_id: receipt_id.clone(),
+            status: ReceiptStatus::Pending,
+            schema_id: schema_id.to_owned(),
+            message_count,
+            delivered_count,
+            error: None,
+        };
+        let mut inner = self.inner.lock().expect("Receipts lock is not poisoned");
+        while inner.order.len() >= self.capacity.max(1) {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.receipts.remove(&oldest);
+            }
+        }
+        inner.order.push_back(receipt_id.clone());
+        inner.receipts.insert(receipt_id.clone(), receipt);
+        receipt_id
+    }
+
+    /// Records the outcome of the deliveries that were pending when the receipt was created. Does
+    /// nothing for receipts dropped in the meantime.
+    pub fn complete(&self, receipt_id: &str, delivered_count: u64, error: Option<String>) {
+        let mut inner = self.inner.lock().expect("Receipts lock is not poisoned");
+        if let Some(receipt) = inner.receipts.get_mut(receipt_id) {
+            receipt.delivered_count += delivered_count;
+            receipt.status = if error.is_some() {
+                ReceiptStatus::Failed
+            } else {
+                ReceiptStatus::Delivered
+            };
+            receipt.error = error;
+        }
+    }
+
+    pub fn get(&self, receipt_id: &str) -> Option<Receipt> {
+        let inner = self.inner.lock().expect("Receipts lock is not poisoned");
+        inner.receipts.get(receipt_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,40 @@
+use super::{ReceiptStatus, Receipts};
+
+#[test]
+fn test_receipt_lifecycle() {
+    let receipts = Receipts::new(10);
+    let receipt_id = receipts.create("1", 3, 1);
+    let receipt = receipts.get(&receipt_id).unwrap();
+    assert_eq!(receipt.status, ReceiptStatus::Pending);
+    assert_eq!(receipt.schema_id, "1");
+    assert_eq!(receipt.message_count, 3);
+    assert_eq!(receipt.delivered_count, 1);
+
+    receipts.complete(&receipt_id, 2, None);
+    let receipt = receipts.get(&receipt_id).unwrap();
+    assert_eq!(receipt.status, ReceiptStatus::Delivered);
+    assert_eq!(receipt.delivered_count, 3);
+
+    let receipt_id = receipts.create("1", 2, 0);
+    receipts.complete(&receipt_id, 1, Some("Message timed out".to_owned()));
+    let receipt = receipts.get(&receipt_id).unwrap();
+    assert_eq!(receipt.status, ReceiptStatus::Failed);
+    assert_eq!(receipt.error.as_deref(), Some("Message timed out"));
+
+    assert_eq!(receipts.get("unknown"), None);
+}
+
+#[test]
+fn test_oldest_receipts_dropped() {
+    let receipts = Receipts::new(2);
+    let first = receipts.create("1", 1, 0);
+    let second = receipts.create("1", 1, 0);
+    let third = receipts.create("1", 1, 0);
+    assert_eq!(receipts.get(&first), None);
+    assert!(receipts.get(&second).is_some());
+    assert!(receipts.get(&third).is_some());
+
+    // completing a dropped receipt does nothing
+    receipts.complete(&first, 1, None);
+    assert_eq!(receipts.get(&first), None);
+}
@@ -0,0 +1,227 @@
+//! Dropping, masking and hashing of JSON message fields.
+
+use std::{fmt, fs};
+
+use bytes::Bytes;
+use common::config::ConfigError;
+use hmac::{Hmac, Mac};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::config::{RedactAction, RedactConfig};
+use crate::error::{Error, Result};
+use crate::metrics::SCHEMA_ID_ATTRIBUTE;
+
+const MASK: &str = "****";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug)]
+pub struct RedactError(serde_json::Error);
+
+impl fmt::Display for RedactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid JSON message: {}", self.0)
+    }
+}
+
+impl std::error::Error for RedactError {}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    /// `*`, any key or array index
+    Any,
+    /// `**`, any number of nested keys or array indexes, including none
+    AnyDepth,
+}
+
+/// Parses a JSON pointer (`/user/email`) or a dot separated glob path (`**.email`,
+/// `users.*.phone`).
+fn parse_path(path: &str) -> std::result::Result<Vec<Segment>, String> {
+    let segments: Vec<Segment> = if let Some(pointer) = path.strip_prefix('/') {
+        pointer
+            .split('/')
+            .map(|token| Segment::Key(token.replace("~1", "/").replace("~0", "~")))
+            .collect()
+    } else {
+        path.split('.')
+            .map(|segment| match segment {
+                "*" => Segment::Any,
+                "**" => Segment::AnyDepth,
+                key => Segment::Key(key.to_owned()),
+            })
+            .collect()
+    };
+    // paths need to end in a field to redact, empty keys are only possible in JSON pointers
+    let valid = match segments.last() {
+        None | Some(Segment::AnyDepth) => false,
+        Some(Segment::Key(key)) => !key.is_empty() || path.starts_with('/'),
+        Some(Segment::Any) => true,
+    };
+    if valid {
+        Ok(segments)
+    } else {
+        Err(format!("Invalid redaction path '{}'", path))
+    }
+}
+
+pub struct Redactor {
+    rules: Vec<(Vec<Segment>, RedactAction)>,
+    hmac_key: Option<Vec<u8>>,
+    redacted_fields: Counter<u64>,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactConfig, redacted_fields: Counter<u64>) -> Result<Redactor> {
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in &config.rules {
+            let segments = parse_path(&rule.path).map_err(ConfigError::Invalid)?;
+            rules.push((segments, rule.action.clone()));
+        }
+
+        let hmac_key = match &config.hmac_key_file {
+            Some(path) => Some(fs::read(path)?.trim_ascii().to_vec()),
+            None => None,
+        };
+        if hmac_key.is_none() && rules.iter().any(|(_, a)| *a == RedactAction::Hash) {
+            return Err(Error::from(ConfigError::Invalid(
+                "Redaction rules with the 'hash' action require 'hmac_key_file'".to_owned(),
+            )));
+        }
+
+        Ok(Redactor {
+            rules,
+            hmac_key,
+            redacted_fields,
+        })
+    }
+
+    /// Applies the rules to the JSON message. The message is returned as is if no field
+    /// matched.
+    pub fn redact(
+        &self,
+        message: Bytes,
+        schema_id: &str,
+    ) -> std::result::Result<Bytes, RedactError> {
+        let mut value: Value = serde_json::from_slice(&message).map_err(RedactError)?;
+        let mut count = 0;
+        for (segments, action) in &self.rules {
+            self.redact_value(&mut value, segments, action, &mut count);
+        }
+        if count == 0 {
+            return Ok(message);
+        }
+        self.redacted_fields.add(
+            count,
+            &[KeyValue::new(SCHEMA_ID_ATTRIBUTE, schema_id.to_owned())],
+        );
+        Ok(Bytes::from(
+            serde_json::to_vec(&value).map_err(RedactError)?,
+        ))
+    }
+
+    fn redact_value(
+        &self,
+        value: &mut Value,
+        segments: &[Segment],
+        action: &RedactAction,
+        count: &mut u64,
+    ) {
+        let Some((segment, rest)) = segments.split_first() else {
+            return;
+        };
+
+        if let Segment::AnyDepth = segment {
+            self.redact_value(value, rest, action, count);
+            for child in children(value) {
+                self.redact_value(child, segments, action, count);
+            }
+            return;
+        }
+
+        if !rest.is_empty() {
+            for child in matching_children(value, segment) {
+                self.redact_value(child, rest, action, count);
+            }
+            return;
+        }
+
+        match action {
+            RedactAction::Drop => *count += drop_matching(value, segment),
+            RedactAction::Mask | RedactAction::Hash => {
+                for child in matching_children(value, segment) {
+                    *child = match action {
+                        RedactAction::Hash => Value::String(self.hash(child)),
+                        _ => Value::String(MASK.to_owned()),
+                    };
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    fn hash(&self, value: &Value) -> String {
+        let key = self.hmac_key.as_deref().unwrap_or_default();
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        match value {
+            Value::String(s) => mac.update(s.as_bytes()),
+            other => mac.update(other.to_string().as_bytes()),
+        }
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+fn children(value: &mut Value) -> Vec<&mut Value> {
+    match value {
+        Value::Object(object) => object.values_mut().collect(),
+        Value::Array(array) => array.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn matching_children<'a>(value: &'a mut Value, segment: &Segment) -> Vec<&'a mut Value> {
+    match segment {
+        Segment::Key(key) => match value {
+            Value::Object(object) => object.get_mut(key).into_iter().collect(),
+            Value::Array(array) => key
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| array.get_mut(i))
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        },
+        Segment::Any | Segment::AnyDepth => children(value),
+    }
+}
+
+// returns the number of dropped fields
+fn drop_matching(value: &mut Value, segment: &Segment) -> u64 {
+    match (value, segment) {
+        (Value::Object(object), Segment::Key(key)) => u64::from(object.shift_remove(key).is_some()),
+        (Value::Array(array), Segment::Key(key)) => match key.parse::<usize>() {
+            Ok(i) if i < array.len() => {
+                array.remove(i);
+                1
+            }
+            _ => 0,
+        },
+        (Value::Object(object), _) => {
+            let count = object.len() as u64;
+            object.clear();
+            count
+        }
+        (Value::Array(array), _) => {
+            let count = array.len() as u64;
+            array.clear();
+            count
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test;
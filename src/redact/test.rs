@@ -0,0 +1,96 @@
+use std::fs;
+
+use bytes::Bytes;
+use opentelemetry::global;
+
+use crate::config::{RedactAction, RedactConfig, RedactRule};
+
+use super::{Redactor, parse_path};
+
+fn redactor(rules: &[(&str, RedactAction)], hmac_key: Option<&str>) -> Redactor {
+    let hmac_key_file = hmac_key.map(|key| {
+        let path = std::env::temp_dir().join(format!("ingest-redact-test-{}", std::process::id()));
+        fs::write(&path, format!("{}\n", key)).unwrap();
+        path.to_string_lossy().into_owned()
+    });
+    let config = RedactConfig {
+        rules: rules
+            .iter()
+            .map(|(path, action)| RedactRule {
+                path: (*path).to_owned(),
+                action: action.clone(),
+            })
+            .collect(),
+        hmac_key_file,
+    };
+    let counter = global::meter("test").u64_counter("test").build();
+    Redactor::new(&config, counter).unwrap()
+}
+
+fn redact(redactor: &Redactor, message: &'static str) -> Bytes {
+    redactor
+        .redact(Bytes::from_static(message.as_bytes()), "1")
+        .unwrap()
+}
+
+#[test]
+fn test_parse_path() {
+    assert!(parse_path("/a/b~1c").is_ok());
+    assert!(parse_path("a.*.b").is_ok());
+    assert!(parse_path("a.**").is_err());
+    assert!(parse_path("a.").is_err());
+    assert!(parse_path("").is_err());
+}
+
+#[test]
+fn test_redact_pointer() {
+    let r = redactor(
+        &[
+            ("/user/email", RedactAction::Mask),
+            ("/user/phones/1", RedactAction::Drop),
+            ("/a~1b", RedactAction::Drop),
+        ],
+        None,
+    );
+    assert_eq!(
+        redact(
+            &r,
+            r#"{"user":{"email":"a@b.c","phones":["1","2","3"],"name":"x"},"a/b":1,"c":2}"#
+        ),
+        r#"{"user":{"email":"****","phones":["1","3"],"name":"x"},"c":2}"#
+    );
+}
+
+#[test]
+fn test_redact_glob() {
+    let r = redactor(
+        &[
+            ("**.email", RedactAction::Drop),
+            ("users.*.phone", RedactAction::Mask),
+        ],
+        None,
+    );
+    assert_eq!(
+        redact(
+            &r,
+            r#"{"email":1,"users":[{"phone":"1","email":"a"},{"x":{"email":"b"}}],"phone":"2"}"#
+        ),
+        r#"{"users":[{"phone":"****"},{"x":{}}],"phone":"2"}"#
+    );
+}
+
+#[test]
+fn test_redact_hash() {
+    let r = redactor(&[("email", RedactAction::Hash)], Some("key"));
+    // echo -n "a@b.c" | openssl dgst -sha256 -hmac key
+    assert_eq!(
+        redact(&r, r#"{"email":"a@b.c"}"#),
+        r#"{"email":"39ae49c50426b2bd08543508b376ed900cccf8729da709864ff1e0ef842c25b6"}"#
+    );
+}
+
+#[test]
+fn test_redact_no_match_unchanged() {
+    let r = redactor(&[("email", RedactAction::Drop)], None);
+    assert_eq!(redact(&r, r#"{ "a" : 1 }"#), r#"{ "a" : 1 }"#);
+}
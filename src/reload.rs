@@ -0,0 +1,113 @@
+//! Values loaded from files that are reloaded when the files change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::error::Result;
+
+type Loader<T> = fn(&Path) -> Result<T>;
+
+/// A value loaded from a file or a directory. `watch` starts a task that reloads the value when
+/// the file's modification time changes, or for a directory when any of its entries is added,
+/// removed or modified. If reloading fails, the previous value is kept.
+pub struct Reloadable<T> {
+    path: PathBuf,
+    load: Loader<T>,
+    value: RwLock<Arc<T>>,
+    modified: Mutex<Option<Modified>>,
+}
+
+impl<T: Send + Sync + 'static> Reloadable<T> {
+    pub fn load(path: impl Into<PathBuf>, load: Loader<T>) -> Result<Arc<Reloadable<T>>> {
+        let path = path.into();
+        let modified = modified(&path);
+        let value = load(&path)?;
+        Ok(Arc::new(Reloadable {
+            path,
+            load,
+            value: RwLock::new(Arc::new(value)),
+            modified: Mutex::new(modified),
+        }))
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.value.read().unwrap().clone()
+    }
+
+    /// Returns whether the value was reloaded.
+    pub fn reload_if_modified(&self) -> bool {
+        let modified = modified(&self.path);
+        let mut last_modified = self.modified.lock().unwrap();
+        if modified.is_none() || modified == *last_modified {
+            return false;
+        }
+        *last_modified = modified;
+
+        match (self.load)(&self.path) {
+            Ok(value) => {
+                *self.value.write().unwrap() = Arc::new(value);
+                info!("Reloaded {}", self.path.display());
+                true
+            }
+            Err(e) => {
+                error!(
+                    "Reloading {} failed, keeping the previous version: {}",
+                    self.path.display(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Starts a task that reloads the value every `interval` if the file was modified. Loading
+    /// runs on the blocking thread pool, as parsing a large file would stall a runtime worker.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let reloadable = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // the first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let reloadable = reloadable.clone();
+                if let Err(e) =
+                    tokio::task::spawn_blocking(move || reloadable.reload_if_modified()).await
+                {
+                    error!("Reloading task failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+// the modification times of a file, or of a directory and of each of its entries by name, as
+// editing a file in place leaves the modification time of its directory unchanged
+type Modified = Vec<(PathBuf, SystemTime)>;
+
+fn modified(path: &Path) -> Option<Modified> {
+    let mut modified = vec![(
+        path.to_owned(),
+        fs::metadata(path).and_then(|m| m.modified()).ok()?,
+    )];
+    if path.is_dir() {
+        let entries = fs::read_dir(path).ok()?;
+        for entry in entries {
+            let path = entry.ok()?.path();
+            // an entry removed while listing is seen on the next check
+            if let Ok(entry_modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                modified.push((path, entry_modified));
+            }
+        }
+        modified.sort();
+    }
+    Some(modified)
+}
+
+#[cfg(test)]
+mod test;
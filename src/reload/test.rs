@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use common::config::ConfigError;
+
+use crate::error::{Error, Result};
+
+use super::Reloadable;
+
+fn load_number(path: &Path) -> Result<u32> {
+    fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|e| Error::from(ConfigError::Invalid(format!("{}", e))))
+}
+
+fn touch(path: &Path, contents: &str, modified: SystemTime) {
+    fs::write(path, contents).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+#[test]
+fn test_reload_if_modified() {
+    let path = std::env::temp_dir().join(format!("ingest-reload-test-{}", std::process::id()));
+    let start = SystemTime::now();
+    touch(&path, "1", start);
+
+    let reloadable = Reloadable::load(&path, load_number).unwrap();
+    assert_eq!(*reloadable.get(), 1);
+    assert!(!reloadable.reload_if_modified());
+
+    touch(&path, "2", start + Duration::from_secs(1));
+    assert!(reloadable.reload_if_modified());
+    assert_eq!(*reloadable.get(), 2);
+
+    // invalid contents keep the previous value
+    touch(&path, "two", start + Duration::from_secs(2));
+    assert!(!reloadable.reload_if_modified());
+    assert_eq!(*reloadable.get(), 2);
+
+    fs::remove_file(&path).unwrap();
+    assert!(!reloadable.reload_if_modified());
+    assert_eq!(*reloadable.get(), 2);
+}
+
+// the sum of the numbers in the files of a directory
+fn load_sum(path: &Path) -> Result<u32> {
+    let mut sum = 0;
+    for entry in fs::read_dir(path)? {
+        sum += load_number(&entry?.path())?;
+    }
+    Ok(sum)
+}
+
+#[test]
+fn test_reload_directory() {
+    let path = std::env::temp_dir().join(format!("ingest-reload-dir-test-{}", std::process::id()));
+    fs::create_dir(&path).unwrap();
+    let start = SystemTime::now();
+    touch(&path.join("a"), "1", start);
+
+    let reloadable = Reloadable::load(&path, load_sum).unwrap();
+    assert_eq!(*reloadable.get(), 1);
+    assert!(!reloadable.reload_if_modified());
+
+    // a file added
+    touch(&path.join("b"), "2", start);
+    assert!(reloadable.reload_if_modified());
+    assert_eq!(*reloadable.get(), 3);
+
+    // a file modified in place, which leaves the directory unchanged
+    touch(&path.join("a"), "4", start + Duration::from_secs(1));
+    assert!(reloadable.reload_if_modified());
+    assert_eq!(*reloadable.get(), 6);
+    assert!(!reloadable.reload_if_modified());
+
+    // a file removed
+    fs::remove_file(path.join("b")).unwrap();
+    assert!(reloadable.reload_if_modified());
+    assert_eq!(*reloadable.get(), 4);
+
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_watch() {
+    let path =
+        std::env::temp_dir().join(format!("ingest-reload-watch-test-{}", std::process::id()));
+    let start = SystemTime::now();
+    touch(&path, "1", start);
+
+    let reloadable = Reloadable::load(&path, load_number).unwrap();
+    let watch = reloadable.watch(Duration::from_millis(10));
+    touch(&path, "2", start + Duration::from_secs(1));
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while *reloadable.get() != 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    watch.abort();
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,209 @@
+//! Parsing of Kafka REST Proxy produce requests, in the v2 and v3 formats.
+
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::Value;
+
+pub const JSON_V2: &str = "application/vnd.kafka.json.v2+json";
+pub const BINARY_V2: &str = "application/vnd.kafka.binary.v2+json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType {
+    Json,
+    Binary,
+    String,
+}
+
+impl DataType {
+    fn from_name(name: &str) -> Option<DataType> {
+        match name {
+            "JSON" => Some(DataType::Json),
+            "BINARY" => Some(DataType::Binary),
+            "STRING" => Some(DataType::String),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DataType::Json => "JSON",
+            DataType::Binary => "BINARY",
+            DataType::String => "STRING",
+        }
+    }
+}
+
+/// The key or value of a record, serialized.
+#[derive(Debug, PartialEq)]
+pub struct Data {
+    pub data_type: DataType,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ProduceRecord {
+    pub key: Option<Data>,
+    /// None for tombstones.
+    pub value: Option<Data>,
+    pub partition: Option<i32>,
+    pub headers: Vec<(String, Bytes)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RestProxyError(pub String);
+
+impl fmt::Display for RestProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RestProxyError {}
+
+/// The embedded format of the keys and values of a v2 request, from its content type.
+pub fn v2_format(content_type: &str) -> Option<DataType> {
+    match content_type.split(';').next()?.trim() {
+        JSON_V2 | "application/json" => Some(DataType::Json),
+        BINARY_V2 => Some(DataType::Binary),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+struct V2Request {
+    records: Vec<V2Record>,
+}
+
+#[derive(Deserialize)]
+struct V2Record {
+    #[serde(default)]
+    key: Value,
+    #[serde(default)]
+    value: Value,
+    #[serde(default)]
+    partition: Option<i32>,
+}
+
+pub fn v2_records(body: &[u8], format: DataType) -> Result<Vec<ProduceRecord>, RestProxyError> {
+    let request: V2Request =
+        serde_json::from_slice(body).map_err(|e| RestProxyError(e.to_string()))?;
+    request
+        .records
+        .into_iter()
+        .map(|record| {
+            Ok(ProduceRecord {
+                key: data(format, record.key)?,
+                value: data(format, record.value)?,
+                partition: record.partition,
+                headers: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct V3Record {
+    #[serde(default)]
+    partition_id: Option<i32>,
+    #[serde(default)]
+    headers: Vec<V3Header>,
+    #[serde(default)]
+    key: Option<V3Data>,
+    #[serde(default)]
+    value: Option<V3Data>,
+}
+
+#[derive(Deserialize)]
+struct V3Header {
+    name: String,
+    /// Base64 encoded
+    #[serde(default)]
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct V3Data {
+    #[serde(default, rename = "type")]
+    data_type: Option<String>,
+    #[serde(default)]
+    data: Value,
+}
+
+/// Parses the concatenated records of a v3 request, each of them valid or not. Invalid JSON ends
+/// the records.
+pub fn v3_records(body: &[u8]) -> Vec<Result<ProduceRecord, RestProxyError>> {
+    let mut records = Vec::new();
+    for record in serde_json::Deserializer::from_slice(body).into_iter::<Value>() {
+        match record {
+            Ok(record) => records.push(
+                serde_json::from_value(record)
+                    .map_err(|e| RestProxyError(e.to_string()))
+                    .and_then(v3_record),
+            ),
+            Err(e) => {
+                records.push(Err(RestProxyError(e.to_string())));
+                break;
+            }
+        }
+    }
+    records
+}
+
+fn v3_record(record: V3Record) -> Result<ProduceRecord, RestProxyError> {
+    let headers = record
+        .headers
+        .into_iter()
+        .map(|header| {
+            let value = match header.value {
+                Some(value) => Bytes::from(decode_base64(&value)?),
+                None => Bytes::new(),
+            };
+            Ok((header.name, value))
+        })
+        .collect::<Result<_, RestProxyError>>()?;
+    Ok(ProduceRecord {
+        key: record.key.map(v3_data).transpose()?.flatten(),
+        value: record.value.map(v3_data).transpose()?.flatten(),
+        partition: record.partition_id,
+        headers,
+    })
+}
+
+/// JSON data when the type is not set.
+fn v3_data(v3_data: V3Data) -> Result<Option<Data>, RestProxyError> {
+    let data_type = match v3_data.data_type {
+        Some(name) => DataType::from_name(&name)
+            .ok_or_else(|| RestProxyError(format!("Unsupported data type {}", name)))?,
+        None => DataType::Json,
+    };
+    data(data_type, v3_data.data)
+}
+
+fn data(data_type: DataType, data: Value) -> Result<Option<Data>, RestProxyError> {
+    let bytes = match (data_type, data) {
+        (_, Value::Null) => return Ok(None),
+        (DataType::Json, data) => serde_json::to_vec(&data).expect("JSON values serialize"),
+        (DataType::Binary, Value::String(data)) => decode_base64(&data)?,
+        (DataType::String, Value::String(data)) => data.into_bytes(),
+        (data_type, _) => {
+            return Err(RestProxyError(format!(
+                "{} data must be a string",
+                data_type.name()
+            )));
+        }
+    };
+    Ok(Some(Data { data_type, bytes }))
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, RestProxyError> {
+    STANDARD
+        .decode(data)
+        .map_err(|e| RestProxyError(format!("Invalid base64 data: {}", e)))
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,114 @@
+use bytes::Bytes;
+
+use super::{Data, DataType, ProduceRecord, RestProxyError, v2_format, v2_records, v3_records};
+
+fn json(bytes: &[u8]) -> Option<Data> {
+    Some(Data {
+        data_type: DataType::Json,
+        bytes: bytes.to_vec(),
+    })
+}
+
+#[test]
+fn test_v2_format() {
+    assert_eq!(
+        v2_format("application/vnd.kafka.json.v2+json"),
+        Some(DataType::Json)
+    );
+    assert_eq!(
+        v2_format("application/vnd.kafka.binary.v2+json; charset=utf-8"),
+        Some(DataType::Binary)
+    );
+    assert_eq!(v2_format("application/vnd.kafka.avro.v2+json"), None);
+}
+
+#[test]
+fn test_v2_json_records() {
+    let records = v2_records(
+        br#"{"records": [{"key": "k", "value": {"a": 1}, "partition": 2}, {"value": [1]}]}"#,
+        DataType::Json,
+    )
+    .unwrap();
+    assert_eq!(
+        records,
+        vec![
+            ProduceRecord {
+                key: json(br#""k""#),
+                value: json(br#"{"a":1}"#),
+                partition: Some(2),
+                headers: vec![],
+            },
+            ProduceRecord {
+                value: json(b"[1]"),
+                ..Default::default()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_v2_binary_records() {
+    let records = v2_records(
+        br#"{"records": [{"key": "a2V5", "value": "dmFsdWU="}, {"key": "a2V5", "value": null}]}"#,
+        DataType::Binary,
+    )
+    .unwrap();
+    assert_eq!(records[0].key.as_ref().unwrap().bytes, b"key");
+    assert_eq!(records[0].value.as_ref().unwrap().bytes, b"value");
+    assert_eq!(records[1].value, None);
+
+    assert!(v2_records(br#"{"records": [{"value": 1}]}"#, DataType::Binary).is_err());
+    assert!(
+        v2_records(
+            br#"{"records": [{"value": "not base64!"}]}"#,
+            DataType::Binary
+        )
+        .is_err()
+    );
+    assert!(v2_records(br#"{"value": "a"}"#, DataType::Json).is_err());
+}
+
+#[test]
+fn test_v3_records() {
+    let records = v3_records(
+        br#"{
+            "partition_id": 1,
+            "headers": [{"name": "h", "value": "dg=="}, {"name": "empty"}],
+            "key": {"type": "STRING", "data": "k"},
+            "value": {"type": "JSON", "data": {"a": 1}}
+        }
+        {"value": {"data": "v"}}
+        {"value": {"type": "AVRO", "data": "v"}}
+        {"partition_id": "x"}
+        {"value""#,
+    );
+    assert_eq!(records.len(), 5);
+    assert_eq!(
+        records[0],
+        Ok(ProduceRecord {
+            key: Some(Data {
+                data_type: DataType::String,
+                bytes: b"k".to_vec(),
+            }),
+            value: json(br#"{"a":1}"#),
+            partition: Some(1),
+            headers: vec![
+                ("h".to_owned(), Bytes::from_static(b"v")),
+                ("empty".to_owned(), Bytes::new()),
+            ],
+        })
+    );
+    assert_eq!(
+        records[1],
+        Ok(ProduceRecord {
+            value: json(br#""v""#),
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        records[2],
+        Err(RestProxyError("Unsupported data type AVRO".to_owned()))
+    );
+    assert!(records[3].is_err());
+    assert!(records[4].is_err());
+}
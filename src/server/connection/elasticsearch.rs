@@ -0,0 +1,232 @@
+//! Elasticsearch bulk API. The documents of index and create actions are sent as JSON messages to
+//! the schema routed from their index, keyed by their id, and the response reports the result of
+//! each action once the documents are delivered, so that clients retry the failed ones.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Instant;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, web};
+use bytes::Bytes;
+use serde_json::{Value, json};
+use tracing::{instrument, warn};
+
+use crate::config::ElasticsearchConfig;
+use crate::elasticsearch::{
+    Action, BulkItem, ItemError, error_response, info, item_response, parse,
+};
+use crate::forward::wildcard_matches;
+use crate::kafka::{DeliveryReceiver, Records, deliveries};
+use crate::server::connection::prepare::Preparation;
+use crate::server::connection::{ip_address, metadata_headers};
+use crate::server::{ServerState, WSError};
+
+fn config(state: &ServerState) -> &ElasticsearchConfig {
+    state
+        .elasticsearch
+        .as_ref()
+        .expect("The bulk API is only served when configured")
+}
+
+/// Clients check that they are talking to Elasticsearch with this header.
+fn response(status: StatusCode, body: &Value) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header(("X-Elastic-Product", "Elasticsearch"))
+        .json(body)
+}
+
+pub async fn cluster_info(state: web::Data<ServerState>) -> HttpResponse {
+    response(StatusCode::OK, &info(&config(&state).version))
+}
+
+pub async fn bulk(
+    req: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    _bulk(req, body, None, state).await
+}
+
+pub async fn index_bulk(
+    req: HttpRequest,
+    body: web::Bytes,
+    index: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    _bulk(req, body, Some(index.into_inner()), state).await
+}
+
+#[instrument(level = "debug", skip_all, fields(index))]
+async fn _bulk(
+    req: HttpRequest,
+    body: web::Bytes,
+    index: Option<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let started = Instant::now();
+    if let Some(index) = &index {
+        tracing::Span::current().record("index", index.as_str());
+    }
+    let items = match parse(&body, index.as_deref()) {
+        Ok(items) => items,
+        Err(e) => {
+            return response(
+                StatusCode::BAD_REQUEST,
+                &error_response(400, "illegal_argument_exception", &e.to_string()),
+            );
+        }
+    };
+
+    let ip_address = ip_address(&req);
+    let mut sender = Sender {
+        config: config(&state),
+        req: &req,
+        state: &state,
+        ip_address: &ip_address,
+        headers: HashMap::new(),
+    };
+    // all documents are sent before any delivery is awaited
+    let sent: Vec<_> = items.iter().map(|item| sender.send(item)).collect();
+
+    let mut errors = false;
+    let mut results = Vec::with_capacity(items.len());
+    for (item, sent) in items.iter().zip(sent) {
+        let result = match sent {
+            Ok(Some(mut delivery_rx)) => delivered(&mut delivery_rx).await,
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        errors |= result.is_err();
+        results.push(item_response(item, result));
+    }
+    response(
+        StatusCode::OK,
+        &json!({
+            "took": started.elapsed().as_millis() as u64,
+            "errors": errors,
+            "items": results,
+        }),
+    )
+}
+
+struct Sender<'a> {
+    config: &'a ElasticsearchConfig,
+    req: &'a HttpRequest,
+    state: &'a ServerState,
+    ip_address: &'a str,
+    /// The metadata headers of each schema the documents are sent to
+    headers: HashMap<String, Vec<(String, Bytes)>>,
+}
+
+impl Sender<'_> {
+    fn schema_id(&self, index: &str) -> Option<&str> {
+        self.config
+            .route
+            .iter()
+            .find(|route| wildcard_matches(&route.index, index))
+            .map(|route| route.schema_id.as_str())
+            .or(self.config.schema_id.as_deref())
+    }
+
+    /// Prepares and sends the document of an item to Kafka without waiting for its delivery.
+    /// Returns None for documents dropped by the filter.
+    fn send(&mut self, item: &BulkItem) -> Result<Option<DeliveryReceiver>, ItemError> {
+        let source = match (item.action, &item.source) {
+            (Action::Index | Action::Create, Some(source)) => source.clone(),
+            _ => {
+                return Err(ItemError {
+                    status: 400,
+                    error_type: "illegal_argument_exception",
+                    reason: format!("The [{}] action is not supported", item.action.name()),
+                });
+            }
+        };
+        let state = self.state;
+        if source.len() > state.max_event_size_bytes as usize {
+            return Err(ItemError {
+                status: 400,
+                error_type: "illegal_argument_exception",
+                reason: format!(
+                    "The document exceeds the maximum size of {} bytes",
+                    state.max_event_size_bytes
+                ),
+            });
+        }
+
+        let Some(schema_id) = self.schema_id(&item.index).map(str::to_owned) else {
+            return Err(ItemError {
+                status: 404,
+                error_type: "index_not_found_exception",
+                reason: format!("No schema is routed from the index [{}]", item.index),
+            });
+        };
+        // the bulk API has no authentication of its own
+        if state.authenticates(&schema_id) {
+            return Err(ItemError {
+                status: 403,
+                error_type: "security_exception",
+                reason: format!(
+                    "The schema of the index [{}] only accepts authenticated requests",
+                    item.index
+                ),
+            });
+        }
+        let schema_config = state.schema_config(&schema_id);
+        let headers: &[_] = self
+            .headers
+            .entry(schema_id.clone())
+            .or_insert_with(|| metadata_headers(state, &schema_id, schema_config, self.ip_address));
+        let preparation = Preparation {
+            is_json: true,
+            ..Preparation::new(state, &schema_id, self.req.headers(), headers)
+        };
+        let source = match preparation.prepare(source) {
+            Ok(Some(source)) => source,
+            Ok(None) => {
+                state.metrics.record_dropped(1, &schema_id);
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(ItemError {
+                    status: 400,
+                    error_type: "mapper_parsing_exception",
+                    reason: e.message(),
+                });
+            }
+        };
+
+        let (delivery_tx, delivery_rx) = deliveries();
+        state
+            .kafka
+            .send(
+                &source,
+                item.id.as_deref().map(str::as_bytes),
+                headers,
+                &schema_config.destination_topic,
+                &schema_config.librdkafka_config,
+                Records::single(source.len()),
+                delivery_tx,
+            )
+            .map_err(rejected)?;
+        Ok(Some(delivery_rx))
+    }
+}
+
+/// Clients retry the items rejected with a 429 status.
+fn rejected(e: impl Display) -> ItemError {
+    warn!("Failed to send document: {}", e);
+    ItemError {
+        status: 429,
+        error_type: "es_rejected_execution_exception",
+        reason: "Failed to deliver the document".to_owned(),
+    }
+}
+
+async fn delivered(delivery_rx: &mut DeliveryReceiver) -> Result<(), ItemError> {
+    match delivery_rx.recv().await {
+        Some(Ok(_)) => Ok(()),
+        Some(Err(e)) => Err(rejected(e)),
+        None => Err(rejected("Delivery result not received")),
+    }
+}
@@ -0,0 +1,385 @@
+//! gRPC ingestion. Each request message is forwarded as a single Kafka message, using the config
+//! of its schema like HTTP requests do.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::SocketAddr;
+
+use actix_web::error::{ErrorPayloadTooLarge, ErrorServiceUnavailable, PayloadError};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::web;
+use bytes::Bytes;
+use futures::StreamExt;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_util::sync::CancellationToken;
+use tonic::metadata::{KeyAndValueRef, MetadataMap};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{debug, error, info, instrument};
+
+use crate::admission::Admitted;
+use crate::error::{Error, Result};
+use crate::jwt::{self, JwtError};
+use crate::kafka::{DeliveryReceiver, Records, deliveries};
+use crate::proto::ingest_server::{Ingest, IngestServer};
+use crate::proto::{
+    DeliveryResult, IngestRequest, IngestResponse, PartitionOffsets, StreamSummary,
+};
+use crate::server::connection::prepare::Preparation;
+use crate::server::connection::{http, metadata_headers};
+use crate::server::{ServerState, WSError};
+use crate::signature::SignatureError;
+
+// room for the other fields of a request on top of the payload
+const MAX_REQUEST_OVERHEAD_BYTES: usize = 64 * 1024;
+
+// messages of a stream awaiting their delivery before the stream waits for them
+const MAX_STREAM_IN_FLIGHT: usize = 1024;
+
+pub struct GrpcServer {
+    addr: SocketAddr,
+    shutdown: CancellationToken,
+    task_handle: JoinHandle<std::result::Result<(), tonic::transport::Error>>,
+}
+
+impl GrpcServer {
+    pub async fn start(address: SocketAddr, state: web::Data<ServerState>) -> Result<GrpcServer> {
+        let listener = TcpListener::bind(address).await?;
+        let addr = listener.local_addr()?;
+        info!("gRPC server listening at {}", addr);
+
+        let max_message_size = state.max_event_size_bytes as usize + MAX_REQUEST_OVERHEAD_BYTES;
+        let service =
+            IngestServer::new(IngestService { state }).max_decoding_message_size(max_message_size);
+        let shutdown = CancellationToken::new();
+        let task_handle = tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(
+                    TcpListenerStream::new(listener),
+                    shutdown.clone().cancelled_owned(),
+                ),
+        );
+        Ok(GrpcServer {
+            addr,
+            shutdown,
+            task_handle,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops accepting requests and waits for the ones in progress.
+    pub async fn stop(self) {
+        self.shutdown.cancel();
+        match self.task_handle.await {
+            Ok(Err(err)) => error!(%err, "gRPC server error"),
+            Err(err) => error!(%err, "Error joining gRPC server task"),
+            Ok(Ok(())) => {}
+        }
+    }
+
+    pub fn kill(&self) {
+        self.task_handle.abort();
+    }
+}
+
+#[derive(Clone)]
+struct IngestService {
+    state: web::Data<ServerState>,
+}
+
+/// A message sent to Kafka awaiting its delivery, holding its share of the memory budget until
+/// then, or the result of one that was not sent.
+type Sent = std::result::Result<(DeliveryReceiver, Admitted), DeliveryResult>;
+
+#[tonic::async_trait]
+impl Ingest for IngestService {
+    async fn ingest(
+        &self,
+        request: Request<IngestRequest>,
+    ) -> std::result::Result<Response<IngestResponse>, Status> {
+        let ip_address = ip_address(&request);
+        let request_headers = request_headers(request.metadata());
+        let message = request.into_inner();
+        let mut sent = self.sent(message, &ip_address, &request_headers).await;
+        Ok(Response::new(IngestResponse {
+            result: Some(sent_result(&mut sent).await),
+            ..Default::default()
+        }))
+    }
+
+    async fn ingest_stream(
+        &self,
+        request: Request<Streaming<IngestRequest>>,
+    ) -> std::result::Result<Response<IngestResponse>, Status> {
+        let ip_address = ip_address(&request);
+        let request_headers = request_headers(request.metadata());
+        let mut messages = request.into_inner();
+
+        // messages are sent as they arrive and their results summed up once delivered. the
+        // stream is not read while too many messages are in flight
+        let mut summary = StreamSummary::default();
+        let mut pending = VecDeque::new();
+        let mut stream_ended = false;
+        let mut stream_error = None;
+        loop {
+            tokio::select! {
+                message = messages.next(),
+                    if !stream_ended && pending.len() < MAX_STREAM_IN_FLIGHT =>
+                {
+                    match message {
+                        Some(Ok(message)) => {
+                            summary.received_count += 1;
+                            pending.push_back(
+                                self.sent(message, &ip_address, &request_headers).await,
+                            );
+                        }
+                        Some(Err(status)) => {
+                            stream_ended = true;
+                            stream_error = Some(status);
+                        }
+                        None => stream_ended = true,
+                    }
+                }
+                result = next_result(&mut pending), if !pending.is_empty() => {
+                    summary.add(result);
+                }
+                else => break,
+            }
+        }
+        // the messages sent before a stream error are still awaited
+        if let Some(status) = stream_error {
+            return Err(status);
+        }
+        Ok(Response::new(IngestResponse {
+            summary: Some(summary),
+            ..Default::default()
+        }))
+    }
+}
+
+impl IngestService {
+    /// Sends the message without waiting for its delivery, once its request is authenticated for
+    /// its schema and it is admitted by the memory budget.
+    async fn sent(
+        &self,
+        message: IngestRequest,
+        ip_address: &str,
+        request_headers: &HeaderMap,
+    ) -> Sent {
+        // signatures are of whole HTTP bodies, which gRPC messages are not
+        if self.state.verifier(&message.schema_id).is_some() {
+            return Err(refused(&message.schema_id, SignatureError::Unsupported));
+        }
+        let auth_headers = self.authenticate(&message.schema_id, request_headers)?;
+        let admission = &self.state.admission;
+        let Some(admitted) = admission
+            .admit(&message.schema_id, message.payload.len() as u64)
+            .await
+        else {
+            return Err(failed(Error::from(ErrorServiceUnavailable(
+                "Memory budget exceeded, retry later",
+            ))));
+        };
+        match self.send(message, ip_address, auth_headers) {
+            Ok(Some(delivery_rx)) => Ok((delivery_rx, admitted)),
+            Ok(None) => Err(dropped()),
+            Err(e) => Err(failed(e)),
+        }
+    }
+
+    /// Authenticates the request like an HTTP request to the schema, with its metadata as the
+    /// headers. Returns the headers identifying the client, with the claims to forward.
+    fn authenticate(
+        &self,
+        schema_id: &str,
+        request_headers: &HeaderMap,
+    ) -> std::result::Result<Vec<(String, Bytes)>, DeliveryResult> {
+        let state = &self.state;
+        let mut headers = Vec::new();
+        if state.schema_config(schema_id).api_key_auth {
+            let key_id = http::api_key_id(state, schema_id, request_headers, "", "POST")
+                .map_err(|e| refused(schema_id, e))?;
+            headers.push((state.header_names.api_key_id.clone(), Bytes::from(key_id)));
+        }
+        if let Some(jwt_validator) = state.jwt_validator(schema_id) {
+            let claims = jwt::bearer_token(request_headers)
+                .ok_or(JwtError::MissingToken)
+                .and_then(|token| jwt_validator.validate(token, schema_id, &state.header_names))
+                .map_err(|e| refused(schema_id, e))?;
+            headers.extend(claims);
+        }
+        Ok(headers)
+    }
+
+    /// Prepares and sends the message to Kafka without waiting for the delivery. Returns None
+    /// for messages dropped by the filter.
+    #[instrument(level = "debug", skip_all, fields(schema_id = message.schema_id.as_str()))]
+    fn send(
+        &self,
+        message: IngestRequest,
+        ip_address: &str,
+        auth_headers: Vec<(String, Bytes)>,
+    ) -> Result<Option<DeliveryReceiver>> {
+        let state = &self.state;
+        let schema_id = message.schema_id.as_str();
+        let schema_config = state.schema_config(schema_id);
+        if message.payload.len() > state.max_event_size_bytes as usize {
+            return Err(Error::from(ErrorPayloadTooLarge(PayloadError::Overflow)));
+        }
+
+        let mut headers = metadata_headers(state, schema_id, schema_config, ip_address);
+        headers.extend(auth_headers);
+
+        // filter rules match the message headers like they match HTTP request headers
+        let mut request_headers = HeaderMap::new();
+        for header in &message.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(header.name.as_bytes()),
+                HeaderValue::from_bytes(&header.value),
+            ) {
+                request_headers.append(name, value);
+            }
+        }
+
+        let preparation = Preparation::new(state, schema_id, &request_headers, &headers);
+        let Some(payload) = preparation.prepare(Bytes::from(message.payload))? else {
+            state.metrics.record_dropped(1, schema_id);
+            return Ok(None);
+        };
+
+        // clients cannot set the headers the service adds
+        headers.extend(
+            message
+                .headers
+                .into_iter()
+                .filter(|header| !state.header_names.is_reserved(&header.name))
+                .map(|header| (header.name, Bytes::from(header.value))),
+        );
+        let (delivery_tx, delivery_rx) = deliveries();
+        state.kafka.send(
+            &payload,
+            message.key.as_deref(),
+            &headers,
+            &schema_config.destination_topic,
+            &schema_config.librdkafka_config,
+            Records::single(payload.len()),
+            delivery_tx,
+        )?;
+        Ok(Some(delivery_rx))
+    }
+}
+
+impl StreamSummary {
+    fn add(&mut self, result: DeliveryResult) {
+        if result.delivered {
+            self.delivered_count += 1;
+        } else if result.dropped {
+            self.dropped_count += 1;
+        } else if self.error.is_empty() {
+            self.error = result.error;
+        }
+        let (Some(partition), Some(offset)) = (result.partition, result.offset) else {
+            return;
+        };
+        match self
+            .partition_offsets
+            .iter_mut()
+            .find(|offsets| offsets.partition == partition)
+        {
+            Some(offsets) => {
+                offsets.first_offset = offsets.first_offset.min(offset);
+                offsets.last_offset = offsets.last_offset.max(offset);
+                offsets.count += 1;
+            }
+            None => self.partition_offsets.push(PartitionOffsets {
+                partition,
+                first_offset: offset,
+                last_offset: offset,
+                count: 1,
+            }),
+        }
+    }
+}
+
+/// The ASCII metadata of a request, as HTTP headers.
+fn request_headers(metadata: &MetadataMap) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for entry in metadata.iter() {
+        if let KeyAndValueRef::Ascii(key, value) = entry
+            && let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(key.as_str().as_bytes()),
+                HeaderValue::from_bytes(value.as_encoded_bytes()),
+            )
+        {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+fn ip_address<T>(request: &Request<T>) -> String {
+    request
+        .remote_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default()
+}
+
+async fn sent_result(sent: &mut Sent) -> DeliveryResult {
+    match sent {
+        Ok((delivery_rx, _)) => delivery_result(delivery_rx).await,
+        Err(result) => std::mem::take(result),
+    }
+}
+
+/// The result of the oldest pending message, once it is delivered.
+async fn next_result(pending: &mut VecDeque<Sent>) -> DeliveryResult {
+    let result = match pending.front_mut() {
+        Some(sent) => sent_result(sent).await,
+        None => return failed(Error::from(std::io::Error::other("No message pending"))),
+    };
+    pending.pop_front();
+    result
+}
+
+async fn delivery_result(delivery_rx: &mut DeliveryReceiver) -> DeliveryResult {
+    match delivery_rx.recv().await {
+        Some(Ok(delivery)) => DeliveryResult {
+            delivered: true,
+            partition: Some(delivery.partition),
+            offset: Some(delivery.offset),
+            ..Default::default()
+        },
+        Some(Err(e)) => failed(Error::from(e)),
+        None => failed(Error::from(std::io::Error::other(
+            "Delivery result not received",
+        ))),
+    }
+}
+
+fn dropped() -> DeliveryResult {
+    DeliveryResult {
+        dropped: true,
+        ..Default::default()
+    }
+}
+
+/// The result of a message refused before it is prepared.
+fn refused(schema_id: &str, e: impl fmt::Display) -> DeliveryResult {
+    debug!(schema_id, "Message refused: {}", e);
+    DeliveryResult {
+        error: e.to_string(),
+        ..Default::default()
+    }
+}
+
+fn failed(e: Error) -> DeliveryResult {
+    DeliveryResult {
+        error: e.message(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,120 @@
+//! Splunk HTTP Event Collector API. The events of a request are sent as JSON messages, with their
+//! metadata fields, to the schema of the token the request is authorized with. Requests succeed or
+//! fail as a whole once their events are delivered, so that forwarders retry the failed ones.
+
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{HttpRequest, HttpResponse, web};
+use bytes::Bytes;
+use serde_json::{Map, Value, json};
+use tracing::{debug, instrument, warn};
+
+use crate::hec::{self, HecError};
+use crate::server::ServerState;
+use crate::server::connection::prepare::Preparation;
+use crate::server::connection::{ip_address, metadata_headers, send_all};
+
+// the query parameters of the raw endpoint set on its events
+const RAW_METADATA_FIELDS: [&str; 4] = ["host", "source", "sourcetype", "index"];
+
+pub async fn event(
+    req: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let result = async {
+        let schema_id = authorize(&req, &state)?;
+        let events = hec::events(&body)?;
+        send(&req, schema_id, events, &state).await
+    };
+    response(result.await)
+}
+
+pub async fn raw(
+    req: HttpRequest,
+    body: web::Bytes,
+    query: web::Query<HashMap<String, String>>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let result = async {
+        let schema_id = authorize(&req, &state)?;
+        let metadata: Map<String, Value> = RAW_METADATA_FIELDS
+            .iter()
+            .filter_map(|field| Some((field.to_string(), json!(query.get(*field)?))))
+            .collect();
+        let events = hec::raw_events(&body, &metadata)?;
+        send(&req, schema_id, events, &state).await
+    };
+    response(result.await)
+}
+
+pub async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(hec::healthy())
+}
+
+fn response(result: Result<(), HecError>) -> HttpResponse {
+    match result {
+        Ok(()) => HttpResponse::Ok().json(hec::success()),
+        Err(e) => {
+            let status = StatusCode::from_u16(e.status()).expect("HEC statuses are valid");
+            HttpResponse::build(status).json(e.to_json())
+        }
+    }
+}
+
+/// The schema of the token of a request.
+fn authorize<'a>(req: &HttpRequest, state: &'a ServerState) -> Result<&'a str, HecError> {
+    let tokens = state
+        .hec_tokens
+        .as_ref()
+        .expect("The HEC API is only served when configured");
+    let token = hec::token(
+        req.headers()
+            .get(AUTHORIZATION)
+            .map(|value| value.as_bytes()),
+    )?;
+    tokens.schema_id(token).ok_or(HecError::InvalidToken)
+}
+
+/// Prepares all events, then sends them to Kafka and waits for their delivery.
+#[instrument(level = "debug", skip_all, fields(schema_id))]
+async fn send(
+    req: &HttpRequest,
+    schema_id: &str,
+    events: Vec<Map<String, Value>>,
+    state: &ServerState,
+) -> Result<(), HecError> {
+    tracing::Span::current().record("schema_id", schema_id);
+    let schema_config = state.schema_config(schema_id);
+    let headers = metadata_headers(state, schema_id, schema_config, &ip_address(req));
+    let preparation = Preparation {
+        is_json: true,
+        ..Preparation::new(state, schema_id, req.headers(), &headers)
+    };
+
+    let mut messages = Vec::with_capacity(events.len());
+    for (number, event) in events.into_iter().enumerate() {
+        let event = Bytes::from(serde_json::to_vec(&event).expect("JSON values serialize"));
+        if event.len() > state.max_event_size_bytes as usize {
+            debug!("Event {} exceeds the maximum size", number);
+            return Err(HecError::InvalidDataFormat(number));
+        }
+        match preparation.prepare(event) {
+            Ok(Some(event)) => messages.push(event),
+            Ok(None) => state.metrics.record_dropped(1, schema_id),
+            Err(e) => {
+                debug!("Invalid event {}: {}", number, e);
+                return Err(HecError::InvalidDataFormat(number));
+            }
+        }
+    }
+
+    send_all(state, schema_config, &headers, &messages)
+        .await
+        .map_err(|e| {
+            warn!("Failed to deliver events: {}", e);
+            HecError::ServerBusy
+        })
+}
@@ -1,24 +1,28 @@
-use std::ops::Index;
-
 use actix_web::error::{ErrorBadRequest, ErrorPayloadTooLarge, PayloadError};
 use actix_web::http::StatusCode;
-use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use actix_web::http::header::{
+    AUTHORIZATION, CONTENT_LENGTH, HeaderMap, RETRY_AFTER, WWW_AUTHENTICATE,
+};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError, web};
 use async_stream::stream;
 use bytes::Bytes;
+use futures::future::{self, Either};
 use futures::{Stream, pin_mut};
-use tracing::{instrument, trace};
+use tracing::{debug, instrument, trace};
 
 use futures::stream::StreamExt;
-use rdkafka::error::KafkaError;
 use serde::Serialize;
-use tokio::sync::mpsc;
-use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
-use tokio_util::io::StreamReader;
 
-use crate::config::{ContentType, HeaderNames, SchemaConfig};
+use crate::auth::{self, ApiKeyId, AuthError};
+use crate::batching::Batch;
+use crate::cloudevents::{self, Mode};
+use crate::config::{AckMode, ContentType, Framing, HeaderNames, SchemaConfig};
 use crate::error::{Error, Result};
-use crate::kafka::Kafka;
+use crate::framing::{self, FrameError};
+use crate::jwt::{self, ForwardedClaims, JwtError};
+use crate::kafka::{Delivery, DeliveryReceiver, DeliverySender, Kafka, Records, deliveries};
 use crate::python::{ProcessorResponse, call_processor_process, call_processor_process_head};
+use crate::server::connection::prepare::Preparation;
 use crate::server::{PythonProcessor, ServerState};
 
 pub async fn handle_with_trailing_path(
@@ -39,7 +43,7 @@ pub async fn handle(
     _handle(req, body_stream, path.into_inner(), state).await
 }
 
-async fn _handle(
+pub(crate) async fn _handle(
     req: HttpRequest,
     mut body_stream: web::Payload,
     schema_id: String,
@@ -48,10 +52,7 @@ async fn _handle(
     // do something with tenant from authentication or config if/when multitenant
     //let _tenant_id = get_tenant_id(&req);
 
-    let schema_config = state
-        .schema_configs
-        .get(&schema_id)
-        .unwrap_or(&state.default_schema_config);
+    let schema_config = state.schema_config(&schema_id);
 
     if !schema_config
         .allowed_methods
@@ -60,6 +61,35 @@ async fn _handle(
         return Ok(HttpResponse::MethodNotAllowed().finish());
     }
 
+    if let Err(response) = authenticate(&req, &schema_id, schema_config, &state) {
+        return Ok(response);
+    }
+
+    // at most the largest event size of a body is buffered at once, as a whole body, a frame or a
+    // batch, so requests are admitted by their length up to it, and keep their share of the
+    // memory budget until responded to
+    let body_size = buffered_size(req.headers(), state.max_event_size_bytes);
+    let Some(_admitted) = state.admission.admit(&schema_id, body_size).await else {
+        return Ok(HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, state.admission.retry_after_seconds))
+            .finish());
+    };
+
+    // signatures are of the whole body, which is verified before the Python plugin reads it
+    let mut body_stream = match state.verifier(&schema_id) {
+        Some(verifier) => {
+            let body = read_body(&mut body_stream, state.max_event_size_bytes as usize).await?;
+            if let Err(e) = verifier.verify(req.headers(), &body) {
+                debug!(schema_id, "Request signature not verified: {}", e);
+                return Ok(HttpResponse::Unauthorized().json(e.to_json()));
+            }
+            Either::Left(futures::stream::once(future::ready(Ok::<_, PayloadError>(
+                body,
+            ))))
+        }
+        None => Either::Right(body_stream),
+    };
+
     let mut response_status = schema_config.response_status;
     let mut response_headers: Vec<(String, String)> = Vec::new();
     let mut response_body_opt: Option<Vec<u8>> = None;
@@ -106,7 +136,7 @@ async fn _handle(
         body_read = Bytes::new();
     }
 
-    let ingest_response = if should_forward {
+    let mut ingest_response = if should_forward {
         let s = stream! {
             yield Ok(body_read);
             while let Some(chunk) = body_stream.next().await {
@@ -114,26 +144,24 @@ async fn _handle(
             }
         };
         pin_mut!(s);
-        forward(
-            req,
-            s,
-            &schema_id,
-            schema_config,
-            &state.header_names,
-            state.kafka.clone(),
-            state.max_event_size_bytes as usize,
-        )
-        .await
+        let wait_for_delivery = schema_config.ack_mode == AckMode::Sync;
+        forward(req, s, &schema_id, schema_config, &state, wait_for_delivery).await
     } else {
-        IngestResponse {
-            ingested_count: 0,
-            ingested_bytes: 0,
-            ingested_content_type: ContentType::Binary,
-            ingested_schema_id: schema_id,
-            error: None,
-        }
+        IngestResponse::new(&schema_id, ContentType::Binary)
     };
 
+    // handed off even on errors, so that the failures of the messages in flight are logged
+    if let Some(pending_deliveries) = ingest_response.pending_deliveries.take() {
+        let receipt = schema_config.ack_mode == AckMode::Receipt && ingest_response.error.is_none();
+        ingest_response.receipt_id = state.track_deliveries(
+            pending_deliveries,
+            &schema_id,
+            ingest_response.ingested_count,
+            receipt,
+        );
+        response_status = StatusCode::ACCEPTED.as_u16();
+    }
+
     if let Some(e) = &ingest_response.error {
         response_status = e.status_code().as_u16();
     }
@@ -160,11 +188,104 @@ async fn _handle(
     Ok(response_builder.body(response_body))
 }
 
+/// Authenticates the requests to schemas with `api_key_auth` or `jwt`, before their body is read,
+/// and records the id of their key or their claims to forward in the request extensions.
+pub fn authenticate(
+    req: &HttpRequest,
+    schema_id: &str,
+    schema_config: &SchemaConfig,
+    state: &ServerState,
+) -> std::result::Result<(), HttpResponse> {
+    if schema_config.api_key_auth {
+        authenticate_api_key(req, schema_id, state)?;
+    }
+    if let Some(jwt_validator) = state.jwt_validator(schema_id) {
+        let claims = jwt::bearer_token(req.headers())
+            .ok_or(JwtError::MissingToken)
+            .and_then(|token| jwt_validator.validate(token, schema_id, &state.header_names));
+        match claims {
+            Ok(claims) => {
+                req.extensions_mut().insert(ForwardedClaims(claims));
+            }
+            Err(e) => {
+                debug!(schema_id, "Request token not valid: {}", e);
+                let status = StatusCode::from_u16(e.status()).expect("JWT statuses are valid");
+                let mut response = HttpResponse::build(status);
+                if status == StatusCode::UNAUTHORIZED {
+                    response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+                }
+                return Err(response.json(e.to_json()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn authenticate_api_key(
+    req: &HttpRequest,
+    schema_id: &str,
+    state: &ServerState,
+) -> std::result::Result<(), HttpResponse> {
+    let key_id = api_key_id(
+        state,
+        schema_id,
+        req.headers(),
+        req.query_string(),
+        req.method().as_str(),
+    );
+    match key_id {
+        Ok(key_id) => {
+            req.extensions_mut().insert(ApiKeyId(key_id));
+            Ok(())
+        }
+        Err(e) => {
+            debug!(schema_id, "Request not authenticated: {}", e);
+            let status = StatusCode::from_u16(e.status()).expect("Auth statuses are valid");
+            let mut response = HttpResponse::build(status);
+            if status == StatusCode::UNAUTHORIZED
+                && state.api_keys_config.as_ref().is_some_and(|c| c.bearer)
+            {
+                response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+            }
+            Err(response.json(e.to_json()))
+        }
+    }
+}
+
+/// The id of the API key of a request to a schema with `api_key_auth`, if it allows the schema
+/// and the method.
+pub fn api_key_id(
+    state: &ServerState,
+    schema_id: &str,
+    headers: &HeaderMap,
+    query: &str,
+    method: &str,
+) -> std::result::Result<String, AuthError> {
+    let (Some(api_keys), Some(config)) = (&state.api_keys, &state.api_keys_config) else {
+        unreachable!("API keys are loaded when a schema has api_key_auth");
+    };
+    let key = auth::request_key(config, headers, query)?;
+    let api_keys = api_keys.get();
+    let api_key = api_keys.authenticate(&key).ok_or(AuthError::InvalidKey)?;
+    if !api_key.allows(schema_id, method) {
+        return Err(AuthError::Forbidden(api_key.id.clone()));
+    }
+    Ok(api_key.id.clone())
+}
+
+/// The delivery status of the messages of a request, for schemas in the `receipt` ack mode.
+pub async fn receipt(receipt_id: web::Path<String>, state: web::Data<ServerState>) -> HttpResponse {
+    match state.receipts.get(&receipt_id) {
+        Some(receipt) => HttpResponse::Ok().json(receipt),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
 #[instrument(level = "debug", skip_all, fields(body_read_size))]
 pub async fn process_python(
     req: &HttpRequest,
     python_processor: &PythonProcessor,
-    body_stream: &mut web::Payload,
+    body_stream: &mut (impl Stream<Item = std::result::Result<Bytes, PayloadError>> + Unpin),
     read_max_body_bytes: usize,
 ) -> Result<(Option<ProcessorResponse>, Bytes)> {
     let url = req.uri().to_string();
@@ -256,11 +377,127 @@ pub struct IngestResponse {
     pub ingested_bytes: u128,
     pub ingested_content_type: ContentType,
     pub ingested_schema_id: String,
+    /// Messages dropped by the filter rules
+    #[serde(skip_serializing_if = "is_zero")]
+    pub dropped_count: u64,
+    /// Where the message was delivered, for requests of a single message when
+    /// `response_delivery` is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<DeliveredMessage>,
+    /// The offsets of the delivered messages per partition, when `response_partition_offsets` is
+    /// enabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub partition_offsets: Vec<PartitionOffsets>,
+    /// The receipt to poll for the delivery status, in the `receipt` ack mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt_id: Option<String>,
+    /// The deliveries not waited for, when not in the `sync` ack mode
+    #[serde(skip)]
+    pub pending_deliveries: Option<PendingDeliveries>,
     #[serde(skip)]
     // XXX: should figure out how to serialize this to return with the response as "ingest_error": ""
     pub error: Option<Error>,
 }
 
+impl IngestResponse {
+    /// The response of a request to the schema that ingested nothing yet.
+    pub fn new(schema_id: &str, content_type: ContentType) -> IngestResponse {
+        IngestResponse {
+            ingested_count: 0,
+            ingested_bytes: 0,
+            ingested_content_type: content_type,
+            ingested_schema_id: schema_id.to_owned(),
+            dropped_count: 0,
+            delivery: None,
+            partition_offsets: Vec::new(),
+            receipt_id: None,
+            pending_deliveries: None,
+            error: None,
+        }
+    }
+
+    /// The response of a request that failed before any of its messages were delivered.
+    pub fn failed(schema_id: &str, content_type: ContentType, error: Error) -> IngestResponse {
+        IngestResponse {
+            error: Some(error),
+            ..IngestResponse::new(schema_id, content_type)
+        }
+    }
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// The deliveries of a request responded to before they were all received.
+pub struct PendingDeliveries {
+    pub delivery_rx: DeliveryReceiver,
+    /// Messages delivered before the response
+    pub delivered_count: u64,
+}
+
+#[derive(Serialize)]
+pub struct DeliveredMessage {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since the epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+impl From<Delivery> for DeliveredMessage {
+    fn from(delivery: Delivery) -> Self {
+        DeliveredMessage {
+            topic: delivery.topic,
+            partition: delivery.partition,
+            offset: delivery.offset,
+            timestamp: delivery.timestamp,
+        }
+    }
+}
+
+/// The range of offsets the messages of a request were delivered to in a partition. Messages of
+/// other requests can be interleaved, so `count` can be lower than the size of the range.
+#[derive(Serialize)]
+pub struct PartitionOffsets {
+    pub partition: i32,
+    pub first_offset: i64,
+    pub last_offset: i64,
+    pub count: u64,
+}
+
+impl PartitionOffsets {
+    fn add(partition_offsets: &mut Vec<PartitionOffsets>, delivery: &Delivery) {
+        match partition_offsets
+            .iter_mut()
+            .find(|offsets| offsets.partition == delivery.partition)
+        {
+            Some(offsets) => {
+                offsets.first_offset = offsets.first_offset.min(delivery.offset);
+                offsets.last_offset = offsets.last_offset.max(delivery.offset);
+                offsets.count += 1;
+            }
+            None => partition_offsets.push(PartitionOffsets {
+                partition: delivery.partition,
+                first_offset: delivery.offset,
+                last_offset: delivery.offset,
+                count: 1,
+            }),
+        }
+    }
+}
+
+/// The bytes of a body buffered at once when at most `max_bytes` of it are: its `Content-Length`
+/// up to `max_bytes`, or `max_bytes` when it is chunked.
+pub fn buffered_size(headers: &HeaderMap, max_bytes: u64) -> u64 {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map_or(max_bytes, |length| length.min(max_bytes))
+}
+
 #[instrument(
     level = "debug",
     skip_all,
@@ -271,10 +508,12 @@ pub async fn forward(
     body_stream: impl Stream<Item = std::result::Result<Bytes, PayloadError>>,
     schema_id: &str,
     schema_config: &SchemaConfig,
-    header_names: &HeaderNames,
-    kafka: Kafka,
-    max_event_size_bytes: usize,
+    state: &ServerState,
+    wait_for_delivery: bool,
 ) -> IngestResponse {
+    let header_names = &state.header_names;
+    let kafka = &state.kafka;
+    let max_event_size_bytes = state.max_event_size_bytes as usize;
     let ip_address = req
         .connection_info()
         .realip_remote_addr()
@@ -321,16 +560,54 @@ pub async fn forward(
         ))
     }
 
-    let mut header_keys: Vec<String>;
-    if schema_config.forward_request_http_headers {
-        header_keys = Vec::with_capacity(req.headers().len());
-        for (k, _) in req.headers() {
-            header_keys.push(header_names.http_header_prefix.clone() + k.as_str());
+    if schema_config.geoip
+        && let Some(geoip) = &state.geoip
+    {
+        headers.extend(geoip.get().headers(&ip_address, header_names));
+    }
+
+    if schema_config.user_agent
+        && let Some(user_agent) = &state.user_agent
+        && let Some(user_agent_header) = req
+            .headers()
+            .get("user-agent")
+            .and_then(|h| h.to_str().ok())
+    {
+        headers.extend(user_agent.get().headers(user_agent_header, header_names));
+    }
+
+    let api_key_id = req.extensions().get::<ApiKeyId>().map(|id| id.0.clone());
+    if let Some(api_key_id) = &api_key_id {
+        headers.push((
+            header_names.api_key_id.clone(),
+            Bytes::from(api_key_id.clone()),
+        ));
+    }
+
+    let has_claims = match req.extensions().get::<ForwardedClaims>() {
+        Some(ForwardedClaims(claims)) => {
+            headers.extend(claims.iter().cloned());
+            true
         }
-        for (i, (_, v)) in req.headers().iter().enumerate() {
-            // header_keys.push(header_names.http_header_prefix.clone() + k.as_str());
-            let name = header_keys.index(i);
-            headers.push((name.clone(), Bytes::from(v.as_bytes().to_vec())))
+        None => false,
+    };
+
+    if schema_config.forward_request_http_headers {
+        for (k, v) in req.headers() {
+            // the key or token itself is never forwarded
+            if api_key_id.is_some()
+                && let Some(api_keys_config) = &state.api_keys_config
+                && auth::is_key_header(api_keys_config, k.as_str())
+            {
+                continue;
+            }
+            if has_claims && *k == AUTHORIZATION {
+                continue;
+            }
+            headers.push((
+                header_names.http_header_prefix.clone() + k.as_str(),
+                Bytes::from(v.as_bytes().to_vec()),
+            ))
         }
     }
 
@@ -345,6 +622,7 @@ pub async fn forward(
                 "application/x-ndjson" | "application/jsonlines" | "application/x-jsonlines" => {
                     ContentType::Jsonlines
                 }
+                "application/json-seq" => ContentType::JsonSeq,
                 "application/json" => ContentType::Json,
                 _ => ContentType::Binary,
             }
@@ -362,12 +640,53 @@ pub async fn forward(
     };
 
     tracing::Span::current().record("content_type", tracing::field::display(&content_type));
+    if schema_config.cloudevents {
+        let request_content_type = req
+            .headers()
+            .get("content-type")
+            .and_then(|h| h.to_str().ok());
+        match Mode::from_content_type(request_content_type) {
+            Mode::Binary => match cloudevents::binary_headers(req.headers()) {
+                Ok(cloudevents_headers) => headers.extend(cloudevents_headers),
+                Err(e) => {
+                    return IngestResponse::failed(
+                        schema_id,
+                        content_type,
+                        Error::from(ErrorBadRequest(e)),
+                    );
+                }
+            },
+            mode => {
+                return forward_cloudevents(
+                    &req,
+                    body_stream,
+                    mode,
+                    schema_id,
+                    schema_config,
+                    state,
+                    &headers,
+                    wait_for_delivery,
+                )
+                .await;
+            }
+        }
+    }
+    let preparation = Preparation {
+        is_json: content_type.is_json(),
+        ..Preparation::new(state, schema_id, req.headers(), &headers)
+    };
     let mut messages_received: u64 = 0;
     let mut messages_delivered: u64 = 0;
+    let mut messages_dropped: u64 = 0;
     let mut bytes_count: u128 = 0;
+    // of the messages enqueued, reported when the deliveries are not waited for
+    let mut bytes_received: u128 = 0;
     let mut error = None;
-    match content_type {
-        ContentType::Json | ContentType::Binary => {
+    let mut delivery = None;
+    let mut partition_offsets = Vec::new();
+    let mut pending_deliveries = None;
+    match schema_config.framing(&content_type) {
+        Framing::None => {
             messages_received = 1;
             tracing::Span::current().record("message_count", messages_received);
 
@@ -377,115 +696,131 @@ pub async fn forward(
             while let Some(item) = body_stream.next().await {
                 let chunk = match item {
                     Err(e) => {
-                        return IngestResponse {
-                            ingested_count: messages_delivered,
-                            ingested_bytes: bytes_count,
-                            ingested_content_type: content_type,
-                            ingested_schema_id: schema_id.to_owned(),
-                            error: Some(Error::from(actix_web::Error::from(e))),
-                        };
+                        return IngestResponse::failed(
+                            schema_id,
+                            content_type,
+                            Error::from(actix_web::Error::from(e)),
+                        );
                     }
                     Ok(item) => item,
                 };
                 body.extend_from_slice(&chunk);
                 if body.len() > max_event_size_bytes {
-                    return IngestResponse {
-                        ingested_count: messages_delivered,
-                        ingested_bytes: bytes_count,
-                        ingested_content_type: content_type,
-                        ingested_schema_id: schema_id.to_owned(),
-                        error: Some(Error::from(ErrorPayloadTooLarge(PayloadError::Overflow))),
-                    };
+                    return IngestResponse::failed(
+                        schema_id,
+                        content_type,
+                        Error::from(ErrorPayloadTooLarge(PayloadError::Overflow)),
+                    );
                 }
             }
 
-            let body = if let ContentType::Json = content_type {
-                let s = String::from_utf8(body.as_ref().to_vec());
-                match s {
-                    Err(e) => {
-                        return IngestResponse {
-                            ingested_count: messages_delivered,
-                            ingested_bytes: bytes_count,
-                            ingested_content_type: content_type,
-                            ingested_schema_id: schema_id.to_owned(),
-                            error: Some(Error::from(ErrorBadRequest(e))),
-                        };
-                    }
-                    Ok(s) => Bytes::from(s.trim().as_bytes().to_vec()),
+            let body = match preparation.prepare(body.freeze()) {
+                Err(e) => {
+                    return IngestResponse::failed(schema_id, content_type, e);
                 }
-            } else {
-                body.freeze()
+                Ok(None) => {
+                    state.metrics.record_dropped(1, schema_id);
+                    return IngestResponse {
+                        dropped_count: 1,
+                        ..IngestResponse::new(schema_id, content_type)
+                    };
+                }
+                Ok(Some(body)) => body,
             };
             bytes_count = body.len() as u128;
+            bytes_received = bytes_count;
 
-            let (delivered_tx, mut delivered_rx) = mpsc::channel(512);
+            let (delivered_tx, mut delivered_rx) = deliveries();
             send_to_kafka(
                 &body,
                 &headers,
-                &kafka,
+                kafka,
                 &schema_config.destination_topic,
                 &schema_config.librdkafka_config,
+                Records::single(body.len()),
                 delivered_tx,
-            )
-            .await;
+            );
 
-            match delivered_rx.recv().await.unwrap() {
-                Err(e) => {
-                    return IngestResponse {
-                        ingested_count: 0,
-                        ingested_bytes: 0,
-                        ingested_content_type: content_type,
-                        ingested_schema_id: schema_id.to_owned(),
-                        error: Some(Error::from(e)),
-                    };
+            // without waiting, the result is only ready when the message could not be enqueued
+            let result = if wait_for_delivery {
+                delivered_rx.recv().await
+            } else {
+                delivered_rx.try_recv()
+            };
+            match result {
+                None => {
+                    pending_deliveries = Some(PendingDeliveries {
+                        delivery_rx: delivered_rx,
+                        delivered_count: 0,
+                    });
                 }
-                Ok(_) => {
+                Some(Err(e)) => {
+                    return IngestResponse::failed(schema_id, content_type, Error::from(e));
+                }
+                Some(Ok(message_delivery)) => {
                     messages_delivered += 1;
+                    if schema_config.response_delivery {
+                        delivery = Some(DeliveredMessage::from(message_delivery));
+                    }
                 }
             };
         }
-        ContentType::Jsonlines => {
-            let (delivered_tx, mut delivered_rx) = mpsc::channel(512);
+        framing => {
+            let (delivered_tx, mut delivered_rx) = deliveries();
             let mut delivered_tx = Some(delivered_tx);
 
-            pin_mut!(body_stream);
-            // convert the bytes stream into an async read and use line codec framing to turn that
-            // into a lines stream
-            // https://docs.rs/tokio-util/latest/tokio_util/io/struct.StreamReader.html
-            // https://docs.rs/tokio/1.32.0/tokio/io/trait.AsyncBufReadExt.html#method.read_until
+            // only JSON records can be batched, the batch is a valid JSON lines message, with
+            // the records put on a single line, or a JSON array
+            let mut batch = schema_config
+                .batching
+                .as_ref()
+                .filter(|_| content_type.is_json())
+                .map(|batching_config| Batch::new(batching_config, max_event_size_bytes));
+            let max_frame_length = batch
+                .as_ref()
+                .map(|b| b.max_record_length())
+                .unwrap_or(max_event_size_bytes);
+            // enriched records must fit in a batch too
+            let preparation = Preparation {
+                max_length: max_frame_length,
+                ..preparation
+            };
 
-            let stream_reader = StreamReader::new(
+            pin_mut!(body_stream);
+            // split the bytes stream into a stream of messages, slicing them out of the received
+            // chunks where possible
+            let mut newline_stream = framing::frames(
                 body_stream
-                    // StreamReader needs errors to be std::io::Error, so convert them
+                    // frames are read with std::io::Error errors, so convert them
                     .map(|result| result.map_err(std::io::Error::other)),
-            );
-
-            let mut newline_stream = FramedRead::new(
-                stream_reader,
-                LinesCodec::new_with_max_length(max_event_size_bytes),
+                &framing,
+                max_frame_length,
             );
 
             let mut newline_stream_done = false;
 
             loop {
                 // 2 select branches
-                // 1. listens to newlines arriving from the stream and send it to kafka without
+                // 1. listens to frames arriving from the stream and send it to kafka without
                 // waiting for delivery. kafka delivery callback will put the result in the delivery
                 // channel
                 // 2. listens to the delivery channel
-                // once listening to new lines is done or has an error, and no more deliveries are
+                // once listening to new frames is done or has an error, and no more deliveries are
                 // pending, the loop exits and the response can be sent
                 tokio::select! {
                     line_opt = newline_stream.next(), if !newline_stream_done => {
                         if let Some(line) = line_opt {
                             match line.map_err(|e| {
                                 return match e {
-                                    LinesCodecError::MaxLineLengthExceeded => {
+                                    FrameError::MaxLengthExceeded => {
                                         Error::from(ErrorPayloadTooLarge(PayloadError::Overflow))
                                     }
-                                    LinesCodecError::Io(io_error) => Error::from(io_error),
+                                    FrameError::Truncated => {
+                                        Error::from(ErrorBadRequest(FrameError::Truncated))
+                                    }
+                                    FrameError::Io(io_error) => Error::from(io_error),
                                 };
-                            }) {
+                            }).and_then(|data| preparation.prepare(data)) {
                                 Err(e) => {
                                     // on error set the current error and stop reading the request
                                     // stream. don't exit early to give a chance to in-flight
@@ -493,87 +828,336 @@ pub async fn forward(
                                     // listener
                                     error = Some(e);
                                     newline_stream_done = true; // disable this select branch
+                                    // records received before the error are still sent
+                                    if let Some((payload, records)) = batch.as_mut().and_then(|b| b.take()) {
+                                        send_batch_to_kafka(
+                                            &payload,
+                                            &headers,
+                                            header_names,
+                                            kafka,
+                                            schema_config,
+                                            records,
+                                            delivered_tx.as_ref().cloned().unwrap(),
+                                        );
+                                    }
                                     delivered_tx.take();
                                     // drop the original sender here, so that once the remaining senders
                                     // that belong to spawned produce tasks are dropped, the receiver
                                     // returns None
                                 },
-                                Ok(data) => {
-                                    let data = data.trim(); // XXX: create new codec that returns bytes, not string
+                                Ok(None) => {
+                                    messages_dropped += 1;
+                                    trace!(messages_received, messages_dropped, "Frame dropped");
+                                }
+                                Ok(Some(data)) => {
                                     if !data.is_empty() {
                                         messages_received += 1;
-                                        trace!(messages_received, messages_delivered, "JSON received");
+                                        bytes_received += data.len() as u128;
+                                        trace!(messages_received, messages_delivered, "Frame received");
                                         tracing::Span::current().record("message_count", messages_received);
                                         let delivered_tx = delivered_tx.as_ref().cloned().unwrap();
-                                        send_to_kafka(
-                                            data.as_ref(),
-                                            &headers,
-                                            &kafka,
-                                            schema_config.destination_topic.as_str(),
-                                            schema_config.librdkafka_config.as_str(),
-                                            delivered_tx
-                                        ).await;
+                                        if let Some(batch) = batch.as_mut() {
+                                            if let Some((payload, records)) = batch.push(&data) {
+                                                send_batch_to_kafka(
+                                                    &payload,
+                                                    &headers,
+                                                    header_names,
+                                                    kafka,
+                                                    schema_config,
+                                                    records,
+                                                    delivered_tx,
+                                                );
+                                            }
+                                        } else {
+                                            send_to_kafka(
+                                                data.as_ref(),
+                                                &headers,
+                                                kafka,
+                                                schema_config.destination_topic.as_str(),
+                                                schema_config.librdkafka_config.as_str(),
+                                                Records::single(data.len()),
+                                                delivered_tx
+                                            );
+                                        }
                                     }
                                 }
                             }
                         } else {
-                            trace!(messages_received, messages_delivered, "end of JSON lines received");
+                            trace!(messages_received, messages_delivered, "end of frames received");
                             newline_stream_done = true; // disable this select branch
+                            if let Some((payload, records)) = batch.as_mut().and_then(|b| b.take()) {
+                                send_batch_to_kafka(
+                                    &payload,
+                                    &headers,
+                                    header_names,
+                                    kafka,
+                                    schema_config,
+                                    records,
+                                    delivered_tx.as_ref().cloned().unwrap(),
+                                );
+                            }
                             delivered_tx.take();
                             // drop the original sender here, so that once the remaining senders
                             // that belong to spawned produce tasks are dropped, the receiver
                             // returns None
                         }
                     }
-                    Some(res) = delivered_rx.recv() => {
+                    // once the frames are sent, the deliveries are left pending when not waited for
+                    Some(res) = delivered_rx.recv(), if wait_for_delivery || !newline_stream_done => {
                         match res {
                             Err(e) => {
                                 // when a message fails to be delivered, we need to return its error
                                 // since we cannot close the connection, we must wait for the client
                                 // to close it. so accumulate all the errors and return them with
                                 // the response. TODO, for now return the first delivery error only
-                                trace!(messages_received, messages_delivered, "JSON line kafka delivery error '{e}'");
+                                trace!(messages_received, messages_delivered, "Frame kafka delivery error '{e}'");
                                 // don't overwrite an error already set by a request stream error or
                                 // by an earlier delivery error
                                 if error.is_none() {
                                     error = Some(Error::from(e))
                                 }
                             },
-                            Ok(data_len) => {
-                                bytes_count += data_len as u128;
-                                messages_delivered += 1;
-                                trace!(messages_received, messages_delivered, "JSON line delivered to kafka");
+                            Ok(delivery) => {
+                                bytes_count += delivery.records.bytes as u128;
+                                messages_delivered += delivery.records.count;
+                                if schema_config.response_partition_offsets {
+                                    PartitionOffsets::add(&mut partition_offsets, &delivery);
+                                }
+                                trace!(messages_received, messages_delivered, "Frame delivered to kafka");
                             }
                         }
                     }
                     else => {
-                        trace!(messages_received, messages_delivered, "no more JSON lines or kafka deliveries to wait from, returning response");
+                        trace!(messages_received, messages_delivered, "no more frames or kafka deliveries to wait from, returning response");
                         break
                     },
                 };
             }
+            if !wait_for_delivery {
+                // the results already received, like those of the messages that could not be
+                // enqueued, are still part of the response
+                while let Some(res) = delivered_rx.try_recv() {
+                    match res {
+                        Err(e) => {
+                            if error.is_none() {
+                                error = Some(Error::from(e))
+                            }
+                        }
+                        Ok(delivery) => {
+                            bytes_count += delivery.records.bytes as u128;
+                            messages_delivered += delivery.records.count;
+                            if schema_config.response_partition_offsets {
+                                PartitionOffsets::add(&mut partition_offsets, &delivery);
+                            }
+                        }
+                    }
+                }
+                pending_deliveries = Some(PendingDeliveries {
+                    delivery_rx: delivered_rx,
+                    delivered_count: messages_delivered,
+                });
+            }
         }
     };
+    if messages_dropped > 0 {
+        state.metrics.record_dropped(messages_dropped, schema_id);
+    }
+    partition_offsets.sort_by_key(|offsets| offsets.partition);
+    let (ingested_count, ingested_bytes) = if wait_for_delivery {
+        (messages_delivered, bytes_count)
+    } else {
+        (messages_received, bytes_received)
+    };
     IngestResponse {
-        ingested_count: messages_delivered,
-        ingested_bytes: bytes_count,
-        ingested_content_type: content_type,
-        ingested_schema_id: schema_id.to_owned(),
+        ingested_count,
+        ingested_bytes,
+        dropped_count: messages_dropped,
+        delivery,
+        partition_offsets,
+        pending_deliveries,
         error,
+        ..IngestResponse::new(schema_id, content_type)
     }
 }
 
-pub async fn send_to_kafka(
+/// Forwards the events of a CloudEvents structured mode request, each as a message of its data
+/// with its attributes as headers. The events are all prepared before any is sent.
+#[allow(clippy::too_many_arguments)]
+async fn forward_cloudevents(
+    req: &HttpRequest,
+    body_stream: impl Stream<Item = std::result::Result<Bytes, PayloadError>>,
+    mode: Mode,
+    schema_id: &str,
+    schema_config: &SchemaConfig,
+    state: &ServerState,
+    headers: &[(String, Bytes)],
+    wait_for_delivery: bool,
+) -> IngestResponse {
+    let response = |ingested_count, ingested_bytes, dropped_count, error| IngestResponse {
+        ingested_count,
+        ingested_bytes,
+        dropped_count,
+        error,
+        ..IngestResponse::new(schema_id, ContentType::Json)
+    };
+    let body = match read_body(body_stream, state.max_event_size_bytes as usize).await {
+        Ok(body) => body,
+        Err(e) => return response(0, 0, 0, Some(e)),
+    };
+    let events = match cloudevents::structured(&body, mode) {
+        Ok(events) => events,
+        Err(e) => return response(0, 0, 0, Some(Error::from(ErrorBadRequest(e)))),
+    };
+    tracing::Span::current().record("message_count", events.len());
+
+    let mut messages = Vec::with_capacity(events.len());
+    let mut messages_dropped = 0;
+    for event in events {
+        let mut event_headers = headers.to_vec();
+        event_headers.extend(event.headers);
+        let preparation = Preparation {
+            is_json: event.is_json,
+            ..Preparation::new(state, schema_id, req.headers(), &event_headers)
+        };
+        match preparation.prepare(event.data) {
+            Ok(Some(data)) => messages.push((data, event_headers)),
+            Ok(None) => messages_dropped += 1,
+            Err(e) => return response(0, 0, 0, Some(e)),
+        }
+    }
+    if messages_dropped > 0 {
+        state.metrics.record_dropped(messages_dropped, schema_id);
+    }
+
+    let (delivery_tx, mut delivery_rx) = deliveries();
+    for (data, event_headers) in &messages {
+        send_to_kafka(
+            data,
+            event_headers,
+            &state.kafka,
+            &schema_config.destination_topic,
+            &schema_config.librdkafka_config,
+            Records::single(data.len()),
+            delivery_tx.clone(),
+        );
+    }
+    drop(delivery_tx);
+    let mut messages_delivered = 0;
+    let mut bytes_count = 0;
+    let mut error = None;
+    let mut delivery = None;
+    let mut partition_offsets = Vec::new();
+    // without waiting, only the results already received are part of the response
+    while let Some(result) = if wait_for_delivery {
+        delivery_rx.recv().await
+    } else {
+        delivery_rx.try_recv()
+    } {
+        match result {
+            Ok(message_delivery) => {
+                messages_delivered += message_delivery.records.count;
+                bytes_count += message_delivery.records.bytes as u128;
+                if mode == Mode::Structured {
+                    if schema_config.response_delivery {
+                        delivery = Some(DeliveredMessage::from(message_delivery));
+                    }
+                } else if schema_config.response_partition_offsets {
+                    PartitionOffsets::add(&mut partition_offsets, &message_delivery);
+                }
+            }
+            // the first delivery error is returned
+            Err(e) => {
+                if error.is_none() {
+                    error = Some(Error::from(e));
+                }
+            }
+        }
+    }
+    partition_offsets.sort_by_key(|offsets| offsets.partition);
+    if !wait_for_delivery {
+        let bytes_received = messages.iter().map(|(data, _)| data.len() as u128).sum();
+        return IngestResponse {
+            delivery,
+            partition_offsets,
+            pending_deliveries: Some(PendingDeliveries {
+                delivery_rx,
+                delivered_count: messages_delivered,
+            }),
+            ..response(
+                messages.len() as u64,
+                bytes_received,
+                messages_dropped,
+                error,
+            )
+        };
+    }
+    IngestResponse {
+        delivery,
+        partition_offsets,
+        ..response(messages_delivered, bytes_count, messages_dropped, error)
+    }
+}
+
+/// Reads a whole body, failing once it exceeds `max_size`.
+async fn read_body(
+    body_stream: impl Stream<Item = std::result::Result<Bytes, PayloadError>>,
+    max_size: usize,
+) -> Result<Bytes> {
+    let mut body = web::BytesMut::new();
+    pin_mut!(body_stream);
+    while let Some(chunk) = body_stream.next().await {
+        body.extend_from_slice(&chunk.map_err(|e| Error::from(actix_web::Error::from(e)))?);
+        if body.len() > max_size {
+            return Err(Error::from(ErrorPayloadTooLarge(PayloadError::Overflow)));
+        }
+    }
+    Ok(body.freeze())
+}
+
+/// Sends a batch of records as a single message, with a header recording the record count.
+fn send_batch_to_kafka(
+    payload: &[u8],
+    headers: &[(String, Bytes)],
+    header_names: &HeaderNames,
+    kafka: &Kafka,
+    schema_config: &SchemaConfig,
+    records: Records,
+    delivery_tx: DeliverySender,
+) {
+    let mut batch_headers = headers.to_vec();
+    batch_headers.push((
+        header_names.record_count.clone(),
+        Bytes::from(records.count.to_string()),
+    ));
+    send_to_kafka(
+        payload,
+        &batch_headers,
+        kafka,
+        &schema_config.destination_topic,
+        &schema_config.librdkafka_config,
+        records,
+        delivery_tx,
+    );
+}
+
+pub fn send_to_kafka(
     data: &[u8],
     headers: &[(String, Bytes)],
     kafka: &Kafka,
     topic: &str,
     producer_name: &str,
-    delivery_tx: mpsc::Sender<std::result::Result<usize, KafkaError>>,
+    records: Records,
+    delivery_tx: DeliverySender,
 ) {
-    if let Err(e) = kafka.send(data, headers, topic, producer_name, delivery_tx.clone())
-        && delivery_tx.send(Err(e)).await.is_err()
-    {
-        panic!("Could not send delivery result, the delivery result channel has been closed")
+    if let Err(e) = kafka.send(
+        data,
+        None,
+        headers,
+        topic,
+        producer_name,
+        records,
+        delivery_tx.clone(),
+    ) {
+        delivery_tx.send(Err(e));
     }
 }
@@ -1,2 +1,91 @@
+use actix_web::HttpRequest;
+use bytes::Bytes;
+use rdkafka::error::KafkaError;
+
+use crate::config::SchemaConfig;
+use crate::kafka::{Records, deliveries};
+use crate::server::ServerState;
+
+pub mod elasticsearch;
+pub mod grpc;
+pub mod hec;
 pub mod http;
-// pub mod ws;
+pub mod otlp;
+mod prepare;
+pub mod rest_proxy;
+pub mod socket;
+pub mod ws;
+
+/// The metadata Kafka headers of messages received on connections other than HTTP requests.
+pub fn metadata_headers(
+    state: &ServerState,
+    schema_id: &str,
+    schema_config: &SchemaConfig,
+    ip_address: &str,
+) -> Vec<(String, Bytes)> {
+    let header_names = &state.header_names;
+    let mut headers: Vec<(String, Bytes)> = vec![
+        (
+            header_names.schema_id.clone(),
+            Bytes::copy_from_slice(schema_id.as_bytes()),
+        ),
+        (
+            header_names.ip.clone(),
+            Bytes::copy_from_slice(ip_address.as_bytes()),
+        ),
+    ];
+    if schema_config.forward_ingest_version {
+        headers.push((
+            header_names.ingest_version.clone(),
+            Bytes::from(crate::PKG_VERSION),
+        ));
+    }
+    if schema_config.geoip
+        && let Some(geoip) = &state.geoip
+    {
+        headers.extend(geoip.get().headers(ip_address, header_names));
+    }
+    headers
+}
+
+/// The IP address of the client of an HTTP request, from the forwarding headers when set.
+pub fn ip_address(req: &HttpRequest) -> String {
+    req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or("")
+        .to_owned()
+}
+
+/// Sends the messages of a request to the topic of its schema and waits for their delivery.
+/// Sending stops at the first message that fails to be sent, and the messages already sent are
+/// awaited in any case. Returns the last error.
+pub async fn send_all(
+    state: &ServerState,
+    schema_config: &SchemaConfig,
+    headers: &[(String, Bytes)],
+    messages: &[Bytes],
+) -> Result<(), KafkaError> {
+    let mut failed = None;
+    let (delivery_tx, mut delivery_rx) = deliveries();
+    for message in messages {
+        if let Err(e) = state.kafka.send(
+            message,
+            None,
+            headers,
+            &schema_config.destination_topic,
+            &schema_config.librdkafka_config,
+            Records::single(message.len()),
+            delivery_tx.clone(),
+        ) {
+            failed = Some(e);
+            break;
+        }
+    }
+    drop(delivery_tx);
+    while let Some(result) = delivery_rx.recv().await {
+        if let Err(e) = result {
+            failed = Some(e);
+        }
+    }
+    failed.map_or(Ok(()), Err)
+}
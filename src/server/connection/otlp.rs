@@ -0,0 +1,398 @@
+//! OTLP/HTTP receiver. Export requests are split into a Kafka message per resource, encoded as an
+//! export request of that resource only, or into a JSON message per log record, span or metric
+//! along with its resource and scope. The messages of each signal are sent using the config of its
+//! schema, and the export succeeds once they are all delivered.
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{HttpRequest, HttpResponse, web};
+use bytes::Bytes;
+use opentelemetry_proto::tonic::collector::logs::v1::{
+    ExportLogsPartialSuccess, ExportLogsServiceRequest, ExportLogsServiceResponse,
+};
+use opentelemetry_proto::tonic::collector::metrics::v1::{
+    ExportMetricsPartialSuccess, ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+};
+use opentelemetry_proto::tonic::collector::trace::v1::{
+    ExportTracePartialSuccess, ExportTraceServiceRequest, ExportTraceServiceResponse,
+};
+use opentelemetry_proto::tonic::metrics::v1::Metric;
+use opentelemetry_proto::tonic::metrics::v1::metric::Data;
+use prost::Message;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use tracing::{instrument, warn};
+
+use crate::config::{OtlpConfig, OtlpFormat, OtlpSignalConfig};
+use crate::server::connection::prepare::Preparation;
+use crate::server::connection::{ip_address, metadata_headers, send_all};
+use crate::server::{ServerState, WSError};
+
+const PROTOBUF: &str = "application/x-protobuf";
+const JSON: &str = "application/json";
+
+// google.rpc.Code values
+const INVALID_ARGUMENT: i32 = 3;
+const UNAVAILABLE: i32 = 14;
+
+/// The body of failed responses, a google.rpc.Status without details.
+#[derive(Clone, PartialEq, Message, Serialize)]
+struct Status {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+}
+
+/// The encoding of a request, which its response is encoded with too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Protobuf,
+    Json,
+}
+
+impl Encoding {
+    fn from_request(req: &HttpRequest) -> Option<Encoding> {
+        let content_type = req.headers().get(CONTENT_TYPE)?.to_str().ok()?;
+        match content_type.split(';').next()?.trim() {
+            PROTOBUF => Some(Encoding::Protobuf),
+            JSON => Some(Encoding::Json),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Encoding::Protobuf => PROTOBUF,
+            Encoding::Json => JSON,
+        }
+    }
+
+    fn decode<T: Message + Default + DeserializeOwned>(self, body: &[u8]) -> Result<T, String> {
+        match self {
+            Encoding::Protobuf => T::decode(body).map_err(|e| e.to_string()),
+            Encoding::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn response<T: Message + Serialize>(self, status: StatusCode, message: &T) -> HttpResponse {
+        let body = match self {
+            Encoding::Protobuf => message.encode_to_vec(),
+            Encoding::Json => serde_json::to_vec(message).expect("OTLP messages serialize to JSON"),
+        };
+        HttpResponse::build(status)
+            .content_type(self.content_type())
+            .body(body)
+    }
+
+    fn error(self, status: StatusCode, code: i32, message: String) -> HttpResponse {
+        self.response(status, &Status { code, message })
+    }
+}
+
+/// The export request of a signal.
+pub trait Signal: Message + Default + DeserializeOwned {
+    type Response: Message + Serialize;
+
+    fn config(otlp: &OtlpConfig) -> Option<&OtlpSignalConfig>;
+
+    /// Splits the request into a request per resource, with the number of items of each.
+    fn split(self) -> Vec<(Self, u64)>;
+
+    /// The items with their resource and scope as JSON objects, with the number of items each
+    /// one counts for.
+    fn json_items(self) -> Vec<(Value, u64)>;
+
+    fn response(partial_success: Option<(i64, String)>) -> Self::Response;
+}
+
+impl Signal for ExportLogsServiceRequest {
+    type Response = ExportLogsServiceResponse;
+
+    fn config(otlp: &OtlpConfig) -> Option<&OtlpSignalConfig> {
+        otlp.logs.as_ref()
+    }
+
+    fn split(self) -> Vec<(Self, u64)> {
+        self.resource_logs
+            .into_iter()
+            .map(|resource_logs| {
+                let count = resource_logs
+                    .scope_logs
+                    .iter()
+                    .map(|scope_logs| scope_logs.log_records.len() as u64)
+                    .sum();
+                let request = ExportLogsServiceRequest {
+                    resource_logs: vec![resource_logs],
+                };
+                (request, count)
+            })
+            .collect()
+    }
+
+    fn json_items(self) -> Vec<(Value, u64)> {
+        let mut items = Vec::new();
+        for resource_logs in self.resource_logs {
+            for scope_logs in resource_logs.scope_logs {
+                for log_record in scope_logs.log_records {
+                    items.push((
+                        json!({
+                            "resource": resource_logs.resource,
+                            "scope": scope_logs.scope,
+                            "logRecord": log_record,
+                        }),
+                        1,
+                    ));
+                }
+            }
+        }
+        items
+    }
+
+    fn response(partial_success: Option<(i64, String)>) -> Self::Response {
+        ExportLogsServiceResponse {
+            partial_success: partial_success.map(|(rejected_log_records, error_message)| {
+                ExportLogsPartialSuccess {
+                    rejected_log_records,
+                    error_message,
+                }
+            }),
+        }
+    }
+}
+
+impl Signal for ExportTraceServiceRequest {
+    type Response = ExportTraceServiceResponse;
+
+    fn config(otlp: &OtlpConfig) -> Option<&OtlpSignalConfig> {
+        otlp.traces.as_ref()
+    }
+
+    fn split(self) -> Vec<(Self, u64)> {
+        self.resource_spans
+            .into_iter()
+            .map(|resource_spans| {
+                let count = resource_spans
+                    .scope_spans
+                    .iter()
+                    .map(|scope_spans| scope_spans.spans.len() as u64)
+                    .sum();
+                let request = ExportTraceServiceRequest {
+                    resource_spans: vec![resource_spans],
+                };
+                (request, count)
+            })
+            .collect()
+    }
+
+    fn json_items(self) -> Vec<(Value, u64)> {
+        let mut items = Vec::new();
+        for resource_spans in self.resource_spans {
+            for scope_spans in resource_spans.scope_spans {
+                for span in scope_spans.spans {
+                    items.push((
+                        json!({
+                            "resource": resource_spans.resource,
+                            "scope": scope_spans.scope,
+                            "span": span,
+                        }),
+                        1,
+                    ));
+                }
+            }
+        }
+        items
+    }
+
+    fn response(partial_success: Option<(i64, String)>) -> Self::Response {
+        ExportTraceServiceResponse {
+            partial_success: partial_success.map(|(rejected_spans, error_message)| {
+                ExportTracePartialSuccess {
+                    rejected_spans,
+                    error_message,
+                }
+            }),
+        }
+    }
+}
+
+/// Metrics are counted by data points, like the partial success of their exports.
+fn data_points(metric: &Metric) -> u64 {
+    let count = match &metric.data {
+        Some(Data::Gauge(gauge)) => gauge.data_points.len(),
+        Some(Data::Sum(sum)) => sum.data_points.len(),
+        Some(Data::Histogram(histogram)) => histogram.data_points.len(),
+        Some(Data::ExponentialHistogram(histogram)) => histogram.data_points.len(),
+        Some(Data::Summary(summary)) => summary.data_points.len(),
+        None => 0,
+    };
+    count as u64
+}
+
+impl Signal for ExportMetricsServiceRequest {
+    type Response = ExportMetricsServiceResponse;
+
+    fn config(otlp: &OtlpConfig) -> Option<&OtlpSignalConfig> {
+        otlp.metrics.as_ref()
+    }
+
+    fn split(self) -> Vec<(Self, u64)> {
+        self.resource_metrics
+            .into_iter()
+            .map(|resource_metrics| {
+                let count = resource_metrics
+                    .scope_metrics
+                    .iter()
+                    .flat_map(|scope_metrics| &scope_metrics.metrics)
+                    .map(data_points)
+                    .sum();
+                let request = ExportMetricsServiceRequest {
+                    resource_metrics: vec![resource_metrics],
+                };
+                (request, count)
+            })
+            .collect()
+    }
+
+    fn json_items(self) -> Vec<(Value, u64)> {
+        let mut items = Vec::new();
+        for resource_metrics in self.resource_metrics {
+            for scope_metrics in resource_metrics.scope_metrics {
+                for metric in scope_metrics.metrics {
+                    let count = data_points(&metric);
+                    items.push((
+                        json!({
+                            "resource": resource_metrics.resource,
+                            "scope": scope_metrics.scope,
+                            "metric": metric,
+                        }),
+                        count,
+                    ));
+                }
+            }
+        }
+        items
+    }
+
+    fn response(partial_success: Option<(i64, String)>) -> Self::Response {
+        ExportMetricsServiceResponse {
+            partial_success: partial_success.map(|(rejected_data_points, error_message)| {
+                ExportMetricsPartialSuccess {
+                    rejected_data_points,
+                    error_message,
+                }
+            }),
+        }
+    }
+}
+
+/// The items rejected from an export, with the reason of the first rejection.
+#[derive(Default)]
+struct Rejected {
+    count: u64,
+    error_message: Option<String>,
+}
+
+impl Rejected {
+    fn add(&mut self, count: u64, error_message: impl FnOnce() -> String) {
+        self.count += count;
+        self.error_message.get_or_insert_with(error_message);
+    }
+
+    fn partial_success(self) -> Option<(i64, String)> {
+        self.error_message
+            .map(|error_message| (self.count as i64, error_message))
+    }
+}
+
+pub async fn export<S: Signal>(
+    req: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let Some(config) = state.otlp.as_ref().and_then(S::config) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let Some(encoding) = Encoding::from_request(&req) else {
+        return HttpResponse::UnsupportedMediaType().body(format!(
+            "Export requests must be either {} or {}",
+            PROTOBUF, JSON
+        ));
+    };
+    let request: S = match encoding.decode(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return encoding.error(
+                StatusCode::BAD_REQUEST,
+                INVALID_ARGUMENT,
+                format!("Invalid export request: {}", e),
+            );
+        }
+    };
+    match send(&req, request, config, &state).await {
+        Ok(rejected) => encoding.response(StatusCode::OK, &S::response(rejected.partial_success())),
+        // the whole request is retried by the client
+        Err(message) => encoding.error(StatusCode::SERVICE_UNAVAILABLE, UNAVAILABLE, message),
+    }
+}
+
+/// Sends the messages of an export request to Kafka and waits for their delivery. Returns the
+/// rejected items, or why the request should be retried.
+#[instrument(level = "debug", skip_all, fields(schema_id = config.schema_id.as_str()))]
+async fn send<S: Signal>(
+    req: &HttpRequest,
+    request: S,
+    config: &OtlpSignalConfig,
+    state: &ServerState,
+) -> Result<Rejected, String> {
+    let schema_id = config.schema_id.as_str();
+    let schema_config = state.schema_config(schema_id);
+    let headers = metadata_headers(state, schema_id, schema_config, &ip_address(req));
+    let preparation = Preparation {
+        is_json: config.format == OtlpFormat::Json,
+        ..Preparation::new(state, schema_id, req.headers(), &headers)
+    };
+
+    let messages: Vec<(Bytes, u64)> = match config.format {
+        OtlpFormat::Protobuf => request
+            .split()
+            .into_iter()
+            .map(|(request, count)| (Bytes::from(request.encode_to_vec()), count))
+            .collect(),
+        OtlpFormat::Json => request
+            .json_items()
+            .into_iter()
+            .map(|(item, count)| {
+                let item = serde_json::to_vec(&item).expect("JSON values serialize");
+                (Bytes::from(item), count)
+            })
+            .collect(),
+    };
+
+    let mut rejected = Rejected::default();
+    let mut prepared = Vec::with_capacity(messages.len());
+    for (message, count) in messages {
+        if message.len() > state.max_event_size_bytes as usize {
+            rejected.add(count, || {
+                format!(
+                    "Messages are limited to {} bytes",
+                    state.max_event_size_bytes
+                )
+            });
+            continue;
+        }
+        match preparation.prepare(message) {
+            Ok(Some(message)) => prepared.push(message),
+            Ok(None) => state.metrics.record_dropped(1, schema_id),
+            Err(e) => rejected.add(count, || e.message()),
+        }
+    }
+    match send_all(state, schema_config, &headers, &prepared).await {
+        Ok(()) => Ok(rejected),
+        Err(e) => {
+            warn!(schema_id, "Failed to send export request: {}", e);
+            Err("Failed to deliver the export request".to_owned())
+        }
+    }
+}
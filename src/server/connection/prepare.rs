@@ -0,0 +1,91 @@
+use actix_web::error::{ErrorBadRequest, ErrorPayloadTooLarge, PayloadError};
+use actix_web::http::header::HeaderMap;
+use bytes::Bytes;
+
+use crate::config::SchemaConfig;
+use crate::enrich::enrich;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::framing;
+use crate::redact::Redactor;
+use crate::server::ServerState;
+
+/// What is done to each message before it is sent to Kafka.
+pub struct Preparation<'a> {
+    pub is_json: bool,
+    pub schema_id: &'a str,
+    pub schema_config: &'a SchemaConfig,
+    pub filter: Option<&'a Filter>,
+    pub redactor: Option<&'a Redactor>,
+    pub request_headers: &'a HeaderMap,
+    // the metadata forwarded as Kafka headers
+    pub headers: &'a [(String, Bytes)],
+    // the max length of messages once prepared
+    pub max_length: usize,
+}
+
+impl<'a> Preparation<'a> {
+    /// The preparation of the messages of a schema received other than by an ingest request,
+    /// which are JSON unless the schema has another content type.
+    pub fn new(
+        state: &'a ServerState,
+        schema_id: &'a str,
+        request_headers: &'a HeaderMap,
+        headers: &'a [(String, Bytes)],
+    ) -> Preparation<'a> {
+        let schema_config = state.schema_config(schema_id);
+        Preparation {
+            is_json: schema_config
+                .content_type
+                .as_ref()
+                .is_none_or(|content_type| content_type.is_json()),
+            schema_id,
+            schema_config,
+            filter: state.filter(schema_id),
+            redactor: state.redactor(schema_id),
+            request_headers,
+            headers,
+            max_length: state.max_event_size_bytes as usize,
+        }
+    }
+
+    /// Trims JSON messages, validates them as UTF-8, and redacts them and adds the metadata to
+    /// them if configured. Other messages are left as is. Returns None for messages dropped by
+    /// the filter.
+    pub fn prepare(&self, data: Bytes) -> Result<Option<Bytes>> {
+        if !self.is_json {
+            return Ok(self.keep(&data).then_some(data));
+        }
+        let data = framing::trim(data);
+        if self.schema_config.validate_utf8 {
+            std::str::from_utf8(&data).map_err(|e| Error::from(ErrorBadRequest(e)))?;
+        }
+        if data.is_empty() {
+            return Ok(Some(data));
+        }
+        if !self.keep(&data) {
+            return Ok(None);
+        }
+        let data = match self.redactor {
+            Some(redactor) => redactor
+                .redact(data, self.schema_id)
+                .map_err(|e| Error::from(ErrorBadRequest(e)))?,
+            None => data,
+        };
+        if let Some(enrich_config) = &self.schema_config.enrich {
+            let data = enrich(&data, self.headers, enrich_config)
+                .map_err(|e| Error::from(ErrorBadRequest(e)))?;
+            // the added metadata can take a message over the max length
+            if data.len() > self.max_length {
+                return Err(Error::from(ErrorPayloadTooLarge(PayloadError::Overflow)));
+            }
+            return Ok(Some(data));
+        }
+        Ok(Some(data))
+    }
+
+    fn keep(&self, data: &[u8]) -> bool {
+        self.filter
+            .is_none_or(|filter| filter.keep(data, self.is_json, self.request_headers))
+    }
+}
@@ -0,0 +1,274 @@
+//! Kafka REST Proxy produce API. The records of clients are produced as is to the allowed topics,
+//! without any schema config, and the response reports the partition and offset of each record
+//! once delivered.
+
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{HttpRequest, HttpResponse, web};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use serde_json::{Value, json};
+use tracing::{instrument, warn};
+
+use crate::config::{RestProxyConfig, RestProxyTopic};
+use crate::kafka::{self, Delivery, DeliveryReceiver, Records, deliveries};
+use crate::rest_proxy::{self, Data, ProduceRecord, RestProxyError};
+use crate::server::ServerState;
+
+const V2_CONTENT_TYPE: &str = "application/vnd.kafka.v2+json";
+
+fn config(state: &ServerState) -> &RestProxyConfig {
+    state
+        .rest_proxy
+        .as_ref()
+        .expect("The REST proxy API is only served when configured")
+}
+
+fn topic<'a>(state: &'a ServerState, name: &str) -> Option<&'a RestProxyTopic> {
+    config(state).topic.iter().find(|topic| topic.name == name)
+}
+
+fn v2_error(status: StatusCode, error_code: u32, message: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(V2_CONTENT_TYPE)
+        .json(json!({"error_code": error_code, "message": message}))
+}
+
+fn v3_error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({"error_code": status.as_u16(), "message": message}))
+}
+
+#[instrument(level = "debug", skip_all, fields(topic))]
+pub async fn v2_produce(
+    req: HttpRequest,
+    body: web::Bytes,
+    topic_name: web::Path<String>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    tracing::Span::current().record("topic", topic_name.as_str());
+    let Some(topic) = topic(&state, &topic_name) else {
+        return v2_error(StatusCode::NOT_FOUND, 40401, "Topic not found.");
+    };
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let Some(format) = rest_proxy::v2_format(content_type) else {
+        return v2_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            415,
+            "Only the JSON and binary embedded formats are supported.",
+        );
+    };
+    let records = match rest_proxy::v2_records(&body, format) {
+        Ok(records) => records,
+        Err(e) => return v2_error(StatusCode::UNPROCESSABLE_ENTITY, 42201, &e.to_string()),
+    };
+
+    // all records are sent before any delivery is awaited
+    let sent: Vec<_> = records
+        .iter()
+        .map(|record| send(&state, topic, record))
+        .collect();
+    let mut offsets = Vec::with_capacity(sent.len());
+    for sent in sent {
+        offsets.push(match delivered(sent).await {
+            Ok(delivery) => json!({
+                "partition": delivery.partition,
+                "offset": delivery.offset,
+                "error_code": null,
+                "error": null,
+            }),
+            // 1 for non retriable errors, 2 for retriable ones
+            Err(e) => json!({
+                "partition": null,
+                "offset": null,
+                "error_code": if e.is_retriable() { 2 } else { 1 },
+                "error": e.to_string(),
+            }),
+        });
+    }
+    HttpResponse::Ok()
+        .content_type(V2_CONTENT_TYPE)
+        .json(json!({
+            "key_schema_id": null,
+            "value_schema_id": null,
+            "offsets": offsets,
+        }))
+}
+
+pub async fn v3_clusters(state: web::Data<ServerState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "kind": "KafkaClusterList",
+        "data": [{"kind": "KafkaCluster", "cluster_id": config(&state).cluster_id}],
+    }))
+}
+
+/// The result of a single record is returned with its status, the results of several records are
+/// returned one per line.
+#[instrument(level = "debug", skip_all, fields(topic))]
+pub async fn v3_produce(
+    body: web::Bytes,
+    path: web::Path<(String, String)>,
+    state: web::Data<ServerState>,
+) -> HttpResponse {
+    let (cluster_id, topic_name) = path.into_inner();
+    tracing::Span::current().record("topic", topic_name.as_str());
+    let config = config(&state);
+    if cluster_id != config.cluster_id {
+        return v3_error(
+            StatusCode::NOT_FOUND,
+            &format!("Cluster {} cannot be found.", cluster_id),
+        );
+    }
+    let Some(topic) = topic(&state, &topic_name) else {
+        return v3_error(
+            StatusCode::NOT_FOUND,
+            &format!("Topic {} cannot be found.", topic_name),
+        );
+    };
+    let records = rest_proxy::v3_records(&body);
+    if records.is_empty() {
+        return v3_error(StatusCode::BAD_REQUEST, "The request has no records.");
+    }
+
+    let sent: Vec<_> = records
+        .into_iter()
+        .map(|record| {
+            let record = record.map_err(Failure::Invalid)?;
+            let delivery_rx = send(&state, topic, &record)?;
+            Ok((record, delivery_rx))
+        })
+        .collect();
+    let mut results = Vec::with_capacity(sent.len());
+    for sent in sent {
+        let result = match sent {
+            Ok((record, delivery_rx)) => delivered(Ok(delivery_rx))
+                .await
+                .map(|delivery| (record, delivery)),
+            Err(e) => Err(e),
+        };
+        results.push(match result {
+            Ok((record, delivery)) => (
+                StatusCode::OK,
+                json!({
+                    "error_code": 200,
+                    "cluster_id": config.cluster_id,
+                    "topic_name": topic.name,
+                    "partition_id": delivery.partition,
+                    "offset": delivery.offset,
+                    "key": data_json(&record.key),
+                    "value": data_json(&record.value),
+                }),
+            ),
+            Err(e) => (
+                e.status(),
+                json!({"error_code": e.status().as_u16(), "message": e.to_string()}),
+            ),
+        });
+    }
+
+    if let [(status, result)] = results.as_slice() {
+        return HttpResponse::build(*status).json(result);
+    }
+    let mut body = Vec::new();
+    for (_, result) in &results {
+        serde_json::to_writer(&mut body, result).expect("JSON values serialize");
+        body.push(b'\n');
+    }
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(body)
+}
+
+fn data_json(data: &Option<Data>) -> Value {
+    match data {
+        Some(data) => json!({"type": data.data_type.name(), "size": data.bytes.len()}),
+        None => Value::Null,
+    }
+}
+
+/// Why a record was not produced.
+#[derive(Debug)]
+enum Failure {
+    Invalid(RestProxyError),
+    Kafka(KafkaError),
+}
+
+impl Failure {
+    fn is_retriable(&self) -> bool {
+        match self {
+            Failure::Invalid(_) => false,
+            Failure::Kafka(e) => !matches!(
+                e.rdkafka_error_code(),
+                Some(
+                    RDKafkaErrorCode::MessageSizeTooLarge
+                        | RDKafkaErrorCode::UnknownPartition
+                        | RDKafkaErrorCode::UnknownTopic
+                        | RDKafkaErrorCode::UnknownTopicOrPartition
+                )
+            ),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        if let Failure::Kafka(e) = self
+            && kafka::is_unavailable(e)
+        {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else if self.is_retriable() {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Invalid(e) => write!(f, "Invalid record: {}", e),
+            Failure::Kafka(e) => write!(f, "Failed to produce the record: {}", e),
+        }
+    }
+}
+
+/// Sends a record to Kafka without waiting for its delivery.
+fn send(
+    state: &ServerState,
+    topic: &RestProxyTopic,
+    record: &ProduceRecord,
+) -> Result<DeliveryReceiver, Failure> {
+    let value = record.value.as_ref().map(|value| value.bytes.as_slice());
+    let (delivery_tx, delivery_rx) = deliveries();
+    state
+        .kafka
+        .send_to_partition(
+            value,
+            record.key.as_ref().map(|key| key.bytes.as_slice()),
+            &record.headers,
+            &topic.name,
+            record.partition,
+            &topic.librdkafka_config,
+            Records::single(value.map_or(0, <[u8]>::len)),
+            delivery_tx,
+        )
+        .map_err(|e| {
+            warn!("Failed to send record: {}", e);
+            Failure::Kafka(e)
+        })?;
+    Ok(delivery_rx)
+}
+
+async fn delivered(sent: Result<DeliveryReceiver, Failure>) -> Result<Delivery, Failure> {
+    match sent?.recv().await {
+        Some(Ok(delivery)) => Ok(delivery),
+        Some(Err(e)) => {
+            warn!("Record delivery failed: {}", e);
+            Err(Failure::Kafka(e))
+        }
+        None => Err(Failure::Kafka(KafkaError::Canceled)),
+    }
+}
@@ -0,0 +1,499 @@
+//! TCP and UDP listeners for newline delimited records, each sending to a fixed schema, and syslog
+//! and Fluent Forward listeners. Records are sent to Kafka without acknowledging them to the
+//! client, except for forward chunks, and records that cannot be sent are counted as dropped.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use actix_web::http::header::HeaderMap;
+use actix_web::web;
+use bytes::Bytes;
+use common::config::ConfigError;
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::time::{Instant, Sleep};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{Instrument, debug, info, info_span, warn};
+
+use crate::config::{SchemaConfig, ServiceConfig, TlsConfig};
+use crate::error::Result;
+use crate::framing::{DelimitedFrames, FrameError};
+use crate::kafka::{DeliveryReceiver, DeliverySender, Records, deliveries};
+use crate::server::ServerState;
+use crate::server::connection::metadata_headers;
+use crate::server::connection::prepare::Preparation;
+use forward::ForwardListener;
+use syslog::SyslogListener;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+// wait before accepting again after an error, which is usually running out of file descriptors
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+pub struct SocketListeners {
+    tcp_addrs: Vec<SocketAddr>,
+    udp_addrs: Vec<SocketAddr>,
+    syslog_addrs: Vec<SocketAddr>,
+    forward_addrs: Vec<SocketAddr>,
+    shutdown: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl SocketListeners {
+    pub async fn start(
+        service: &ServiceConfig,
+        state: &web::Data<ServerState>,
+    ) -> Result<SocketListeners> {
+        // bind everything first, so that nothing is left running on errors
+        let mut tcp_listeners = Vec::with_capacity(service.tcp_listener.len());
+        for config in &service.tcp_listener {
+            let tls = config.tls.as_ref().map(tls_handshake).transpose()?;
+            tcp_listeners.push((TcpListener::bind(config.address).await?, tls, config));
+        }
+        let mut udp_sockets = Vec::with_capacity(service.udp_listener.len());
+        for config in &service.udp_listener {
+            udp_sockets.push((UdpSocket::bind(config.address).await?, config));
+        }
+        let mut syslog_listeners = Vec::with_capacity(service.syslog_listener.len());
+        for config in &service.syslog_listener {
+            syslog_listeners.push(SyslogListener::bind(config).await?);
+        }
+        let mut forward_listeners = Vec::with_capacity(service.forward_listener.len());
+        for config in &service.forward_listener {
+            forward_listeners.push(ForwardListener::bind(config).await?);
+        }
+
+        let shutdown = CancellationToken::new();
+        let tasks = TaskTracker::new();
+        let mut tcp_addrs = Vec::with_capacity(tcp_listeners.len());
+        for (listener, tls, config) in tcp_listeners {
+            let addr = listener.local_addr()?;
+            info!(schema_id = config.schema_id, "TCP listener at {}", addr);
+            tcp_addrs.push(addr);
+            let sink = RecordSink::start(&config.schema_id, state, &tasks);
+            let idle_timeout = Duration::from_secs(config.idle_timeout_seconds);
+            let connection_shutdown = shutdown.clone();
+            tasks.spawn(tcp_listener(
+                listener,
+                tls,
+                idle_timeout,
+                shutdown.clone(),
+                tasks.clone(),
+                move |stream, peer| {
+                    tcp_connection(stream, peer, sink.clone(), connection_shutdown.clone())
+                },
+            ));
+        }
+        let mut udp_addrs = Vec::with_capacity(udp_sockets.len());
+        for (socket, config) in udp_sockets {
+            let addr = socket.local_addr()?;
+            info!(schema_id = config.schema_id, "UDP listener at {}", addr);
+            udp_addrs.push(addr);
+            let sink = RecordSink::start(&config.schema_id, state, &tasks);
+            tasks.spawn(udp_listener(socket, sink, shutdown.clone()));
+        }
+        let mut syslog_addrs = Vec::with_capacity(syslog_listeners.len());
+        for listener in syslog_listeners {
+            syslog_addrs.push(listener.start(state, &shutdown, &tasks)?);
+        }
+        let mut forward_addrs = Vec::with_capacity(forward_listeners.len());
+        for listener in forward_listeners {
+            forward_addrs.push(listener.start(state, &shutdown, &tasks)?);
+        }
+
+        Ok(SocketListeners {
+            tcp_addrs,
+            udp_addrs,
+            syslog_addrs,
+            forward_addrs,
+            shutdown,
+            tasks,
+        })
+    }
+
+    pub fn tcp_addrs(&self) -> &[SocketAddr] {
+        &self.tcp_addrs
+    }
+
+    pub fn udp_addrs(&self) -> &[SocketAddr] {
+        &self.udp_addrs
+    }
+
+    pub fn syslog_addrs(&self) -> &[SocketAddr] {
+        &self.syslog_addrs
+    }
+
+    pub fn forward_addrs(&self) -> &[SocketAddr] {
+        &self.forward_addrs
+    }
+
+    /// Stops the listeners and closes their connections, then waits for the deliveries of the
+    /// records already received.
+    pub async fn stop(self) {
+        self.shutdown.cancel();
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+
+    pub fn kill(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// The TLS handshake of the connections of a TLS listener.
+#[derive(Clone)]
+pub struct TlsHandshake {
+    acceptor: TlsAcceptor,
+    timeout: Duration,
+}
+
+/// Loads the certificate chain and private key of a TLS listener.
+pub fn tls_handshake(config: &TlsConfig) -> Result<TlsHandshake> {
+    let certificates = CertificateDer::pem_file_iter(&config.certificate_file)
+        .and_then(|certificates| certificates.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| {
+            ConfigError::Invalid(format!(
+                "Invalid TLS certificate file '{}': {}",
+                config.certificate_file, e
+            ))
+        })?;
+    let private_key = PrivateKeyDer::from_pem_file(&config.private_key_file).map_err(|e| {
+        ConfigError::Invalid(format!(
+            "Invalid TLS private key file '{}': {}",
+            config.private_key_file, e
+        ))
+    })?;
+    let server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .and_then(|builder| {
+        builder
+            .with_no_client_auth()
+            .with_single_cert(certificates, private_key)
+    })
+    .map_err(|e| ConfigError::Invalid(format!("Invalid TLS configuration: {}", e)))?;
+    Ok(TlsHandshake {
+        acceptor: TlsAcceptor::from(Arc::new(server_config)),
+        timeout: Duration::from_secs(config.handshake_timeout_seconds),
+    })
+}
+
+/// Sends the records of a listener to Kafka. Delivery failures are logged and counted.
+#[derive(Clone)]
+struct RecordSink {
+    schema_id: String,
+    state: web::Data<ServerState>,
+    delivery_tx: DeliverySender,
+}
+
+impl RecordSink {
+    fn start(schema_id: &str, state: &web::Data<ServerState>, tasks: &TaskTracker) -> RecordSink {
+        let (delivery_tx, delivery_rx) = deliveries();
+        tasks.spawn(drain_deliveries(
+            delivery_rx,
+            schema_id.to_owned(),
+            state.clone(),
+        ));
+        RecordSink {
+            schema_id: schema_id.to_owned(),
+            state: state.clone(),
+            delivery_tx,
+        }
+    }
+
+    fn schema_config(&self) -> &SchemaConfig {
+        self.state.schema_config(&self.schema_id)
+    }
+
+    fn preparation<'a>(
+        &'a self,
+        headers: &'a [(String, Bytes)],
+        request_headers: &'a HeaderMap,
+    ) -> Preparation<'a> {
+        Preparation::new(&self.state, &self.schema_id, request_headers, headers)
+    }
+
+    /// Prepares and sends a record, without waiting for its delivery.
+    fn send(&self, record: Bytes, preparation: &Preparation) {
+        let metrics = &self.state.metrics;
+        match preparation.prepare(record) {
+            Err(e) => {
+                debug!("Dropping invalid record: {}", e);
+                metrics.record_socket_dropped(&self.schema_id, "invalid");
+            }
+            Ok(None) => metrics.record_dropped(1, &self.schema_id),
+            Ok(Some(record)) if record.is_empty() => {}
+            Ok(Some(record)) => {
+                let schema_config = preparation.schema_config;
+                if let Err(e) = self.state.kafka.send(
+                    &record,
+                    None,
+                    preparation.headers,
+                    &schema_config.destination_topic,
+                    &schema_config.librdkafka_config,
+                    Records::single(record.len()),
+                    self.delivery_tx.clone(),
+                ) {
+                    warn!(schema_id = self.schema_id, "Dropping record: {}", e);
+                    metrics.record_socket_dropped(&self.schema_id, "kafka");
+                }
+            }
+        }
+    }
+}
+
+/// Runs until the listener and its connections are done and the deliveries of their records are
+/// reported.
+async fn drain_deliveries(
+    mut delivery_rx: DeliveryReceiver,
+    schema_id: String,
+    state: web::Data<ServerState>,
+) {
+    while let Some(result) = delivery_rx.recv().await {
+        if let Err(e) = result {
+            warn!(schema_id, "Record delivery failed: {}", e);
+            state.metrics.record_socket_dropped(&schema_id, "kafka");
+        }
+    }
+}
+
+trait ConnectionStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ConnectionStream for S {}
+
+/// A TCP connection, after the TLS handshake for listeners with TLS. Reads fail once it has been
+/// idle for the idle timeout of its listener.
+type Connection = Box<dyn ConnectionStream>;
+
+/// Accepts connections until the listener is stopped, and runs `connection` for each of them.
+async fn tcp_listener<F, Fut>(
+    listener: TcpListener,
+    tls: Option<TlsHandshake>,
+    idle_timeout: Duration,
+    shutdown: CancellationToken,
+    tasks: TaskTracker,
+    connection: F,
+) where
+    F: Fn(Connection, SocketAddr) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Error accepting TCP connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            },
+            _ = shutdown.cancelled() => break,
+        };
+        let tls = tls.clone();
+        let connection = connection.clone();
+        tasks.spawn(
+            async move {
+                let stream: Connection = match tls {
+                    None => Box::new(IdleTimeout::new(stream, idle_timeout)),
+                    Some(tls) => {
+                        match tokio::time::timeout(tls.timeout, tls.acceptor.accept(stream)).await {
+                            Ok(Ok(stream)) => Box::new(IdleTimeout::new(stream, idle_timeout)),
+                            Ok(Err(e)) => {
+                                debug!("TLS handshake failed: {}", e);
+                                return;
+                            }
+                            Err(_) => {
+                                debug!("TLS handshake timed out");
+                                return;
+                            }
+                        }
+                    }
+                };
+                connection(stream, peer).await
+            }
+            .instrument(info_span!("tcp_connection", %peer)),
+        );
+    }
+}
+
+/// Fails reads with `TimedOut` once nothing was read for `timeout`. The timer restarts on each
+/// read, so that a slow client sending a large frame is not considered idle.
+struct IdleTimeout<S> {
+    stream: S,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl<S> IdleTimeout<S> {
+    fn new(stream: S, timeout: Duration) -> IdleTimeout<S> {
+        IdleTimeout {
+            stream,
+            timeout,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeout<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.stream).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                this.deadline.as_mut().reset(Instant::now() + this.timeout);
+                Poll::Ready(result)
+            }
+            Poll::Pending => match this.deadline.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    debug!("Closing idle connection");
+                    Poll::Ready(Err(io::Error::from(io::ErrorKind::TimedOut)))
+                }
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Waits for the next frame of a connection. Returns None when the connection ends or the
+/// listener is stopped.
+async fn next_frame<S>(
+    frames: &mut S,
+    shutdown: &CancellationToken,
+) -> Option<std::result::Result<Bytes, FrameError>>
+where
+    S: Stream<Item = std::result::Result<Bytes, FrameError>> + Unpin,
+{
+    tokio::select! {
+        frame = frames.next() => frame,
+        _ = shutdown.cancelled() => None,
+    }
+}
+
+async fn tcp_connection(
+    stream: Connection,
+    peer: SocketAddr,
+    sink: RecordSink,
+    shutdown: CancellationToken,
+) {
+    let state = &sink.state;
+    let headers = metadata_headers(
+        state,
+        &sink.schema_id,
+        sink.schema_config(),
+        &peer.ip().to_string(),
+    );
+    let request_headers = HeaderMap::new();
+    let preparation = sink.preparation(&headers, &request_headers);
+    let mut records = DelimitedFrames::new(
+        ReaderStream::new(stream),
+        b'\n',
+        state.max_event_size_bytes as usize,
+    );
+
+    while let Some(record) = next_frame(&mut records, &shutdown).await {
+        match record {
+            Ok(record) => sink.send(record, &preparation),
+            Err(FrameError::MaxLengthExceeded) => {
+                warn!("Closing connection, a record exceeds the maximum size");
+                state
+                    .metrics
+                    .record_socket_dropped(&sink.schema_id, "too_large");
+                break;
+            }
+            Err(e) => {
+                debug!("Closing connection: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Waits for the next datagram, which is copied out of `buf`. Returns None when the listener is
+/// stopped.
+async fn next_datagram(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    shutdown: &CancellationToken,
+) -> Option<(Bytes, SocketAddr)> {
+    loop {
+        tokio::select! {
+            received = socket.recv_from(buf) => match received {
+                Ok((length, peer)) => return Some((Bytes::copy_from_slice(&buf[..length]), peer)),
+                Err(e) => debug!("Error receiving datagram: {}", e),
+            },
+            _ = shutdown.cancelled() => return None,
+        }
+    }
+}
+
+async fn udp_listener(socket: UdpSocket, sink: RecordSink, shutdown: CancellationToken) {
+    let state = &sink.state;
+    let max_record_size = state.max_event_size_bytes as usize;
+    let request_headers = HeaderMap::new();
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    while let Some((datagram, peer)) = next_datagram(&socket, &mut buf, &shutdown).await {
+        let headers = metadata_headers(
+            state,
+            &sink.schema_id,
+            sink.schema_config(),
+            &peer.ip().to_string(),
+        );
+        let preparation = sink.preparation(&headers, &request_headers);
+        for record in datagram_records(datagram) {
+            if record.len() > max_record_size {
+                state
+                    .metrics
+                    .record_socket_dropped(&sink.schema_id, "too_large");
+            } else {
+                sink.send(record, &preparation);
+            }
+        }
+    }
+}
+
+/// Splits a datagram into its newline delimited records, skipping empty ones.
+fn datagram_records(datagram: Bytes) -> Vec<Bytes> {
+    datagram
+        .split(|b| *b == b'\n')
+        .filter(|record| !record.is_empty())
+        .map(|record| datagram.slice_ref(record))
+        .collect()
+}
+
+mod forward;
+mod syslog;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,254 @@
+//! Fluent Forward listeners. The records of each message are sent as JSON messages to the schema
+//! routed from the message tag, and messages with a chunk id are acknowledged once all their
+//! records are delivered. Clients retry the chunks that are not acknowledged, so the connection is
+//! closed when a record fails to be sent to Kafka.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::header::HeaderMap;
+use actix_web::web;
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio_util::codec::FramedRead;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, warn};
+
+use super::{Connection, TlsHandshake, drain_deliveries, next_frame, tcp_listener, tls_handshake};
+use crate::config::ForwardListenerConfig;
+use crate::error::Result;
+use crate::forward::{ForwardCodec, ForwardMessage, TagPattern, ack, decode};
+use crate::framing::FrameError;
+use crate::kafka::{DeliveryReceiver, Records, deliveries};
+use crate::server::ServerState;
+use crate::server::connection::metadata_headers;
+use crate::server::connection::prepare::Preparation;
+
+/// A bound forward listener, not accepting connections yet.
+pub(super) struct ForwardListener<'a> {
+    listener: TcpListener,
+    tls: Option<TlsHandshake>,
+    config: &'a ForwardListenerConfig,
+}
+
+impl ForwardListener<'_> {
+    pub(super) async fn bind(config: &ForwardListenerConfig) -> Result<ForwardListener<'_>> {
+        let tls = config.tls.as_ref().map(tls_handshake).transpose()?;
+        Ok(ForwardListener {
+            listener: TcpListener::bind(config.address).await?,
+            tls,
+            config,
+        })
+    }
+
+    pub(super) fn start(
+        self,
+        state: &web::Data<ServerState>,
+        shutdown: &CancellationToken,
+        tasks: &TaskTracker,
+    ) -> Result<SocketAddr> {
+        let config = self.config;
+        let addr = self.listener.local_addr()?;
+        info!("Forward listener at {}", addr);
+
+        let forwarder = Arc::new(Forwarder {
+            routes: config
+                .route
+                .iter()
+                .map(|route| (TagPattern::new(&route.tag), route.schema_id.clone()))
+                .collect(),
+            schema_id: config.schema_id.clone(),
+            time_key: config.time_key.clone(),
+            max_chunk_size: config.max_chunk_size_bytes as usize,
+            state: state.clone(),
+            tasks: tasks.clone(),
+        });
+        let idle_timeout = Duration::from_secs(config.idle_timeout_seconds);
+        let connection_shutdown = shutdown.clone();
+        tasks.spawn(tcp_listener(
+            self.listener,
+            self.tls,
+            idle_timeout,
+            shutdown.clone(),
+            tasks.clone(),
+            move |stream, peer| {
+                tcp_connection(stream, peer, forwarder.clone(), connection_shutdown.clone())
+            },
+        ));
+        Ok(addr)
+    }
+}
+
+struct Forwarder {
+    routes: Vec<(TagPattern, String)>,
+    schema_id: Option<String>,
+    time_key: Option<String>,
+    max_chunk_size: usize,
+    state: web::Data<ServerState>,
+    // runs the delivery reports of messages that are not acknowledged
+    tasks: TaskTracker,
+}
+
+impl Forwarder {
+    fn schema_id<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches(tag))
+            .map(|(_, schema_id)| schema_id.as_str())
+            .or(self.schema_id.as_deref())
+            .unwrap_or(tag)
+    }
+
+    /// Sends the records of a message without waiting for their delivery, and returns the
+    /// receiver of their delivery reports with the count of records rejected as invalid or too
+    /// large. Returns None when a record could not be sent.
+    fn send(
+        &self,
+        message: ForwardMessage,
+        schema_id: &str,
+        peer: SocketAddr,
+    ) -> Option<(DeliveryReceiver, u64)> {
+        let state = &self.state;
+        let metrics = &state.metrics;
+        let schema_config = state.schema_config(schema_id);
+        let mut headers = metadata_headers(state, schema_id, schema_config, &peer.ip().to_string());
+        headers.push((
+            state.header_names.fluent_tag.clone(),
+            Bytes::copy_from_slice(message.tag.as_bytes()),
+        ));
+        let request_headers = HeaderMap::new();
+        let preparation = Preparation {
+            is_json: true,
+            ..Preparation::new(state, schema_id, &request_headers, &headers)
+        };
+
+        let (delivery_tx, delivery_rx) = deliveries();
+        let mut rejected = 0;
+        for entry in message.entries {
+            let record = serde_json::to_vec(&entry.into_json(self.time_key.as_deref()))
+                .expect("Records serialize to JSON");
+            if record.len() > state.max_event_size_bytes as usize {
+                metrics.record_socket_dropped(schema_id, "too_large");
+                rejected += 1;
+                continue;
+            }
+            let record = match preparation.prepare(Bytes::from(record)) {
+                Ok(Some(record)) => record,
+                Ok(None) => {
+                    metrics.record_dropped(1, schema_id);
+                    continue;
+                }
+                Err(e) => {
+                    debug!("Dropping invalid record: {}", e);
+                    metrics.record_socket_dropped(schema_id, "invalid");
+                    rejected += 1;
+                    continue;
+                }
+            };
+            if let Err(e) = state.kafka.send(
+                &record,
+                None,
+                &headers,
+                &schema_config.destination_topic,
+                &schema_config.librdkafka_config,
+                Records::single(record.len()),
+                delivery_tx.clone(),
+            ) {
+                warn!(schema_id, "Failed to send record: {}", e);
+                metrics.record_socket_dropped(schema_id, "kafka");
+                // the records already sent are reported before the connection is closed
+                self.tasks.spawn(drain_deliveries(
+                    delivery_rx,
+                    schema_id.to_owned(),
+                    state.clone(),
+                ));
+                return None;
+            }
+        }
+        Some((delivery_rx, rejected))
+    }
+}
+
+/// Waits for the delivery reports of all records. Returns false when any failed.
+async fn delivered(
+    mut delivery_rx: DeliveryReceiver,
+    schema_id: &str,
+    state: &ServerState,
+) -> bool {
+    let mut delivered = true;
+    while let Some(result) = delivery_rx.recv().await {
+        if let Err(e) = result {
+            warn!(schema_id, "Record delivery failed: {}", e);
+            state.metrics.record_socket_dropped(schema_id, "kafka");
+            delivered = false;
+        }
+    }
+    delivered
+}
+
+async fn tcp_connection(
+    stream: Connection,
+    peer: SocketAddr,
+    forwarder: Arc<Forwarder>,
+    shutdown: CancellationToken,
+) {
+    let mut messages = FramedRead::new(stream, ForwardCodec::new(forwarder.max_chunk_size));
+
+    while let Some(message) = next_frame(&mut messages, &shutdown).await {
+        let message = match message.map(|message| decode(&message, forwarder.max_chunk_size)) {
+            Ok(Ok(message)) => message,
+            Ok(Err(e)) => {
+                debug!("Closing connection: {}", e);
+                break;
+            }
+            Err(FrameError::MaxLengthExceeded) => {
+                warn!("Closing connection, a forward message exceeds the maximum size");
+                break;
+            }
+            Err(e) => {
+                debug!("Closing connection: {}", e);
+                break;
+            }
+        };
+
+        let schema_id = forwarder.schema_id(&message.tag).to_owned();
+        let chunk = message.chunk.clone();
+        let Some((delivery_rx, rejected)) = forwarder.send(message, &schema_id, peer) else {
+            break;
+        };
+        // chunks are still acknowledged, since their rejected records would be rejected again
+        if rejected > 0 {
+            warn!(
+                schema_id,
+                chunk = chunk.as_deref(),
+                "Dropped {} invalid or too large records of a message",
+                rejected
+            );
+        }
+        let Some(chunk) = chunk else {
+            forwarder.tasks.spawn(drain_deliveries(
+                delivery_rx,
+                schema_id,
+                forwarder.state.clone(),
+            ));
+            continue;
+        };
+        // not interrupted by shutdown, so that delivered chunks are acknowledged
+        if !delivered(delivery_rx, &schema_id, &forwarder.state).await {
+            debug!("Closing connection, chunk {} is not acknowledged", chunk);
+            break;
+        }
+        let stream = messages.get_mut();
+        let written = async {
+            stream.write_all(&ack(&chunk)).await?;
+            stream.flush().await
+        };
+        if let Err(e) = written.await {
+            debug!("Closing connection: {}", e);
+            break;
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Syslog listeners. Each message is parsed, and sent to the schema of the first route matching its
+//! facility and app name.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::header::HeaderMap;
+use actix_web::web;
+use bytes::Bytes;
+use common::config::ConfigError;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio_util::codec::FramedRead;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, warn};
+
+use super::{
+    Connection, MAX_DATAGRAM_SIZE, RecordSink, TlsHandshake, next_datagram, next_frame,
+    tcp_listener, tls_handshake,
+};
+use crate::config::{HeaderNames, SyslogFormat, SyslogListenerConfig, SyslogProtocol, SyslogRoute};
+use crate::error::{Error, Result};
+use crate::framing::FrameError;
+use crate::server::ServerState;
+use crate::server::connection::metadata_headers;
+use crate::server::connection::prepare::Preparation;
+use crate::syslog::{SyslogCodec, SyslogMessage, facility_code, parse};
+
+enum SyslogSocket {
+    Tcp(TcpListener, Option<TlsHandshake>),
+    Udp(UdpSocket),
+}
+
+/// A bound syslog listener with a valid config, not receiving messages yet.
+pub(super) struct SyslogListener<'a> {
+    socket: SyslogSocket,
+    config: &'a SyslogListenerConfig,
+    conditions: Vec<RouteCondition>,
+}
+
+impl SyslogListener<'_> {
+    pub(super) async fn bind(config: &SyslogListenerConfig) -> Result<SyslogListener<'_>> {
+        let conditions = config
+            .route
+            .iter()
+            .map(RouteCondition::new)
+            .collect::<Result<Vec<_>>>()?;
+        let socket = match config.protocol {
+            SyslogProtocol::Tcp => {
+                let tls = config.tls.as_ref().map(tls_handshake).transpose()?;
+                SyslogSocket::Tcp(TcpListener::bind(config.address).await?, tls)
+            }
+            SyslogProtocol::Udp if config.tls.is_some() => {
+                return Err(Error::from(ConfigError::Invalid(
+                    "TLS is only supported by TCP syslog listeners".to_owned(),
+                )));
+            }
+            SyslogProtocol::Udp => SyslogSocket::Udp(UdpSocket::bind(config.address).await?),
+        };
+        Ok(SyslogListener {
+            socket,
+            config,
+            conditions,
+        })
+    }
+
+    pub(super) fn start(
+        self,
+        state: &web::Data<ServerState>,
+        shutdown: &CancellationToken,
+        tasks: &TaskTracker,
+    ) -> Result<SocketAddr> {
+        let config = self.config;
+        let addr = match &self.socket {
+            SyslogSocket::Tcp(listener, _) => listener.local_addr()?,
+            SyslogSocket::Udp(socket) => socket.local_addr()?,
+        };
+        info!(
+            schema_id = config.schema_id,
+            "Syslog {:?} listener at {}", config.protocol, addr
+        );
+
+        // routes to the same schema share its sink
+        let mut sinks: HashMap<String, RecordSink> = HashMap::new();
+        let mut sink = |schema_id: &str| {
+            sinks
+                .entry(schema_id.to_owned())
+                .or_insert_with(|| RecordSink::start(schema_id, state, tasks))
+                .clone()
+        };
+        let default = sink(&config.schema_id);
+        let routes = self
+            .conditions
+            .into_iter()
+            .zip(&config.route)
+            .map(|(condition, route)| (condition, sink(&route.schema_id)))
+            .collect();
+        let router = Arc::new(Router {
+            routes,
+            default,
+            format: config.format,
+        });
+
+        match self.socket {
+            SyslogSocket::Tcp(listener, tls) => {
+                let idle_timeout = Duration::from_secs(config.idle_timeout_seconds);
+                let connection_shutdown = shutdown.clone();
+                tasks.spawn(tcp_listener(
+                    listener,
+                    tls,
+                    idle_timeout,
+                    shutdown.clone(),
+                    tasks.clone(),
+                    move |stream, peer| {
+                        tcp_connection(stream, peer, router.clone(), connection_shutdown.clone())
+                    },
+                ));
+            }
+            SyslogSocket::Udp(socket) => {
+                tasks.spawn(udp_listener(socket, router, shutdown.clone()));
+            }
+        }
+        Ok(addr)
+    }
+}
+
+/// Each condition is met when not set.
+struct RouteCondition {
+    facilities: Option<Vec<u8>>,
+    app_names: Option<Vec<String>>,
+}
+
+impl RouteCondition {
+    fn new(route: &SyslogRoute) -> Result<RouteCondition> {
+        let facilities = match &route.facility {
+            Some(names) => Some(
+                names
+                    .iter()
+                    .map(|name| {
+                        facility_code(name).ok_or_else(|| {
+                            ConfigError::Invalid(format!("Unknown syslog facility '{}'", name))
+                        })
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        Ok(RouteCondition {
+            facilities,
+            app_names: route.app_name.clone(),
+        })
+    }
+
+    fn matches(&self, message: &SyslogMessage) -> bool {
+        self.facilities
+            .as_ref()
+            .is_none_or(|facilities| facilities.contains(&message.facility))
+            && self.app_names.as_ref().is_none_or(|app_names| {
+                message
+                    .app_name
+                    .as_ref()
+                    .is_some_and(|app_name| app_names.contains(app_name))
+            })
+    }
+}
+
+struct Router {
+    routes: Vec<(RouteCondition, RecordSink)>,
+    default: RecordSink,
+    format: SyslogFormat,
+}
+
+impl Router {
+    /// Parses and sends a message, without waiting for its delivery.
+    fn send(&self, record: Bytes, peer: SocketAddr) {
+        let message = match parse(&record) {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("Dropping syslog message: {}", e);
+                self.default
+                    .state
+                    .metrics
+                    .record_socket_dropped(&self.default.schema_id, "invalid");
+                return;
+            }
+        };
+        let sink = self
+            .routes
+            .iter()
+            .find(|(condition, _)| condition.matches(&message))
+            .map_or(&self.default, |(_, sink)| sink);
+
+        let state = &sink.state;
+        let mut headers = metadata_headers(
+            state,
+            &sink.schema_id,
+            sink.schema_config(),
+            &peer.ip().to_string(),
+        );
+        let (record, is_json) = match self.format {
+            SyslogFormat::Json => (
+                Bytes::from(
+                    serde_json::to_vec(&message).expect("Syslog messages serialize to JSON"),
+                ),
+                true,
+            ),
+            SyslogFormat::Raw => {
+                headers.extend(syslog_headers(&message, &state.header_names));
+                (record, false)
+            }
+        };
+        let request_headers = HeaderMap::new();
+        let preparation = Preparation {
+            is_json,
+            ..sink.preparation(&headers, &request_headers)
+        };
+        sink.send(record, &preparation);
+    }
+
+    fn record_too_large(&self) {
+        self.default
+            .state
+            .metrics
+            .record_socket_dropped(&self.default.schema_id, "too_large");
+    }
+}
+
+/// The parsed header fields of a message forwarded raw.
+fn syslog_headers(message: &SyslogMessage, header_names: &HeaderNames) -> Vec<(String, Bytes)> {
+    let mut headers = vec![
+        (
+            header_names.syslog_facility.clone(),
+            Bytes::from_static(message.facility_name().as_bytes()),
+        ),
+        (
+            header_names.syslog_severity.clone(),
+            Bytes::from_static(message.severity_name().as_bytes()),
+        ),
+    ];
+    let fields = [
+        (&header_names.syslog_timestamp, &message.timestamp),
+        (&header_names.syslog_hostname, &message.hostname),
+        (&header_names.syslog_app_name, &message.app_name),
+        (&header_names.syslog_proc_id, &message.proc_id),
+        (&header_names.syslog_msg_id, &message.msg_id),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            headers.push((name.clone(), Bytes::copy_from_slice(value.as_bytes())));
+        }
+    }
+    headers
+}
+
+async fn tcp_connection(
+    stream: Connection,
+    peer: SocketAddr,
+    router: Arc<Router>,
+    shutdown: CancellationToken,
+) {
+    let max_message_size = router.default.state.max_event_size_bytes as usize;
+    let mut messages = FramedRead::new(stream, SyslogCodec::new(max_message_size));
+
+    while let Some(message) = next_frame(&mut messages, &shutdown).await {
+        match message {
+            Ok(message) => router.send(message, peer),
+            Err(FrameError::MaxLengthExceeded) => {
+                warn!("Closing connection, a syslog message exceeds the maximum size");
+                router.record_too_large();
+                break;
+            }
+            Err(e) => {
+                debug!("Closing connection: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Each datagram holds a single message (RFC 5426).
+async fn udp_listener(socket: UdpSocket, router: Arc<Router>, shutdown: CancellationToken) {
+    let max_message_size = router.default.state.max_event_size_bytes as usize;
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    while let Some((datagram, peer)) = next_datagram(&socket, &mut buf, &shutdown).await {
+        if datagram.len() > max_message_size {
+            router.record_too_large();
+        } else if !datagram.trim_ascii().is_empty() {
+            router.send(datagram, peer);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::io;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{IdleTimeout, datagram_records};
+
+#[test]
+fn test_datagram_records() {
+    let records = datagram_records(Bytes::from_static(b"{\"a\":1}\n\n{\"b\":2}\r\n{\"c\""));
+    assert_eq!(records, vec!["{\"a\":1}", "{\"b\":2}\r", "{\"c\""]);
+
+    assert!(datagram_records(Bytes::from_static(b"\n")).is_empty());
+    assert!(datagram_records(Bytes::new()).is_empty());
+}
+
+#[tokio::test]
+async fn test_idle_timeout_restarts_on_read() {
+    let (mut client, server) = tokio::io::duplex(64);
+    let mut connection = IdleTimeout::new(server, Duration::from_millis(200));
+    let mut buf = [0u8; 8];
+
+    // a frame sent slower than the idle timeout, in reads that each restart it
+    for _ in 0..4 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.write_all(b"a").await.unwrap();
+        assert_eq!(connection.read(&mut buf).await.unwrap(), 1);
+    }
+
+    let error = connection.read(&mut buf).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
@@ -1,3 +1,4 @@
+/// Errors that can be sent back to WebSocket clients in acknowledgements.
 pub trait WSError {
     fn message(&self) -> String;
 }
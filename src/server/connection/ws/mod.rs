@@ -1,314 +1,199 @@
-use std::collections::HashMap;
+//! WebSocket ingestion. Each WebSocket message is handled like the body of an HTTP request to the
+//! same schema and acknowledged with the result.
 
-use actix::{
-    fut::WrapFuture, Actor, ActorContext, ActorFuture, Arbiter, AsyncContext, Handler,
-    StreamHandler,
-};
-use actix_http::ws::Item;
-use actix_web::{web, HttpRequest, Responder};
-use actix_web_actors::ws;
-use bytes::{Bytes, BytesMut};
-use common::logging;
-use tracing::{error, trace, warn, Span};
-use tracing_futures::Instrument;
+use std::future::ready;
+
+use actix_web::error::{ErrorServiceUnavailable, PayloadError};
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, CloseCode, CloseReason, Session};
+use bytes::Bytes;
+use futures::stream::{self, FuturesOrdered, StreamExt};
+use serde::Serialize;
+use tracing::{Instrument, debug, info_span, trace, warn};
 
-pub use close::WSClose;
 pub use error::WSError;
 
-use crate::error::Error;
-use crate::event::forward_to_kafka;
-use crate::kafka::Kafka;
-use crate::server::{get_tenant_id, ServerState};
+use super::http::{self, IngestResponse, forward};
+use crate::config::{ContentType, SchemaConfig};
+use crate::error::{Error, Result};
+use crate::server::ServerState;
+use crate::signature::SignatureError;
 
-mod close;
 mod error;
 
+/// Messages forwarded concurrently per connection. Further messages are read once the oldest one
+/// is acknowledged.
+const MAX_IN_FLIGHT_MESSAGES: usize = 64;
+
+/// Sent back for each message, in the order the messages were received.
+#[derive(Serialize)]
+struct Ack {
+    /// Number of the acknowledged message on the connection, starting at 1
+    seq: u64,
+    success: bool,
+    #[serde(flatten)]
+    response: IngestResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
 pub async fn handle(
     req: HttpRequest,
-    stream: web::Payload,
+    body_stream: web::Payload,
+    path: web::Path<String>,
     state: web::Data<ServerState>,
-) -> impl Responder {
-    let log_span = logging::ws_span();
-
-    log_span.in_scope(|| trace!("Upgrading connection to WebSocket"));
-
-    if state.accepting_ws() {
-        ws::start(
-            WSHandler::new(state.kafka.clone(), get_tenant_id(&req), state, log_span),
-            &req,
-            stream,
-        )
-    } else {
-        Err(Error::WSNotAccepted.into())
+) -> Result<HttpResponse> {
+    let schema_id = path.into_inner();
+    // without WebSockets enabled `ws` is just a trailing path
+    if !state.schema_config(&schema_id).websocket || !is_upgrade(&req) {
+        return http::_handle(req, body_stream, schema_id, state).await;
     }
-}
-
-pub struct WSHandler {
-    log_span: Span,
-    kafka: Kafka,
-    server_state: web::Data<ServerState>,
-    tenant_id: i64,
-    /// Used for continuation messages
-    fragment_buf: Option<BytesMut>,
-}
-
-impl WSHandler {
-    fn new(
-        kafka: Kafka,
-        tenant_id: i64,
-        server_state: web::Data<ServerState>,
-        log_span: Span,
-    ) -> Self {
-        Self {
-            log_span,
-            kafka,
-            server_state,
-            tenant_id,
-            fragment_buf: None,
-        }
+    // before the upgrade, so that the key id is forwarded with every message of the connection
+    let schema_config = state.schema_config(&schema_id);
+    if let Err(response) = http::authenticate(&req, &schema_id, schema_config, &state) {
+        return Ok(response);
     }
-
-    fn handle_events(&self, ctx: &mut <Self as Actor>::Context, events: Bytes) {
-        let fut = forward_to_kafka(
-            vec![events],
-            HashMap::new(),
-            self.kafka.clone(),
-            self.tenant_id,
-        )
-        .in_current_span();
-
-        let span = Span::current();
-        let actor_fut = fut.into_actor(self).map(move |result, _, ctx| {
-            let _span_guard = span.enter();
-
-            Self::send_response(ctx, result);
-        });
-
-        ctx.spawn(actor_fut);
+    // signatures are of whole HTTP bodies, which WebSocket messages are not
+    if state.verifier(&schema_id).is_some() {
+        let e = SignatureError::Unsupported;
+        debug!(schema_id, "WebSocket upgrade refused: {}", e);
+        return Ok(HttpResponse::Unauthorized().json(e.to_json()));
+    }
+    if !state.accepting_ws() {
+        return Err(Error::WSNotAccepted);
     }
 
-    fn handle_continuation(&mut self, ctx: &mut <Self as Actor>::Context, fragment: Item) {
-        match fragment {
-            Item::FirstText(b) | Item::FirstBinary(b) => {
-                if self.fragment_buf.is_none() {
-                    let buf = BytesMut::from(b.as_ref());
+    trace!("Upgrading connection to WebSocket");
+    let (response, session, messages) = actix_ws::handle(&req, body_stream).map_err(Error::from)?;
+    let max_message_size = state.max_event_size_bytes as usize;
+    let messages = messages
+        .max_frame_size(max_message_size)
+        .aggregate_continuations()
+        .max_continuation_size(max_message_size);
+
+    let span = info_span!("websocket", schema_id);
+    let connection_state = state.clone();
+    actix_web::rt::spawn(state.ws_connections.track_future(
+        connection(req, schema_id, session, messages, connection_state).instrument(span),
+    ));
+    Ok(response)
+}
 
-                    self.fragment_buf = Some(buf);
-                } else {
-                    self.protocol_error_close(ctx, "Client sent first continuation fragment twice");
-                }
-            }
+fn is_upgrade(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::UPGRADE)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.eq_ignore_ascii_case("websocket"))
+}
 
-            Item::Continue(b) => {
-                if let Some(buf) = self.fragment_buf.as_mut() {
-                    buf.extend_from_slice(b.as_ref());
-                } else {
-                    self.protocol_error_close(
-                        ctx,
-                        "Client sent continue fragment without starting continuation",
-                    )
+async fn connection(
+    req: HttpRequest,
+    schema_id: String,
+    mut session: Session,
+    mut messages: AggregatedMessageStream,
+    state: web::Data<ServerState>,
+) {
+    let schema_config = state.schema_config(&schema_id);
+    let mut in_flight = FuturesOrdered::new();
+    let mut seq: u64 = 0;
+    let mut reading = true;
+    let mut close_reason = None;
+
+    // once reading stops, because the client closed the connection or the server is stopping,
+    // the messages in flight are still acknowledged before closing
+    loop {
+        tokio::select! {
+            message = messages.next(), if reading && in_flight.len() < MAX_IN_FLIGHT_MESSAGES => {
+                match message {
+                    Some(Ok(AggregatedMessage::Text(text))) => {
+                        seq += 1;
+                        in_flight.push_back(ingest(&req, &schema_id, schema_config, &state, seq, text.into_bytes()));
+                    }
+                    Some(Ok(AggregatedMessage::Binary(data))) => {
+                        seq += 1;
+                        in_flight.push_back(ingest(&req, &schema_id, schema_config, &state, seq, data));
+                    }
+                    Some(Ok(AggregatedMessage::Ping(data))) => {
+                        if session.pong(&data).await.is_err() {
+                            reading = false;
+                        }
+                    }
+                    Some(Ok(AggregatedMessage::Pong(_))) => {}
+                    Some(Ok(AggregatedMessage::Close(reason))) => {
+                        trace!(?reason, "Connection closed by client");
+                        reading = false;
+                    }
+                    Some(Err(e)) => {
+                        warn!("Closing. Protocol error: {}", e);
+                        reading = false;
+                        close_reason = Some(CloseReason::from(CloseCode::Protocol));
+                    }
+                    None => reading = false,
                 }
             }
-
-            Item::Last(b) => {
-                let opt_buf = self.fragment_buf.take();
-
-                if let Some(mut buf) = opt_buf {
-                    buf.extend_from_slice(b.as_ref());
-
-                    self.handle_events(ctx, buf.into());
-                } else {
-                    self.protocol_error_close(
-                        ctx,
-                        "Client sent last fragment without starting continuation",
-                    )
+            Some(ack) = in_flight.next() => {
+                let ack = serde_json::to_string(&ack).expect("Acks serialize to JSON");
+                if session.text(ack).await.is_err() {
+                    debug!("Connection closed before all messages were acknowledged");
+                    return;
                 }
             }
-        }
-    }
-
-    fn send_response<E>(ctx: &mut <Self as Actor>::Context, result: Result<(), E>)
-    where
-        E: WSError,
-    {
-        ctx.text(Self::response_json(result))
-    }
-
-    fn response_json<T, E>(result: Result<T, E>) -> String
-    where
-        E: WSError,
-    {
-        let mut res_obj = json::object! {
-            "success" => result.is_ok(),
-        };
-
-        if let Err(e) = result {
-            if let Err(json_e) = res_obj.insert("error", e.message()) {
-                error!(
-                    "Impossible error inserting error message into websocket response: {}",
-                    json_e
-                )
-            }
-        } else {
-            trace!("Sending back successful response");
-        }
-
-        res_obj.dump()
-    }
-
-    fn handle_client_close(
-        &self,
-        reason: Option<ws::CloseReason>,
-        ctx: &mut <Self as Actor>::Context,
-    ) {
-        use ws::CloseCode::Normal;
-
-        if let Some(reason) = reason {
-            match reason.code {
-                Normal => trace!(
-                    "Connection closed by client normally; Description: {:?}",
-                    reason.description
-                ),
-                code => warn!(
-                    "Connection closed by client with code {:?}; Description: {:?}",
-                    code, reason.description
-                ),
+            _ = state.ws_close.cancelled(), if reading => {
+                trace!("Server stopping. Closing connection");
+                reading = false;
+                close_reason = Some(CloseReason::from(CloseCode::Restart));
             }
-        } else {
-            trace!("Connection closed by client. No reason provided");
+            else => break,
         }
-
-        self.stop(ctx);
     }
 
-    fn state_register(&self, ctx: &mut <Self as Actor>::Context) {
-        let state = self.server_state.clone();
-        let addr = ctx.address();
-        let fut = async move { state.register_ws(addr).await }
-            .instrument(self.log_span.clone())
-            .into_actor(self)
-            .map(|result, handler, ctx| {
-                let _span_guard = handler.log_span.enter();
-
-                // if client managed to connect while server is shutting down
-                // close connection immediately
-                if let Err(Error::WSNotAccepted) = result {
-                    warn!("Connection opened while server was shutting down. Closing it");
-                    Self::internal_close(ctx);
-                }
-            });
-
-        ctx.wait(fut);
-    }
-
-    fn state_unregister(&self, ctx: &mut <Self as Actor>::Context) {
-        let state = self.server_state.clone();
-        let addr = ctx.address();
-
-        let fut = async move { state.unregister_ws(&addr).await }.in_current_span();
-
-        Arbiter::spawn(fut);
-    }
-
-    fn internal_close(ctx: &mut <Self as Actor>::Context) {
-        let reason = ws::CloseReason {
-            code: ws::CloseCode::Restart,
-            description: None,
-        };
-
-        ctx.close(Some(reason));
-
-        ctx.stop();
-    }
-
-    /// Used when actix didn't catch protocol errors for some reason
-    fn protocol_error_close(&self, ctx: &mut <Self as Actor>::Context, desc: &str) {
-        error!(
-            "Protocol error not handled by actix! {}; Closing connection",
-            desc
-        );
-
-        let reason = ws::CloseReason {
-            code: ws::CloseCode::Protocol,
-            description: None,
-        };
-
-        ctx.close(Some(reason));
-
-        self.stop(ctx);
-    }
-
-    /// Use to unregister websocket actor from app state and stop it.
-    fn stop(&self, ctx: &mut <Self as Actor>::Context) {
-        self.state_unregister(ctx);
-
-        ctx.stop();
-    }
+    let _ = session.close(close_reason).await;
 }
 
-type WSMessage = Result<ws::Message, ws::ProtocolError>;
-
-impl Actor for WSHandler {
-    type Context = ws::WebsocketContext<Self>;
-
-    fn started(&mut self, ctx: &mut Self::Context) {
-        self.state_register(ctx);
-    }
-}
-
-impl StreamHandler<WSMessage> for WSHandler {
-    fn handle(&mut self, msg: WSMessage, ctx: &mut Self::Context) {
-        use ws::Message::*;
-
-        let ws_msg_span = logging::ws_msg_span(&self.log_span);
-        let _span_guard = ws_msg_span.enter();
-
-        trace!("Got new message");
-
-        match msg {
-            Ok(Binary(buf)) => {
-                self.handle_events(ctx, buf);
-            }
-
-            Ok(Text(buf)) => {
-                self.handle_events(ctx, buf.into());
-            }
-
-            Ok(Ping(data)) => {
-                ctx.pong(&data);
-            }
-
-            Ok(Pong(_)) => {}
-
-            Ok(Close(reason)) => {
-                self.handle_client_close(reason, ctx);
-            }
-
-            Ok(Continuation(fragment)) => {
-                self.handle_continuation(ctx, fragment);
-            }
-
-            Ok(Nop) => {
-                error!("Received Nop message. Doing nothing");
-            }
-
-            Err(e) => {
-                warn!("Closing. Protocol error: {}", e);
-
-                self.stop(ctx);
-            }
+async fn ingest(
+    req: &HttpRequest,
+    schema_id: &str,
+    schema_config: &SchemaConfig,
+    state: &ServerState,
+    seq: u64,
+    data: Bytes,
+) -> Ack {
+    // each message takes its share of the memory budget until acknowledged
+    let Some(_admitted) = state.admission.admit(schema_id, data.len() as u64).await else {
+        let error = Error::from(ErrorServiceUnavailable(
+            "Memory budget exceeded, retry later",
+        ));
+        return Ack {
+            seq,
+            success: false,
+            error: Some(error.message()),
+            response: IngestResponse::failed(
+                schema_id,
+                schema_config
+                    .content_type
+                    .clone()
+                    .unwrap_or(ContentType::Json),
+                error,
+            ),
         };
-    }
-}
-
-impl Handler<WSClose> for WSHandler {
-    type Result = ();
-
-    fn handle(&mut self, _msg: WSClose, ctx: &mut Self::Context) {
-        let _log_guard = self.log_span.enter();
-
-        trace!("Received internal close message. Closing connection");
-
-        Self::internal_close(ctx);
+    };
+    let body_stream = stream::once(ready(Ok::<_, PayloadError>(data)));
+    // messages are acked once delivered, whatever the ack mode of the schema
+    let response = forward(
+        req.clone(),
+        body_stream,
+        schema_id,
+        schema_config,
+        state,
+        true,
+    )
+    .await;
+    let error = response.error.as_ref().map(|e| e.message());
+    Ack {
+        seq,
+        success: error.is_none(),
+        response,
+        error,
     }
 }
@@ -1,22 +1,48 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use actix_web::http::Method;
-use actix_web::middleware::Condition;
-use actix_web::{App, HttpResponse, HttpServer, dev::ServerHandle, web};
+use actix_web::body::{BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServerHandle, ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::{Condition, Next, from_fn};
+use actix_web::{App, HttpResponse, HttpServer, web};
 use common::config::ConfigError;
+use futures::future::LocalBoxFuture;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
 use pyo3::{Py, PyAny};
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use tracing::{debug, error, info, warn};
 use vec1::Vec1;
 
-// pub use connection::ws::WSError;
+use connection::grpc::GrpcServer;
+use connection::socket::SocketListeners;
+pub use connection::ws::WSError;
+use connection::{elasticsearch, hec, otlp, rest_proxy};
 use state::ServerState;
 
-use crate::config::{PythonProcessorConfig, SchemaConfig};
+use crate::admission::{Admission, Budget};
+use crate::auth::ApiKeys;
+use crate::config::{PythonProcessorConfig, SchemaConfig, ShutdownConfig};
+use crate::enrich::geoip::GeoIp;
+use crate::enrich::user_agent::UserAgent;
+use crate::filter::Filter;
+use crate::hec::HecTokens;
+use crate::jwt::JwtValidator;
+use crate::metrics::{Metrics, SCHEMA_ID_ATTRIBUTE};
 use crate::python::{import_and_call_callable, init_python};
+use crate::receipts::Receipts;
+use crate::redact::Redactor;
+use crate::reload::Reloadable;
+use crate::signature::Verifier;
 use crate::{Config, error::Error, error::Result, kafka::Kafka};
 
 mod connection;
@@ -26,8 +52,12 @@ pub struct Server {
     server_handle: ServerHandle,
     server_task_handle: JoinHandle<std::io::Result<()>>,
     kafka: Kafka,
-    // state: web::Data<ServerState>,
+    state: web::Data<ServerState>,
     bound_addrs: Vec<SocketAddr>,
+    grpc_server: Option<GrpcServer>,
+    socket_listeners: SocketListeners,
+    reload_tasks: Vec<JoinHandle<()>>,
+    shutdown: ShutdownConfig,
 }
 
 fn validate_convert_method(s: &str) -> std::result::Result<String, String> {
@@ -64,6 +94,15 @@ impl Server {
                 default_schema_config.librdkafka_config, kafka_producer_names
             ))));
         }
+        for topic in config.service.rest_proxy.iter().flat_map(|c| &c.topic) {
+            if !kafka_producer_names.contains(&topic.librdkafka_config.as_str()) {
+                return Err(Error::from(ConfigError::Invalid(format!(
+                    "Librdkafka config with name '{}' configured on REST proxy topic '{}' not found. \
+                    Available librdkafka configs: {:?}",
+                    topic.librdkafka_config, topic.name, kafka_producer_names
+                ))));
+            }
+        }
         let methods_cleaned = validate_convert_methods(&default_schema_config.allowed_methods)
             .map_err(ConfigError::Invalid)?;
         default_schema_config.allowed_methods = methods_cleaned;
@@ -134,6 +173,79 @@ impl Server {
                     .unwrap_or(default_schema_config.destination_topic.clone()),
                 python_request_processor: c.schema_config.python_request_processor.clone(),
                 librdkafka_config,
+                framing: default_schema_config
+                    .framing
+                    .clone()
+                    .into_iter()
+                    .chain(c.schema_config.framing.clone())
+                    .collect(),
+                validate_utf8: c
+                    .schema_config
+                    .validate_utf8
+                    .unwrap_or(default_schema_config.validate_utf8),
+                batching: c
+                    .schema_config
+                    .batching
+                    .clone()
+                    .or(default_schema_config.batching.clone()),
+                enrich: c
+                    .schema_config
+                    .enrich
+                    .clone()
+                    .or(default_schema_config.enrich.clone()),
+                geoip: c.schema_config.geoip.unwrap_or(default_schema_config.geoip),
+                user_agent: c
+                    .schema_config
+                    .user_agent
+                    .unwrap_or(default_schema_config.user_agent),
+                redact: c
+                    .schema_config
+                    .redact
+                    .clone()
+                    .or(default_schema_config.redact.clone()),
+                filter: c
+                    .schema_config
+                    .filter
+                    .clone()
+                    .unwrap_or(default_schema_config.filter.clone()),
+                websocket: c
+                    .schema_config
+                    .websocket
+                    .unwrap_or(default_schema_config.websocket),
+                cloudevents: c
+                    .schema_config
+                    .cloudevents
+                    .unwrap_or(default_schema_config.cloudevents),
+                response_delivery: c
+                    .schema_config
+                    .response_delivery
+                    .unwrap_or(default_schema_config.response_delivery),
+                response_partition_offsets: c
+                    .schema_config
+                    .response_partition_offsets
+                    .unwrap_or(default_schema_config.response_partition_offsets),
+                ack_mode: c
+                    .schema_config
+                    .ack_mode
+                    .unwrap_or(default_schema_config.ack_mode),
+                max_in_flight_bytes: c
+                    .schema_config
+                    .max_in_flight_bytes
+                    .or(default_schema_config.max_in_flight_bytes),
+                api_key_auth: c
+                    .schema_config
+                    .api_key_auth
+                    .unwrap_or(default_schema_config.api_key_auth),
+                signature: c
+                    .schema_config
+                    .signature
+                    .clone()
+                    .or(default_schema_config.signature.clone()),
+                jwt: c
+                    .schema_config
+                    .jwt
+                    .clone()
+                    .or(default_schema_config.jwt.clone()),
             };
             if schema_configs
                 .insert(c.schema_id.clone(), schema_config)
@@ -150,6 +262,102 @@ impl Server {
             }
         }
 
+        let metrics = Metrics::new();
+        let (default_redactor, redactors) =
+            per_schema(&default_schema_config, &schema_configs, |schema_config| {
+                schema_config
+                    .redact
+                    .as_ref()
+                    .map(|c| Redactor::new(c, metrics.redacted_fields.clone()))
+                    .transpose()
+            })?;
+        let (default_filter, filters) =
+            per_schema(&default_schema_config, &schema_configs, |schema_config| {
+                if schema_config.filter.is_empty() {
+                    Ok(None)
+                } else {
+                    Filter::new(&schema_config.filter).map(Some)
+                }
+            })?;
+        let (default_verifier, verifiers) =
+            per_schema(&default_schema_config, &schema_configs, |schema_config| {
+                schema_config
+                    .signature
+                    .as_ref()
+                    .map(Verifier::new)
+                    .transpose()
+            })?;
+        let bearer_api_keys = config.service.api_keys.as_ref().is_some_and(|c| c.bearer);
+        let (default_jwt_validator, jwt_validators) =
+            per_schema(&default_schema_config, &schema_configs, |schema_config| {
+                if schema_config.jwt.is_some() && schema_config.api_key_auth && bearer_api_keys {
+                    return Err(Error::from(ConfigError::Invalid(
+                        "'jwt' and 'api_key_auth' with bearer API keys can't both be enabled"
+                            .to_owned(),
+                    )));
+                }
+                schema_config
+                    .jwt
+                    .as_ref()
+                    .map(JwtValidator::new)
+                    .transpose()
+            })?;
+        let (default_budget, budgets) =
+            per_schema(&default_schema_config, &schema_configs, |schema_config| {
+                Ok(schema_config.max_in_flight_bytes.map(Budget::new))
+            })?;
+        let admission = Admission::new(
+            &config.service.memory_budget,
+            default_budget,
+            budgets,
+            metrics.clone(),
+        );
+
+        let reload_check_interval =
+            Duration::from_secs(config.service.database_reload_check_seconds);
+        let mut reload_tasks = Vec::new();
+        let geoip = load_database(
+            config.service.geoip_database.as_deref(),
+            "geoip",
+            "geoip_database",
+            GeoIp::open,
+            &default_schema_config,
+            &schema_configs,
+            |schema_config| schema_config.geoip,
+        )?;
+        let user_agent = load_database(
+            config.service.user_agent_database.as_deref(),
+            "user_agent",
+            "user_agent_database",
+            UserAgent::open,
+            &default_schema_config,
+            &schema_configs,
+            |schema_config| schema_config.user_agent,
+        )?;
+        let api_keys = load_database(
+            config.service.api_keys.as_ref().map(|c| c.path.as_str()),
+            "api_key_auth",
+            "api_keys",
+            ApiKeys::load,
+            &default_schema_config,
+            &schema_configs,
+            |schema_config| schema_config.api_key_auth,
+        )?;
+        if let Some(geoip) = &geoip {
+            reload_tasks.push(geoip.watch(reload_check_interval));
+        }
+        if let Some(user_agent) = &user_agent {
+            reload_tasks.push(user_agent.watch(reload_check_interval));
+        }
+        if let Some(api_keys) = &api_keys {
+            reload_tasks.push(api_keys.watch(reload_check_interval));
+        }
+        for jwt_validator in default_jwt_validator.iter().chain(jwt_validators.values()) {
+            if let Some(jwks) = jwt_validator.jwks() {
+                reload_tasks.push(jwks.watch(reload_check_interval));
+            }
+        }
+
         let state = web::Data::new(ServerState {
             kafka: kafka.clone(),
             header_names: config.headers,
@@ -157,19 +365,79 @@ impl Server {
             schema_configs,
             python_processor_resolver,
             max_event_size_bytes: config.service.max_event_size_bytes,
+            geoip,
+            user_agent,
+            default_redactor,
+            redactors,
+            default_filter,
+            filters,
+            default_verifier,
+            verifiers,
+            default_jwt_validator,
+            jwt_validators,
+            metrics,
+            otlp: config.service.otlp.clone(),
+            elasticsearch: config.service.elasticsearch.clone(),
+            hec_tokens: config
+                .service
+                .splunk_hec
+                .as_ref()
+                .map(|splunk_hec| HecTokens::new(&splunk_hec.token)),
+            rest_proxy: config.service.rest_proxy.clone(),
+            api_keys,
+            api_keys_config: config.service.api_keys.clone(),
+            ws_close: CancellationToken::new(),
+            ws_connections: TaskTracker::new(),
+            not_ready: CancellationToken::new(),
+            draining: CancellationToken::new(),
+            requests: TaskTracker::new(),
+            receipts: Arc::new(Receipts::new(config.service.max_receipts)),
+            pending_deliveries: TaskTracker::new(),
+            admission,
         });
+
+        // started first, since loading their TLS config can fail
+        let socket_listeners = SocketListeners::start(&config.service, &state).await?;
+
         let app_state = state.clone();
+        let max_otlp_request_size = config
+            .service
+            .otlp
+            .as_ref()
+            .map(|otlp| otlp.max_request_size_bytes as usize);
+        let max_elasticsearch_request_size = config
+            .service
+            .elasticsearch
+            .as_ref()
+            .map(|elasticsearch| elasticsearch.max_request_size_bytes as usize);
+        let max_splunk_hec_request_size = config
+            .service
+            .splunk_hec
+            .as_ref()
+            .map(|splunk_hec| splunk_hec.max_request_size_bytes as usize);
+        let max_rest_proxy_request_size = config
+            .service
+            .rest_proxy
+            .as_ref()
+            .map(|rest_proxy| rest_proxy.max_request_size_bytes as usize);
 
         let http_server = HttpServer::new(move || {
             let state = app_state.clone();
 
             App::new()
                 .app_data(state)
+                .wrap(from_fn(reject_when_draining))
                 .wrap(common::logging::actix_web::tracing_logger())
                 .wrap(Condition::new(
                     config.logging.sentry.enabled,
                     sentry_actix::Sentry::new(),
                 ))
+                .service(web::resource("/ready").route(web::get().to(ready)))
+                // before the ingest scope, which would take "receipts" as a schema id
+                .service(
+                    web::resource("/ingest/receipts/{receipt_id}")
+                        .route(web::get().to(connection::http::receipt)),
+                )
                 .service(
                     web::scope("/ingest/{schema_id}")
                         .wrap(Condition::new(
@@ -177,13 +445,32 @@ impl Server {
                             otel_metrics(), // needs to be under /ingest/{schema_id} path to catch the parsed schema id
                         ))
                         .service(web::resource("").route(web::route().to(connection::http::handle)))
+                        .service(
+                            web::resource("/ws").route(web::route().to(connection::ws::handle)),
+                        )
                         .service(
                             web::resource("/{rest:.*}").route(
                                 web::route().to(connection::http::handle_with_trailing_path),
                             ),
                         ),
                 )
-                // .service(web::resource("/ws").route(web::get().to(connection::ws::handle)))
+                .configure(|cfg| {
+                    if let Some(max_request_size) = max_otlp_request_size {
+                        cfg.service(
+                            otlp_scope(max_request_size)
+                                .wrap(from_fn(admit_buffered("otlp", max_request_size))),
+                        );
+                    }
+                    if let Some(max_request_size) = max_elasticsearch_request_size {
+                        elasticsearch_routes(cfg, max_request_size);
+                    }
+                    if let Some(max_request_size) = max_splunk_hec_request_size {
+                        splunk_hec_routes(cfg, max_request_size);
+                    }
+                    if let Some(max_request_size) = max_rest_proxy_request_size {
+                        rest_proxy_routes(cfg, max_request_size);
+                    }
+                })
                 .default_service(
                     web::route()
                         .to(HttpResponse::NotFound)
@@ -192,6 +479,7 @@ impl Server {
         })
         .disable_signals()
         .keep_alive(Duration::from_secs(config.service.keepalive_seconds))
+        .shutdown_timeout(config.service.shutdown.grace_period_seconds)
         .workers(config.service.num_workers)
         .bind(&config.service.address)?;
 
@@ -202,19 +490,61 @@ impl Server {
         let server_handle = server.handle();
         let server_task_handle = tokio::spawn(server);
 
+        let grpc_server = match config.service.grpc_address {
+            Some(grpc_address) => Some(GrpcServer::start(grpc_address, state.clone()).await?),
+            None => None,
+        };
+
         Ok(Server {
             server_handle,
             server_task_handle,
             kafka,
             bound_addrs,
-            // state,
+            grpc_server,
+            socket_listeners,
+            reload_tasks,
+            state,
+            shutdown: config.service.shutdown,
         })
     }
 
-    /// Will gracefully stop the server.
-    pub async fn stop(self) {
-        // debug!("Closing all WebSocket connections");
-        // self.state.close_all_ws().await;
+    /// Will gracefully stop the server: fails the readiness check for the drain delay, then
+    /// rejects new requests and gives the ones in flight the grace period to finish, and finally
+    /// flushes the producers. Returns the count of messages left unflushed per producer, of the
+    /// producers that could not flush them all.
+    pub async fn stop(self) -> HashMap<String, i32> {
+        info!(
+            "Failing readiness checks for {} seconds",
+            self.shutdown.drain_delay_seconds
+        );
+        self.state.not_ready.cancel();
+        tokio::time::sleep(Duration::from_secs(self.shutdown.drain_delay_seconds)).await;
+        self.state.draining.cancel();
+
+        debug!("Closing all WebSocket connections");
+        self.state.close_all_ws().await;
+
+        if let Some(grpc_server) = self.grpc_server {
+            info!("Stopping gRPC server");
+            grpc_server.stop().await;
+        }
+
+        info!("Stopping TCP and UDP listeners");
+        self.socket_listeners.stop().await;
+
+        info!(
+            "Waiting up to {} seconds for the requests in flight",
+            self.shutdown.grace_period_seconds
+        );
+        // the web server keeps rejecting new requests meanwhile
+        self.state.requests.close();
+        let grace_period = Duration::from_secs(self.shutdown.grace_period_seconds);
+        if tokio::time::timeout(grace_period, self.state.requests.wait())
+            .await
+            .is_err()
+        {
+            warn!("Requests still in flight after the grace period");
+        }
 
         info!("Stopping web server");
         // true means gracefully
@@ -224,8 +554,25 @@ impl Server {
             error!(%err, "Error joining server task");
         }
 
+        for task in &self.reload_tasks {
+            task.abort();
+        }
+
         info!("Stopping kafka producer");
-        self.kafka.stop();
+        let unflushed = self
+            .kafka
+            .stop(Duration::from_secs(self.shutdown.flush_timeout_seconds));
+        if unflushed.is_empty() {
+            debug!("Waiting for the deliveries of the requests already acknowledged");
+            self.state.wait_pending_deliveries().await;
+        }
+        for (name, count) in &unflushed {
+            error!(
+                "Kafka producer '{}' left {} messages unflushed",
+                name, count
+            );
+        }
+        unflushed
     }
 
     /// Will ungracefully shut the server down.
@@ -233,11 +580,95 @@ impl Server {
     pub async fn kill(self) {
         warn!("Killing server");
         self.server_handle.stop(false).await;
+        if let Some(grpc_server) = &self.grpc_server {
+            grpc_server.kill();
+        }
+        self.socket_listeners.kill();
+        for task in &self.reload_tasks {
+            task.abort();
+        }
     }
 
     pub fn addrs(&self) -> &[SocketAddr] {
         &self.bound_addrs
     }
+
+    pub fn grpc_addr(&self) -> Option<SocketAddr> {
+        self.grpc_server.as_ref().map(|s| s.addr())
+    }
+
+    pub fn tcp_addrs(&self) -> &[SocketAddr] {
+        self.socket_listeners.tcp_addrs()
+    }
+
+    pub fn udp_addrs(&self) -> &[SocketAddr] {
+        self.socket_listeners.udp_addrs()
+    }
+
+    pub fn syslog_addrs(&self) -> &[SocketAddr] {
+        self.socket_listeners.syslog_addrs()
+    }
+
+    pub fn forward_addrs(&self) -> &[SocketAddr] {
+        self.socket_listeners.forward_addrs()
+    }
+}
+
+/// Builds the per schema state, like a redactor, for the default schema config and for each schema
+/// config that needs one. Configuration errors are prefixed with the schema they are for.
+fn per_schema<T>(
+    default_schema_config: &SchemaConfig,
+    schema_configs: &HashMap<String, SchemaConfig>,
+    build: impl Fn(&SchemaConfig) -> Result<Option<T>>,
+) -> Result<(Option<T>, HashMap<String, T>)> {
+    let default = build(default_schema_config)?;
+    let mut per_schema = HashMap::new();
+    for (schema_id, schema_config) in schema_configs.iter() {
+        let built = build(schema_config).map_err(|e| match e {
+            Error::Config(ConfigError::Invalid(s)) => {
+                Error::from(ConfigError::Invalid(format!("Schema {}: {}", schema_id, s)))
+            }
+            e => e,
+        })?;
+        if let Some(built) = built {
+            per_schema.insert(schema_id.clone(), built);
+        }
+    }
+    Ok((default, per_schema))
+}
+
+/// Loads a database used by schemas with the `option_name` schema option enabled. Fails if such
+/// schemas exist without the database being configured.
+fn load_database<T: Send + Sync + 'static>(
+    path: Option<&str>,
+    option_name: &str,
+    path_option_name: &str,
+    open: fn(&Path) -> Result<T>,
+    default_schema_config: &SchemaConfig,
+    schema_configs: &HashMap<String, SchemaConfig>,
+    is_enabled: fn(&SchemaConfig) -> bool,
+) -> Result<Option<Arc<Reloadable<T>>>> {
+    match path {
+        Some(path) => Ok(Some(Reloadable::load(path, open)?)),
+        None => {
+            if is_enabled(default_schema_config) {
+                return Err(Error::from(ConfigError::Invalid(format!(
+                    "'{}' enabled on default schema config but '{}' is not configured",
+                    option_name, path_option_name
+                ))));
+            }
+            if let Some(schema_id) = schema_configs
+                .iter()
+                .find_map(|(schema_id, c)| is_enabled(c).then_some(schema_id))
+            {
+                return Err(Error::from(ConfigError::Invalid(format!(
+                    "'{}' enabled on schema '{}' but '{}' is not configured",
+                    option_name, schema_id, path_option_name
+                ))));
+            }
+            Ok(None)
+        }
+    }
 }
 
 pub struct PythonProcessor {
@@ -400,6 +831,151 @@ impl PythonProcessorResolver {
     }
 }
 
+/// The OTLP/HTTP endpoints, answering 404 for the signals that are not configured.
+fn otlp_scope(max_request_size: usize) -> actix_web::Scope {
+    web::scope("/v1")
+        .app_data(web::PayloadConfig::new(max_request_size))
+        .route(
+            "/logs",
+            web::post().to(otlp::export::<ExportLogsServiceRequest>),
+        )
+        .route(
+            "/traces",
+            web::post().to(otlp::export::<ExportTraceServiceRequest>),
+        )
+        .route(
+            "/metrics",
+            web::post().to(otlp::export::<ExportMetricsServiceRequest>),
+        )
+}
+
+/// The Elasticsearch endpoints used by shippers with an Elasticsearch output.
+fn elasticsearch_routes(cfg: &mut web::ServiceConfig, max_request_size: usize) {
+    cfg.service(
+        web::resource("/")
+            .route(web::get().to(elasticsearch::cluster_info))
+            .route(web::head().to(elasticsearch::cluster_info)),
+    )
+    .service(
+        web::resource("/_bulk")
+            .app_data(web::PayloadConfig::new(max_request_size))
+            .wrap(from_fn(admit_buffered("elasticsearch", max_request_size)))
+            .route(web::post().to(elasticsearch::bulk))
+            .route(web::put().to(elasticsearch::bulk)),
+    )
+    .service(
+        web::resource("/{index}/_bulk")
+            .app_data(web::PayloadConfig::new(max_request_size))
+            .wrap(from_fn(admit_buffered("elasticsearch", max_request_size)))
+            .route(web::post().to(elasticsearch::index_bulk))
+            .route(web::put().to(elasticsearch::index_bulk)),
+    );
+}
+
+fn splunk_hec_routes(cfg: &mut web::ServiceConfig, max_request_size: usize) {
+    cfg.service(
+        web::resource([
+            "/services/collector",
+            "/services/collector/event",
+            "/services/collector/event/1.0",
+        ])
+        .app_data(web::PayloadConfig::new(max_request_size))
+        .wrap(from_fn(admit_buffered("splunk_hec", max_request_size)))
+        .route(web::post().to(hec::event)),
+    )
+    .service(
+        web::resource(["/services/collector/raw", "/services/collector/raw/1.0"])
+            .app_data(web::PayloadConfig::new(max_request_size))
+            .wrap(from_fn(admit_buffered("splunk_hec", max_request_size)))
+            .route(web::post().to(hec::raw)),
+    )
+    .service(
+        web::resource([
+            "/services/collector/health",
+            "/services/collector/health/1.0",
+        ])
+        .route(web::get().to(hec::health)),
+    );
+}
+
+/// The produce endpoints of the Kafka REST Proxy v2 and v3 APIs.
+fn rest_proxy_routes(cfg: &mut web::ServiceConfig, max_request_size: usize) {
+    cfg.service(
+        web::resource("/topics/{topic}")
+            .app_data(web::PayloadConfig::new(max_request_size))
+            .wrap(from_fn(admit_buffered("rest_proxy", max_request_size)))
+            .route(web::post().to(rest_proxy::v2_produce)),
+    )
+    .service(web::resource("/v3/clusters").route(web::get().to(rest_proxy::v3_clusters)))
+    .service(
+        web::resource("/v3/clusters/{cluster_id}/topics/{topic}/records")
+            .app_data(web::PayloadConfig::new(max_request_size))
+            .wrap(from_fn(admit_buffered("rest_proxy", max_request_size)))
+            .route(web::post().to(rest_proxy::v3_produce)),
+    );
+}
+
+/// Readiness check for load balancers, failing once the server is shutting down.
+async fn ready(state: web::Data<ServerState>) -> HttpResponse {
+    if state.is_ready() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::ServiceUnavailable().finish()
+    }
+}
+
+/// Rejects new requests once the server is draining, closing their connections, and tracks the
+/// others until they are responded to.
+async fn reject_when_draining(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let state = req.app_data::<web::Data<ServerState>>();
+    if state.is_some_and(|state| state.is_draining()) {
+        let response = HttpResponse::ServiceUnavailable().force_close().finish();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    let _in_flight = state.map(|state| state.requests.token());
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+// the response of a middleware of resources, which can respond instead of them
+type ResourceResponse = LocalBoxFuture<
+    'static,
+    std::result::Result<ServiceResponse<EitherBody<BoxBody>>, actix_web::Error>,
+>;
+
+/// Admits the requests of an endpoint that reads whole bodies of up to `max_request_size` bytes
+/// by the memory budget before their body is read, and holds their share of it until they are
+/// responded to.
+fn admit_buffered(
+    endpoint: &'static str,
+    max_request_size: usize,
+) -> impl Fn(ServiceRequest, Next<BoxBody>) -> ResourceResponse {
+    move |req, next| {
+        Box::pin(async move {
+            let Some(state) = req.app_data::<web::Data<ServerState>>().cloned() else {
+                return next
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body);
+            };
+            let bytes = connection::http::buffered_size(req.headers(), max_request_size as u64);
+            let Some(_admitted) = state.admission.admit_to_endpoint(endpoint, bytes).await else {
+                let response = HttpResponse::ServiceUnavailable()
+                    .insert_header((RETRY_AFTER, state.admission.retry_after_seconds))
+                    .finish();
+                return Ok(req.into_response(response).map_into_right_body());
+            };
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
 fn otel_metrics() -> opentelemetry_instrumentation_actix_web::RequestMetrics {
     opentelemetry_instrumentation_actix_web::RequestMetrics::builder()
         .with_metric_attrs_from_req(metric_attributes_from_req)
@@ -414,7 +990,7 @@ fn metric_attributes_from_req(
         opentelemetry_instrumentation_actix_web::metrics_attributes_from_request(req, http_route);
     let path = req.match_info();
     if let Some(schema_id) = path.get("schema_id").map(|s| s.to_owned()) {
-        attrs.push(opentelemetry::KeyValue::new("ingest.schema.id", schema_id));
+        attrs.push(opentelemetry::KeyValue::new(SCHEMA_ID_ATTRIBUTE, schema_id));
     }
     attrs
 }
@@ -1,21 +1,28 @@
-// use std::collections::HashSet;
-// use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
-
-// use actix::Addr;
-// use futures::future::{join_all, FutureExt};
-// use tokio::sync::RwLock;
-// use tracing::{error, trace};
-
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::config::{HeaderNames, SchemaConfig};
-// use crate::error::{Error, Result};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::warn;
+
+use crate::admission::Admission;
+use crate::auth::ApiKeys;
+use crate::config::{
+    ApiKeysConfig, ElasticsearchConfig, HeaderNames, OtlpConfig, RestProxyConfig, SchemaConfig,
+};
+use crate::enrich::geoip::GeoIp;
+use crate::enrich::user_agent::UserAgent;
+use crate::filter::Filter;
+use crate::hec::HecTokens;
+use crate::jwt::JwtValidator;
 use crate::kafka::Kafka;
+use crate::metrics::Metrics;
+use crate::receipts::Receipts;
+use crate::redact::Redactor;
+use crate::reload::Reloadable;
 use crate::server::PythonProcessorResolver;
-
-// use super::connection::ws::{WSClose, WSHandler};
-
-// const DEFAULT_WS_CAP: usize = 128;
+use crate::server::connection::http::PendingDeliveries;
+use crate::signature::Verifier;
 
 pub struct ServerState {
     pub kafka: Kafka,
@@ -24,76 +31,156 @@ pub struct ServerState {
     pub schema_configs: HashMap<String, SchemaConfig>,
     pub python_processor_resolver: PythonProcessorResolver,
     pub max_event_size_bytes: u64,
-    // ws_connections: RwLock<HashSet<Addr<WSHandler>>>,
-    // accept_ws: AtomicBool,
+    pub geoip: Option<Arc<Reloadable<GeoIp>>>,
+    pub user_agent: Option<Arc<Reloadable<UserAgent>>>,
+    pub default_redactor: Option<Redactor>,
+    /// Redactors of the schemas with their own configuration
+    pub redactors: HashMap<String, Redactor>,
+    pub default_filter: Option<Filter>,
+    /// Filters of the schemas with their own configuration
+    pub filters: HashMap<String, Filter>,
+    pub default_verifier: Option<Verifier>,
+    /// Signature verifiers of the schemas with their own configuration
+    pub verifiers: HashMap<String, Verifier>,
+    pub default_jwt_validator: Option<JwtValidator>,
+    /// JWT validators of the schemas with their own configuration
+    pub jwt_validators: HashMap<String, JwtValidator>,
+    pub metrics: Metrics,
+    pub otlp: Option<OtlpConfig>,
+    pub elasticsearch: Option<ElasticsearchConfig>,
+    /// The schemas of the Splunk HEC tokens, when the HEC API is served
+    pub hec_tokens: Option<HecTokens>,
+    pub rest_proxy: Option<RestProxyConfig>,
+    pub api_keys: Option<Arc<Reloadable<ApiKeys>>>,
+    pub api_keys_config: Option<ApiKeysConfig>,
+    /// Cancelled when the server stops, to close the WebSocket connections
+    pub ws_close: CancellationToken,
+    pub ws_connections: TaskTracker,
+    /// Cancelled when the server starts shutting down, to fail the readiness checks
+    pub not_ready: CancellationToken,
+    /// Cancelled once the drain delay of the shutdown is over, to reject new requests
+    pub draining: CancellationToken,
+    /// HTTP requests in flight, waited for before stopping the web server
+    pub requests: TaskTracker,
+    pub receipts: Arc<Receipts>,
+    /// Tasks waiting for the deliveries of the requests responded to before them
+    pub pending_deliveries: TaskTracker,
+    pub admission: Admission,
 }
 
-// impl ServerState {
-//     pub fn new(
-//         kafka: Kafka,
-//         header_names: HeaderNames,
-//         default_schema_config: SchemaConfig,
-//         schema_configs: HashMap<String, SchemaConfig>,
-//         python_processor_resolver: PythonProcessorResolver,
-//         max_event_size_bytes: u64,
-//     ) -> Self {
-//         Self {
-//             kafka,
-//             header_names,
-//             default_schema_config,
-//             schema_configs,
-//             python_processor_resolver,
-//             max_event_size_bytes,
-//             // ws_connections: RwLock::new(HashSet::with_capacity(DEFAULT_WS_CAP)),
-//             // accept_ws: AtomicBool::new(true),
-//         }
-//     }
+impl ServerState {
+    pub fn schema_config(&self, schema_id: &str) -> &SchemaConfig {
+        self.schema_configs
+            .get(schema_id)
+            .unwrap_or(&self.default_schema_config)
+    }
+
+    pub fn redactor(&self, schema_id: &str) -> Option<&Redactor> {
+        if self.schema_configs.contains_key(schema_id) {
+            self.redactors.get(schema_id)
+        } else {
+            self.default_redactor.as_ref()
+        }
+    }
+
+    pub fn filter(&self, schema_id: &str) -> Option<&Filter> {
+        if self.schema_configs.contains_key(schema_id) {
+            self.filters.get(schema_id)
+        } else {
+            self.default_filter.as_ref()
+        }
+    }
+
+    pub fn verifier(&self, schema_id: &str) -> Option<&Verifier> {
+        if self.schema_configs.contains_key(schema_id) {
+            self.verifiers.get(schema_id)
+        } else {
+            self.default_verifier.as_ref()
+        }
+    }
 
-// pub async fn register_ws(&self, ws_addr: Addr<WSHandler>) -> Result<()> {
-//     if !self.accepting_ws() {
-//         return Err(Error::WSNotAccepted);
-//     }
-//
-//     let mut ws_connections = self.ws_connections.write().await;
-//
-//     if !ws_connections.insert(ws_addr) {
-//         error!("Tried to register same webscket actor twice");
-//     } else {
-//         trace!("Registered new websocket connection");
-//     }
-//
-//     Ok(())
-// }
-//
-// pub async fn unregister_ws(&self, ws_addr: &Addr<WSHandler>) {
-//     let mut ws_connections = self.ws_connections.write().await;
-//
-//     if !ws_connections.remove(ws_addr) {
-//         error!("Tried to unregister nonexistent websocket actor");
-//     } else {
-//         trace!("Unregistered websocket connection");
-//     }
-// }
-//
-// pub async fn close_all_ws(&self) {
-//     self.accept_ws.store(false, Relaxed);
-//
-//     let ws_connections = self.ws_connections.read().await;
-//
-//     join_all(ws_connections.iter().map(|ws_conn| {
-//         ws_conn.send(WSClose).map(|r| {
-//             if let Err(e) = r {
-//                 error!(
-//                     "Could not send internal close message to websocket connection: {}",
-//                     e
-//                 );
-//             }
-//         })
-//     }))
-//     .await;
-// }
-//
-// pub fn accepting_ws(&self) -> bool {
-//     self.accept_ws.load(Relaxed)
-// }
-// }
+    pub fn jwt_validator(&self, schema_id: &str) -> Option<&JwtValidator> {
+        if self.schema_configs.contains_key(schema_id) {
+            self.jwt_validators.get(schema_id)
+        } else {
+            self.default_jwt_validator.as_ref()
+        }
+    }
+
+    /// Whether the HTTP requests to the schema are authenticated, with an API key, a JWT, a
+    /// signature or by a Python processor.
+    pub fn authenticates(&self, schema_id: &str) -> bool {
+        self.schema_config(schema_id).api_key_auth
+            || self.jwt_validator(schema_id).is_some()
+            || self.verifier(schema_id).is_some()
+            || self
+                .python_processor_resolver
+                .get(schema_id, "POST")
+                .is_some()
+    }
+
+    /// Stops accepting WebSocket connections and closes the open ones, once the messages they are
+    /// forwarding are acknowledged.
+    pub async fn close_all_ws(&self) {
+        self.ws_close.cancel();
+        self.ws_connections.close();
+        self.ws_connections.wait().await;
+    }
+
+    pub fn accepting_ws(&self) -> bool {
+        !self.ws_close.is_cancelled()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        !self.not_ready.is_cancelled()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.is_cancelled()
+    }
+
+    /// Waits in the background for the pending deliveries of a request, recording their outcome in
+    /// a new receipt when `receipt` is set. Returns the id of the receipt.
+    pub fn track_deliveries(
+        &self,
+        pending_deliveries: PendingDeliveries,
+        schema_id: &str,
+        message_count: u64,
+        receipt: bool,
+    ) -> Option<String> {
+        let PendingDeliveries {
+            mut delivery_rx,
+            delivered_count,
+        } = pending_deliveries;
+        let receipt_id = receipt.then(|| {
+            self.receipts
+                .create(schema_id, message_count, delivered_count)
+        });
+        let receipts = self.receipts.clone();
+        let schema_id = schema_id.to_owned();
+        let tracked_receipt_id = receipt_id.clone();
+        self.pending_deliveries.spawn(async move {
+            let mut delivered_count = 0;
+            let mut error = None;
+            while let Some(result) = delivery_rx.recv().await {
+                match result {
+                    Ok(delivery) => delivered_count += delivery.records.count,
+                    Err(e) => {
+                        warn!(schema_id, %e, "Message acknowledged before its delivery failed");
+                        error.get_or_insert_with(|| e.to_string());
+                    }
+                }
+            }
+            if let Some(receipt_id) = tracked_receipt_id {
+                receipts.complete(&receipt_id, delivered_count, error);
+            }
+        });
+        receipt_id
+    }
+
+    /// Waits for the pending deliveries of the requests already responded to.
+    pub async fn wait_pending_deliveries(&self) {
+        self.pending_deliveries.close();
+        self.pending_deliveries.wait().await;
+    }
+}
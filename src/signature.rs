@@ -0,0 +1,304 @@
+//! Verification of the HMAC signatures of webhook requests, with the presets of common providers.
+
+use std::fmt;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header::HeaderMap;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use common::config::ConfigError;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::{Sha256, Sha512};
+
+use crate::config::{HmacAlgorithm, SignatureConfig, SignatureEncoding, SignatureProvider};
+use crate::error::{Error, Result};
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Timestamp,
+    Body,
+}
+
+pub struct Verifier {
+    secret: Vec<u8>,
+    header: String,
+    algorithm: HmacAlgorithm,
+    encoding: SignatureEncoding,
+    prefix: String,
+    // the header is `t=timestamp,v1=signature`, with a signature per secret being rolled
+    stripe_format: bool,
+    timestamp_header: Option<String>,
+    signed_content: Vec<Part>,
+    timestamp_tolerance_seconds: u64,
+}
+
+/// The options of a provider, before the configured ones.
+struct Preset {
+    header: Option<&'static str>,
+    encoding: SignatureEncoding,
+    prefix: &'static str,
+    signed_content: &'static str,
+    timestamp_header: Option<&'static str>,
+}
+
+fn preset(provider: SignatureProvider) -> Preset {
+    let preset = Preset {
+        header: None,
+        encoding: SignatureEncoding::Hex,
+        prefix: "",
+        signed_content: "{body}",
+        timestamp_header: None,
+    };
+    match provider {
+        SignatureProvider::Github => Preset {
+            header: Some("x-hub-signature-256"),
+            prefix: "sha256=",
+            ..preset
+        },
+        SignatureProvider::Stripe => Preset {
+            header: Some("stripe-signature"),
+            signed_content: "{timestamp}.{body}",
+            ..preset
+        },
+        SignatureProvider::Slack => Preset {
+            header: Some("x-slack-signature"),
+            prefix: "v0=",
+            signed_content: "v0:{timestamp}:{body}",
+            timestamp_header: Some("x-slack-request-timestamp"),
+            ..preset
+        },
+        SignatureProvider::Shopify => Preset {
+            header: Some("x-shopify-hmac-sha256"),
+            encoding: SignatureEncoding::Base64,
+            ..preset
+        },
+        SignatureProvider::Hmac => preset,
+    }
+}
+
+impl Verifier {
+    pub fn new(config: &SignatureConfig) -> Result<Verifier> {
+        let invalid = |s: String| Error::from(ConfigError::Invalid(s));
+        let preset = preset(config.provider);
+        let header = config
+            .header
+            .clone()
+            .or(preset.header.map(str::to_owned))
+            .ok_or_else(|| {
+                invalid("'signature' with the 'hmac' provider requires 'header'".to_owned())
+            })?;
+        let stripe_format = config.provider == SignatureProvider::Stripe;
+        let timestamp_header = config
+            .timestamp_header
+            .clone()
+            .or(preset.timestamp_header.map(str::to_owned));
+        let signed_content = parse_signed_content(
+            config
+                .signed_content
+                .as_deref()
+                .unwrap_or(preset.signed_content),
+        )
+        .map_err(invalid)?;
+        if signed_content.contains(&Part::Timestamp) && !stripe_format && timestamp_header.is_none()
+        {
+            return Err(invalid(
+                "'signature' with a signed '{timestamp}' requires 'timestamp_header'".to_owned(),
+            ));
+        }
+        Ok(Verifier {
+            secret: fs::read(&config.secret_file)?.trim_ascii().to_vec(),
+            header,
+            algorithm: config.algorithm.unwrap_or(HmacAlgorithm::Sha256),
+            encoding: config.encoding.unwrap_or(preset.encoding),
+            prefix: config.prefix.clone().unwrap_or(preset.prefix.to_owned()),
+            stripe_format,
+            timestamp_header,
+            signed_content,
+            timestamp_tolerance_seconds: config.timestamp_tolerance_seconds,
+        })
+    }
+
+    /// Verifies the signature of a request with its whole body. Requests with a signed timestamp
+    /// are rejected when it is not within the tolerance of now.
+    pub fn verify(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> std::result::Result<(), SignatureError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("The clock is after the epoch")
+            .as_secs();
+        self.verify_at(headers, body, now)
+    }
+
+    fn verify_at(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: u64,
+    ) -> std::result::Result<(), SignatureError> {
+        let header = headers
+            .get(self.header.as_str())
+            .and_then(|h| h.to_str().ok())
+            .ok_or(SignatureError::MissingSignature)?;
+        let (timestamp, signatures) = if self.stripe_format {
+            let mut timestamp = None;
+            let mut signatures = Vec::new();
+            for item in header.split(',') {
+                match item.trim().split_once('=') {
+                    Some(("t", t)) => timestamp = Some(t),
+                    Some(("v1", signature)) => signatures.push(signature),
+                    _ => {}
+                }
+            }
+            (
+                Some(timestamp.ok_or(SignatureError::MissingTimestamp)?),
+                signatures,
+            )
+        } else {
+            let timestamp = match &self.timestamp_header {
+                Some(name) => Some(
+                    headers
+                        .get(name.as_str())
+                        .and_then(|h| h.to_str().ok())
+                        .ok_or(SignatureError::MissingTimestamp)?,
+                ),
+                None => None,
+            };
+            let signature = header
+                .strip_prefix(self.prefix.as_str())
+                .ok_or(SignatureError::InvalidSignature)?;
+            (timestamp, vec![signature])
+        };
+
+        if let Some(timestamp) = timestamp {
+            let timestamp: u64 = timestamp
+                .trim()
+                .parse()
+                .map_err(|_| SignatureError::InvalidTimestamp)?;
+            if now.abs_diff(timestamp) > self.timestamp_tolerance_seconds {
+                return Err(SignatureError::ExpiredTimestamp);
+            }
+        }
+
+        let signatures: Vec<Vec<u8>> = signatures
+            .iter()
+            .filter_map(|signature| match self.encoding {
+                SignatureEncoding::Hex => hex::decode(signature.trim()).ok(),
+                SignatureEncoding::Base64 => STANDARD.decode(signature.trim()).ok(),
+            })
+            .collect();
+        let content = self.signed_content.iter().map(|part| match part {
+            Part::Literal(literal) => literal.as_bytes(),
+            Part::Timestamp => timestamp.unwrap_or_default().as_bytes(),
+            Part::Body => body,
+        });
+        let verified = match self.algorithm {
+            HmacAlgorithm::Sha256 => verify_mac::<Hmac<Sha256>>(&self.secret, content, &signatures),
+            HmacAlgorithm::Sha512 => verify_mac::<Hmac<Sha512>>(&self.secret, content, &signatures),
+        };
+        if verified {
+            Ok(())
+        } else {
+            Err(SignatureError::InvalidSignature)
+        }
+    }
+}
+
+fn verify_mac<'a, M: Mac + KeyInit + Clone>(
+    secret: &[u8],
+    content: impl Iterator<Item = &'a [u8]>,
+    signatures: &[Vec<u8>],
+) -> bool {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    for part in content {
+        mac.update(part);
+    }
+    signatures
+        .iter()
+        .any(|signature| mac.clone().verify_slice(signature).is_ok())
+}
+
+/// Parses a signed content template, which has to sign the body.
+fn parse_signed_content(template: &str) -> std::result::Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_owned()));
+        }
+        let placeholder = &rest[start..];
+        if let Some(after) = placeholder.strip_prefix("{timestamp}") {
+            parts.push(Part::Timestamp);
+            rest = after;
+        } else if let Some(after) = placeholder.strip_prefix("{body}") {
+            parts.push(Part::Body);
+            rest = after;
+        } else {
+            return Err(format!(
+                "Signed content '{}' has an unknown placeholder, only '{{timestamp}}' and '{{body}}' are supported",
+                template
+            ));
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_owned()));
+    }
+    if !parts.contains(&Part::Body) {
+        return Err(format!("Signed content '{}' has no '{{body}}'", template));
+    }
+    Ok(parts)
+}
+
+/// The reasons requests fail verification, all responded to with a 401 and their JSON.
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    MissingSignature,
+    MissingTimestamp,
+    InvalidTimestamp,
+    /// The signed timestamp is not within the tolerance of now.
+    ExpiredTimestamp,
+    InvalidSignature,
+    /// Messages of WebSocket connections and gRPC requests, which are not signed HTTP bodies.
+    Unsupported,
+}
+
+impl SignatureError {
+    fn code(&self) -> &'static str {
+        match self {
+            SignatureError::MissingSignature => "missing_signature",
+            SignatureError::MissingTimestamp => "missing_timestamp",
+            SignatureError::InvalidTimestamp => "invalid_timestamp",
+            SignatureError::ExpiredTimestamp => "expired_timestamp",
+            SignatureError::InvalidSignature => "invalid_signature",
+            SignatureError::Unsupported => "signature_unsupported",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"error": self.code(), "message": self.to_string()})
+    }
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SignatureError::MissingSignature => "Signature is required",
+            SignatureError::MissingTimestamp => "Signed timestamp is required",
+            SignatureError::InvalidTimestamp => "Invalid signed timestamp",
+            SignatureError::ExpiredTimestamp => "Signed timestamp is outside the tolerance",
+            SignatureError::InvalidSignature => "Invalid signature",
+            SignatureError::Unsupported => "Signed schemas only accept HTTP requests",
+        })
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+#[cfg(test)]
+mod test;
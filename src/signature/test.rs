@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::PathBuf;
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::config::{HmacAlgorithm, SignatureConfig, SignatureProvider};
+
+use super::{Part, SignatureError, Verifier, parse_signed_content};
+
+const BODY: &[u8] = br#"{"a":1}"#;
+const NOW: u64 = 1_700_000_000;
+
+fn secret_file() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ingest-signature-test-{}", std::process::id()));
+    fs::write(&path, "secret\n").unwrap();
+    path
+}
+
+fn config(provider: SignatureProvider) -> SignatureConfig {
+    SignatureConfig {
+        provider,
+        secret_file: secret_file().to_str().unwrap().to_owned(),
+        header: None,
+        algorithm: None,
+        encoding: None,
+        prefix: None,
+        signed_content: None,
+        timestamp_header: None,
+        timestamp_tolerance_seconds: 300,
+    }
+}
+
+fn headers(headers: &[(&'static str, &str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for &(name, value) in headers {
+        map.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_str(value).unwrap(),
+        );
+    }
+    map
+}
+
+// echo -n '{"a":1}' | openssl dgst -sha256 -hmac secret
+#[test]
+fn test_github() {
+    let verifier = Verifier::new(&config(SignatureProvider::Github)).unwrap();
+    let signature = "sha256=aa9e2e3575f5d7098b6caccd790888c36d5fdb63342a73bada2d6a51747a8494";
+    assert_eq!(
+        verifier.verify_at(&headers(&[("x-hub-signature-256", signature)]), BODY, NOW),
+        Ok(())
+    );
+    assert_eq!(
+        verifier.verify_at(&headers(&[("x-hub-signature-256", signature)]), b"{}", NOW),
+        Err(SignatureError::InvalidSignature)
+    );
+    assert_eq!(
+        verifier.verify_at(&headers(&[]), BODY, NOW),
+        Err(SignatureError::MissingSignature)
+    );
+}
+
+#[test]
+fn test_stripe() {
+    let verifier = Verifier::new(&config(SignatureProvider::Stripe)).unwrap();
+    // a signature with a rolled secret, then the one with the current secret
+    let header = "t=1700000000,v1=00ff,v1=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686,v0=ab";
+    let headers = headers(&[("stripe-signature", header)]);
+    assert_eq!(verifier.verify_at(&headers, BODY, NOW + 300), Ok(()));
+    // replayed later
+    assert_eq!(
+        verifier.verify_at(&headers, BODY, NOW + 301),
+        Err(SignatureError::ExpiredTimestamp)
+    );
+    assert_eq!(
+        verifier.verify_at(
+            &self::headers(&[("stripe-signature", "v1=00ff")]),
+            BODY,
+            NOW
+        ),
+        Err(SignatureError::MissingTimestamp)
+    );
+}
+
+#[test]
+fn test_slack() {
+    let verifier = Verifier::new(&config(SignatureProvider::Slack)).unwrap();
+    let signature = "v0=7d06e4dcbb54167f0033658ac8c45abe35b1fa257ff38a448e290762b595f902";
+    assert_eq!(
+        verifier.verify_at(
+            &headers(&[
+                ("x-slack-signature", signature),
+                ("x-slack-request-timestamp", "1700000000")
+            ]),
+            BODY,
+            NOW - 10
+        ),
+        Ok(())
+    );
+    // the timestamp is signed
+    assert_eq!(
+        verifier.verify_at(
+            &headers(&[
+                ("x-slack-signature", signature),
+                ("x-slack-request-timestamp", "1700000001")
+            ]),
+            BODY,
+            NOW
+        ),
+        Err(SignatureError::InvalidSignature)
+    );
+    assert_eq!(
+        verifier.verify_at(&headers(&[("x-slack-signature", signature)]), BODY, NOW),
+        Err(SignatureError::MissingTimestamp)
+    );
+}
+
+#[test]
+fn test_shopify() {
+    let verifier = Verifier::new(&config(SignatureProvider::Shopify)).unwrap();
+    let signature = "qp4uNXX11wmLbKzNeQiIw21f22M0KnO62i1qUXR6hJQ=";
+    assert_eq!(
+        verifier.verify_at(&headers(&[("x-shopify-hmac-sha256", signature)]), BODY, NOW),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_hmac() {
+    assert!(Verifier::new(&config(SignatureProvider::Hmac)).is_err());
+    let verifier = Verifier::new(&SignatureConfig {
+        header: Some("x-signature".to_owned()),
+        algorithm: Some(HmacAlgorithm::Sha512),
+        ..config(SignatureProvider::Hmac)
+    })
+    .unwrap();
+    let signature = "42f08c0da01c9a46d4e5663d5f2140526a016507c598182c6769cca2bcf7ba43c395b719b01d288f5ad322564dce1d40ca28ef50b876fc49a33d13d6783114dd";
+    assert_eq!(
+        verifier.verify_at(&headers(&[("x-signature", signature)]), BODY, NOW),
+        Ok(())
+    );
+    // a signed timestamp needs its header
+    assert!(
+        Verifier::new(&SignatureConfig {
+            header: Some("x-signature".to_owned()),
+            signed_content: Some("{timestamp}.{body}".to_owned()),
+            ..config(SignatureProvider::Hmac)
+        })
+        .is_err()
+    );
+}
+
+#[test]
+fn test_parse_signed_content() {
+    assert_eq!(
+        parse_signed_content("v0:{timestamp}:{body}"),
+        Ok(vec![
+            Part::Literal("v0:".to_owned()),
+            Part::Timestamp,
+            Part::Literal(":".to_owned()),
+            Part::Body,
+        ])
+    );
+    assert!(parse_signed_content("{timestamp}").is_err());
+    assert!(parse_signed_content("{url}{body}").is_err());
+}
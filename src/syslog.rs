@@ -0,0 +1,427 @@
+//! Parsing of syslog messages in the RFC 5424 and RFC 3164 (BSD) formats, and splitting of syslog
+//! TCP streams into messages (RFC 6587).
+
+use std::{fmt, io};
+
+use bytes::{Buf, Bytes, BytesMut};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use tokio_util::codec::Decoder;
+
+use crate::framing::FrameError;
+
+pub const FACILITY_NAMES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+const SEVERITY_NAMES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+// RFC 3164 section 4.3.3, user.notice for messages without a priority
+const DEFAULT_PRIORITY: u8 = 13;
+const MAX_PRIORITY: u8 = 191;
+const NIL: &[u8] = b"-";
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+// "Mmm dd hh:mm:ss"
+const RFC3164_TIMESTAMP_LENGTH: usize = 15;
+const MONTHS: [&[u8]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+// octet counts longer than this can't be valid message lengths
+const MAX_LENGTH_DIGITS: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum SyslogError {
+    /// An RFC 5424 header field is missing.
+    MissingField(&'static str),
+    InvalidStructuredData,
+}
+
+impl fmt::Display for SyslogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SyslogError::*;
+
+        match self {
+            MissingField(field) => write!(f, "Invalid syslog message: missing {}", field),
+            InvalidStructuredData => write!(f, "Invalid syslog message: invalid structured data"),
+        }
+    }
+}
+
+impl std::error::Error for SyslogError {}
+
+pub fn facility_code(name: &str) -> Option<u8> {
+    FACILITY_NAMES
+        .iter()
+        .position(|facility| *facility == name)
+        .map(|code| code as u8)
+}
+
+/// A parsed syslog message. Its JSON representation has the facility and severity names and
+/// leaves out the header fields that are missing.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SyslogMessage {
+    #[serde(serialize_with = "serialize_facility")]
+    pub facility: u8,
+    #[serde(serialize_with = "serialize_severity")]
+    pub severity: u8,
+    /// 1 for RFC 5424 messages, not set for RFC 3164 ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proc_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<String>,
+    /// The parameters of each structured data element by element id.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub structured_data: Map<String, Value>,
+    pub message: String,
+}
+
+impl SyslogMessage {
+    pub fn facility_name(&self) -> &'static str {
+        FACILITY_NAMES[self.facility as usize]
+    }
+
+    pub fn severity_name(&self) -> &'static str {
+        SEVERITY_NAMES[self.severity as usize]
+    }
+}
+
+fn serialize_facility<S: Serializer>(facility: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(FACILITY_NAMES[*facility as usize])
+}
+
+fn serialize_severity<S: Serializer>(severity: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(SEVERITY_NAMES[*severity as usize])
+}
+
+/// Parses an RFC 5424 message, or an RFC 3164 one when it has no version after the priority.
+/// RFC 3164 messages are parsed leniently like relays do: a missing priority is user.notice, and
+/// the header fields that don't look like the format are left in the message.
+pub fn parse(message: &[u8]) -> Result<SyslogMessage, SyslogError> {
+    let message = message.trim_ascii_end();
+    let (priority, rest) = match priority(message) {
+        Some((priority, rest)) => (Some(priority), rest),
+        None => (None, message),
+    };
+    let mut parsed = SyslogMessage {
+        facility: priority.unwrap_or(DEFAULT_PRIORITY) >> 3,
+        severity: priority.unwrap_or(DEFAULT_PRIORITY) & 7,
+        ..Default::default()
+    };
+    match (priority, rest.strip_prefix(b"1 ")) {
+        (Some(_), Some(rest)) => parse_rfc5424(&mut parsed, rest)?,
+        _ => parse_rfc3164(&mut parsed, rest),
+    }
+    Ok(parsed)
+}
+
+/// `<PRI>`, with a value of at most 191.
+fn priority(message: &[u8]) -> Option<(u8, &[u8])> {
+    let rest = message.strip_prefix(b"<")?;
+    let end = rest.iter().take(4).position(|b| *b == b'>')?;
+    let digits = &rest[..end];
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let priority: u8 = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    (priority <= MAX_PRIORITY).then_some((priority, &rest[end + 1..]))
+}
+
+/// `TIMESTAMP SP HOSTNAME SP APP-NAME SP PROCID SP MSGID SP STRUCTURED-DATA [SP MSG]`, after the
+/// priority and version.
+fn parse_rfc5424(parsed: &mut SyslogMessage, rest: &[u8]) -> Result<(), SyslogError> {
+    parsed.version = Some(1);
+    let mut fields = rest.splitn(6, |b| *b == b' ');
+    parsed.timestamp = header_field(fields.next(), "timestamp")?;
+    parsed.hostname = header_field(fields.next(), "hostname")?;
+    parsed.app_name = header_field(fields.next(), "app name")?;
+    parsed.proc_id = header_field(fields.next(), "proc id")?;
+    parsed.msg_id = header_field(fields.next(), "msg id")?;
+    let rest = fields
+        .next()
+        .ok_or(SyslogError::MissingField("structured data"))?;
+
+    let rest = structured_data(rest, &mut parsed.structured_data)?;
+    let message = match rest {
+        [] => rest,
+        [b' ', message @ ..] => message,
+        _ => return Err(SyslogError::InvalidStructuredData),
+    };
+    parsed.message = lossy(message.strip_prefix(UTF8_BOM).unwrap_or(message));
+    Ok(())
+}
+
+fn header_field(field: Option<&[u8]>, name: &'static str) -> Result<Option<String>, SyslogError> {
+    match field {
+        None | Some([]) => Err(SyslogError::MissingField(name)),
+        Some(NIL) => Ok(None),
+        Some(field) => Ok(Some(lossy(field))),
+    }
+}
+
+/// Parses `-` or one or more `[SD-ID PARAM-NAME="PARAM-VALUE" ...]` elements into `elements`, and
+/// returns the rest of the message.
+fn structured_data<'a>(
+    data: &'a [u8],
+    elements: &mut Map<String, Value>,
+) -> Result<&'a [u8], SyslogError> {
+    if let Some(rest) = data.strip_prefix(NIL) {
+        return Ok(rest);
+    }
+    if !data.starts_with(b"[") {
+        return Err(SyslogError::InvalidStructuredData);
+    }
+
+    let mut rest = data;
+    while let Some(element) = rest.strip_prefix(b"[") {
+        let id_end = element
+            .iter()
+            .position(|b| *b == b' ' || *b == b']')
+            .filter(|id_end| *id_end > 0)
+            .ok_or(SyslogError::InvalidStructuredData)?;
+        let id = lossy(&element[..id_end]);
+        rest = &element[id_end..];
+
+        let mut params = Map::new();
+        loop {
+            match rest {
+                [b']', after @ ..] => {
+                    rest = after;
+                    break;
+                }
+                [b' ', param @ ..] => {
+                    let name_end = param
+                        .iter()
+                        .position(|b| *b == b'=')
+                        .filter(|name_end| *name_end > 0)
+                        .ok_or(SyslogError::InvalidStructuredData)?;
+                    let value = param[name_end + 1..]
+                        .strip_prefix(b"\"")
+                        .ok_or(SyslogError::InvalidStructuredData)?;
+                    let (value, after) = param_value(value)?;
+                    params.insert(lossy(&param[..name_end]), Value::String(value));
+                    rest = after;
+                }
+                _ => return Err(SyslogError::InvalidStructuredData),
+            }
+        }
+        elements.insert(id, Value::Object(params));
+    }
+    Ok(rest)
+}
+
+/// Unescapes a parameter value up to its closing quote, and returns the rest after the quote.
+fn param_value(value: &[u8]) -> Result<(String, &[u8]), SyslogError> {
+    let mut unescaped = Vec::with_capacity(value.len());
+    let mut i = 0;
+    loop {
+        match value.get(i) {
+            None => return Err(SyslogError::InvalidStructuredData),
+            Some(b'"') => return Ok((lossy(&unescaped), &value[i + 1..])),
+            Some(b'\\') if matches!(value.get(i + 1), Some(b'"' | b'\\' | b']')) => {
+                unescaped.push(value[i + 1]);
+                i += 2;
+            }
+            Some(b) => {
+                unescaped.push(*b);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// `TIMESTAMP SP HOSTNAME SP TAG[PID]: MSG` after the priority, where any part of the header can
+/// be missing.
+fn parse_rfc3164(parsed: &mut SyslogMessage, mut rest: &[u8]) {
+    if rest.len() > RFC3164_TIMESTAMP_LENGTH
+        && is_rfc3164_timestamp(&rest[..RFC3164_TIMESTAMP_LENGTH])
+        && rest[RFC3164_TIMESTAMP_LENGTH] == b' '
+    {
+        parsed.timestamp = Some(lossy(&rest[..RFC3164_TIMESTAMP_LENGTH]));
+        rest = &rest[RFC3164_TIMESTAMP_LENGTH + 1..];
+        // the hostname is left out by some senders, in which case the tag follows the timestamp
+        if let Some(end) = rest.iter().position(|b| *b == b' ')
+            && !rest[..end].ends_with(b":")
+            && !rest[..end].contains(&b'[')
+        {
+            parsed.hostname = Some(lossy(&rest[..end]));
+            rest = &rest[end + 1..];
+        }
+    }
+
+    let tag_end = rest
+        .iter()
+        .position(|b| !(b.is_ascii_alphanumeric() || b"-_./".contains(b)))
+        .unwrap_or(rest.len());
+    if tag_end > 0 {
+        let after_tag = &rest[tag_end..];
+        let (proc_id, after_tag) = match after_tag.strip_prefix(b"[") {
+            Some(pid) => match pid.iter().position(|b| *b == b']') {
+                Some(end) => (Some(lossy(&pid[..end])), &pid[end + 1..]),
+                None => (None, after_tag),
+            },
+            None => (None, after_tag),
+        };
+        if let Some(message) = after_tag.strip_prefix(b":") {
+            parsed.app_name = Some(lossy(&rest[..tag_end]));
+            parsed.proc_id = proc_id;
+            rest = message.strip_prefix(b" ").unwrap_or(message);
+        }
+    }
+    parsed.message = lossy(rest);
+}
+
+fn is_rfc3164_timestamp(timestamp: &[u8]) -> bool {
+    let digit_at = |i: usize| timestamp[i].is_ascii_digit();
+    MONTHS.contains(&&timestamp[..3])
+        && timestamp[3] == b' '
+        && (timestamp[4] == b' ' || digit_at(4))
+        && digit_at(5)
+        && timestamp[6] == b' '
+        && digit_at(7)
+        && digit_at(8)
+        && timestamp[9] == b':'
+        && digit_at(10)
+        && digit_at(11)
+        && timestamp[12] == b':'
+        && digit_at(13)
+        && digit_at(14)
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Decoder for syslog over TCP, where each message is either preceded by its length and a space
+/// (octet counting) or followed by a newline (non-transparent framing). The framing is detected
+/// for each message, as messages of the latter start with `<` and never with a digit.
+pub struct SyslogCodec {
+    max_length: usize,
+    // of the buffer already searched for the newline ending a non-transparent framed message,
+    // so that it is not searched again on each read
+    next_index: usize,
+}
+
+impl SyslogCodec {
+    pub fn new(max_length: usize) -> SyslogCodec {
+        SyslogCodec {
+            max_length,
+            next_index: 0,
+        }
+    }
+}
+
+impl Decoder for SyslogCodec {
+    type Item = Bytes;
+    type Error = FrameError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, FrameError> {
+        // line endings and NUL trailers left over by the previous message
+        let skipped = buf
+            .iter()
+            .position(|b| !matches!(b, b'\n' | b'\r' | b'\0'))
+            .unwrap_or(buf.len());
+        buf.advance(skipped);
+
+        // never past the end of the buffer, in case it was replaced
+        self.next_index = self.next_index.min(buf.len());
+        match buf.first() {
+            None => Ok(None),
+            Some(b) if b.is_ascii_digit() => {
+                let Some(space) = buf
+                    .iter()
+                    .take(MAX_LENGTH_DIGITS + 1)
+                    .position(|b| *b == b' ')
+                else {
+                    return if buf.len() > MAX_LENGTH_DIGITS {
+                        Err(invalid_length())
+                    } else {
+                        Ok(None)
+                    };
+                };
+                let length: usize = std::str::from_utf8(&buf[..space])
+                    .ok()
+                    .and_then(|length| length.parse().ok())
+                    .ok_or_else(invalid_length)?;
+                if length > self.max_length {
+                    return Err(FrameError::MaxLengthExceeded);
+                }
+                if buf.len() < space + 1 + length {
+                    buf.reserve(space + 1 + length - buf.len());
+                    return Ok(None);
+                }
+                buf.advance(space + 1);
+                Ok(Some(buf.split_to(length).freeze()))
+            }
+            Some(_) => match buf[self.next_index..].iter().position(|b| *b == b'\n') {
+                Some(end) if self.next_index + end > self.max_length => {
+                    Err(FrameError::MaxLengthExceeded)
+                }
+                Some(end) => {
+                    let frame = buf.split_to(self.next_index + end).freeze();
+                    buf.advance(1);
+                    self.next_index = 0;
+                    Ok(Some(frame))
+                }
+                None if buf.len() > self.max_length => Err(FrameError::MaxLengthExceeded),
+                None => {
+                    self.next_index = buf.len();
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, FrameError> {
+        match self.decode(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            // the last message of non-transparent framing doesn't need a newline
+            None if !buf[0].is_ascii_digit() => {
+                self.next_index = 0;
+                Ok(Some(buf.split().freeze()))
+            }
+            None => Err(FrameError::Truncated),
+        }
+    }
+}
+
+fn invalid_length() -> FrameError {
+    FrameError::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Invalid syslog message length",
+    ))
+}
+
+#[cfg(test)]
+mod test;
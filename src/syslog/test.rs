@@ -0,0 +1,209 @@
+use bytes::{Bytes, BytesMut};
+use serde_json::json;
+use tokio_util::codec::Decoder;
+
+use crate::framing::FrameError;
+
+use super::{SyslogCodec, SyslogError, SyslogMessage, facility_code, parse};
+
+#[test]
+fn test_parse_rfc5424() {
+    let message = parse(
+        b"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+        [exampleSDID@32473 iut=\"3\" eventSource=\"Application\"][examplePriority@32473 class=\"high\"] \
+        \xEF\xBB\xBFAn application event log entry...\n",
+    )
+    .unwrap();
+    assert_eq!(message.facility_name(), "local4");
+    assert_eq!(message.severity_name(), "notice");
+    assert_eq!(
+        serde_json::to_value(&message).unwrap(),
+        json!({
+            "facility": "local4",
+            "severity": "notice",
+            "version": 1,
+            "timestamp": "2003-10-11T22:14:15.003Z",
+            "hostname": "mymachine.example.com",
+            "app_name": "evntslog",
+            "msg_id": "ID47",
+            "structured_data": {
+                "exampleSDID@32473": {"iut": "3", "eventSource": "Application"},
+                "examplePriority@32473": {"class": "high"}
+            },
+            "message": "An application event log entry..."
+        })
+    );
+}
+
+#[test]
+fn test_parse_rfc5424_nil_values() {
+    let message = parse(b"<34>1 - - - - - -").unwrap();
+    assert_eq!(
+        message,
+        SyslogMessage {
+            facility: 4,
+            severity: 2,
+            version: Some(1),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_parse_rfc5424_escaped_param_value() {
+    let message = parse(br#"<14>1 - host app 42 - [meta quote="a \"b\" \] \\ \c"] msg"#).unwrap();
+    assert_eq!(message.proc_id.as_deref(), Some("42"));
+    assert_eq!(
+        message.structured_data["meta"],
+        json!({"quote": r#"a "b" ] \ \c"#})
+    );
+    assert_eq!(message.message, "msg");
+}
+
+#[test]
+fn test_parse_rfc5424_invalid() {
+    assert_eq!(
+        parse(b"<14>1 2003-10-11T22:14:15.003Z host app"),
+        Err(SyslogError::MissingField("proc id"))
+    );
+    assert_eq!(
+        parse(b"<14>1 - host app - - [unterminated a=\"b\" msg"),
+        Err(SyslogError::InvalidStructuredData)
+    );
+    assert_eq!(
+        parse(b"<14>1 - host app - - nodata msg"),
+        Err(SyslogError::InvalidStructuredData)
+    );
+    assert_eq!(
+        parse(b"<14>1 - host app - - [id a=b] msg"),
+        Err(SyslogError::InvalidStructuredData)
+    );
+}
+
+#[test]
+fn test_parse_rfc3164() {
+    let message =
+        parse(b"<34>Oct 11 22:14:15 mymachine su[230]: 'su root' failed for lonvick on /dev/pts/8")
+            .unwrap();
+    assert_eq!(
+        message,
+        SyslogMessage {
+            facility: 4,
+            severity: 2,
+            timestamp: Some("Oct 11 22:14:15".to_owned()),
+            hostname: Some("mymachine".to_owned()),
+            app_name: Some("su".to_owned()),
+            proc_id: Some("230".to_owned()),
+            message: "'su root' failed for lonvick on /dev/pts/8".to_owned(),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_parse_rfc3164_partial_headers() {
+    // no hostname
+    let message = parse(b"<13>Feb  5 17:32:18 sshd: Accepted publickey").unwrap();
+    assert_eq!(message.timestamp.as_deref(), Some("Feb  5 17:32:18"));
+    assert_eq!(message.hostname, None);
+    assert_eq!(message.app_name.as_deref(), Some("sshd"));
+    assert_eq!(message.message, "Accepted publickey");
+
+    // no timestamp nor tag
+    let message = parse(b"<13>just a message: with a colon").unwrap();
+    assert_eq!(message.timestamp, None);
+    assert_eq!(message.app_name, None);
+    assert_eq!(message.message, "just a message: with a colon");
+
+    // no priority
+    let message = parse(b"kernel: oops").unwrap();
+    assert_eq!((message.facility, message.severity), (1, 5));
+    assert_eq!(message.app_name.as_deref(), Some("kernel"));
+    assert_eq!(message.message, "oops");
+
+    // invalid priority
+    let message = parse(b"<192>1 - - - - - -").unwrap();
+    assert_eq!(message.version, None);
+    assert_eq!(message.message, "<192>1 - - - - - -");
+}
+
+#[test]
+fn test_facility_code() {
+    assert_eq!(facility_code("kern"), Some(0));
+    assert_eq!(facility_code("authpriv"), Some(10));
+    assert_eq!(facility_code("local7"), Some(23));
+    assert_eq!(facility_code("unknown"), None);
+}
+
+fn decode_all(
+    codec: &mut SyslogCodec,
+    data: &[u8],
+) -> (Vec<Bytes>, Result<Option<Bytes>, FrameError>) {
+    let mut buf = BytesMut::from(data);
+    let mut frames = Vec::new();
+    while let Some(frame) = codec.decode(&mut buf).unwrap() {
+        frames.push(frame);
+    }
+    (frames, codec.decode_eof(&mut buf))
+}
+
+#[test]
+fn test_codec_mixed_framing() {
+    let mut codec = SyslogCodec::new(100);
+    let (frames, eof) = decode_all(
+        &mut codec,
+        b"9 <13>a\nb c\r\n<13>line\n\n16 <13>with\nnewline<13>last",
+    );
+    assert_eq!(frames, vec!["<13>a\nb c", "<13>line", "<13>with\nnewline"]);
+    assert_eq!(eof.unwrap().unwrap(), "<13>last");
+}
+
+#[test]
+fn test_codec_partial_octet_counted_frame() {
+    let mut codec = SyslogCodec::new(100);
+    let mut buf = BytesMut::from(&b"10 <13>a"[..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(b"bcdef");
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "<13>abcdef");
+
+    let (frames, eof) = decode_all(&mut codec, b"10 <13>a");
+    assert!(frames.is_empty());
+    assert!(matches!(eof, Err(FrameError::Truncated)));
+}
+
+#[test]
+fn test_codec_partial_non_transparent_frame() {
+    let mut codec = SyslogCodec::new(100);
+    let mut buf = BytesMut::from(&b"\n<13>a"[..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    // the bytes already searched for the newline are not searched again
+    assert_eq!(codec.next_index, 5);
+    buf.extend_from_slice(b"bc");
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert_eq!(codec.next_index, 7);
+    buf.extend_from_slice(b"d\n<13>e");
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "<13>abcd");
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert_eq!(codec.next_index, 5);
+}
+
+#[test]
+fn test_codec_errors() {
+    let mut codec = SyslogCodec::new(10);
+    assert!(matches!(
+        codec.decode(&mut BytesMut::from(&b"11 <13>abcdefg"[..])),
+        Err(FrameError::MaxLengthExceeded)
+    ));
+    assert!(matches!(
+        codec.decode(&mut BytesMut::from(&b"<13>abcdefghijk"[..])),
+        Err(FrameError::MaxLengthExceeded)
+    ));
+    assert!(matches!(
+        codec.decode(&mut BytesMut::from(&b"12345678901 <13>a"[..])),
+        Err(FrameError::Io(_))
+    ));
+    assert!(matches!(
+        codec.decode(&mut BytesMut::from(&b"1a <13>a"[..])),
+        Err(FrameError::Io(_))
+    ));
+}
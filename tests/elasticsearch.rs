@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use reqwest::{Body, Client, StatusCode};
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_elasticsearch_bulk() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["elasticsearch"] = serde_json::json!({
+        "route": [{"index": "filebeat-*", "schema_id": "filebeat"}]
+    });
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("x-elastic-product").unwrap(),
+        "Elasticsearch"
+    );
+
+    let body = "{\"create\":{\"_id\":\"1\"}}\n{\"message\":\"a\"}\n\
+        {\"delete\":{\"_id\":\"2\"}}\n";
+    let res = client
+        .post(format!("http://{}/filebeat-8.11/_bulk", addr))
+        .header("content-type", "application/x-ndjson")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["errors"], true);
+    assert_eq!(body["items"][0]["create"]["_index"], "filebeat-8.11");
+    assert_eq!(body["items"][0]["create"]["status"], 201);
+    assert_eq!(body["items"][1]["delete"]["status"], 400);
+
+    let res = client
+        .post(format!("http://{}/_bulk", addr))
+        .body("{\"index\":{}}\n{}\n")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "illegal_argument_exception");
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_elasticsearch_bulk_refused() {
+    let secret_path =
+        std::env::temp_dir().join(format!("ingest-bulk-secret-{}", std::process::id()));
+    std::fs::write(&secret_path, "secret").unwrap();
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{
+            "schema_id": "signed",
+            "signature": {"provider": "github", "secret_file": secret_path.to_str().unwrap()}
+        }]
+    }));
+    config["service"]["elasticsearch"] = serde_json::json!({
+        "route": [{"index": "webhooks-*", "schema_id": "signed"}]
+    });
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+
+    // documents are only sent to the routed schemas that do not authenticate requests
+    let body = "{\"index\":{\"_index\":\"webhooks-1\"}}\n{\"message\":\"a\"}\n\
+        {\"index\":{\"_index\":\"other\"}}\n{\"message\":\"b\"}\n";
+    let res = Client::new()
+        .post(format!("http://{}/_bulk", addr))
+        .header("content-type", "application/x-ndjson")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["errors"], true);
+    assert_eq!(body["items"][0]["index"]["status"], 403);
+    assert_eq!(
+        body["items"][0]["index"]["error"]["type"],
+        "security_exception"
+    );
+    assert_eq!(body["items"][1]["index"]["status"], 404);
+    assert_eq!(
+        body["items"][1]["index"]["error"]["type"],
+        "index_not_found_exception"
+    );
+
+    server.kill().await;
+    std::fs::remove_file(secret_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_elasticsearch_memory_budget() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "memory_budget": {
+            "max_in_flight_bytes": 4096,
+            "queue_timeout_ms": 50,
+            "retry_after_seconds": 3
+        }
+    }));
+    config["service"]["elasticsearch"] = serde_json::json!({});
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+
+    // no schema is routed from the index, so that no document is sent
+    let body = "{\"index\":{\"_index\":\"other\"}}\n{\"message\":\"a\"}\n";
+
+    // a chunked body takes the whole budget until it is responded to
+    let streaming = client
+        .post(format!("http://{}/_bulk", addr))
+        .header("content-type", "application/x-ndjson")
+        .body(Body::wrap_stream(vec_to_stream(
+            vec![body[..10].to_owned(), body[10..].to_owned()],
+            true,
+        )))
+        .send();
+    let rejected = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let res = client
+            .post(format!("http://{}/_bulk", addr))
+            .header("content-type", "application/x-ndjson")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()["retry-after"], "3");
+    };
+    let (res, ()) = tokio::join!(streaming, rejected);
+    assert_eq!(res.unwrap().status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{}/_bulk", addr))
+        .header("content-type", "application/x-ndjson")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    server.kill().await;
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_forward_listener_ack() {
+    use rmpv::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["forward_listener"] = serde_json::json!([{
+        "address": "127.0.0.1:0",
+        "route": [{"tag": "kube.**", "schema_id": "kube"}],
+        "time_key": "time"
+    }]);
+    let server = start_server(config).await.unwrap();
+
+    let record = Value::Map(vec![(Value::from("log"), Value::from("a"))]);
+    let message = Value::Array(vec![
+        Value::from("kube.web"),
+        Value::Array(vec![Value::Array(vec![Value::from(1), record])]),
+        Value::Map(vec![(Value::from("chunk"), Value::from("c1"))]),
+    ]);
+    let mut encoded = Vec::new();
+    rmpv::encode::write_value(&mut encoded, &message).unwrap();
+
+    let mut stream = TcpStream::connect(server.forward_addrs()[0]).await.unwrap();
+    stream.write_all(&encoded).await.unwrap();
+    // acknowledged once delivered
+    let mut response = vec![0u8; 64];
+    let length = tokio::time::timeout(Duration::from_secs(10), stream.read(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        rmpv::decode::read_value(&mut &response[..length]).unwrap(),
+        Value::Map(vec![(Value::from("ack"), Value::from("c1"))])
+    );
+
+    server.stop().await;
+}
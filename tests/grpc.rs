@@ -0,0 +1,283 @@
+use ingest::Server;
+use ingest::proto::ingest_client::IngestClient;
+use ingest::proto::{DeliveryResult, Header, IngestRequest};
+
+mod util;
+use util::*;
+
+async fn grpc_client(server: &Server) -> IngestClient<tonic::transport::Channel> {
+    let addr = server.grpc_addr().unwrap();
+    IngestClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_grpc_ingest() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["grpc_address"] = serde_json::json!("127.0.0.1:0");
+    let server = start_server(config).await.unwrap();
+    let mut client = grpc_client(&server).await;
+
+    let response = client
+        .ingest(IngestRequest {
+            schema_id: "1".to_owned(),
+            payload: DATA.as_bytes().to_vec(),
+            key: Some(b"key".to_vec()),
+            headers: vec![Header {
+                name: "source".to_owned(),
+                value: b"test".to_vec(),
+            }],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let result = response.result.unwrap();
+    assert!(result.delivered && !result.dropped && result.error.is_empty());
+    // the delivery is reported
+    assert!(result.partition.is_some() && result.offset.is_some());
+
+    server.kill().await;
+}
+
+#[tokio::test]
+async fn test_grpc_ingest_stream() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "filter": [
+                {"field": {"path": "/level", "values": ["debug"]}, "action": {"type": "drop"}}
+            ]
+        }
+    }));
+    config["service"]["grpc_address"] = serde_json::json!("127.0.0.1:0");
+    config["service"]["max_event_size_bytes"] = serde_json::json!(20);
+    let server = start_server(config).await.unwrap();
+    let mut client = grpc_client(&server).await;
+
+    let messages = [
+        r#"{"level":"info"}"#,
+        r#"{"level":"debug"}"#,
+        r#"{"level":"info","a":"1234"}"#,
+    ]
+    .map(|payload| IngestRequest {
+        schema_id: "1".to_owned(),
+        payload: payload.as_bytes().to_vec(),
+        ..Default::default()
+    });
+    let summary = client
+        .ingest_stream(futures::stream::iter(messages))
+        .await
+        .unwrap()
+        .into_inner()
+        .summary
+        .unwrap();
+    assert_eq!(summary.received_count, 3);
+    assert_eq!(summary.delivered_count, 1);
+    assert_eq!(summary.dropped_count, 1);
+    assert!(!summary.error.is_empty());
+    assert_eq!(summary.partition_offsets.len(), 1);
+    assert_eq!(summary.partition_offsets[0].count, 1);
+
+    server.kill().await;
+}
+
+#[tokio::test]
+async fn test_grpc_signed_schema_refused() {
+    let secret_path =
+        std::env::temp_dir().join(format!("ingest-grpc-secret-{}", std::process::id()));
+    std::fs::write(&secret_path, "secret").unwrap();
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{
+            "schema_id": "2",
+            "signature": {"provider": "github", "secret_file": secret_path.to_str().unwrap()}
+        }]
+    }));
+    config["service"]["grpc_address"] = serde_json::json!("127.0.0.1:0");
+    let server = start_server(config).await.unwrap();
+    let mut client = grpc_client(&server).await;
+
+    let response = client
+        .ingest(IngestRequest {
+            schema_id: "2".to_owned(),
+            payload: DATA.as_bytes().to_vec(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        response.result,
+        Some(DeliveryResult {
+            delivered: false,
+            dropped: false,
+            error: "Signed schemas only accept HTTP requests".to_owned(),
+            ..Default::default()
+        })
+    );
+
+    server.kill().await;
+    std::fs::remove_file(secret_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_grpc_api_key_auth() {
+    let keys_path =
+        std::env::temp_dir().join(format!("ingest-grpc-api-keys-{}.json", std::process::id()));
+    // the secrets are "secret" and "other"
+    std::fs::write(
+        &keys_path,
+        r#"[
+            {"id": "team-a", "sha256": "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b", "schemas": ["2"]},
+            {"id": "team-b", "sha256": "d9298a10d1b0735837dc4bd85dac641b0f3cef27a47e5d53a54f2f3f5b2fcffa", "schemas": ["3"]}
+        ]"#,
+    )
+    .unwrap();
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{"schema_id": "2", "api_key_auth": true}],
+        "api_keys": {"path": keys_path.to_str().unwrap()}
+    }));
+    config["service"]["grpc_address"] = serde_json::json!("127.0.0.1:0");
+    let server = start_server(config).await.unwrap();
+    let mut client = grpc_client(&server).await;
+    let request = |metadata: Option<(&'static str, &'static str)>| {
+        let mut request = tonic::Request::new(IngestRequest {
+            schema_id: "2".to_owned(),
+            payload: DATA.as_bytes().to_vec(),
+            ..Default::default()
+        });
+        if let Some((key, value)) = metadata {
+            request.metadata_mut().insert(key, value.parse().unwrap());
+        }
+        request
+    };
+
+    for (metadata, error) in [
+        (None, "API key is required"),
+        (Some(("x-api-key", "unknown")), "Invalid API key"),
+        (
+            Some(("authorization", "Bearer other")),
+            "API key 'team-b' is not allowed",
+        ),
+    ] {
+        let response = client.ingest(request(metadata)).await.unwrap().into_inner();
+        assert_eq!(
+            response.result,
+            Some(DeliveryResult {
+                delivered: false,
+                dropped: false,
+                error: error.to_owned(),
+                ..Default::default()
+            })
+        );
+    }
+
+    let response = client
+        .ingest(request(Some(("x-api-key", "secret"))))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.result.unwrap().delivered);
+
+    server.kill().await;
+    std::fs::remove_file(keys_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_grpc_jwt() {
+    let jwks_path =
+        std::env::temp_dir().join(format!("ingest-grpc-jwks-{}.json", std::process::id()));
+    // the base64url of the HS256 secret
+    std::fs::write(
+        &jwks_path,
+        r#"{"keys": [{"kty": "oct", "kid": "k1", "alg": "HS256", "k": "YSBzaGFyZWQgc2VjcmV0IG9mIHRoaXJ0eSBzaXggYnl0ZXMu"}]}"#,
+    )
+    .unwrap();
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{
+            "schema_id": "2",
+            "jwt": {
+                "algorithms": ["HS256"],
+                "jwks_file": jwks_path.to_str().unwrap(),
+                "issuer": "https://issuer",
+                "forward_claims": ["sub"],
+                "schemas_claim": "schemas"
+            }
+        }]
+    }));
+    config["service"]["grpc_address"] = serde_json::json!("127.0.0.1:0");
+    let server = start_server(config).await.unwrap();
+    let mut client = grpc_client(&server).await;
+    let request = |schemas: Option<&str>| {
+        let mut request = tonic::Request::new(IngestRequest {
+            schema_id: "2".to_owned(),
+            payload: DATA.as_bytes().to_vec(),
+            ..Default::default()
+        });
+        if let Some(schemas) = schemas {
+            let exp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                + 60;
+            let token = jsonwebtoken::encode(
+                &jsonwebtoken::Header {
+                    kid: Some("k1".to_owned()),
+                    ..Default::default()
+                },
+                &serde_json::json!({
+                    "iss": "https://issuer",
+                    "exp": exp,
+                    "sub": "app-1",
+                    "schemas": schemas
+                }),
+                &jsonwebtoken::EncodingKey::from_secret(b"a shared secret of thirty six bytes."),
+            )
+            .unwrap();
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+        }
+        request
+    };
+
+    for (schemas, error) in [
+        (None, "Bearer token is required"),
+        (Some("1 3"), "Token is not allowed for the schema"),
+    ] {
+        let response = client.ingest(request(schemas)).await.unwrap().into_inner();
+        assert_eq!(
+            response.result,
+            Some(DeliveryResult {
+                delivered: false,
+                dropped: false,
+                error: error.to_owned(),
+                ..Default::default()
+            })
+        );
+    }
+
+    let response = client
+        .ingest(request(Some("1 2")))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.result.unwrap().delivered);
+
+    server.kill().await;
+    std::fs::remove_file(jwks_path).unwrap();
+}
@@ -0,0 +1,70 @@
+use reqwest::{Client, StatusCode};
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_splunk_hec() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["splunk_hec"] = serde_json::json!({
+        "token": [{"token": "secret", "schema_id": "splunk"}]
+    });
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+
+    let res = client
+        .post(format!("http://{}/services/collector/event", addr))
+        .header("authorization", "Splunk secret")
+        .body(r#"{"event": "a", "sourcetype": "test"}{"event": {"b": 1}}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_response(res, StatusCode::OK, Some(r#"{"text":"Success","code":0}"#)).await;
+
+    let res = client
+        .post(format!(
+            "http://{}/services/collector/raw?sourcetype=access",
+            addr
+        ))
+        .header("authorization", "Splunk secret")
+        .body("line 1\nline 2\n")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{}/services/collector/event", addr))
+        .header("authorization", "Splunk secret")
+        .body(r#"{"host": "a"}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_response(
+        res,
+        StatusCode::BAD_REQUEST,
+        Some(r#"{"text":"Event field is required","code":12,"invalid-event-number":0}"#),
+    )
+    .await;
+
+    let res = client
+        .post(format!("http://{}/services/collector/event", addr))
+        .header("authorization", "Splunk other")
+        .body(r#"{"event": "a"}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_response(
+        res,
+        StatusCode::FORBIDDEN,
+        Some(r#"{"text":"Invalid token","code":4}"#),
+    )
+    .await;
+
+    server.stop().await;
+}
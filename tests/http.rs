@@ -1,195 +1,8 @@
-use async_stream::try_stream;
-use reqwest::{Body, Client, Method, RequestBuilder, Response, StatusCode};
+use reqwest::{Body, Client, Method, StatusCode};
 use std::time::Duration;
 
-use common::config::ConfigError;
-use futures::TryStream;
-use ingest::{Config, Server, error::Error, error::Result as IResult};
-
-async fn start_server(mut config: serde_json::Value) -> IResult<Server> {
-    unsafe {
-        std::env::set_var(
-            "PYTHONPATH",
-            env!("CARGO_MANIFEST_DIR").to_owned() + "/tests",
-        );
-    }
-
-    config["logging"] = serde_json::json!({
-        "console": {
-            "enabled": std::env::var("TEST_ENABLE_LOG").is_ok()
-        },
-        "otel_metrics": false,
-        "otel_tracing": false,
-        "log_level": "debug,rdkafka=debug,h2=debug,tower=debug,hyper=debug,tonic=debug,actix_http=debug,want=debug,actix_server=debug,mio=debug,opentelemetry=info"
-    });
-
-    let config: Config = serde_json::from_value(config).unwrap();
-    let _ = common::logging::init(config.logging.clone(), "test", "test");
-    Server::start(config).await
-}
-
-async fn request<T: Into<Body>>(
-    server_config: serde_json::Value,
-    path: &str,
-    body: T,
-    method: Method,
-) -> IResult<Response> {
-    Ok(request_with_headers(server_config, path, body, method, Vec::new()).await?)
-}
-
-async fn request_with_headers<T: Into<Body>>(
-    server_config: serde_json::Value,
-    path: &str,
-    body: T,
-    method: Method,
-    headers: Vec<(String, String)>,
-) -> IResult<Response> {
-    let (server, req) = build_request(server_config, path, method, headers).await?;
-    let res = req.body(body).send().await.unwrap();
-
-    server.kill().await;
-    Ok(res)
-}
-
-fn vec_to_stream(
-    v: Vec<String>,
-    delay: bool,
-) -> impl TryStream<Ok = String, Error = std::io::Error> {
-    try_stream! {
-        for d in v {
-            yield d;
-            if delay {
-                tokio::time::sleep(Duration::from_secs(2)).await;
-            }
-        }
-    }
-}
-
-async fn request_with_stream(
-    server_config: serde_json::Value,
-    schema_id: &str,
-    body: Vec<String>,
-    method: Method,
-    headers: Vec<(String, String)>,
-    delay: bool,
-) -> IResult<Response> {
-    let (server, req) = build_request(server_config, schema_id, method, headers).await?;
-
-    let s = vec_to_stream(body, delay);
-
-    let res = req.body(Body::wrap_stream(s)).send().await.unwrap();
-
-    server.kill().await;
-    Ok(res)
-}
-
-async fn build_request(
-    server_config: serde_json::Value,
-    path: &str,
-    method: Method,
-    headers: Vec<(String, String)>,
-) -> IResult<(Server, RequestBuilder)> {
-    let server = start_server(server_config).await?;
-    let client = Client::new();
-
-    let addr = &server.addrs().first().unwrap().to_string();
-
-    let mut req = client.request(method, format!("http://{}/ingest/{}", addr, path));
-
-    for (k, v) in headers {
-        req = req.header(k, v);
-    }
-    Ok((server, req))
-}
-
-fn broker_addr() -> String {
-    std::env::var("BROKER_ADDRESS").unwrap_or("localhost:19092".to_owned())
-}
-
-fn server_config(service_config: serde_json::Value) -> serde_json::Value {
-    _server_config(service_config, None)
-}
-
-fn _server_config(
-    service_config: serde_json::Value,
-    librdkafka_config_opt: Option<serde_json::Value>,
-) -> serde_json::Value {
-    let mut conf = service_config.clone();
-    conf["address"] = serde_json::json!("127.0.0.1:0");
-    conf["python_plugin_src_dir"] =
-        serde_json::json!(env!("CARGO_MANIFEST_DIR").to_owned() + "/src/python");
-    let librdkafka_config = if let Some(librdkafka_config) = librdkafka_config_opt {
-        librdkafka_config
-    } else {
-        serde_json::json!([{"config": {"bootstrap.servers": broker_addr().as_str()}}])
-    };
-    serde_json::json!({
-        "service": conf,
-        "librdkafka" : librdkafka_config
-    })
-}
-
-fn server_config_with_librdkafka(
-    service_config: serde_json::Value,
-    librdkafka_config: serde_json::Value,
-) -> serde_json::Value {
-    _server_config(service_config, Some(librdkafka_config))
-}
-
-// language=json
-const DATA: &str = r#"[{"some":{"nested":"data"}},{"some":{"deeper":{"nested":"data"}}}]"#;
-const DATA_LEN: u128 = DATA.len() as u128;
-
-async fn assert_ingest_response(
-    res: Response,
-    status: StatusCode,
-    ingested_opt: Option<(String, u64, u128, String)>,
-) {
-    let expected_body = if let Some((content_type, ingested_count, ingested_bytes, schema_id)) =
-        ingested_opt
-    {
-        Some(format!(
-            r#"{{"ingested_count":{},"ingested_bytes":{},"ingested_content_type":"{}","ingested_schema_id":"{}"}}"#,
-            ingested_count, ingested_bytes, content_type, schema_id
-        ))
-    } else {
-        None
-    };
-    // this fails! actix web bug?
-    // assert_eq!(
-    //     res.headers()["content-length"],
-    //     expected_body.len().to_string()
-    // );
-    let content_type = expected_body
-        .as_ref()
-        .map(|_| res.headers().get("content-type"))
-        .flatten()
-        .cloned();
-    assert_response(res, status, expected_body.as_deref()).await;
-    if let Some(_) = expected_body {
-        assert_eq!(content_type.unwrap(), "application/json");
-    }
-}
-
-async fn assert_response(res: Response, status: StatusCode, body: Option<&str>) {
-    assert_eq!(res.status(), status);
-    let expected_body = if let Some(b) = body {
-        b.to_owned()
-    } else {
-        "".to_owned()
-    };
-    assert_eq!(res.text().await.unwrap(), expected_body);
-}
-
-fn assert_is_config_error<T>(r: IResult<T>, err_txt: &str) {
-    assert!(r.is_err());
-    if let Err(err) = r {
-        assert!(matches!(err, Error::Config(ConfigError::Invalid(_))));
-        if let Error::Config(ConfigError::Invalid(s)) = err {
-            assert_eq!(s, err_txt);
-        }
-    }
-}
+mod util;
+use util::*;
 
 #[tokio::test]
 async fn test_response_default() {
@@ -478,6 +291,139 @@ async fn test_response_ndjson_ignore_empty_lines() {
     .await;
 }
 
+#[tokio::test]
+async fn test_response_delivery() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "response_delivery": true
+        }
+    }));
+
+    let res = request(config, "1", DATA, Method::POST).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["delivery"]["topic"], "test");
+    assert!(body["delivery"]["partition"].as_i64().unwrap() >= 0);
+    assert!(body["delivery"]["offset"].as_i64().unwrap() >= 0);
+    assert!(body["delivery"]["timestamp"].as_i64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn test_response_delivery_disabled() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+        }
+    }));
+
+    let res = request(config, "1", DATA, Method::POST).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert!(body.get("delivery").is_none());
+}
+
+#[tokio::test]
+async fn test_response_ndjson_partition_offsets() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "response_partition_offsets": true
+        }
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"line1\": \"1\"}\n{\"line2\": \"2\"}\n{\"line3\": \"3\"}\n";
+
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["delivery"], serde_json::Value::Null);
+    let partition_offsets = body["partition_offsets"].as_array().unwrap();
+    let count: u64 = partition_offsets
+        .iter()
+        .map(|offsets| {
+            assert!(offsets["first_offset"].as_i64() <= offsets["last_offset"].as_i64());
+            offsets["count"].as_u64().unwrap()
+        })
+        .sum();
+    assert_eq!(count, 3);
+}
+
+#[tokio::test]
+async fn test_ack_mode_accepted() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "ack_mode": "accepted"
+        }
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"line1\": \"1\"}\n{\"line2\": \"2\"}\n";
+
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::ACCEPTED,
+        Some(("application/jsonlines".to_owned(), 2, 28, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_ack_mode_receipt() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "ack_mode": "receipt"
+        }
+    }));
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+
+    let res = client
+        .post(format!("http://{}/ingest/1", addr))
+        .body("{\"line1\": \"1\"}\n{\"line2\": \"2\"}\n")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["ingested_count"], 2);
+    let receipt_id = body["receipt_id"].as_str().unwrap().to_owned();
+
+    let receipt_url = format!("http://{}/ingest/receipts/{}", addr, receipt_id);
+    let mut receipt = serde_json::Value::Null;
+    for _ in 0..50 {
+        let res = client.get(&receipt_url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        receipt = res.json().await.unwrap();
+        if receipt["status"] != "pending" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(receipt["receipt_id"], receipt_id.as_str());
+    assert_eq!(receipt["status"], "delivered");
+    assert_eq!(receipt["schema_id"], "1");
+    assert_eq!(receipt["message_count"], 2);
+    assert_eq!(receipt["delivered_count"], 2);
+
+    let res = client
+        .get(format!("http://{}/ingest/receipts/unknown", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    server.stop().await;
+}
+
 #[tokio::test]
 async fn test_response_chunked_transfer() {
     let config = server_config(serde_json::json!({
@@ -533,129 +479,967 @@ async fn test_response_chunked_transfer_ndjson() {
 }
 
 #[tokio::test]
-async fn test_response_chunked_transfer_ndjson_delay() {
+async fn test_response_chunked_transfer_ndjson_delay() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines"
+        }
+    }));
+
+    let data = vec![
+        "{\"line\": 1}\n".to_owned(),
+        "{\"line\": 2}\n".to_owned(),
+        "{\"line\": 3}\n".to_owned(),
+    ];
+
+    let res = request_with_stream(config, "1", data, Method::POST, Vec::new(), true)
+        .await
+        .unwrap();
+
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/jsonlines".to_owned(), 3, 33, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+        },
+        "max_event_size_bytes": 2
+    }));
+
+    let data = "12345";
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_chunked_transfer_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "max_event_size_bytes": 5
+    }));
+
+    let data = vec![
+        "11".to_owned(),
+        "22".to_owned(),
+        "333".to_owned(),
+        "44".to_owned(),
+    ];
+
+    let res = request_with_stream(config, "1", data, Method::POST, Vec::new(), false)
+        .await
+        .unwrap();
+
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_line_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines"
+        },
+        "max_event_size_bytes": 2
+    }));
+
+    let data = "12\n34\n563\n23";
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/jsonlines".to_owned(), 2, 4, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_chunked_transfer_ndjson_line_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines"
+        },
+        "max_event_size_bytes": 2
+    }));
+
+    let data = vec![
+        "11\n".to_owned(),
+        "22\n".to_owned(),
+        "333\n".to_owned(),
+        "44\n".to_owned(),
+    ];
+
+    let res = request_with_stream(config, "1", data, Method::POST, Vec::new(), false)
+        .await
+        .unwrap();
+
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/jsonlines".to_owned(), 2, 4, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_json_seq() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+
+    // language=json
+    let data = "\x1e{\"seq1\": \"1\"}\n\x1e{\"seq2\": \"2\"}\n\x1e  \n";
+
+    let res = request_with_headers(
+        config,
+        "1",
+        data,
+        Method::POST,
+        vec![("Content-Type".to_owned(), "application/json-seq".to_owned())],
+    )
+    .await
+    .unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/json-seq".to_owned(), 2, 26, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_custom_delimiter() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "framing": {
+                "application/jsonlines": {"type": "delimiter", "delimiter": 0}
+            }
+        }
+    }));
+
+    // language=json
+    let data = "{\"line1\": \"1\"}\0{\"line2\": \"2\"}\n\0{\"line3\": \"3\"}";
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/jsonlines".to_owned(), 3, 42, "1".to_owned())),
+    )
+    .await;
+}
+
+fn length_prefixed(frames: &[&[u8]]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for frame in frames {
+        data.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        data.extend_from_slice(frame);
+    }
+    data
+}
+
+#[tokio::test]
+async fn test_response_length_prefixed_binary() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/octet-stream",
+            "framing": {
+                "application/octet-stream": {"type": "length_prefixed"}
+            }
+        }
+    }));
+
+    // not valid UTF-8, and whitespace is kept
+    let data = length_prefixed(&[b"\xff\xfe\n", b" \x00\x01\x02 ", b"\xc3\x28"]);
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/octet-stream".to_owned(), 3, 10, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_length_prefixed_frame_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/octet-stream",
+            "framing": {
+                "application/octet-stream": {"type": "length_prefixed"}
+            }
+        },
+        "max_event_size_bytes": 2
+    }));
+
+    let data = length_prefixed(&[b"12", b"34", b"563", b"23"]);
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/octet-stream".to_owned(), 2, 4, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_length_prefixed_truncated() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/octet-stream",
+            "framing": {
+                "application/octet-stream": {"type": "length_prefixed"}
+            }
+        }
+    }));
+
+    let mut data = length_prefixed(&[b"12", b"3456"]);
+    data.truncate(data.len() - 1);
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::BAD_REQUEST,
+        Some(("application/octet-stream".to_owned(), 1, 2, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_invalid_utf8() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines"
+        }
+    }));
+
+    let data = b"{\"line1\": \"1\"}\n{\"line2\": \"\xc3\x28\"}\n{\"line3\": \"3\"}\n".to_vec();
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::BAD_REQUEST,
+        Some(("application/jsonlines".to_owned(), 1, 14, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_invalid_utf8_without_validation() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "validate_utf8": false
+        }
+    }));
+
+    let data = b"{\"line1\": \"1\"}\n{\"line2\": \"\xc3\x28\"}\n{\"line3\": \"3\"}\n".to_vec();
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/jsonlines".to_owned(), 3, 43, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_batching() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "batching": {"max_count": 2}
+        }
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"line0\": \"0\"}\n{\"line1\": \"1\"}\n\n{\"line2\": \"2\"}\n{\"line3\": \"3\"}\n{\"line4\": \"4\"}";
+
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/jsonlines".to_owned(), 5, 70, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_batching_json_array_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "batching": {"format": "json_array", "max_bytes": 8}
+        },
+        "max_event_size_bytes": 4
+    }));
+
+    // a record of max_event_size_bytes does not fit in an array
+    let data = "12\n34\n5678\n23";
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/jsonlines".to_owned(), 2, 4, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_batching_json_array_enrich_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "forward_ingest_version": false,
+            "enrich": {"fields": ["ncube-ingest-ip"]},
+            "batching": {"format": "json_array", "max_bytes": 64}
+        },
+        "max_event_size_bytes": 38
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"a\":1}\n";
+
+    // {"a":1,"ncube-ingest-ip":"127.0.0.1"} fits in max_event_size_bytes, but not in an array
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/jsonlines".to_owned(), 0, 0, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_enrich() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "forward_ingest_version": false,
+            "enrich": {
+                "fields": ["ncube-ingest-schema-id", "ncube-ingest-ip"],
+                "envelope_key": "_meta"
+            }
+        }
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"line1\": \"1\"}\n{\"line2\": \"2\"}\n";
+
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    // {"line1":"1","_meta":{"ncube-ingest-schema-id":"1","ncube-ingest-ip":"127.0.0.1"}}
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/jsonlines".to_owned(), 2, 164, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_enrich_conflict() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "enrich": {"fields": ["ncube-ingest-ip"], "on_conflict": "reject"}
+        },
+    }));
+
+    // language=json
+    let data = "{\"ncube-ingest-ip\": \"1.1.1.1\"}";
+
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::BAD_REQUEST,
+        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_enrich_limit_exceeded() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "enrich": {"fields": ["ncube-ingest-ip"]}
+        },
+        "max_event_size_bytes": 20
+    }));
+
+    // language=json
+    let data = "{\"a\":1}";
+
+    // {"a":1,"ncube-ingest-ip":"127.0.0.1"} is over the max size
+    let res = request(config, "1", data, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_user_agent_enrich() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "forward_ingest_version": false,
+            "user_agent": true,
+            "enrich": {
+                "fields": [
+                    "ncube-ingest-schema-id",
+                    "ncube-ingest-ip",
+                    "ncube-ingest-ua-browser",
+                    "ncube-ingest-ua-browser-version",
+                    "ncube-ingest-ua-os",
+                    "ncube-ingest-ua-device"
+                ]
+            }
+        }
+    }));
+    config["service"]["user_agent_database"] =
+        serde_json::json!(env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/data/regexes.yaml");
+
+    // language=json
+    let data = "{\"a\":1}";
+
+    let res = request_with_headers(
+        config,
+        "1",
+        data,
+        Method::POST,
+        vec![(
+            "User-Agent".to_owned(),
+            "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0".to_owned(),
+        )],
+    )
+    .await
+    .unwrap();
+    // {"a":1,"ncube-ingest-schema-id":"1","ncube-ingest-ip":"127.0.0.1",
+    // "ncube-ingest-ua-browser":"Firefox","ncube-ingest-ua-browser-version":"120.0",
+    // "ncube-ingest-ua-os":"Linux"}
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/json".to_owned(), 1, 173, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_redact() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "redact": {
+                "rules": [
+                    {"path": "**.email", "action": "drop"},
+                    {"path": "/card/number", "action": "mask"}
+                ]
+            }
+        }
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"user\": {\"email\": \"a@b.c\", \"name\": \"x\"}}\n{\"email\": \"d\", \"card\": {\"number\": \"1234\"}}\n";
+
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    // {"user":{"name":"x"}}
+    // {"card":{"number":"****"}}
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/jsonlines".to_owned(), 2, 47, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_ndjson_filter() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "filter": [
+                {"field": {"path": "/level", "values": ["debug"]}, "action": {"type": "drop"}}
+            ]
+        }
+    }));
+
+    // language=jsonlines
+    let datalines = "{\"level\":\"info\",\"a\":1}\n{\"level\":\"debug\",\"a\":2}\n{\"level\":\"warn\",\"a\":3}\n";
+
+    let res = request(config, "1", datalines, Method::POST).await.unwrap();
+    assert_response(
+        res,
+        StatusCode::OK,
+        Some(
+            r#"{"ingested_count":2,"ingested_bytes":44,"ingested_content_type":"application/jsonlines","ingested_schema_id":"1","dropped_count":1}"#,
+        ),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_response_filter_header() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "filter": [
+                {"header": {"name": "X-Debug"}, "action": {"type": "drop"}}
+            ]
+        }
+    }));
+
+    let res = request_with_headers(
+        config,
+        "1",
+        "{}",
+        Method::POST,
+        vec![("X-Debug".to_owned(), "1".to_owned())],
+    )
+    .await
+    .unwrap();
+    assert_response(
+        res,
+        StatusCode::OK,
+        Some(
+            r#"{"ingested_count":0,"ingested_bytes":0,"ingested_content_type":"application/json","ingested_schema_id":"1","dropped_count":1}"#,
+        ),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_shutdown_drain() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+        },
+        "shutdown": {
+            "drain_delay_seconds": 1
+        }
+    }));
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+    let ready_url = format!("http://{}/ready", addr);
+
+    let res = client.get(&ready_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let draining = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let res = client.get(&ready_url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        // requests are still handled during the drain delay
+        let res = client
+            .post(format!("http://{}/ingest/1", addr))
+            .body(DATA)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // an upload in flight when the drain delay is over has the grace period to finish
+        let upload = client
+            .post(format!("http://{}/ingest/1", addr))
+            .body(Body::wrap_stream(vec_to_stream(
+                vec![DATA[..10].to_owned(), DATA[10..].to_owned()],
+                true,
+            )))
+            .send();
+        let rejected = async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            // while new requests are rejected, closing their connection
+            let res = Client::new()
+                .post(format!("http://{}/ingest/1", addr))
+                .body(DATA)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(res.headers()["connection"], "close");
+        };
+        let (res, ()) = tokio::join!(upload, rejected);
+        assert_eq!(res.unwrap().status(), StatusCode::OK);
+    };
+    let (unflushed, ()) = tokio::join!(server.stop(), draining);
+    assert!(unflushed.is_empty());
+}
+
+#[tokio::test]
+async fn test_memory_budget() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+        },
+        "schema_config": [{"schema_id": "2", "max_in_flight_bytes": 1024}],
+        "memory_budget": {
+            "queue_timeout_ms": 50,
+            "retry_after_seconds": 3
+        }
+    }));
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+
+    // a chunked body takes the whole budget of the schema until it is read
+    let streaming = client
+        .post(format!("http://{}/ingest/2", addr))
+        .body(Body::wrap_stream(vec_to_stream(
+            vec!["a".to_owned(), "b".to_owned()],
+            true,
+        )))
+        .send();
+    let rejected = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let res = client
+            .post(format!("http://{}/ingest/2", addr))
+            .body(DATA)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()["retry-after"], "3");
+        // schemas without a budget are admitted
+        let res = client
+            .post(format!("http://{}/ingest/1", addr))
+            .body(DATA)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    };
+    let (res, ()) = tokio::join!(streaming, rejected);
+    assert_eq!(res.unwrap().status(), StatusCode::OK);
+
+    // the budget is released once responded to
+    let res = client
+        .post(format!("http://{}/ingest/2", addr))
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    server.kill().await;
+}
+
+#[tokio::test]
+async fn test_api_key_auth() {
+    let keys_path =
+        std::env::temp_dir().join(format!("ingest-api-keys-{}.json", std::process::id()));
+    // the secrets are "secret" and "other"
+    std::fs::write(
+        &keys_path,
+        r#"[
+            {"id": "team-a", "sha256": "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b", "schemas": ["2", "logs-*"]},
+            {"id": "team-b", "sha256": "d9298a10d1b0735837dc4bd85dac641b0f3cef27a47e5d53a54f2f3f5b2fcffa", "schemas": ["3"]}
+        ]"#,
+    )
+    .unwrap();
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+        },
+        "schema_config": [{"schema_id": "2", "api_key_auth": true}],
+        "api_keys": {"path": keys_path.to_str().unwrap()}
+    }));
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+    let url = format!("http://{}/ingest/2", addr);
+
+    let res = client.post(&url).body(DATA).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(res.headers()["www-authenticate"], "Bearer");
+    assert_eq!(
+        res.text().await.unwrap(),
+        r#"{"error":"missing_api_key","message":"API key is required"}"#
+    );
+
+    let res = client
+        .post(&url)
+        .header("x-api-key", "unknown")
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client
+        .post(&url)
+        .bearer_auth("other")
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        res.text().await.unwrap(),
+        r#"{"error":"forbidden","message":"API key 'team-b' is not allowed"}"#
+    );
+
+    let res = client
+        .post(&url)
+        .bearer_auth("secret")
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // schemas without api_key_auth don't need a key
+    let res = client
+        .post(format!("http://{}/ingest/1", addr))
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    server.kill().await;
+    std::fs::remove_file(keys_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_signature_github() {
+    let secret_path =
+        std::env::temp_dir().join(format!("ingest-webhook-secret-{}", std::process::id()));
+    std::fs::write(&secret_path, "secret").unwrap();
     let config = server_config(serde_json::json!({
         "default_schema_config": {
             "destination_topic": "test",
-            "content_type": "application/jsonlines"
-        }
+        },
+        "schema_config": [{
+            "schema_id": "2",
+            "signature": {"provider": "github", "secret_file": secret_path.to_str().unwrap()}
+        }]
     }));
+    let server = start_server(config).await.unwrap();
+    let url = format!("http://{}/ingest/2", server.addrs().first().unwrap());
+    let client = Client::new();
 
-    let data = vec![
-        "{\"line\": 1}\n".to_owned(),
-        "{\"line\": 2}\n".to_owned(),
-        "{\"line\": 3}\n".to_owned(),
-    ];
-
-    let res = request_with_stream(config, "1", data, Method::POST, Vec::new(), true)
+    // echo -n "$DATA" | openssl dgst -sha256 -hmac secret
+    let res = client
+        .post(&url)
+        .header(
+            "x-hub-signature-256",
+            "sha256=e6919c4a78f9c4d35187f4589c97d0a2c7b1136a2e843114d0a1ab25230a5598",
+        )
+        .body(DATA)
+        .send()
         .await
         .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
 
-    assert_ingest_response(
-        res,
-        StatusCode::OK,
-        Some(("application/jsonlines".to_owned(), 3, 33, "1".to_owned())),
-    )
-    .await;
+    let res = client
+        .post(&url)
+        .header("x-hub-signature-256", "sha256=00ff")
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.text().await.unwrap(),
+        r#"{"error":"invalid_signature","message":"Invalid signature"}"#
+    );
+    server.kill().await;
+    std::fs::remove_file(secret_path).unwrap();
 }
 
 #[tokio::test]
-async fn test_response_limit_exceeded() {
+async fn test_jwt() {
+    let jwks_path = std::env::temp_dir().join(format!("ingest-jwks-{}.json", std::process::id()));
+    // the base64url of the HS256 secret
+    std::fs::write(
+        &jwks_path,
+        r#"{"keys": [{"kty": "oct", "kid": "k1", "alg": "HS256", "k": "YSBzaGFyZWQgc2VjcmV0IG9mIHRoaXJ0eSBzaXggYnl0ZXMu"}]}"#,
+    )
+    .unwrap();
     let config = server_config(serde_json::json!({
         "default_schema_config": {
             "destination_topic": "test",
         },
-        "max_event_size_bytes": 2
+        "schema_config": [{
+            "schema_id": "2",
+            "jwt": {
+                "algorithms": ["HS256"],
+                "jwks_file": jwks_path.to_str().unwrap(),
+                "issuer": "https://issuer",
+                "forward_claims": ["sub"],
+                "schemas_claim": "schemas"
+            }
+        }]
     }));
+    let server = start_server(config).await.unwrap();
+    let url = format!("http://{}/ingest/2", server.addrs().first().unwrap());
+    let client = Client::new();
+    let token = |schemas: &str| {
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header {
+                kid: Some("k1".to_owned()),
+                ..Default::default()
+            },
+            &serde_json::json!({
+                "iss": "https://issuer",
+                "exp": exp,
+                "sub": "app-1",
+                "schemas": schemas
+            }),
+            &jsonwebtoken::EncodingKey::from_secret(b"a shared secret of thirty six bytes."),
+        )
+        .unwrap()
+    };
 
-    let data = "12345";
+    let res = client.post(&url).body(DATA).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.text().await.unwrap(),
+        r#"{"error":"missing_token","message":"Bearer token is required"}"#
+    );
 
-    let res = request(config, "1", data, Method::POST).await.unwrap();
-    assert_ingest_response(
-        res,
-        StatusCode::PAYLOAD_TOO_LARGE,
-        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
-    )
-    .await;
+    let res = client
+        .post(&url)
+        .bearer_auth(token("1 3"))
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = client
+        .post(&url)
+        .bearer_auth(token("1 2"))
+        .body(DATA)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    server.kill().await;
+    std::fs::remove_file(jwks_path).unwrap();
 }
 
-#[tokio::test]
-async fn test_response_chunked_transfer_limit_exceeded() {
-    let config = server_config(serde_json::json!({
+fn cloudevents_config() -> serde_json::Value {
+    server_config(serde_json::json!({
         "default_schema_config": {
-            "destination_topic": "test"
-        },
-        "max_event_size_bytes": 5
-    }));
+            "destination_topic": "test",
+            "cloudevents": true
+        }
+    }))
+}
 
-    let data = vec![
-        "11".to_owned(),
-        "22".to_owned(),
-        "333".to_owned(),
-        "44".to_owned(),
+#[tokio::test]
+async fn test_cloudevents_binary() {
+    let headers = vec![
+        ("content-type".to_owned(), "application/json".to_owned()),
+        ("ce-specversion".to_owned(), "1.0".to_owned()),
+        ("ce-id".to_owned(), "1".to_owned()),
+        ("ce-source".to_owned(), "/orders".to_owned()),
+        ("ce-type".to_owned(), "order.created".to_owned()),
     ];
-
-    let res = request_with_stream(config, "1", data, Method::POST, Vec::new(), false)
+    let res = request_with_headers(cloudevents_config(), "1", DATA, Method::POST, headers)
         .await
         .unwrap();
-
     assert_ingest_response(
         res,
-        StatusCode::PAYLOAD_TOO_LARGE,
-        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
+        StatusCode::OK,
+        Some(("application/json".to_owned(), 1, DATA_LEN, "1".to_owned())),
     )
     .await;
-}
-
-#[tokio::test]
-async fn test_response_ndjson_line_limit_exceeded() {
-    let config = server_config(serde_json::json!({
-        "default_schema_config": {
-            "destination_topic": "test",
-            "content_type": "application/jsonlines"
-        },
-        "max_event_size_bytes": 2
-    }));
-
-    let data = "12\n34\n563\n23";
-
-    let res = request(config, "1", data, Method::POST).await.unwrap();
 
+    let headers = vec![
+        ("content-type".to_owned(), "application/json".to_owned()),
+        ("ce-specversion".to_owned(), "1.0".to_owned()),
+        ("ce-id".to_owned(), "1".to_owned()),
+    ];
+    let res = request_with_headers(cloudevents_config(), "1", DATA, Method::POST, headers)
+        .await
+        .unwrap();
     assert_ingest_response(
         res,
-        StatusCode::PAYLOAD_TOO_LARGE,
-        Some(("application/jsonlines".to_owned(), 2, 4, "1".to_owned())),
+        StatusCode::BAD_REQUEST,
+        Some(("application/json".to_owned(), 0, 0, "1".to_owned())),
     )
     .await;
 }
 
 #[tokio::test]
-async fn test_response_chunked_transfer_ndjson_line_limit_exceeded() {
-    let config = server_config(serde_json::json!({
-        "default_schema_config": {
-            "destination_topic": "test",
-            "content_type": "application/jsonlines"
-        },
-        "max_event_size_bytes": 2
-    }));
-
-    let data = vec![
-        "11\n".to_owned(),
-        "22\n".to_owned(),
-        "333\n".to_owned(),
-        "44\n".to_owned(),
-    ];
-
-    let res = request_with_stream(config, "1", data, Method::POST, Vec::new(), false)
+async fn test_cloudevents_structured_batch() {
+    let headers = vec![(
+        "content-type".to_owned(),
+        "application/cloudevents-batch+json".to_owned(),
+    )];
+    let body = r#"[
+        {"specversion": "1.0", "id": "1", "source": "/orders", "type": "order.created", "data": {"a": 1}},
+        {"specversion": "1.0", "id": "2", "source": "/orders", "type": "order.created", "data": {"b": 2}}
+    ]"#;
+    let res = request_with_headers(cloudevents_config(), "1", body, Method::POST, headers)
         .await
         .unwrap();
-
     assert_ingest_response(
         res,
-        StatusCode::PAYLOAD_TOO_LARGE,
-        Some(("application/jsonlines".to_owned(), 2, 4, "1".to_owned())),
+        StatusCode::OK,
+        Some(("application/json".to_owned(), 2, 14, "1".to_owned())),
     )
     .await;
+
+    let headers = vec![(
+        "content-type".to_owned(),
+        "application/cloudevents+json".to_owned(),
+    )];
+    let body = r#"{"specversion": "1.0", "id": "1", "source": "/orders", "data": {"a": 1}}"#;
+    let res = request_with_headers(cloudevents_config(), "1", body, Method::POST, headers)
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
@@ -1237,6 +2021,95 @@ async fn test_config_conflicting_librdkafka_config_explicit_names() {
     );
 }
 
+#[tokio::test]
+async fn test_config_api_key_auth_without_keys() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "api_key_auth": true
+        }
+    }));
+
+    let r = start_server(config).await;
+    assert_is_config_error(
+        r,
+        "'api_key_auth' enabled on default schema config but 'api_keys' is not configured",
+    );
+}
+
+#[tokio::test]
+async fn test_config_jwt_without_keys() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+        },
+        "schema_config": [{
+            "schema_id": "3",
+            "jwt": {"algorithms": ["RS256"]}
+        }]
+    }));
+
+    let r = start_server(config).await;
+    assert_is_config_error(r, "Schema 3: 'jwt' requires either 'jwks_file' or 'pem'");
+}
+
+#[tokio::test]
+async fn test_config_geoip_without_database() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{
+            "schema_id": "3",
+            "geoip": true
+        }]
+    }));
+
+    let r = start_server(config).await;
+    assert_is_config_error(
+        r,
+        "'geoip' enabled on schema '3' but 'geoip_database' is not configured",
+    );
+}
+
+#[tokio::test]
+async fn test_config_redact_hash_without_key() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{
+            "schema_id": "3",
+            "redact": {"rules": [{"path": "/email", "action": "hash"}]}
+        }]
+    }));
+
+    let r = start_server(config).await;
+    assert_is_config_error(
+        r,
+        "Schema 3: Redaction rules with the 'hash' action require 'hmac_key_file'",
+    );
+}
+
+#[tokio::test]
+async fn test_config_filter_invalid_sample_rate() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "schema_config": [{
+            "schema_id": "3",
+            "filter": [{"action": {"type": "sample", "rate": 10, "key_field": "/id"}}]
+        }]
+    }));
+
+    let r = start_server(config).await;
+    assert_is_config_error(
+        r,
+        "Schema 3: Invalid sample rate 10, expected a value between 0 and 1",
+    );
+}
+
 #[tokio::test]
 async fn test_named_librdkafka_config_response_default() {
     let config = server_config_with_librdkafka(
@@ -1260,6 +2133,82 @@ async fn test_named_librdkafka_config_response_default() {
     .await;
 }
 
+#[tokio::test]
+async fn test_config_librdkafka_unknown_failover() {
+    let config = server_config_with_librdkafka(
+        serde_json::json!({
+            "default_schema_config": {
+                "destination_topic": "test"
+            }
+        }),
+        serde_json::json!([
+            {
+                "config": {"bootstrap.servers": broker_addr().as_str()},
+                "circuit_breaker": {},
+                "failover": "no"
+            },
+        ]),
+    );
+
+    let r = start_server(config).await;
+    assert_is_config_error(
+        r,
+        "Failover 'no' of librdkafka configuration 'main' is not another librdkafka configuration",
+    );
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_failover() {
+    // a cluster that can't be reached, whose messages time out
+    let unreachable = serde_json::json!({
+        "bootstrap.servers": "127.0.0.1:1",
+        "message.timeout.ms": "1000"
+    });
+    let config = server_config_with_librdkafka(
+        serde_json::json!({
+            "default_schema_config": {
+                "destination_topic": "test",
+                "librdkafka_config": "down"
+            },
+            "schema_config": [{
+                "schema_id": "2",
+                "librdkafka_config": "down_with_failover"
+            }]
+        }),
+        serde_json::json!([
+            {"config": {"bootstrap.servers": broker_addr().as_str()}},
+            {
+                "name": "down",
+                "config": unreachable,
+                "circuit_breaker": {"failure_threshold": 1}
+            },
+            {
+                "name": "down_with_failover",
+                "config": unreachable,
+                "circuit_breaker": {"failure_threshold": 1},
+                "failover": "main"
+            },
+        ]),
+    );
+    let server = start_server(config).await.unwrap();
+    let client = Client::new();
+    let addr = &server.addrs().first().unwrap().to_string();
+
+    // once a delivery failed, or the brokers were reported down, the circuit is open and requests
+    // fail fast, or are sent to the failover
+    for (schema_id, status) in [
+        ("1", StatusCode::SERVICE_UNAVAILABLE),
+        ("2", StatusCode::OK),
+    ] {
+        let url = format!("http://{}/ingest/{}", addr, schema_id);
+        client.post(&url).body(DATA).send().await.unwrap();
+        let res = client.post(&url).body(DATA).send().await.unwrap();
+        assert_eq!(res.status(), status);
+    }
+
+    server.kill().await;
+}
+
 #[tokio::test]
 async fn test_config_second_librdkafka_config_response_default() {
     let config = server_config_with_librdkafka(
@@ -0,0 +1,123 @@
+use ingest::Server;
+use reqwest::{Client, Response, StatusCode};
+
+mod util;
+use util::*;
+
+async fn otlp_request(
+    server: &Server,
+    signal: &str,
+    content_type: &str,
+    body: Vec<u8>,
+) -> Response {
+    let addr = server.addrs().first().unwrap().to_string();
+    Client::new()
+        .post(format!("http://{}/v1/{}", addr, signal))
+        .header("content-type", content_type)
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_otlp_logs_json_partial_success() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        },
+        "max_event_size_bytes": 1024
+    }));
+    config["service"]["otlp"] = serde_json::json!({
+        "logs": {"schema_id": "otel-logs", "format": "json"}
+    });
+    let server = start_server(config).await.unwrap();
+
+    let request = serde_json::json!({
+        "resourceLogs": [{
+            "resource": {"attributes": [{"key": "service.name", "value": {"stringValue": "web"}}]},
+            "scopeLogs": [{
+                "scope": {"name": "test"},
+                "logRecords": [
+                    {"timeUnixNano": "1700000000000000000", "observedTimeUnixNano": "0", "body": {"stringValue": "a"}},
+                    {"timeUnixNano": "1700000000000000000", "observedTimeUnixNano": "0", "body": {"stringValue": "b".repeat(2048)}}
+                ]
+            }]
+        }]
+    });
+    let res = otlp_request(
+        &server,
+        "logs",
+        "application/json",
+        serde_json::to_vec(&request).unwrap(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["partialSuccess"]["rejectedLogRecords"], 1);
+
+    let res = otlp_request(&server, "logs", "application/json", b"{".to_vec()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["code"], 3);
+
+    let res = otlp_request(&server, "logs", "text/plain", b"{}".to_vec()).await;
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // not configured
+    let res = otlp_request(&server, "traces", "application/json", b"{}".to_vec()).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_otlp_traces_protobuf() {
+    use opentelemetry_proto::tonic::collector::trace::v1::{
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    };
+    use opentelemetry_proto::tonic::trace::v1::{ResourceSpans, ScopeSpans, Span};
+    use prost::Message;
+
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["otlp"] = serde_json::json!({
+        "traces": {"schema_id": "otel-traces"}
+    });
+    let server = start_server(config).await.unwrap();
+
+    let span = Span {
+        trace_id: vec![1; 16],
+        span_id: vec![2; 8],
+        name: "GET /".to_owned(),
+        ..Default::default()
+    };
+    let request = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            scope_spans: vec![ScopeSpans {
+                spans: vec![span.clone(), span],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+    let res = otlp_request(
+        &server,
+        "traces",
+        "application/x-protobuf",
+        request.encode_to_vec(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("content-type").unwrap(),
+        "application/x-protobuf"
+    );
+    let response = ExportTraceServiceResponse::decode(res.bytes().await.unwrap()).unwrap();
+    assert_eq!(response.partial_success, None);
+
+    server.stop().await;
+}
@@ -0,0 +1,98 @@
+use reqwest::{Client, StatusCode};
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_rest_proxy_produce() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["rest_proxy"] = serde_json::json!({
+        "cluster_id": "local",
+        "topic": [{"name": "test"}]
+    });
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let client = Client::new();
+
+    let res = client
+        .post(format!("http://{}/topics/test", addr))
+        .header("content-type", "application/vnd.kafka.json.v2+json")
+        .body(r#"{"records": [{"key": "a", "value": {"b": 1}}, {"value": "c"}]}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["offsets"].as_array().unwrap().len(), 2);
+    assert!(body["offsets"][0]["partition"].as_i64().unwrap() >= 0);
+    assert!(body["offsets"][0]["offset"].as_i64().unwrap() >= 0);
+    assert_eq!(body["offsets"][1]["error_code"], serde_json::Value::Null);
+
+    let res = client
+        .post(format!("http://{}/topics/other", addr))
+        .header("content-type", "application/vnd.kafka.json.v2+json")
+        .body(r#"{"records": [{"value": 1}]}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_response(
+        res,
+        StatusCode::NOT_FOUND,
+        Some(r#"{"error_code":40401,"message":"Topic not found."}"#),
+    )
+    .await;
+
+    let res = client
+        .post(format!(
+            "http://{}/v3/clusters/local/topics/test/records",
+            addr
+        ))
+        .header("content-type", "application/json")
+        .body(r#"{"headers": [{"name": "h", "value": "dg=="}], "value": {"type": "STRING", "data": "v"}}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["topic_name"], "test");
+    assert!(body["offset"].as_i64().unwrap() >= 0);
+    assert_eq!(
+        body["value"],
+        serde_json::json!({"type": "STRING", "size": 1})
+    );
+
+    let res = client
+        .post(format!(
+            "http://{}/v3/clusters/local/topics/test/records",
+            addr
+        ))
+        .body(r#"{"value": {"type": "AVRO", "data": "v"}}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_config_rest_proxy_unknown_librdkafka_config() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["rest_proxy"] = serde_json::json!({
+        "topic": [{"name": "test", "librdkafka_config": "no"}]
+    });
+
+    let r = start_server(config).await;
+    assert_is_config_error(
+        r,
+        "Librdkafka config with name 'no' configured on REST proxy topic 'test' not found. Available librdkafka configs: [\"main\"]",
+    );
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use common::config::ConfigError;
+use ingest::error::Error;
+use tokio::net::TcpStream;
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_tcp_listener_idle_timeout() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines"
+        }
+    }));
+    config["service"]["tcp_listener"] = serde_json::json!([
+        {"address": "127.0.0.1:0", "schema_id": "1", "idle_timeout_seconds": 1}
+    ]);
+    let server = start_server(config).await.unwrap();
+
+    let mut stream = TcpStream::connect(server.tcp_addrs()[0]).await.unwrap();
+    stream.write_all(b"{\"a\":1}\n{\"b\":2}\n").await.unwrap();
+    // the connection is closed by the server once idle
+    let mut buf = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read, 0);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_udp_listener() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["udp_listener"] = serde_json::json!([
+        {"address": "127.0.0.1:0", "schema_id": "1"}
+    ]);
+    let server = start_server(config).await.unwrap();
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(b"{\"a\":1}\n{\"b\":2}", server.udp_addrs()[0])
+        .await
+        .unwrap();
+
+    // stopping waits for the records to be delivered
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_config_tcp_listener_missing_tls_certificate() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["tcp_listener"] = serde_json::json!([{
+        "address": "127.0.0.1:0",
+        "schema_id": "1",
+        "tls": {"certificate_file": "/nonexistent/cert.pem", "private_key_file": "/nonexistent/key.pem"}
+    }]);
+
+    let r = start_server(config).await;
+    assert!(
+        matches!(r, Err(Error::Config(ConfigError::Invalid(s))) if s.starts_with("Invalid TLS certificate file '/nonexistent/cert.pem'"))
+    );
+}
@@ -0,0 +1,62 @@
+use tokio::net::TcpStream;
+
+mod util;
+use util::*;
+
+#[tokio::test]
+async fn test_syslog_listener() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["syslog_listener"] = serde_json::json!([
+        {
+            "address": "127.0.0.1:0",
+            "protocol": "tcp",
+            "schema_id": "syslog",
+            "route": [{"facility": ["auth", "authpriv"], "schema_id": "auth"}]
+        },
+        {"address": "127.0.0.1:0", "protocol": "udp", "schema_id": "syslog", "format": "raw"}
+    ]);
+    let server = start_server(config).await.unwrap();
+
+    let mut stream = TcpStream::connect(server.syslog_addrs()[0]).await.unwrap();
+    stream
+        .write_all(
+            b"<34>Oct 11 22:14:15 mymachine su: 'su root' failed\n\
+            27 <165>1 - host app - - - msg",
+        )
+        .await
+        .unwrap();
+    stream.shutdown().await.unwrap();
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(b"<13>1 - host app - - - msg", server.syslog_addrs()[1])
+        .await
+        .unwrap();
+
+    // stopping waits for the messages to be delivered
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_config_syslog_listener_unknown_facility() {
+    let mut config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+    config["service"]["syslog_listener"] = serde_json::json!([{
+        "address": "127.0.0.1:0",
+        "protocol": "udp",
+        "schema_id": "1",
+        "route": [{"facility": ["auth", "kernel"], "schema_id": "2"}]
+    }]);
+
+    let r = start_server(config).await;
+    assert_is_config_error(r, "Unknown syslog facility 'kernel'");
+}
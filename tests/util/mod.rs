@@ -0,0 +1,195 @@
+//! Helpers shared by the integration tests. Each test file uses only some of them.
+#![allow(dead_code)]
+
+use async_stream::try_stream;
+use reqwest::{Body, Client, Method, RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+use common::config::ConfigError;
+use futures::TryStream;
+use ingest::{Config, Server, error::Error, error::Result as IResult};
+
+pub async fn start_server(mut config: serde_json::Value) -> IResult<Server> {
+    unsafe {
+        std::env::set_var(
+            "PYTHONPATH",
+            env!("CARGO_MANIFEST_DIR").to_owned() + "/tests",
+        );
+    }
+
+    config["logging"] = serde_json::json!({
+        "console": {
+            "enabled": std::env::var("TEST_ENABLE_LOG").is_ok()
+        },
+        "otel_metrics": false,
+        "otel_tracing": false,
+        "log_level": "debug,rdkafka=debug,h2=debug,tower=debug,hyper=debug,tonic=debug,actix_http=debug,want=debug,actix_server=debug,mio=debug,opentelemetry=info"
+    });
+
+    let config: Config = serde_json::from_value(config).unwrap();
+    let _ = common::logging::init(config.logging.clone(), "test", "test");
+    Server::start(config).await
+}
+
+pub async fn request<T: Into<Body>>(
+    server_config: serde_json::Value,
+    path: &str,
+    body: T,
+    method: Method,
+) -> IResult<Response> {
+    Ok(request_with_headers(server_config, path, body, method, Vec::new()).await?)
+}
+
+pub async fn request_with_headers<T: Into<Body>>(
+    server_config: serde_json::Value,
+    path: &str,
+    body: T,
+    method: Method,
+    headers: Vec<(String, String)>,
+) -> IResult<Response> {
+    let (server, req) = build_request(server_config, path, method, headers).await?;
+    let res = req.body(body).send().await.unwrap();
+
+    server.kill().await;
+    Ok(res)
+}
+
+pub fn vec_to_stream(
+    v: Vec<String>,
+    delay: bool,
+) -> impl TryStream<Ok = String, Error = std::io::Error> {
+    try_stream! {
+        for d in v {
+            yield d;
+            if delay {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+pub async fn request_with_stream(
+    server_config: serde_json::Value,
+    schema_id: &str,
+    body: Vec<String>,
+    method: Method,
+    headers: Vec<(String, String)>,
+    delay: bool,
+) -> IResult<Response> {
+    let (server, req) = build_request(server_config, schema_id, method, headers).await?;
+
+    let s = vec_to_stream(body, delay);
+
+    let res = req.body(Body::wrap_stream(s)).send().await.unwrap();
+
+    server.kill().await;
+    Ok(res)
+}
+
+pub async fn build_request(
+    server_config: serde_json::Value,
+    path: &str,
+    method: Method,
+    headers: Vec<(String, String)>,
+) -> IResult<(Server, RequestBuilder)> {
+    let server = start_server(server_config).await?;
+    let client = Client::new();
+
+    let addr = &server.addrs().first().unwrap().to_string();
+
+    let mut req = client.request(method, format!("http://{}/ingest/{}", addr, path));
+
+    for (k, v) in headers {
+        req = req.header(k, v);
+    }
+    Ok((server, req))
+}
+
+pub fn broker_addr() -> String {
+    std::env::var("BROKER_ADDRESS").unwrap_or("localhost:19092".to_owned())
+}
+
+pub fn server_config(service_config: serde_json::Value) -> serde_json::Value {
+    _server_config(service_config, None)
+}
+
+pub fn _server_config(
+    service_config: serde_json::Value,
+    librdkafka_config_opt: Option<serde_json::Value>,
+) -> serde_json::Value {
+    let mut conf = service_config.clone();
+    conf["address"] = serde_json::json!("127.0.0.1:0");
+    conf["python_plugin_src_dir"] =
+        serde_json::json!(env!("CARGO_MANIFEST_DIR").to_owned() + "/src/python");
+    let librdkafka_config = if let Some(librdkafka_config) = librdkafka_config_opt {
+        librdkafka_config
+    } else {
+        serde_json::json!([{"config": {"bootstrap.servers": broker_addr().as_str()}}])
+    };
+    serde_json::json!({
+        "service": conf,
+        "librdkafka" : librdkafka_config
+    })
+}
+
+pub fn server_config_with_librdkafka(
+    service_config: serde_json::Value,
+    librdkafka_config: serde_json::Value,
+) -> serde_json::Value {
+    _server_config(service_config, Some(librdkafka_config))
+}
+
+// language=json
+pub const DATA: &str = r#"[{"some":{"nested":"data"}},{"some":{"deeper":{"nested":"data"}}}]"#;
+pub const DATA_LEN: u128 = DATA.len() as u128;
+
+pub async fn assert_ingest_response(
+    res: Response,
+    status: StatusCode,
+    ingested_opt: Option<(String, u64, u128, String)>,
+) {
+    let expected_body = if let Some((content_type, ingested_count, ingested_bytes, schema_id)) =
+        ingested_opt
+    {
+        Some(format!(
+            r#"{{"ingested_count":{},"ingested_bytes":{},"ingested_content_type":"{}","ingested_schema_id":"{}"}}"#,
+            ingested_count, ingested_bytes, content_type, schema_id
+        ))
+    } else {
+        None
+    };
+    // this fails! actix web bug?
+    // assert_eq!(
+    //     res.headers()["content-length"],
+    //     expected_body.len().to_string()
+    // );
+    let content_type = expected_body
+        .as_ref()
+        .map(|_| res.headers().get("content-type"))
+        .flatten()
+        .cloned();
+    assert_response(res, status, expected_body.as_deref()).await;
+    if let Some(_) = expected_body {
+        assert_eq!(content_type.unwrap(), "application/json");
+    }
+}
+
+pub async fn assert_response(res: Response, status: StatusCode, body: Option<&str>) {
+    assert_eq!(res.status(), status);
+    let expected_body = if let Some(b) = body {
+        b.to_owned()
+    } else {
+        "".to_owned()
+    };
+    assert_eq!(res.text().await.unwrap(), expected_body);
+}
+
+pub fn assert_is_config_error<T>(r: IResult<T>, err_txt: &str) {
+    assert!(r.is_err());
+    if let Err(err) = r {
+        assert!(matches!(err, Error::Config(ConfigError::Invalid(_))));
+        if let Error::Config(ConfigError::Invalid(s)) = err {
+            assert_eq!(s, err_txt);
+        }
+    }
+}
@@ -1,145 +1,148 @@
-// use std::error::Error;
-//
-// use common::config::CommonConfig;
-// use futures::{SinkExt, StreamExt};
-// use reqwest::{Client, StatusCode};
-// use tokio::net::TcpStream;
-// use tokio_tungstenite::{
-//     connect_async as ws_connect,
-//     tungstenite::{
-//         protocol::{frame::coding::CloseCode, CloseFrame},
-//         Message as WSMessage, Result as WSResult,
-//     },
-//     WebSocketStream,
-// };
-// use url::Url;
-//
-// use ingest::{error::Result as IResult, Config, Server};
-//
-// const WS_PATH: &str = "/ws";
-//
-// const DATA: &str = r#"
-//     [
-//         {
-//             "some": {
-//                 "nested": "data"
-//             }
-//         },
-//         {
-//             "some": {
-//                 "deeper": {
-//                     "nested": "data"
-//                 }
-//             }
-//         }
-//     ]
-//     "#;
-//
-// #[actix_rt::test]
-// async fn ws_successful() -> Result<(), Box<dyn Error>> {
-//     let server = start_server()?;
-//
-//     let mut client = ws_client(&server).await?;
-//
-//     let text_msg = WSMessage::text(DATA);
-//     let byte_msg = WSMessage::binary(DATA.as_bytes());
-//
-//     for msg in vec![text_msg, byte_msg] {
-//         client
-//             .send(msg.clone())
-//             .await
-//             .expect("failed to send ws text message");
-//
-//         let res = next_json(&mut client).await?;
-//
-//         if res["success"] == false {
-//             panic!(
-//                 r#"Websocket request "{}" expected successful response, but got "{}""#,
-//                 msg, res
-//             );
-//         }
-//     }
-//
-//     server.kill().await;
-//
-//     Ok(())
-// }
-//
-// #[actix_rt::test]
-// async fn ws_close() -> Result<(), Box<dyn Error>> {
-//     let server = start_server()?;
-//
-//     let mut client = ws_client(&server).await?;
-//
-//     server.stop().await;
-//
-//     let ws_close = client
-//         .next()
-//         .await
-//         .expect("failed to receive ws close message");
-//
-//     match ws_close {
-//         Ok(WSMessage::Close(Some(CloseFrame {
-//             code: CloseCode::Restart,
-//             ..
-//         }))) => {}
-//
-//         _ => panic!(
-//             "Expected websocket client to get close frame with Restart code, but got {:?}",
-//             ws_close
-//         ),
-//     }
-//
-//     Ok(())
-// }
-//
-// fn start_server() -> IResult<Server> {
-//     let mut config = Config::load()?;
-//
-//     // bind to any available port
-//     config.address = ([127, 0, 0, 1], 0).into();
-//
-//     Server::start(config)
-// }
-//
-// fn http_path(server: &Server) -> Url {
-//     let mut url = Url::parse(&format!(
-//         "http://{}/",
-//         &server.addrs().first().unwrap().to_string()
-//     ))
-//     .unwrap();
-//
-//     url.set_path(HTTP_PATH);
-//
-//     url
-// }
-//
-// fn ws_path(server: &Server) -> Url {
-//     let mut url = Url::parse(&format! {
-//         "ws://{}/",
-//         &server.addrs().first().unwrap().to_string()
-//     })
-//     .unwrap();
-//
-//     url.set_path(WS_PATH);
-//
-//     url
-// }
-//
-// async fn ws_client(server: &Server) -> WSResult<WebSocketStream<TcpStream>> {
-//     ws_connect(ws_path(server)).await.map(|(stream, _)| stream)
-// }
-//
-// fn extract_json(ws_result: Option<WSResult<WSMessage>>) -> Result<json::JsonValue, Box<dyn Error>> {
-//     match ws_result {
-//         Some(Ok(WSMessage::Text(t))) => Ok(json::parse(&t)?),
-//         Some(Ok(WSMessage::Binary(b))) => Ok(json::parse(String::from_utf8(b)?.as_str())?),
-//         _ => Err(format!("Expected JSON ws response data; got {:#?}", ws_result).into()),
-//     }
-// }
-//
-// async fn next_json(
-//     client: &mut WebSocketStream<TcpStream>,
-// ) -> Result<json::JsonValue, Box<dyn Error>> {
-//     extract_json(client.next().await)
-// }
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use ingest::Server;
+use reqwest::{Body, Client, Method, StatusCode};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+mod util;
+use util::*;
+
+async fn ws_connect(
+    server: &Server,
+    schema_id: &str,
+) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
+    let addr = server.addrs().first().unwrap().to_string();
+    let (ws, _) =
+        tokio_tungstenite::connect_async(format!("ws://{}/ingest/{}/ws", addr, schema_id))
+            .await
+            .unwrap();
+    ws
+}
+
+#[tokio::test]
+async fn test_websocket_acks() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "content_type": "application/jsonlines",
+            "websocket": true
+        }
+    }));
+    let server = start_server(config).await.unwrap();
+    let mut ws = ws_connect(&server, "1").await;
+
+    // language=jsonlines
+    ws.send(Message::text("{\"a\":1}\n{\"b\":2}\n"))
+        .await
+        .unwrap();
+    ws.send(Message::binary(b"{\"c\":3}".to_vec()))
+        .await
+        .unwrap();
+    for expected in [
+        r#"{"seq":1,"success":true,"ingested_count":2,"ingested_bytes":14,"ingested_content_type":"application/jsonlines","ingested_schema_id":"1"}"#,
+        r#"{"seq":2,"success":true,"ingested_count":1,"ingested_bytes":7,"ingested_content_type":"application/jsonlines","ingested_schema_id":"1"}"#,
+    ] {
+        let ack = ws.next().await.unwrap().unwrap();
+        assert_eq!(ack.into_text().unwrap().as_str(), expected);
+    }
+
+    // stopping the server closes the connection
+    server.stop().await;
+    match ws.next().await.unwrap().unwrap() {
+        Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Restart),
+        other => panic!("Expected a close frame, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_disabled_is_trailing_path() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test"
+        }
+    }));
+
+    let res = request(config, "1/ws", DATA, Method::POST).await.unwrap();
+    assert_ingest_response(
+        res,
+        StatusCode::OK,
+        Some(("application/json".to_owned(), 1, DATA_LEN, "1".to_owned())),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_websocket_memory_budget() {
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "websocket": true
+        },
+        "schema_config": [{"schema_id": "2", "max_in_flight_bytes": 1024}],
+        "memory_budget": {"queue_timeout_ms": 50}
+    }));
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+    let mut ws = ws_connect(&server, "2").await;
+
+    // a chunked body takes the whole budget of the schema until it is read
+    let streaming = Client::new()
+        .post(format!("http://{}/ingest/2", addr))
+        .body(Body::wrap_stream(vec_to_stream(
+            vec!["a".to_owned(), "b".to_owned()],
+            true,
+        )))
+        .send();
+    let rejected = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        ws.send(Message::text(DATA)).await.unwrap();
+        let ack = ws.next().await.unwrap().unwrap();
+        let ack: serde_json::Value = serde_json::from_str(ack.to_text().unwrap()).unwrap();
+        assert_eq!(ack["seq"], 1);
+        assert_eq!(ack["success"], false);
+        assert_eq!(ack["error"], "Memory budget exceeded, retry later");
+    };
+    let (res, ()) = tokio::join!(streaming, rejected);
+    assert_eq!(res.unwrap().status(), StatusCode::OK);
+
+    server.kill().await;
+}
+
+#[tokio::test]
+async fn test_websocket_signed_schema_refused() {
+    let secret_path = std::env::temp_dir().join(format!("ingest-ws-secret-{}", std::process::id()));
+    std::fs::write(&secret_path, "secret").unwrap();
+    let config = server_config(serde_json::json!({
+        "default_schema_config": {
+            "destination_topic": "test",
+            "websocket": true
+        },
+        "schema_config": [{
+            "schema_id": "2",
+            "signature": {"provider": "github", "secret_file": secret_path.to_str().unwrap()}
+        }]
+    }));
+    let server = start_server(config).await.unwrap();
+    let addr = server.addrs().first().unwrap().to_string();
+
+    match tokio_tungstenite::connect_async(format!("ws://{}/ingest/2/ws", addr)).await {
+        Err(Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                response.body().as_deref(),
+                Some(
+                    br#"{"error":"signature_unsupported","message":"Signed schemas only accept HTTP requests"}"#
+                        .as_slice()
+                )
+            );
+        }
+        other => panic!("Expected the upgrade to be refused, got {:?}", other),
+    }
+
+    server.kill().await;
+    std::fs::remove_file(secret_path).unwrap();
+}